@@ -0,0 +1,139 @@
+use flate2::read::GzDecoder;
+use hex::encode;
+use md5::{Digest, Md5};
+use tar::EntryType;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::FsPulseError;
+use crate::items::ItemType;
+
+// The first bytes of every gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// A member of an archive, described the way items are so the two can be compared
+#[derive(Clone, Debug)]
+pub struct ArchiveMember {
+    /// The member's path with the leading components stripped, using the platform's
+    /// separators like item paths do
+    pub path: String,
+    pub item_type: ItemType,
+    pub size: Option<i64>,
+    pub modified: Option<i64>,
+    pub hash: Option<String>,
+}
+
+pub struct Archive {
+    // No fields
+}
+
+impl Archive {
+    /// Reads the members of a tar archive, which may be gzip compressed. `strip_components`
+    /// leading path components are removed from each member (as with tar's option of the
+    /// same name), and members left with no path are skipped. With `hash`, the md5 of each
+    /// file's content is computed, which means reading the whole archive
+    pub fn read_members(path: &Path, strip_components: usize, hash: bool) -> Result<Vec<ArchiveMember>, FsPulseError> {
+        Self::read_tar(Self::open(path)?, strip_components, hash)
+    }
+
+    /// Opens a tar archive for reading, decompressing it if it's gzip compressed
+    pub fn open(path: &Path) -> Result<Box<dyn Read>, FsPulseError> {
+        let mut file = File::open(path)?;
+
+        let mut magic = [0u8; 2];
+        let is_gzip = file.read_exact(&mut magic).is_ok() && magic == GZIP_MAGIC;
+        file.seek(SeekFrom::Start(0))?;
+
+        let reader: Box<dyn Read> = if is_gzip {
+            Box::new(GzDecoder::new(BufReader::new(file)))
+        } else {
+            Box::new(BufReader::new(file))
+        };
+
+        Ok(reader)
+    }
+
+    /// Reads the members of a tar archive from `reader`, as `read_members` does
+    pub fn read_tar(reader: impl Read, strip_components: usize, hash: bool) -> Result<Vec<ArchiveMember>, FsPulseError> {
+        let mut archive = tar::Archive::new(reader);
+        let mut members = Vec::new();
+
+        // A hard link is stored as a reference to a member earlier in the archive, and
+        // describes the same content
+        let mut files: HashMap<PathBuf, usize> = HashMap::new();
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let entry_path = entry.path()?.into_owned();
+
+            let Some(member_path) = Self::strip(&entry_path, strip_components) else {
+                continue;
+            };
+
+            let header = entry.header();
+            let modified = header.mtime().ok().and_then(|mtime| i64::try_from(mtime).ok());
+            let entry_type = header.entry_type();
+
+            let member = match entry_type {
+                EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                    let size = i64::try_from(entry.size()).ok();
+                    let hash = if hash { Some(Self::hash(&mut entry)?) } else { None };
+                    files.insert(entry_path, members.len());
+                    ArchiveMember { path: member_path, item_type: ItemType::File, size, modified, hash }
+                },
+                EntryType::Link => {
+                    let target = entry.link_name()?.map(|target| target.into_owned());
+                    let linked = target.and_then(|target| files.get(&target)).map(|i| &members[*i]);
+                    ArchiveMember {
+                        path: member_path,
+                        item_type: ItemType::File,
+                        size: linked.and_then(|m: &ArchiveMember| m.size),
+                        modified,
+                        hash: linked.and_then(|m: &ArchiveMember| m.hash.clone()),
+                    }
+                },
+                EntryType::Directory => ArchiveMember { path: member_path, item_type: ItemType::Directory, size: None, modified, hash: None },
+                EntryType::Symlink => ArchiveMember { path: member_path, item_type: ItemType::Symlink, size: None, modified, hash: None },
+                // Extended headers describe the member which follows them, and are read by the tar crate
+                EntryType::XGlobalHeader | EntryType::XHeader | EntryType::GNULongName | EntryType::GNULongLink => continue,
+                _ => ArchiveMember { path: member_path, item_type: ItemType::Other, size: None, modified, hash: None },
+            };
+
+            members.push(member);
+        }
+
+        Ok(members)
+    }
+
+    fn strip(path: &Path, strip_components: usize) -> Option<String> {
+        let components: Vec<_> = path.components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .skip(strip_components)
+            .collect();
+
+        if components.is_empty() {
+            return None;
+        }
+
+        Some(components.iter().collect::<PathBuf>().to_string_lossy().into_owned())
+    }
+
+    /// The md5 of everything read from `reader`, as hex
+    pub fn hash(reader: &mut impl Read) -> io::Result<String> {
+        let mut hasher = Md5::new();
+        let mut buffer = [0; 8192];
+
+        loop {
+            let bytes_read = reader.read(&mut buffer)?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..bytes_read]);
+        }
+
+        Ok(encode(hasher.finalize()))
+    }
+}
@@ -0,0 +1,137 @@
+use std::time::SystemTime;
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::utils::Utils;
+
+/// Arguments whose values are secrets, which are left out of the recorded parameters
+const SECRET_ARGS: &[&str] = &["--db-passphrase", "--new-passphrase"];
+
+/// An operation which changed the database, as recorded in the audit log. The log is
+/// append-only: the database refuses to change or delete its entries
+#[derive(Clone, Debug)]
+pub struct AuditEntry {
+    id: i64,
+    time: i64,
+    user: String,
+    operation: String,
+    parameters: String,
+}
+
+impl AuditEntry {
+    /// Records that the operation is being run, by whom, and with the arguments fspulse was
+    /// run with. It's recorded before the operation runs, so that one which fails or is
+    /// interrupted part way through is recorded too
+    pub fn record(db: &Database, operation: &str) -> Result<(), FsPulseError> {
+        db.conn.execute(
+            "INSERT INTO audit_log (time, user, operation, parameters) VALUES (?, ?, ?, ?)",
+            (
+                Utils::system_time_to_db_time(SystemTime::now()),
+                Self::current_user(),
+                operation,
+                Self::command_line(std::env::args().skip(1)),
+            ),
+        )?;
+
+        Ok(())
+    }
+
+    /// Records something an operation did, such as a file `repair` restored, described in
+    /// place of the arguments
+    pub fn record_action(db: &Database, operation: &str, description: &str) -> Result<(), FsPulseError> {
+        db.conn.execute(
+            "INSERT INTO audit_log (time, user, operation, parameters) VALUES (?, ?, ?, ?)",
+            (Utils::system_time_to_db_time(SystemTime::now()), Self::current_user(), operation, description),
+        )?;
+
+        Ok(())
+    }
+
+    /// Calls `func` with each entry, oldest first. With `operation`, only that operation's
+    /// entries are included, and with `limit` only the most recent entries
+    pub fn for_each_entry<F>(db: &Database, operation: Option<&str>, limit: Option<u32>, mut func: F) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&AuditEntry) -> Result<(), FsPulseError>,
+    {
+        let mut stmt = db.conn.prepare(
+            "SELECT id, time, user, operation, parameters FROM (
+                SELECT id, time, user, operation, parameters
+                    FROM audit_log
+                    WHERE ?1 IS NULL OR operation = ?1
+                    ORDER BY id DESC
+                    LIMIT ?2)
+                ORDER BY id ASC"
+        )?;
+        let rows = stmt.query_map((operation, limit.map_or(-1, i64::from)), |row| {
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                time: row.get(1)?,
+                user: row.get(2)?,
+                operation: row.get(3)?,
+                parameters: row.get(4)?,
+            })
+        })?;
+
+        for row in rows {
+            func(&row?)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn time(&self) -> i64 {
+        self.time
+    }
+
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn operation(&self) -> &str {
+        &self.operation
+    }
+
+    pub fn parameters(&self) -> &str {
+        &self.parameters
+    }
+
+    /// The arguments as they'd be typed in a shell, with the values of secret arguments
+    /// replaced by `***`
+    fn command_line(args: impl Iterator<Item = String>) -> String {
+        let mut redact_next = false;
+        let mut parameters = Vec::new();
+
+        for arg in args {
+            let arg = if redact_next {
+                redact_next = false;
+                "***".to_string()
+            } else if let Some(secret) = SECRET_ARGS.iter().find(|secret| arg.starts_with(&format!("{}=", secret))) {
+                format!("{}=***", secret)
+            } else {
+                redact_next = SECRET_ARGS.contains(&arg.as_str());
+                arg
+            };
+
+            let quote = arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || "'\"\\$".contains(c));
+            parameters.push(if quote { format!("'{}'", arg.replace('\'', "'\\''")) } else { arg });
+        }
+
+        parameters.join(" ")
+    }
+
+    #[cfg(unix)]
+    fn current_user() -> String {
+        uzers::get_current_username()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| uzers::get_current_uid().to_string())
+    }
+
+    #[cfg(not(unix))]
+    fn current_user() -> String {
+        std::env::var("USERNAME").unwrap_or_else(|_| "unknown".to_string())
+    }
+}
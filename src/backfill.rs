@@ -0,0 +1,217 @@
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::capabilities::FsCapabilities;
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::hash::{HashAlgo, HashPool, HashQueue, HashResult, DEFAULT_BUFFER_SIZE};
+use crate::items::ItemType;
+use crate::roots::Root;
+use crate::scans::{Scan, ScanState};
+use crate::utils::Utils;
+
+/// Which of a root's files without a hash are hashed first
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum BackfillOrder {
+    /// The most files for the bytes read
+    #[default]
+    Smallest,
+    /// The most bytes covered per file
+    Largest,
+    Path,
+}
+
+impl BackfillOrder {
+    fn order_by(&self) -> &'static str {
+        match self {
+            BackfillOrder::Smallest => "file_size ASC, path ASC",
+            BackfillOrder::Largest => "file_size DESC, path ASC",
+            BackfillOrder::Path => "path ASC",
+        }
+    }
+}
+
+/// How much of the backlog one run of `backfill-hashes` works through, and how quickly
+#[derive(Clone, Debug, Default)]
+pub struct BackfillOptions {
+    pub order: BackfillOrder,
+    /// Hash at most this many files in each root
+    pub limit: Option<u32>,
+    /// Stop queueing files after this many seconds
+    pub budget_secs: Option<i64>,
+    /// Read at most this many bytes a second, on average
+    pub max_rate: Option<u64>,
+    /// Without one, each root is hashed with the algorithm its last hashing scan used
+    pub hash_algo: Option<HashAlgo>,
+    pub hash_threads: usize,
+}
+
+/// A file with no full hash, as the root's last scan recorded it
+struct UnhashedFile {
+    item_id: i64,
+    path: String,
+    file_size: Option<i64>,
+    last_modified: Option<i64>,
+}
+
+/// What one run of `backfill-hashes` did in a root
+#[derive(Clone, Debug, Default)]
+pub struct Backfill {
+    pub files_hashed: i64,
+    pub bytes_hashed: u64,
+    /// Files which changed since the last scan or couldn't be read, left for a scan
+    pub files_skipped: i64,
+    /// Files still without a hash when the run finished
+    pub files_remaining: i64,
+}
+
+impl Backfill {
+    /// Hashes the root's files which shallow scans recorded without a hash, so a database
+    /// built without hashing gradually gets the hashes deep scans and `verify` rely on.
+    /// Only files whose size and modification time still match what the root's last scan
+    /// recorded are hashed, and each hash is recorded as taken by that scan, so nothing is
+    /// recorded as a change: a later hashing scan compares with it as usual. Files which
+    /// changed, or which were offline, are left for a scan. Each file's hash is stored as
+    /// soon as it's taken, so a run that's stopped or runs out of time loses nothing, and
+    /// the next run carries on with the files still without a hash
+    pub fn run(db: &Database, root: &Root, options: &BackfillOptions, deadline: Option<Instant>, hash_algo: HashAlgo) -> Result<Self, FsPulseError> {
+        if let Some(scan) = Scan::get_latest_for_root(db, root.id())?
+            .filter(|s| s.state() != ScanState::Completed && s.state() != ScanState::Aborted)
+        {
+            return Err(FsPulseError::Error(format!(
+                "Scan Id {} of '{}' is incomplete. Resume or abort it with `fspulse scan` before backfilling hashes",
+                scan.id(), root.path())));
+        }
+
+        let mtime_tolerance = FsCapabilities::get_for_root(db, root.id())?.unwrap_or_default().mtime_tolerance();
+
+        let files = {
+            let mut stmt = db.conn.prepare(&format!(
+                "SELECT id, path, file_size, last_modified
+                    FROM items
+                    WHERE root_id = ? AND item_type = ? AND is_tombstone = 0 AND is_ignored = 0
+                    AND file_hash IS NULL AND is_offline = 0
+                    ORDER BY {}
+                    LIMIT ?",
+                options.order.order_by(),
+            ))?;
+            let rows = stmt.query_map((root.id(), ItemType::File.as_str(), options.limit.map_or(-1, i64::from)), |row| {
+                Ok(UnhashedFile {
+                    item_id: row.get(0)?,
+                    path: row.get(1)?,
+                    file_size: row.get(2)?,
+                    last_modified: row.get(3)?,
+                })
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut backfill = Backfill::default();
+
+        let bytes_total: u64 = files.iter().map(|file| file.file_size.unwrap_or_default().max(0) as u64).sum();
+        let bar = ProgressBar::new(bytes_total);
+        bar.set_style(ProgressStyle::default_bar()
+            .template("{msg}\n[{bar:40}] {bytes}/{total_bytes} ({binary_bytes_per_sec}, {eta})")
+            .expect("valid progress template")
+            .progress_chars("#>-"));
+        bar.set_message(format!("Backfilling hashes in '{}'", root.path()));
+
+        let hash_pool = HashPool::new(options.hash_threads);
+        let mut queue = HashQueue::new(&hash_pool);
+        let started = Instant::now();
+        let mut bytes_queued = 0;
+
+        for file in files {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+
+            let absolute_path = root.item_path(&file.path);
+            let unchanged = fs::metadata(&absolute_path).is_ok_and(|metadata| {
+                let modified = metadata.modified().ok().map(Utils::system_time_to_db_time);
+                let same_time = match (file.last_modified, modified) {
+                    (Some(recorded), Some(current)) => (recorded - current).abs() <= mtime_tolerance,
+                    (recorded, current) => recorded == current,
+                };
+                metadata.is_file() && file.file_size == Some(metadata.len() as i64) && same_time
+            });
+            let file_size = file.file_size.unwrap_or_default().max(0) as u64;
+            if !unchanged {
+                backfill.files_skipped += 1;
+                bar.inc(file_size);
+                continue;
+            }
+
+            // Files are queued no faster than the rate allows, which keeps the average
+            // read rate under it however many threads are hashing
+            if let Some(max_rate) = options.max_rate {
+                let due = Duration::from_secs_f64(bytes_queued as f64 / max_rate as f64);
+                if let Some(wait) = due.checked_sub(started.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+            bytes_queued += file_size;
+
+            while queue.queued() >= hash_pool.threads() {
+                match queue.next()? {
+                    Some((file, hashes)) => backfill.record(db, root, &bar, file, hashes, hash_algo)?,
+                    None => break,
+                }
+            }
+
+            queue.submit(file, &absolute_path, &ProgressBar::hidden(), hash_algo, false, DEFAULT_BUFFER_SIZE)?;
+        }
+
+        while let Some((file, hashes)) = queue.next()? {
+            backfill.record(db, root, &bar, file, hashes, hash_algo)?;
+        }
+
+        bar.finish_and_clear();
+
+        backfill.files_remaining = db.conn.query_row(
+            "SELECT COUNT(*) FROM items
+                WHERE root_id = ? AND item_type = ? AND is_tombstone = 0 AND is_ignored = 0 AND file_hash IS NULL",
+            (root.id(), ItemType::File.as_str()),
+            |row| row.get(0),
+        )?;
+
+        Ok(backfill)
+    }
+
+    /// Stores the file's hash, unless it changed while it was read or a scan hashed it
+    /// in the meantime
+    fn record(&mut self, db: &Database, root: &Root, bar: &ProgressBar, file: UnhashedFile, hashes: HashResult, hash_algo: HashAlgo) -> Result<(), FsPulseError> {
+        let file_size = file.file_size.unwrap_or_default().max(0) as u64;
+        bar.inc(file_size);
+
+        let hash = match hashes {
+            Ok(Some((hash, _))) => hash,
+            // Being written, so a scan will see it change
+            Ok(None) => {
+                self.files_skipped += 1;
+                return Ok(());
+            },
+            Err(error) => {
+                Utils::println_above(bar, format!("Error hashing '{}': {}", root.item_path(&file.path).display(), error));
+                self.files_skipped += 1;
+                return Ok(());
+            },
+        };
+
+        let updated = db.conn.execute(
+            "UPDATE items SET file_hash = ?, hash_algo = ?, hash_in_flux = 0, last_hash_scan_id = last_scan_id, last_verified = strftime('%s', 'now', 'utc')
+                WHERE id = ? AND file_hash IS NULL",
+            (&hash, hash_algo.as_str(), file.item_id),
+        )?;
+
+        if updated > 0 {
+            self.files_hashed += 1;
+            self.bytes_hashed += file_size;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use rusqlite::OptionalExtension;
+
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+
+/// Names of the temporary directories created while probing a root. Scans skip them in
+/// case one is seen by a concurrent scan of an enclosing root
+pub const PROBE_DIR_PREFIX: &str = ".fspulse-probe-";
+
+// Resolutions a file system might store modification times at, finest first
+const GRANULARITIES_NS: &[i64] = &[1, 100, 1_000, 1_000_000, 10_000_000, 1_000_000_000, 2_000_000_000];
+
+/// What the file system holding a root can represent. A capability is `None` when it
+/// couldn't be determined, such as when the root isn't writable
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FsCapabilities {
+    pub timestamp_granularity_ns: Option<i64>,
+    pub case_sensitive: Option<bool>,
+    pub symlinks: Option<bool>,
+    pub xattrs: Option<bool>,
+}
+
+impl FsCapabilities {
+    pub fn get_for_root(db: &Database, root_id: i64) -> Result<Option<Self>, FsPulseError> {
+        let capabilities = db.conn.query_row(
+            "SELECT timestamp_granularity, case_sensitive, supports_symlinks, supports_xattrs FROM roots WHERE id = ?",
+            [root_id],
+            |row| Ok(FsCapabilities {
+                timestamp_granularity_ns: row.get(0)?,
+                case_sensitive: row.get(1)?,
+                symlinks: row.get(2)?,
+                xattrs: row.get(3)?,
+            }),
+        )
+        .optional()?;
+
+        // A root that has never been probed has no capabilities recorded at all
+        Ok(capabilities.filter(|c| *c != FsCapabilities::default()))
+    }
+
+    pub fn store(&self, db: &Database, root_id: i64) -> Result<(), FsPulseError> {
+        db.conn.execute(
+            "UPDATE roots SET timestamp_granularity = ?, case_sensitive = ?, supports_symlinks = ?, supports_xattrs = ? WHERE id = ?",
+            (self.timestamp_granularity_ns, self.case_sensitive, self.symlinks, self.xattrs, root_id),
+        )?;
+
+        Ok(())
+    }
+
+    /// Probes the file system holding `root_path` by creating a temporary directory in the
+    /// root, experimenting inside it and removing it again. The root's modification time
+    /// is restored afterwards so the probe doesn't show up as a change to the root
+    pub fn probe(root_path: &Path) -> Self {
+        let probe_dir = root_path.join(format!("{}{}", PROBE_DIR_PREFIX, process::id()));
+        let root_modified = fs::metadata(root_path).and_then(|m| m.modified()).ok();
+
+        if fs::create_dir(&probe_dir).is_err() {
+            return FsCapabilities::default();
+        }
+
+        let capabilities = FsCapabilities {
+            timestamp_granularity_ns: Self::probe_granularity(&probe_dir).ok(),
+            case_sensitive: Self::probe_case_sensitivity(&probe_dir).ok(),
+            symlinks: Self::probe_symlinks(&probe_dir),
+            xattrs: Self::probe_xattrs(&probe_dir),
+        };
+
+        let _ = fs::remove_dir_all(&probe_dir);
+        if let Some(root_modified) = root_modified {
+            let _ = File::open(root_path).and_then(|root| root.set_modified(root_modified));
+        }
+
+        capabilities
+    }
+
+    /// Sets a modification time with an odd number of seconds and a fractional part, and
+    /// sees how much of it survives
+    fn probe_granularity(probe_dir: &Path) -> io::Result<i64> {
+        let path = probe_dir.join("granularity");
+        let file = File::create(&path)?;
+
+        let requested = UNIX_EPOCH + Duration::new(1_000_000_001, 123_456_789);
+        file.set_modified(requested)?;
+        drop(file);
+
+        let stored = fs::metadata(&path)?.modified()?;
+        let error = match stored.duration_since(requested) {
+            Ok(later) => later,
+            Err(earlier) => earlier.duration(),
+        };
+        let error_ns = i64::try_from(error.as_nanos()).unwrap_or(i64::MAX);
+
+        Ok(GRANULARITIES_NS.iter()
+            .copied()
+            .find(|granularity| error_ns < *granularity)
+            .unwrap_or(*GRANULARITIES_NS.last().unwrap()))
+    }
+
+    fn probe_case_sensitivity(probe_dir: &Path) -> io::Result<bool> {
+        File::create(probe_dir.join("case"))?;
+
+        match fs::symlink_metadata(probe_dir.join("CASE")) {
+            Ok(_) => Ok(false),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(true),
+            Err(error) => Err(error),
+        }
+    }
+
+    #[cfg(unix)]
+    fn probe_symlinks(probe_dir: &Path) -> Option<bool> {
+        let link = probe_dir.join("link");
+        let created = std::os::unix::fs::symlink("target", &link).is_ok();
+
+        Some(created && fs::symlink_metadata(&link).is_ok_and(|m| m.file_type().is_symlink()))
+    }
+
+    // Creating symlinks on Windows needs a privilege which is unrelated to what the file system supports
+    #[cfg(not(unix))]
+    fn probe_symlinks(_probe_dir: &Path) -> Option<bool> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn probe_xattrs(probe_dir: &Path) -> Option<bool> {
+        if !xattr::SUPPORTED_PLATFORM {
+            return None;
+        }
+
+        let path = probe_dir.join("xattr");
+        File::create(&path).ok()?;
+
+        Some(xattr::set(&path, "user.fspulse.probe", b"1").is_ok())
+    }
+
+    #[cfg(not(unix))]
+    fn probe_xattrs(_probe_dir: &Path) -> Option<bool> {
+        None
+    }
+
+    /// Modification times which differ by less than this many seconds can't be told apart
+    /// reliably on this file system, so they aren't treated as a modification
+    pub fn mtime_tolerance(&self) -> i64 {
+        self.timestamp_granularity_ns.map_or(0, |ns| (ns / 1_000_000_000 - 1).max(0))
+    }
+
+    /// Describes the limitations of the file system which affect what a scan can detect
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+
+        if let Some(granularity) = self.timestamp_granularity_ns.filter(|ns| *ns > 1_000_000_000) {
+            warnings.push(format!(
+                "modification times are stored to the nearest {}; smaller differences are ignored",
+                Self::format_granularity(granularity)));
+        }
+        if self.case_sensitive == Some(false) {
+            warnings.push("file names are case-insensitive; renaming a file by case only is reported as a delete and an add".to_string());
+        }
+        if self.symlinks == Some(false) {
+            warnings.push("symlinks aren't supported".to_string());
+        }
+        if self.xattrs == Some(false) {
+            warnings.push("extended attributes aren't supported".to_string());
+        }
+
+        warnings
+    }
+
+    /// Lists how this probe differs from an earlier one, which usually means that a
+    /// different file system is now mounted at the root
+    pub fn differences(&self, earlier: &FsCapabilities) -> Vec<String> {
+        let mut differences = Vec::new();
+
+        if self.timestamp_granularity_ns != earlier.timestamp_granularity_ns {
+            differences.push(format!(
+                "timestamp granularity {} -> {}",
+                earlier.timestamp_granularity_ns.map_or("-".to_string(), Self::format_granularity),
+                self.timestamp_granularity_ns.map_or("-".to_string(), Self::format_granularity)));
+        }
+
+        for (name, before, after) in [
+            ("case sensitive", earlier.case_sensitive, self.case_sensitive),
+            ("symlinks", earlier.symlinks, self.symlinks),
+            ("extended attributes", earlier.xattrs, self.xattrs),
+        ] {
+            if before != after {
+                differences.push(format!("{} {} -> {}", name, Self::format_opt_bool(before), Self::format_opt_bool(after)));
+            }
+        }
+
+        differences
+    }
+
+    fn format_granularity(ns: i64) -> String {
+        match ns {
+            ns if ns >= 1_000_000_000 => format!("{}s", ns / 1_000_000_000),
+            ns if ns >= 1_000_000 => format!("{}ms", ns / 1_000_000),
+            ns if ns >= 1_000 => format!("{}µs", ns / 1_000),
+            ns => format!("{}ns", ns),
+        }
+    }
+
+    fn format_opt_bool(value: Option<bool>) -> &'static str {
+        match value {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "unknown",
+        }
+    }
+}
@@ -1,22 +1,30 @@
+use std::collections::HashSet;
 use std::str::FromStr;
 
-use log::info;
-use rusqlite::OptionalExtension;
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate};
+use rusqlite::{params_from_iter, types::Value, Connection, OptionalExtension, Row, Statement};
+use serde::Serialize;
 
 use crate::database::Database;
 use crate::error::FsPulseError;
+use crate::reports::Reports;
+use crate::utils::Utils;
 
-const SQL_FOR_EACH_CHANGE_IN_SCAN: &str = 
-    "SELECT items.item_type, items.path, changes.id, changes.scan_id, changes.item_id, changes.change_type, changes.prev_last_modified, prev_file_size, prev_hash, prev_is_valid
+const SQL_CHANGES_IN_SCAN: &str = 
+    "SELECT items.item_type, items.path, changes.id, changes.scan_id, changes.item_id, changes.change_type, changes.prev_last_modified, prev_file_size, prev_hash, prev_is_valid, prev_symlink_target, acknowledged, review_comment, prev_sample_hash, old_path, similarity,
+            changes.change_type = 'M' AND (changes.prev_last_modified IS NOT NULL OR changes.prev_file_size IS NOT NULL OR changes.prev_symlink_target IS NOT NULL OR changes.prev_owner_uid IS NOT NULL OR changes.prev_owner_gid IS NOT NULL),
+            changes.change_type = 'M' AND (changes.prev_hash IS NOT NULL OR changes.prev_sample_hash IS NOT NULL)
         FROM changes
         JOIN items ON items.id = changes.item_id
-        WHERE changes.scan_id = ?
+        WHERE changes.scan_id = ? AND items.is_ignored = 0{}
         ORDER BY items.path ASC";
-const SQL_FOR_EACH_CHANGE_IN_ITEM: &str = 
-    "SELECT items.item_type, items.path, changes.id, changes.scan_id, changes.item_id, changes.change_type, changes.prev_last_modified, prev_file_size, prev_hash, prev_is_valid
+const SQL_CHANGES_IN_ITEM: &str = 
+    "SELECT items.item_type, items.path, changes.id, changes.scan_id, changes.item_id, changes.change_type, changes.prev_last_modified, prev_file_size, prev_hash, prev_is_valid, prev_symlink_target, acknowledged, review_comment, prev_sample_hash, old_path, similarity,
+            changes.change_type = 'M' AND (changes.prev_last_modified IS NOT NULL OR changes.prev_file_size IS NOT NULL OR changes.prev_symlink_target IS NOT NULL OR changes.prev_owner_uid IS NOT NULL OR changes.prev_owner_gid IS NOT NULL),
+            changes.change_type = 'M' AND (changes.prev_hash IS NOT NULL OR changes.prev_sample_hash IS NOT NULL)
         FROM changes
         JOIN items ON items.id = changes.item_id
-        WHERE changes.item_id = ?
+        WHERE changes.item_id = ?{}
         ORDER BY changes.id ASC";
 
 
@@ -32,6 +40,20 @@ pub struct Change {
     pub prev_file_size: Option<i64>,
     pub prev_hash: Option<String>,
     pub prev_is_valid: Option<bool>,
+    pub prev_symlink_target: Option<String>,
+    pub prev_sample_hash: Option<String>,
+    /// The path the item moved from, for a move
+    pub old_path: Option<String>,
+    /// How much of its previous content a modified file kept, as a percentage
+    pub similarity: Option<i64>,
+    pub acknowledged: bool,
+    pub review_comment: Option<String>,
+    /// For a modification, whether the item's metadata changed: its modification time,
+    /// size, symlink target or owner
+    pub metadata_changed: bool,
+    /// For a modification, whether the file's content changed, as its full or sampled hash
+    /// showed. Scans which don't hash can't tell
+    pub hash_changed: bool,
 
     // Additional non-entity fields
     pub item_type: String,
@@ -45,15 +67,298 @@ pub struct ChangeCounts {
     pub modify_count: i64,
     pub delete_count: i64,
     pub type_change_count: i64,
+    pub move_count: i64,
     pub no_change_count: i64,
 }
 
+/// The length of the periods change statistics are rolled up into. Periods are in local
+/// time, and weeks start on Monday
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ChangePeriod {
+    Day,
+    Week,
+}
+
+impl ChangePeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Day => "day",
+            Self::Week => "week",
+        }
+    }
+
+    /// The first day of the period containing `date`
+    fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Self::Day => date,
+            Self::Week => date - Days::new(date.weekday().num_days_from_monday().into()),
+        }
+    }
+
+    fn days(&self) -> u64 {
+        match self {
+            Self::Day => 1,
+            Self::Week => 7,
+        }
+    }
+}
+
+impl FromStr for ChangePeriod {
+    type Err = FsPulseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "day" => Ok(Self::Day),
+            "week" => Ok(Self::Week),
+            _ => Err(FsPulseError::Error(format!("Invalid period '{}'", s))),
+        }
+    }
+}
+
+/// The scans of a root in one period, and the changes they found
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PeriodChanges {
+    /// The first day of the period, as YYYY-MM-DD
+    pub start: String,
+    pub scans: i64,
+    pub adds: i64,
+    pub modifies: i64,
+    pub deletes: i64,
+    pub type_changes: i64,
+    pub moves: i64,
+}
+
+impl PeriodChanges {
+    pub fn total(&self) -> i64 {
+        self.adds + self.modifies + self.deletes + self.type_changes + self.moves
+    }
+
+    /// The root's scans and changes in each of the last `periods` periods, oldest first
+    /// and ending with the current period. Periods without scans are included
+    pub fn for_root(db: &Database, root_id: i64, period: ChangePeriod, periods: u32) -> Result<Vec<Self>, FsPulseError> {
+        let current = period.start_of(Local::now().date_naive());
+        let first = current - Days::new(period.days() * u64::from(periods.saturating_sub(1)));
+
+        let mut buckets: Vec<PeriodChanges> = (0..u64::from(periods))
+            .map(|i| PeriodChanges {
+                start: (first + Days::new(period.days() * i)).format("%Y-%m-%d").to_string(),
+                ..Default::default()
+            })
+            .collect();
+
+        let since = first.and_hms_opt(0, 0, 0)
+            .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+            .map_or(0, |midnight| midnight.timestamp());
+
+        let mut stmt = db.conn.prepare(
+            "SELECT s.time_of_scan,
+                    COALESCE(SUM(c.change_type = 'A'), 0),
+                    COALESCE(SUM(c.change_type = 'M'), 0),
+                    COALESCE(SUM(c.change_type = 'D'), 0),
+                    COALESCE(SUM(c.change_type = 'T'), 0),
+                    COALESCE(SUM(c.change_type = 'R'), 0)
+                FROM scans s
+                LEFT JOIN changes c ON c.scan_id = s.id
+                    AND c.item_id IN (SELECT id FROM items WHERE root_id = ?1 AND is_ignored = 0)
+                WHERE s.root_id = ?1 AND s.time_of_scan >= ?2
+                GROUP BY s.id"
+        )?;
+
+        let mut rows = stmt.query((root_id, since))?;
+
+        while let Some(row) = rows.next()? {
+            let time_of_scan: i64 = row.get(0)?;
+            let Some(date) = DateTime::from_timestamp(time_of_scan, 0).map(|time| time.with_timezone(&Local).date_naive()) else {
+                continue;
+            };
+
+            let index = (period.start_of(date) - first).num_days() as u64 / period.days();
+            if let Some(bucket) = buckets.get_mut(index as usize) {
+                bucket.scans += 1;
+                bucket.adds += row.get::<_, i64>(1)?;
+                bucket.modifies += row.get::<_, i64>(2)?;
+                bucket.deletes += row.get::<_, i64>(3)?;
+                bucket.type_changes += row.get::<_, i64>(4)?;
+                bucket.moves += row.get::<_, i64>(5)?;
+            }
+        }
+
+        Ok(buckets)
+    }
+}
+
+/// When a deleted item disappeared: after the last scan that found it, and before the
+/// scan that recorded its deletion
+#[derive(Clone, Debug)]
+pub struct Deletion {
+    pub item_id: i64,
+    pub item_type: String,
+    pub item_path: String,
+    pub last_seen_scan_id: i64,
+    pub last_seen_time: i64,
+    pub deleted_scan_id: i64,
+    pub deleted_time: i64,
+}
+
+impl Deletion {
+    // An item's last scan stays where it was when the item is deleted, and the deletion
+    // is its most recent 'D' change. An item that came back isn't a tombstone
+    const SQL_DELETIONS: &str =
+        "SELECT i.id, i.item_type, i.path, i.last_scan_id, ls.time_of_scan, c.scan_id, ds.time_of_scan
+            FROM items i
+            JOIN changes c ON c.id = (
+                SELECT MAX(id) FROM changes WHERE item_id = i.id AND change_type = 'D')
+            JOIN scans ls ON ls.id = i.last_scan_id
+            JOIN scans ds ON ds.id = c.scan_id
+            WHERE i.is_tombstone = 1 AND i.is_ignored = 0";
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Deletion {
+            item_id: row.get(0)?,
+            item_type: row.get(1)?,
+            item_path: row.get(2)?,
+            last_seen_scan_id: row.get(3)?,
+            last_seen_time: row.get(4)?,
+            deleted_scan_id: row.get(5)?,
+            deleted_time: row.get(6)?,
+        })
+    }
+
+    /// The deletion of an item, if it's currently deleted
+    pub fn get_for_item(db: &Database, item_id: i64) -> Result<Option<Self>, FsPulseError> {
+        let sql = format!("{} AND i.id = ?", Self::SQL_DELETIONS);
+        let deletion = db.conn.query_row(&sql, [item_id], Self::from_row).optional()?;
+
+        Ok(deletion)
+    }
+
+    /// Calls `func` with each deleted item of the root whose deletion was recorded in the
+    /// range of scans, in the order they were deleted
+    pub fn for_each_in_root<F>(db: &Database, root_id: i64, from_scan: Option<i64>, to_scan: Option<i64>, mut func: F) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&Deletion) -> Result<(), FsPulseError>,
+    {
+        let sql = format!(
+            "{} AND i.root_id = ?1 AND (?2 IS NULL OR c.scan_id >= ?2) AND (?3 IS NULL OR c.scan_id <= ?3)
+                ORDER BY c.scan_id ASC, i.path ASC",
+            Self::SQL_DELETIONS
+        );
+        let mut stmt = db.conn.prepare(&sql)?;
+        let rows = stmt.query_map((root_id, from_scan, to_scan), Self::from_row)?;
+
+        for row in rows {
+            func(&row?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A file's size, modification time and hash on one side of a change
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct HistoryValues {
+    pub file_size: Option<i64>,
+    pub last_modified: Option<i64>,
+    pub hash: Option<String>,
+}
+
+/// One change to an item, with its values before and after. Values that weren't recorded,
+/// such as those before an item was added, or after it was deleted, are left out
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryEntry {
+    pub change_id: i64,
+    pub scan_id: i64,
+    pub time_of_scan: i64,
+    pub change_type: String,
+    /// The item's path after the change
+    #[serde(serialize_with = "Reports::serialize_path")]
+    pub path: String,
+    #[serde(serialize_with = "Reports::serialize_opt_path")]
+    pub old_path: Option<String>,
+    pub before: Option<HistoryValues>,
+    pub after: Option<HistoryValues>,
+}
+
+impl HistoryEntry {
+    /// Every change to the item, oldest first. Changes only record the values they
+    /// replaced, so values after each change are rebuilt from the item's current values by
+    /// undoing the changes newest first. Hashes are those recorded by hashing scans, and
+    /// what the item held before it was last added or changed type isn't recorded
+    pub fn for_item(db: &Database, item_id: i64) -> Result<Vec<Self>, FsPulseError> {
+        let current: Option<(String, HistoryValues)> = db.conn.query_row(
+            "SELECT path, file_size, last_modified, file_hash FROM items WHERE id = ?",
+            [item_id],
+            |row| Ok((row.get(0)?, HistoryValues {
+                file_size: row.get(1)?,
+                last_modified: row.get(2)?,
+                hash: row.get(3)?,
+            })),
+        ).optional()?;
+        let Some((mut path, values)) = current else {
+            return Err(FsPulseError::Error(format!("Item Id {} not found", item_id)));
+        };
+        let mut values = Some(values);
+
+        let mut stmt = db.conn.prepare(
+            "SELECT c.id, c.scan_id, s.time_of_scan, c.change_type, c.prev_file_size, c.prev_last_modified, c.prev_hash, c.old_path
+                FROM changes c
+                JOIN scans s ON s.id = c.scan_id
+                WHERE c.item_id = ?
+                ORDER BY c.id DESC"
+        )?;
+        let mut rows = stmt.query([item_id])?;
+
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next()? {
+            let change_type: String = row.get(3)?;
+            let old_path: Option<String> = row.get(7)?;
+
+            // A deleted item keeps the values it was last seen with
+            let (before, after) = match change_type.parse::<ChangeType>()? {
+                ChangeType::Delete => (values.clone(), None),
+                ChangeType::Add | ChangeType::TypeChange => (None, values.take()),
+                ChangeType::Modify | ChangeType::Move => {
+                    let (prev_file_size, prev_last_modified, prev_hash): (Option<i64>, Option<i64>, Option<String>) =
+                        (row.get(4)?, row.get(5)?, row.get(6)?);
+                    let before = values.clone().map(|after| HistoryValues {
+                        file_size: prev_file_size.or(after.file_size),
+                        last_modified: prev_last_modified.or(after.last_modified),
+                        hash: prev_hash.or(after.hash),
+                    });
+                    (before.clone(), std::mem::replace(&mut values, before))
+                },
+                ChangeType::NoChange => (values.clone(), values.clone()),
+            };
+
+            entries.push(HistoryEntry {
+                change_id: row.get(0)?,
+                scan_id: row.get(1)?,
+                time_of_scan: row.get(2)?,
+                change_type,
+                path: path.clone(),
+                old_path: old_path.clone(),
+                before,
+                after,
+            });
+
+            if let Some(old_path) = old_path {
+                path = old_path;
+            }
+        }
+
+        entries.reverse();
+        Ok(entries)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum ChangeType {
     Add,
     Delete,
     Modify,
     TypeChange,
+    /// A file left one path and appeared with the same content at another
+    Move,
     NoChange,
 }
 
@@ -64,6 +369,7 @@ impl ChangeType {
             Self::Delete => "D",
             Self::Modify => "M",
             Self::TypeChange => "T",
+            Self::Move => "R",
             Self::NoChange => "N",
         }
     }
@@ -84,6 +390,7 @@ impl FromStr for ChangeType {
             "D" => Ok(Self::Delete),
             "M" => Ok(Self::Modify),
             "T" => Ok(Self::TypeChange),
+            "R" => Ok(Self::Move),
             "N" => Ok(Self::NoChange),
             _ => Err(FsPulseError::Error(format!("Invalid change type: '{}'", s))), 
         }
@@ -96,94 +403,218 @@ impl Change {
     
         conn.query_row(
             "SELECT items.item_type, items.path, changes.id, changes.scan_id, changes.item_id, changes.change_type, 
-                    changes.prev_last_modified, changes.prev_file_size, changes.prev_hash, changes.prev_is_valid
+                    changes.prev_last_modified, changes.prev_file_size, changes.prev_hash, changes.prev_is_valid, changes.prev_symlink_target,
+                    changes.acknowledged, changes.review_comment, changes.prev_sample_hash, changes.old_path, changes.similarity,
+                    changes.change_type = 'M' AND (changes.prev_last_modified IS NOT NULL OR changes.prev_file_size IS NOT NULL OR changes.prev_symlink_target IS NOT NULL OR changes.prev_owner_uid IS NOT NULL OR changes.prev_owner_gid IS NOT NULL),
+                    changes.change_type = 'M' AND (changes.prev_hash IS NOT NULL OR changes.prev_sample_hash IS NOT NULL)
             FROM changes
             JOIN items ON items.id = changes.item_id
             WHERE changes.id = ?", 
             [change_id], 
-            |row| Ok(Change {
-                id: row.get(2)?,  
-                scan_id: row.get(3)?,  
-                item_id: row.get(4)?,  
-                change_type: row.get(5)?,  
-                prev_last_modified: row.get(6)?,  
-                prev_file_size: row.get(7)?,  
-                prev_hash: row.get(8)?,
-                prev_is_valid: row.get(8)?,
-                item_type: row.get(0)?,  
-                item_path: row.get(1)?  
-            })
+            Change::from_row,
         )
         .optional()
         .map_err(FsPulseError::Database)
     }
 
-    pub fn for_each_change_in_scan<F>(db: &Database, scan_id: i64, func: F) -> Result<(), FsPulseError> 
-    where
-        F: FnMut(&Change) -> Result<(), FsPulseError>,   
-    {
-        Self::for_each_change_impl(db, SQL_FOR_EACH_CHANGE_IN_SCAN, scan_id, func)
+    pub fn changes_in_scan(db: &Database, scan_id: i64) -> Result<ChangeRows<'_>, FsPulseError> {
+        Self::changes_in_scan_selected(db, scan_id, &ChangeSelection::default())
     }
 
-    pub fn for_each_change_in_item<F>(db: &Database, item_id: i64, func: F) -> Result<(), FsPulseError> 
-    where
-        F: FnMut(&Change) -> Result<(), FsPulseError>,   
-    {
-        Self::for_each_change_impl(db, SQL_FOR_EACH_CHANGE_IN_ITEM, item_id, func)
+    /// The scan's changes the selection picks
+    pub fn changes_in_scan_selected<'db>(db: &'db Database, scan_id: i64, selection: &ChangeSelection) -> Result<ChangeRows<'db>, FsPulseError> {
+        ChangeRows::prepare(db, SQL_CHANGES_IN_SCAN, scan_id, selection)
     }
 
-    pub fn for_each_change_impl<F>(db: &Database, sql_query: &str, sql_query_param: i64, mut func: F) -> Result<(), FsPulseError>
-    where
-        F: FnMut(&Change) -> Result<(), FsPulseError>,
-    {
-        let mut _change_count = 0;  // used only for logging
+    pub fn changes_in_item(db: &Database, item_id: i64) -> Result<ChangeRows<'_>, FsPulseError> {
+        Self::changes_in_item_selected(db, item_id, &ChangeSelection::default())
+    }
 
-        let mut stmt = db.conn.prepare(sql_query)?;
-        
-        let rows = stmt.query_map([sql_query_param], |row| {
-            Ok(
-                 Change {
-                    id: row.get::<_, i64>(2)?,                          // changes.id
-                    scan_id: row.get::<_, i64>(3)?,                     // changes.scan_id
-                    item_id: row.get::<_, i64>(4)?,                     // changes.item_id
-                    change_type: row.get::<_, String>(5)?,              // changes.change_type
-                    prev_last_modified: row.get::<_, Option<i64>>(6)?,  // changes.prev_last_modified
-                    prev_file_size: row.get::<_, Option<i64>>(7)?,      // changes.prev_file_size
-                    prev_hash: row.get::<_, Option<String>>(8)?,        // changes.prev_hash
-                    prev_is_valid: row.get::<_, Option<bool>>(9)?,      // changes.prev_is_valid
-
-                    // Additional fields
-                    item_type: row.get::<_, String>(0)?,                // items.item_type
-                    item_path: row.get::<_, String>(1)?,                // items.path
-                }
-            )
-        })?;
-        
+    /// The item's changes the selection picks
+    pub fn changes_in_item_selected<'db>(db: &'db Database, item_id: i64, selection: &ChangeSelection) -> Result<ChangeRows<'db>, FsPulseError> {
+        ChangeRows::prepare(db, SQL_CHANGES_IN_ITEM, item_id, selection)
+    }
+
+    /// Pairs each file deleted in a scan with a file added in it that has the same size and
+    /// hash: a rename the scan recorded as a deletion and an addition. Hashing scans record
+    /// such pairs as moves, so only those they couldn't are left: renames found by other
+    /// scans, which don't hash added files and so find none, and renames onto a path that
+    /// already had a history. Each file is paired at most once. Returns the ids of the
+    /// deletion and the addition of each pair
+    pub fn renames_in_scan(db: &Database, scan_id: i64) -> Result<Vec<(i64, i64)>, FsPulseError> {
+        let mut stmt = db.conn.prepare(
+            "SELECT d.id, a.id
+                FROM changes d
+                JOIN items di ON di.id = d.item_id
+                JOIN changes a ON a.scan_id = d.scan_id AND a.change_type = 'A'
+                JOIN items ai ON ai.id = a.item_id
+                WHERE d.scan_id = ? AND d.change_type = 'D'
+                AND di.item_type = 'F' AND ai.item_type = 'F'
+                AND di.is_ignored = 0 AND ai.is_ignored = 0
+                AND di.file_hash IS NOT NULL AND di.file_hash = ai.file_hash AND di.hash_algo IS ai.hash_algo AND di.file_size = ai.file_size
+                ORDER BY d.id, a.id"
+        )?;
+
+        let rows = stmt.query_map([scan_id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))?;
+
+        let mut paired = HashSet::new();
+        let mut renames = Vec::new();
         for row in rows {
-            let change = row?;
+            let (delete_id, add_id) = row?;
+            if !paired.contains(&delete_id) && !paired.contains(&add_id) {
+                paired.insert(delete_id);
+                paired.insert(add_id);
+                renames.push((delete_id, add_id));
+            }
+        }
+
+        Ok(renames)
+    }
 
-            func(&change)?;
-            _change_count += 1;
+    /// The change's type, and for a modification what changed: `M:meta`, `M:content` or
+    /// `M:meta+content`
+    pub fn annotated_type(&self) -> String {
+        match (self.metadata_changed, self.hash_changed) {
+            (true, true) => format!("{}:meta+content", self.change_type),
+            (true, false) => format!("{}:meta", self.change_type),
+            (false, true) => format!("{}:content", self.change_type),
+            (false, false) => self.change_type.clone(),
         }
-        info!("for_each_scan_change_impl - id: {}, changes: {}", sql_query_param, _change_count);
-        Ok(())
     }
 
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Change {
+            id: row.get::<_, i64>(2)?,                          // changes.id
+            scan_id: row.get::<_, i64>(3)?,                     // changes.scan_id
+            item_id: row.get::<_, i64>(4)?,                     // changes.item_id
+            change_type: row.get::<_, String>(5)?,              // changes.change_type
+            prev_last_modified: row.get::<_, Option<i64>>(6)?,  // changes.prev_last_modified
+            prev_file_size: row.get::<_, Option<i64>>(7)?,      // changes.prev_file_size
+            prev_hash: row.get::<_, Option<String>>(8)?,        // changes.prev_hash
+            prev_is_valid: row.get::<_, Option<bool>>(9)?,      // changes.prev_is_valid
+            prev_symlink_target: row.get::<_, Option<String>>(10)?, // changes.prev_symlink_target
+            acknowledged: row.get::<_, bool>(11)?,              // changes.acknowledged
+            review_comment: row.get::<_, Option<String>>(12)?,  // changes.review_comment
+            prev_sample_hash: row.get::<_, Option<String>>(13)?, // changes.prev_sample_hash
+            old_path: row.get::<_, Option<String>>(14)?,        // changes.old_path
+            similarity: row.get::<_, Option<i64>>(15)?,         // changes.similarity
+            metadata_changed: row.get::<_, bool>(16)?,
+            hash_changed: row.get::<_, bool>(17)?,
+
+            // Additional fields
+            item_type: row.get::<_, String>(0)?,                // items.item_type
+            item_path: row.get::<_, String>(1)?,                // items.path
+        })
+    }
+}
+
+/// Which changes a report shows. The default selects every change
+#[derive(Clone, Debug, Default)]
+pub struct ChangeSelection {
+    /// Only changes of these types, or of every type if there are none
+    pub change_types: Vec<ChangeType>,
+    /// Only changes to items at or beneath this path, relative to the root
+    pub path_prefix: Option<String>,
+    /// Only modifications of this kind
+    pub modify_kind: Option<ModifyKind>,
+}
+
+/// What about an item a modification changed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModifyKind {
+    /// The file's content, whether or not its metadata changed too
+    Content,
+    /// Only the item's metadata. Every modification a scan without hashing found is one
+    Metadata,
+}
+
+impl ChangeSelection {
+    /// The conditions the selection adds to a query's WHERE clause, with the values they bind.
+    /// Change types are single letters, so they're written into the query as they are
+    fn conditions(&self) -> (String, Vec<Value>) {
+        let mut conditions = String::new();
+        let mut values = Vec::new();
+
+        if !self.change_types.is_empty() {
+            let letters: Vec<String> = self.change_types.iter().map(|change_type| format!("'{}'", change_type.as_str())).collect();
+            conditions.push_str(&format!(" AND changes.change_type IN ({})", letters.join(", ")));
+        }
+
+        if let Some(path_prefix) = &self.path_prefix {
+            let (condition, prefix_values) = Utils::path_prefix_condition("items.path", path_prefix);
+            conditions.push_str(&format!(" AND {}", condition));
+            values.extend(prefix_values);
+        }
+
+        match self.modify_kind {
+            Some(ModifyKind::Content) => conditions.push_str(
+                " AND changes.change_type = 'M' AND (changes.prev_hash IS NOT NULL OR changes.prev_sample_hash IS NOT NULL)"),
+            Some(ModifyKind::Metadata) => conditions.push_str(
+                " AND changes.change_type = 'M' AND changes.prev_hash IS NULL AND changes.prev_sample_hash IS NULL"),
+            None => (),
+        }
+
+        (conditions, values)
+    }
+}
+
+/// A prepared query over change rows, iterated like [`ItemRows`](crate::ItemRows)
+pub struct ChangeRows<'db> {
+    stmt: Statement<'db>,
+    params: Vec<Value>,
+}
+
+impl<'db> ChangeRows<'db> {
+    /// Prepares `sql`, whose `{}` the selection's conditions replace, binding `param` ahead
+    /// of the values they bind
+    fn prepare(db: &'db Database, sql: &str, param: i64, selection: &ChangeSelection) -> Result<Self, FsPulseError> {
+        let (conditions, values) = selection.conditions();
+        let stmt = db.conn.prepare(&sql.replace("{}", &conditions))?;
+
+        let mut params = vec![param.into()];
+        params.extend(values);
+        Ok(ChangeRows { stmt, params })
+    }
+
+    pub fn iter(&mut self) -> Result<impl Iterator<Item = Result<Change, FsPulseError>> + '_, FsPulseError> {
+        let rows = self.stmt.query_map(params_from_iter(&self.params), Change::from_row)?;
+        Ok(rows.map(|row| row.map_err(FsPulseError::Database)))
+    }
 }
 
 impl ChangeCounts {
-    pub fn new(add_count: i64, modify_count: i64, delete_count: i64, type_change_count: i64, no_change_count: i64) -> Self {
+    pub fn new(add_count: i64, modify_count: i64, delete_count: i64, type_change_count: i64, move_count: i64, no_change_count: i64) -> Self {
         Self {
             add_count,
             modify_count,
             delete_count,
             type_change_count,
+            move_count,
             no_change_count,
         }
     }
 
+    /// The scan's change counts: those it recorded when it completed, or otherwise those
+    /// counted from its changes so far
     pub fn get_by_scan_id(db: &Database, scan_id: i64) -> Result<Self, FsPulseError> {
-        let conn = &db.conn;
+        let recorded = db.conn.query_row(
+            "SELECT add_count, modify_count, delete_count, type_change_count, move_count FROM scans WHERE id = ?",
+            [scan_id],
+            |row| Ok(match (row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?) {
+                (Some(adds), Some(modifies), Some(deletes), Some(type_changes), Some(moves)) =>
+                    Some(Self::new(adds, modifies, deletes, type_changes, moves, 0)),
+                _ => None,
+            }),
+        ).optional()?.flatten();
+
+        match recorded {
+            Some(change_counts) => Ok(change_counts),
+            None => Self::count_for_scan(&db.conn, scan_id),
+        }
+    }
+
+    /// Counts the scan's changes by type
+    pub fn count_for_scan(conn: &Connection, scan_id: i64) -> Result<Self, FsPulseError> {
         let mut change_counts = ChangeCounts::default();
 
         let mut stmt = conn.prepare(
@@ -204,6 +635,7 @@ impl ChangeCounts {
                 ChangeType::Modify => change_counts.set_count_of(ChangeType::Modify, count),
                 ChangeType::NoChange => change_counts.set_count_of(ChangeType::Modify, count),
                 ChangeType::TypeChange => change_counts.set_count_of(ChangeType::TypeChange, count),
+                ChangeType::Move => change_counts.set_count_of(ChangeType::Move, count),
             }
         }
 
@@ -216,19 +648,14 @@ impl ChangeCounts {
             ChangeType::Delete => self.delete_count,
             ChangeType::Modify => self.modify_count,
             ChangeType::TypeChange => self.type_change_count,
+            ChangeType::Move => self.move_count,
             ChangeType::NoChange => self.no_change_count,
         }
     }
 
-    pub fn increment_count_of(&mut self, change_type: ChangeType) {
-        let target = match change_type {
-            ChangeType::Add => &mut self.add_count,
-            ChangeType::Delete => &mut self.delete_count,
-            ChangeType::Modify => &mut self.modify_count,
-            ChangeType::TypeChange => &mut self.type_change_count,
-            ChangeType::NoChange => &mut self.no_change_count,
-       };
-       *target += 1;
+    /// The number of changes of every type, not counting unchanged items
+    pub fn total(&self) -> i64 {
+        self.add_count + self.modify_count + self.delete_count + self.type_change_count + self.move_count
     }
 
     pub fn set_count_of(&mut self, change_type: ChangeType, count: i64) {
@@ -237,6 +664,7 @@ impl ChangeCounts {
             ChangeType::Delete => &mut self.delete_count,
             ChangeType::Modify => &mut self.modify_count,
             ChangeType::TypeChange => &mut self.type_change_count,
+            ChangeType::Move => &mut self.move_count,
             ChangeType::NoChange => &mut self.no_change_count,
        };
        *target = count;   
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::items::ItemType;
+use crate::roots::Root;
+use crate::scans::Scan;
+use crate::utils::Utils;
+
+/// The name of the checksum file written to each directory. It's in the format read by
+/// `md5sum -c`, and scans pass over it
+pub const CHECKSUM_FILE_NAME: &str = ".fspulse.md5";
+
+/// What writing checksum files did
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChecksumSummary {
+    pub written: usize,
+    pub unchanged: usize,
+    pub removed: usize,
+}
+
+pub struct ChecksumFiles {
+    // No fields
+}
+
+impl ChecksumFiles {
+    /// Writes a checksum file to each directory the scan read, listing the md5 of each file
+    /// in the directory which has one (files hashed with another algorithm have none). Files
+    /// already holding the same list are left alone, and checksum files in directories that
+    /// no longer hold hashed files are removed.
+    /// Writing a checksum file changes the directory's modification time, so the new time
+    /// is recorded to keep the next scan from reporting the directory as modified
+    pub fn write_for_scan(db: &Database, root: &Root, scan: &Scan) -> Result<ChecksumSummary, FsPulseError> {
+        let mut summary = ChecksumSummary::default();
+
+        for (dir, lines) in Self::listings(db, root, scan)? {
+            let dir_path = root.item_path(&dir);
+            let checksum_path = dir_path.join(CHECKSUM_FILE_NAME);
+
+            let existing = match fs::read_to_string(&checksum_path) {
+                Ok(existing) => Some(existing),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+                Err(error) => return Err(error.into()),
+            };
+
+            match (lines.is_empty(), existing) {
+                (true, None) => continue,
+                (true, Some(_)) => {
+                    fs::remove_file(&checksum_path)?;
+                    summary.removed += 1;
+                },
+                (false, Some(existing)) if existing == lines => {
+                    summary.unchanged += 1;
+                    continue;
+                },
+                (false, _) => {
+                    fs::write(&checksum_path, &lines)?;
+                    summary.written += 1;
+                },
+            }
+
+            Self::record_dir_mtime(db, root, &dir, &dir_path)?;
+        }
+
+        Ok(summary)
+    }
+
+    /// The contents of the checksum file of each directory the scan read, by the
+    /// directory's path relative to the root. Directories without hashed files get an
+    /// empty listing
+    fn listings(db: &Database, root: &Root, scan: &Scan) -> Result<BTreeMap<String, String>, FsPulseError> {
+        let mut listings: BTreeMap<String, String> = BTreeMap::new();
+        listings.insert(String::new(), String::new());
+
+        // Directories a budgeted scan didn't get to were found, but their files weren't
+        let mut dir_stmt = db.conn.prepare(
+            "SELECT path
+                FROM items
+                WHERE root_id = ?1 AND last_scan_id = ?2 AND item_type = ?3 AND is_tombstone = 0 AND is_ignored = 0
+                AND path NOT IN (SELECT path FROM unchecked_dirs WHERE scan_id = ?2)"
+        )?;
+        let dirs = dir_stmt.query_map((root.id(), scan.id(), ItemType::Directory.as_str()), |row| row.get::<_, String>(0))?;
+        for dir in dirs {
+            listings.insert(dir?, String::new());
+        }
+
+        let mut file_stmt = db.conn.prepare(
+            "SELECT path, file_hash
+                FROM items
+                WHERE root_id = ?1 AND last_scan_id = ?2 AND item_type = ?3 AND is_tombstone = 0 AND is_ignored = 0
+                AND file_hash IS NOT NULL AND hash_algo = 'md5' AND hash_in_flux = 0
+                ORDER BY path ASC"
+        )?;
+        let files = file_stmt.query_map((root.id(), scan.id(), ItemType::File.as_str()), |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+
+        for file in files {
+            let (path, hash) = file?;
+            let path = Path::new(&path);
+            let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+                continue;
+            };
+
+            // A name which wasn't valid UTF-8 was recorded with replacement characters,
+            // and couldn't be found again by the checksum file
+            let name = name.to_string_lossy();
+            if name.contains('\u{FFFD}') {
+                continue;
+            }
+
+            if let Some(lines) = listings.get_mut(dir.to_string_lossy().as_ref()) {
+                lines.push_str(&Self::line(&hash, &name));
+            }
+        }
+
+        Ok(listings)
+    }
+
+    /// A line of a checksum file. As with md5sum, a name containing a backslash or a line
+    /// break is escaped, and its line starts with a backslash
+    fn line(hash: &str, name: &str) -> String {
+        if name.contains(['\\', '\n', '\r']) {
+            let escaped = name.replace('\\', "\\\\").replace('\n', "\\n").replace('\r', "\\r");
+            format!("\\{}  {}\n", hash, escaped)
+        } else {
+            format!("{}  {}\n", hash, name)
+        }
+    }
+
+    fn record_dir_mtime(db: &Database, root: &Root, dir: &str, dir_path: &Path) -> Result<(), FsPulseError> {
+        // The root itself isn't an item
+        if dir.is_empty() {
+            return Ok(());
+        }
+
+        let modified = fs::metadata(dir_path)?.modified().ok().map(Utils::system_time_to_db_time);
+        db.conn.execute(
+            "UPDATE items SET last_modified = ? WHERE root_id = ? AND path = ? AND item_type = ?",
+            (modified, root.id(), dir, ItemType::Directory.as_str()),
+        )?;
+
+        Ok(())
+    }
+}
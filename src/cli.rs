@@ -1,13 +1,44 @@
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use clap::{Parser, Subcommand};
 use log::info;
 
+use std::fs;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-use crate::database::Database;
+use crate::archive::Archive;
+use crate::audit::AuditEntry;
+use crate::backfill::{Backfill, BackfillOptions, BackfillOrder};
+use crate::changes::{ChangePeriod, ChangeSelection, ChangeType, ModifyKind};
+use crate::config::Config;
+use crate::containers::Container;
+use crate::database::{Database, PROJECT_DIR_NAME, SCHEMA_VERSION};
 use crate::error::FsPulseError; 
-use crate::reports::{ReportFormat, Reports}; 
-use crate::scan_machine::do_scan_machine;
-use crate::scans::Scan;
+use crate::estimate::Estimate;
+use crate::excludes::ExcludeRules;
+use crate::gc::Gc;
+use crate::groups::RootGroups;
+use crate::hash::{Sampling, DEFAULT_SAMPLE_CHUNK_SIZE, DEFAULT_SAMPLE_STRIDE};
+use crate::image::Image;
+use crate::items::Item;
+use crate::owners::{Owners, UsageBy};
+use crate::packages::{PackageManager, Packages};
+use crate::prompts::PromptPolicy;
+use crate::prune::{Prune, PrunePolicy};
+use crate::repair::Repairs;
+use crate::reports::{ReportFormat, Reports, TreeDirs}; 
+use crate::retention::AgeBasis;
+use crate::review::{Review, ReviewFormat};
+use crate::roots::Root;
+use crate::scans::{Scan, ScanState};
+use crate::search::ChangeFilter;
+use crate::selftest::SelfTest;
+use crate::scan_machine::{do_scan_all, do_scan_due, do_scan_machine, OfflinePolicy, ScanOptions};
+use crate::schedules::{ScanKind, Schedule};
+use crate::timings::Timings;
+use crate::utils::Utils;
+use crate::verify::Verification;
+use crate::watch::Watch;
     
 /// CLI for fspulse: A filesystem scan and reporting tool.
 #[derive(Parser)]
@@ -15,19 +46,82 @@ use crate::scans::Scan;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Command,
+
+    /// Proceed with destructive or surprising operations without asking for confirmation
+    #[arg(long, global = true, conflicts_with = "non_interactive")]
+    pub yes: bool,
+
+    /// Never ask for confirmation. Operations which need it fail instead
+    #[arg(long, global = true)]
+    pub non_interactive: bool,
+
+    /// Widest path shown in table output. Longer paths are shortened in the middle.
+    /// Defaults to half the report width, up to 80
+    #[arg(long, global = true, value_parser = clap::value_parser!(u64).range(5..))]
+    pub max_path_width: Option<u64>,
+
+    /// Width of the lines reports are laid out to fit. Defaults to the terminal's width,
+    /// or 100 when output isn't to a terminal
+    #[arg(long, global = true, value_parser = clap::value_parser!(u64).range(20..))]
+    pub width: Option<u64>,
+
+    /// Show paths in table output in full, however long
+    #[arg(long, global = true)]
+    pub full_paths: bool,
+
+    /// Escape control characters, invisible formatting characters and undecodable bytes
+    /// in paths shown by reports, and write paths in JSON output as arrays of bytes
+    #[arg(long, global = true)]
+    pub escape_paths: bool,
+
+    /// Show item paths relative to the current directory when it's inside the root
+    /// being reported on, as `git status` does
+    #[arg(long, global = true)]
+    pub relative: bool,
+
+    /// Print how long the command spent in each phase (database queries and writes,
+    /// traversal, hashing, rendering) to stderr when it finishes
+    #[arg(long, global = true)]
+    pub timings: bool,
+
+    /// Passphrase for a database encrypted with SQLCipher. Other users may be able to see
+    /// command lines, so prefer --db-key-file or $FSPULSE_DB_PASSPHRASE
+    #[arg(long, global = true, conflicts_with = "db_key_file", value_name = "PASSPHRASE")]
+    pub db_passphrase: Option<String>,
+
+    /// Read the passphrase for an encrypted database from the first line of this file
+    #[arg(long, global = true, value_name = "PATH")]
+    pub db_key_file: Option<PathBuf>,
+
+    /// Read defaults from this config file instead of `config.toml` in the nearest `.fspulse`
+    /// folder or `~/.config/fspulse/config.toml` (or the platform's equivalent). Command line
+    /// options take precedence over the config
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
 }
 
 /// Available commands in fspulse.
 #[derive(Subcommand)]
 pub enum Command {
+    /// Create a `.fspulse` directory inside a tree to hold a database dedicated to that tree.
+    /// The tree is registered as a root, and commands run from anywhere inside the tree will
+    /// find and use this database automatically
+    Init {
+        /// The directory to initialize (defaults to the current directory)
+        #[arg(long)]
+        root_path: Option<String>,
+    },
+
     /// Perform a filesystem scan on a specified "root". If the root has been scanned previously,
     /// it can be identified by its root-id. A root can also be identified by path by
     /// specifying a root-path. In the case of root-path, an existing root will be used if
-    /// one exists and, if not, a new root will be created
+    /// one exists and, if not, a new root will be created. If no root is specified and the
+    /// database is a project database created with `init`, the project's tree is scanned
     Scan {
         /// Specifies the directory where the database is stored.
-        /// Defaults to the user's home directory (`~/` on Unix, `%USERPROFILE%\` on Windows).
-        /// The database file will always be named "fspulse.db".
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
         #[arg(long)]
         db_path: Option<PathBuf>,
 
@@ -43,238 +137,2865 @@ pub enum Command {
         #[arg(long, conflicts_with_all = ["root_id", "root_path"])]
         last: bool,
 
-        /// Hash files using md5 and compare to previous known hashes
-        #[arg(long)]
+        /// Scan every known root
+        #[arg(long, conflicts_with_all = ["root_id", "root_path", "last"])]
+        all: bool,
+
+        /// Scan each root with a scheduled scan due (see `schedule`), running the most
+        /// thorough scan due for the root. Meant to be run periodically, such as from cron
+        #[arg(long, conflicts_with_all = ["root_id", "root_path", "last", "all", "hashing"])]
+        due: bool,
+
+        /// Number of roots to scan concurrently (with --all)
+        #[arg(long, default_value_t = 1, requires = "all", value_parser = clap::value_parser!(u32).range(1..))]
+        jobs: u32,
+
+        /// Number of threads hashing files, shared by all concurrent scans. Limits how many
+        /// files are read at once (defaults to 1, or to --jobs with --all)
+        #[arg(long, requires = "hashing", value_parser = clap::value_parser!(u32).range(1..))]
+        hash_threads: Option<u32>,
+
+        /// Hash files and compare to previous known hashes
+        #[arg(long, group = "hashing")]
         hash: bool,
 
+        /// Hash only files which are new or whose metadata changed since the last scan,
+        /// trusting the previously recorded hashes of all other files
+        #[arg(long, group = "hashing")]
+        deep_delta: bool,
+
        /// Validate file contents for known file types (tbd)
        #[arg(long)]
        validate: bool,
+
+        /// The algorithm files are hashed with. Without this, a root is hashed with the
+        /// algorithm its last hashing scan used, or md5 if it hasn't been hashed. Files whose
+        /// hash was taken with another algorithm are hashed again, even by --deep-delta, and
+        /// their new hash replaces the old one without being recorded as a change
+        #[arg(long, requires = "hashing", value_parser = ["md5", "sha256", "blake3", "xxh3"])]
+        hash_algo: Option<String>,
+
+        /// When an unchanged hash is used to conclude that a file is unchanged, confirm it with
+        /// a second, independent hash for files of at least this many bytes. Confirmations and
+        /// collisions are recorded on the scan
+        #[arg(long, requires = "hashing", value_name = "BYTES")]
+        confirm_hashes_over: Option<u64>,
+
+        /// Allow a scan that finds no items to mark every previously seen item as deleted.
+        /// Without this, such a scan asks for confirmation or, if it can't ask, is aborted
+        #[arg(long)]
+        allow_empty: bool,
+
+        /// Write progress to stderr as JSON lines (items processed, bytes hashed, current
+        /// path, ETA) for scripts which show progress in their own UI, instead of drawing
+        /// progress bars
+        #[arg(long)]
+        progress_json: bool,
+
+        /// Draw no progress bars. They're also left out when stdout isn't a terminal, such
+        /// as when the output is redirected to a file. The report is still printed
+        #[arg(long)]
+        quiet: bool,
+
+        /// After each scan, print one line summing up its changes and their severity for
+        /// pagers and alerting tools, such as "WARNING: 3 changes, 1 adds, 0 modifies,
+        /// 2 deletes, 0 type changes, 0 moves, scan 481, root /data". Its wording doesn't
+        /// change from scan to scan, so it can be parsed
+        #[arg(long)]
+        summary_line: bool,
+
+        /// How a directory whose modification time changed is treated. A directory's time
+        /// changes whenever an entry is added, removed or renamed in it, so "ignore" keeps
+        /// such directories out of the changes (the new time is still recorded), leaving the
+        /// changed entries to speak for themselves
+        #[arg(long, default_value = "record", value_parser = ["record", "ignore"])]
+        dir_mtime: String,
+
+        /// Record each path the scan excludes, and why, so it can be listed by `report scans`.
+        /// The number of excluded paths is always recorded
+        #[arg(long)]
+        record_exclusions: bool,
+
+        /// Limit how long the scan runs, such as "30min" or "2h". Directories whose items
+        /// changed most in recent scans are read first, and directories not reached in time
+        /// are recorded as not checked rather than treated as unchanged. Hashing also stops
+        /// when the time is up
+        #[arg(long, value_name = "DURATION", value_parser = Utils::parse_interval)]
+        budget: Option<i64>,
+
+        /// Write a checksum file (.fspulse.md5) to each directory listing the md5 of its
+        /// files, so directories can be verified with `md5sum -c` without fspulse
+        #[arg(long, requires = "hashing")]
+        write_checksums: bool,
+
+        /// Treat the root as an archive that should never change: any change found is
+        /// reported as a critical finding and the command exits with status 3. The root's
+        /// first scan records the baseline
+        #[arg(long)]
+        expect_frozen: bool,
+
+        /// Tune hashing to the storage: for the first minutes of hashing, try hashing more
+        /// files at once and reading more of each file at a time, then keep whichever was
+        /// fastest. The settings chosen are recorded on the scan. With --all, at most
+        /// --hash-threads files are hashed at once
+        #[arg(long)]
+        tune_hashing: bool,
+
+        /// Rather than reading files of at least this many bytes in full, hash samples of
+        /// them: their start, their end, and a chunk every --sample-stride bytes. Suits
+        /// large media files, where reading everything costs too much. The sampled hash is
+        /// recorded separately from the full hash, and a change to it is a modification
+        #[arg(long, requires = "hashing", value_name = "BYTES")]
+        sample_over: Option<u64>,
+
+        /// How many bytes are read for each sample
+        #[arg(long, requires = "sample_over", value_name = "BYTES", default_value_t = DEFAULT_SAMPLE_CHUNK_SIZE, value_parser = clap::value_parser!(u64).range(1..))]
+        sample_chunk: u64,
+
+        /// How many bytes apart the samples start
+        #[arg(long, requires = "sample_over", value_name = "BYTES", default_value_t = DEFAULT_SAMPLE_STRIDE, value_parser = clap::value_parser!(u64).range(1..))]
+        sample_stride: u64,
+
+        /// For files of at most this many bytes, keep a digest of the content from which the
+        /// similarity of two versions can be estimated, and record on each modification how
+        /// much of the previous content the file kept: near 100% for a tweak, near 0% for a
+        /// file replaced outright. Files are read again to take the digest when they change
+        #[arg(long, requires = "hashing", value_name = "BYTES")]
+        similarity_under: Option<u64>,
+
+        /// How files whose content is held elsewhere (cloud sync placeholders, files an HSM
+        /// has migrated) are treated. "record" records their metadata but doesn't hash them,
+        /// "skip" leaves them out of the scan, and "hydrate" hashes them, fetching their content
+        #[arg(long, default_value = "record", value_parser = ["record", "skip", "hydrate"])]
+        offline_files: String,
+
+        /// Stop the scan once it has grown the database by more than this many bytes. What
+        /// the scan recorded is kept, and it can be resumed or aborted later like an
+        /// interrupted scan. Scans also stop when the database's device is nearly full
+        #[arg(long, value_name = "BYTES")]
+        max_db_growth: Option<u64>,
+
+        /// Number of items recorded in each database transaction while the tree is read
+        /// (defaults to 1000). Committing less often speeds up scans of large trees. An
+        /// interrupted scan loses the items of its last, uncommitted batch, which are read
+        /// again when it's resumed
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        batch_size: Option<u32>,
+
+        /// Skip paths matching this glob. Like `ignore`, the glob is matched against paths
+        /// relative to the root, and one without a separator matches an item's name at any
+        /// depth. Nothing beneath a matching directory is scanned. May be repeated
+        #[arg(long, value_name = "GLOB", conflicts_with = "due")]
+        exclude: Vec<String>,
+
+        /// Skip paths ignored by the `.gitignore` and `.ignore` files found in the tree
+        #[arg(long, conflicts_with = "due")]
+        respect_gitignore: bool,
+
+        /// Scan paths matching this glob even though --exclude or an ignore file would skip
+        /// them, such as `--exclude '*.log' --include important.log`. Matched like --exclude.
+        /// Items ignored with `ignore` stay ignored. May be repeated
+        #[arg(long, value_name = "GLOB", conflicts_with = "due")]
+        include: Vec<String>,
     },
 
-    /// Generate reports.
-    Report {
-        #[command(subcommand)]
-        report_type: ReportType,
+    /// Search roots and items by path. Every word of the query must appear in the path,
+    /// ignoring case. Items are matched on their path within their root, and can also be
+    /// found by their history of changes
+    Search {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The words to search for. May be left out when searching by changes
+        #[arg(required_unless_present_any = ["min_changes", "within_days", "content_changed", "type_changed"])]
+        query: Vec<String>,
+
+        /// Show at most this many results
+        #[arg(long)]
+        limit: Option<u32>,
+
+        /// Only find items changed at least this many times
+        #[arg(long)]
+        min_changes: Option<u32>,
+
+        /// Only count changes recorded in this many days. On its own, finds items changed
+        /// at least once in that time
+        #[arg(long)]
+        within_days: Option<u32>,
+
+        /// Only find files whose content changed without their size changing, as found by
+        /// hashing scans. Often a sign of corruption or tampering rather than an edit
+        #[arg(long)]
+        content_changed: bool,
+
+        /// Only find items whose type changed, such as from a file to a directory
+        #[arg(long)]
+        type_changed: bool,
     },
-}
 
-/// Available report types.
-#[derive(Subcommand)]
-pub enum ReportType {
-    /// Reports on "roots" which have been scanned in the past
-    Roots {
+    /// Show every change recorded to an item, oldest first, with its size, modification
+    /// time and hash before and after each change
+    History {
         /// Specifies the directory where the database is stored.
-        /// Defaults to the user's home directory (`~/` on Unix, `%USERPROFILE%\` on Windows).
-        /// The database file will always be named "fspulse.db".
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
         #[arg(long)]
         db_path: Option<PathBuf>,
 
-        /// Show details of the root with the specified id
-        #[arg(long, conflicts_with = "root_path")]
+        /// The item's id, or its path: absolute, or within its root. A path is looked up in
+        /// every root
+        item: String,
+
+        /// Report format (table, json).
+        #[arg(long, default_value = "table", value_parser = ["table", "json"])]
+        format: String,
+    },
+
+    /// Scan a root, then watch it for changes and record them as they happen. Once the root
+    /// has been quiet for a moment, the changes are recorded as a scan that reads only the
+    /// directories seen to change. Runs until interrupted
+    Watch {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Watch a known root by id
+        #[arg(long, conflicts_with = "root_path", required_unless_present = "root_path")]
         root_id: Option<u32>,
 
-        /// Show details of the root with the specified path
+        /// Watch a known or new root by path (must be a directory)
         #[arg(long, conflicts_with = "root_id")]
         root_path: Option<String>,
 
-        /// Report format (csv, table).
-        #[arg(long, default_value = "table", value_parser = ["csv", "table"])]
-        format: String,
+        /// Record changes once the root has been quiet for this many seconds
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u64).range(1..))]
+        settle_secs: u64,
+
+        /// Record changes after at most this many seconds, even if the root is still busy
+        #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+        max_wait_secs: u64,
+
+        /// Hash files which are new or whose metadata changed, as `scan --deep-delta` does
+        #[arg(long)]
+        hash: bool,
+
+        /// The algorithm files are hashed with, as with `scan --hash-algo`
+        #[arg(long, requires = "hash", value_parser = ["md5", "sha256", "blake3", "xxh3"])]
+        hash_algo: Option<String>,
+
+        /// Skip paths matching this glob, as `scan --exclude` does. May be repeated
+        #[arg(long, value_name = "GLOB")]
+        exclude: Vec<String>,
+
+        /// Skip paths ignored by the `.gitignore` and `.ignore` files found in the tree
+        #[arg(long)]
+        respect_gitignore: bool,
+
+        /// Watch paths matching this glob even though they'd be skipped, as `scan --include`
+        /// does. May be repeated
+        #[arg(long, value_name = "GLOB")]
+        include: Vec<String>,
     },
 
-    /// Reports on scans.
-    Scans {
+    /// Re-hash a root's files whose size and modification time haven't changed since they
+    /// were hashed, and report any whose content no longer matches its hash: a sign of bit
+    /// rot or other silent corruption. Nothing is recorded as a change, and no hash is
+    /// replaced. Exits with code 4 if a mismatch is found
+    Verify {
         /// Specifies the directory where the database is stored.
-        /// Defaults to the user's home directory (`~/` on Unix, `%USERPROFILE%\` on Windows).
-        /// The database file will always be named "fspulse.db".
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
         #[arg(long)]
         db_path: Option<PathBuf>,
 
-        /// Filter by scan ID.
-        #[arg(long, conflicts_with = "last")]
-        scan_id: Option<u32>,
+        /// The root to verify
+        #[arg(long)]
+        root_id: u32,
 
-        /// Show last N scans (default: 10)
-        #[arg(long, default_value_t = 10, conflicts_with = "scan_id")]
-        last: u32,
+        /// Verify at most this many files, those verified longest ago first
+        #[arg(long)]
+        limit: Option<u32>,
 
-        /// Report format (csv, table).
-        #[arg(long, default_value = "table", value_parser = ["csv", "table"])]
-        format: String,
+        /// Number of threads hashing files. Limits how many files are read at once
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        hash_threads: u32,
     },
 
-    /// Reports on items.
-    Items {
+    /// Restore a root's corrupt and missing files from a mirror holding a known-good copy
+    /// of it. A file is corrupt if `verify` found its content no longer matches its hash,
+    /// and missing if it's gone since the baseline scan. Only a copy with the hash recorded
+    /// at the baseline is restored, and it's hashed again once it's in place. Each file
+    /// restored is recorded in the audit log. Exits with code 1 if a file couldn't be restored
+    Repair {
         /// Specifies the directory where the database is stored.
-        /// Defaults to the user's home directory (`~/` on Unix, `%USERPROFILE%\` on Windows).
-        /// The database file will always be named "fspulse.db".
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
         #[arg(long)]
         db_path: Option<PathBuf>,
 
-        /// Show a specific Item
-        #[arg(long, conflicts_with_all = ["item_path", "root_id"])]
-        item_id: Option<u32>,
+        /// The root to repair
+        #[arg(long)]
+        root_id: u32,
 
-        /// Show all items with a specific path (an item may appear in multiple roots
-        /// (in the case where one root is a subdirectory of another)
-        #[arg(long, conflicts_with_all = ["item_id", "root_id"])]
-        item_path: Option<String>,
+        /// The directory holding the known-good copy, laid out as the root is
+        #[arg(long, value_name = "MIRROR_PATH")]
+        from: PathBuf,
 
-        /// Shows the items seen on the most recent scan of the specified root
-        #[arg(long, conflicts_with_all = ["item_id", "item_path"])]
-        root_id: Option<u32>,
+        /// The scan whose files are restored: a scan id, ROOT@NUMBER or HEAD~N. Defaults to
+        /// the root's latest completed scan
+        #[arg(long)]
+        baseline: Option<String>,
 
-        /// Report format (csv, table, tree).
-        #[arg(long, default_value = "table", value_parser = ["csv", "table", "tree"])]
-        format: String,
+        /// Report what would be restored, checking the mirror's copies, without writing anything
+        #[arg(long)]
+        dry_run: bool,
     },
 
-    /// Reports on changes.
-    Changes {
+    /// Scan a root and exit with code 5 if the scan found changes, for CI jobs and cron
+    /// scripts that alert on exit status alone. A root's first check only records the
+    /// baseline. Exits with code 1 if the scan fails
+    Check {
         /// Specifies the directory where the database is stored.
-        /// Defaults to the user's home directory (`~/` on Unix, `%USERPROFILE%\` on Windows).
-        /// The database file will always be named "fspulse.db".
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
         #[arg(long)]
         db_path: Option<PathBuf>,
 
-        /// Filter by change ID.
-        #[arg(long, conflicts_with_all = ["item_id", "scan_id"])]
-        change_id: Option<u32>,
-
-        /// Filter by item ID (shows all changes affecting the item).
-        #[arg(long, conflicts_with_all = ["change_id", "scan_id"])]
-        item_id: Option<u32>,        
+        /// The root to check, as a root id or a path. A path which isn't a root yet is
+        /// registered
+        root: String,
 
-        /// Filter by scan ID (shows all changes recorded in this scan).
-        #[arg(long, conflicts_with_all = ["change_id", "item_id"])]
-        scan_id: Option<u32>,
+        /// Hash files, so changes to their content are found as well as to their metadata
+        #[arg(long, group = "hashing")]
+        hash: bool,
 
-        /// Report format (csv, table, tree - tree only valid with scan-id).
-        #[arg(long, default_value = "table", value_parser = ["csv", "table", "tree"])]
-        format: String,
-    },
-}
+        /// Hash only files which are new or whose metadata changed since the last scan
+        #[arg(long, group = "hashing")]
+        deep_delta: bool,
 
-impl Cli {
-    pub fn handle_command_line() -> Result<(), FsPulseError>{
-        let args = Cli::parse();
-        
-        match args.command {
-            Command::Scan { db_path, root_id, root_path, last, hash, validate } => {
-                info!(
-                    "Running scan with db_path: {:?}, root_id: {:?}, root_path: {:?}, last: {}, hash: {}, validate: {}",
-                    db_path, root_id, root_path, last, hash, validate
-                );
-                Self::handle_scan(db_path, root_id, root_path, last, hash, validate)?;
-            }
-            Command::Report { report_type } => match report_type {
-                ReportType::Roots { db_path, root_id, root_path, format } => {
-                    info!(
-                        "Generating roots report with db_path: {:?}, root_id: {:?}, root_path: {:?}, format: {}",
-                        db_path, root_id, root_path, format
-                    );
-                    Self::handle_report_roots(db_path, root_id, root_path, format)?;
-                }
-                ReportType::Scans { db_path, scan_id, last, format } => {
-                    info!(
-                        "Generating scans report with db_path: {:?}, scan_id: {:?}, last: {}, format: {}",
-                        db_path, scan_id, last, format
-                    );
-                    Self::handle_report_scans(db_path, scan_id, last, format)?;
-                }
-                ReportType::Items { db_path, item_id, item_path, root_id, format } => {
-                    info!(
-                        "Generating items report with db_path: {:?}, item_id: {:?}, item_path: {:?}, root_id: {:?}, format: {}",
-                        db_path, item_id, item_path, root_id, format
-                    );
-                    Self::handle_report_items(db_path, item_id, item_path, root_id, format)?;
-                }
-                ReportType::Changes { db_path, change_id, item_id, scan_id, format } => {
-                    info!(
-                        "Generating changes report with db_path: {:?}, change_id: {:?}, item_id: {:?}, scan_id: {:?}, format: {}",
-                        db_path, change_id, item_id, scan_id, format
-                    );
-                    Self::handle_report_changes(db_path, change_id, item_id, scan_id, format)?;
-                }
-            },
-        }
+        /// The types of change that fail the check, separated by commas. Other changes are
+        /// reported but leave the exit status at 0
+        #[arg(long, value_delimiter = ',', default_value = "add,modify,delete,type-change,move",
+            value_parser = ["add", "modify", "delete", "type-change", "move"])]
+        fail_on: Vec<String>,
 
-        Ok(())
-    }
+        /// Draw no progress bars
+        #[arg(long)]
+        quiet: bool,
+    },
 
-    /// Handler for `pulse` command.
-    fn handle_scan(
+    /// Hash files that shallow scans recorded without a hash, a portion at a time, so a
+    /// database built without hashing gains the hashes deep scans and `verify` rely on.
+    /// Nothing is recorded as a change, and a run that stops is carried on by the next
+    BackfillHashes {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
         db_path: Option<PathBuf>,
+
+        /// The root to hash. Without this, every root is hashed in turn
+        #[arg(long)]
         root_id: Option<u32>,
-        root_path: Option<String>,
-        last: bool,
-        hash: bool,
-        validate: bool
-    ) -> Result<(), FsPulseError> {
-        let mut db = Database::new(db_path)?;
-        //Scan::do_scan(&mut db, root_id, root_path, last, hash, validate)?;
-        do_scan_machine(&mut db, root_id, root_path, last, hash, validate)?;
 
+        /// Which files are hashed first: the smallest (the most files for the time), the
+        /// largest, or in path order
+        #[arg(long, default_value = "smallest", value_parser = ["smallest", "largest", "path"])]
+        order: String,
 
-        Ok(())
-    }
+        /// Hash at most this many files in each root
+        #[arg(long)]
+        limit: Option<u32>,
 
-    /// Handler for `report paths`
-    fn handle_report_roots(
-        db_path: Option<PathBuf>,
-        root_id: Option<u32>,
-        root_path: Option<String>,
-        format: String,
-    ) -> Result<(), FsPulseError> {
-        let db = Database::new(db_path)?;
-        let format: ReportFormat = format.parse()?;
-        
-        Reports::report_roots(&db, root_id, root_path, format)?;
-        Ok(())
-    }
+        /// Stop after this long, such as "30min" or "2h". Files being hashed when the time
+        /// is up are finished
+        #[arg(long, value_name = "DURATION", value_parser = Utils::parse_interval)]
+        budget: Option<i64>,
 
-    /// Handler for `report scans`
-    fn handle_report_scans(
+        /// Read files at no more than this many bytes a second, on average, to leave the
+        /// disk to other work
+        #[arg(long, value_name = "BYTES", value_parser = clap::value_parser!(u64).range(1..))]
+        max_rate: Option<u64>,
+
+        /// The algorithm files are hashed with. Without this, a root is hashed with the
+        /// algorithm its last hashing scan used, or md5 if it hasn't been hashed
+        #[arg(long, value_parser = ["md5", "sha256", "blake3", "xxh3"])]
+        hash_algo: Option<String>,
+
+        /// Number of threads hashing files. Limits how many files are read at once
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        hash_threads: u32,
+    },
+
+    /// Build a tree in a temporary directory, scan it through adds, modifications, deletes,
+    /// renames and type changes, and check what was recorded. Confirms an installation works
+    Selftest {
+        /// Keep the temporary directory and its database for inspection
+        #[arg(long)]
+        keep: bool,
+    },
+
+    /// Remove orphaned rows left behind by older versions or crashes: scans and items
+    /// whose root no longer exists, and changes whose scan or item no longer exists
+    Gc {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
         db_path: Option<PathBuf>,
-        scan_id: Option<u32>,
-        last: u32,
-        format: String,
-    ) -> Result<(), FsPulseError> {
-        let db = Database::new(db_path)?;
-        let format: ReportFormat = format.parse()?;
 
-        Reports::report_scans(&db, scan_id, last, format)?;
-        Ok(())
-    }
+        /// Report what would be removed without removing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
 
-    /// Handler for `report items`
-    fn handle_report_items(
+    /// Remove old scans along with the changes they found, to keep the database from
+    /// growing without bound. Items are left as the latest scans found them. Scans which
+    /// haven't finished, and each root's latest completed scan, are always kept
+    #[command(group(clap::ArgGroup::new("limit").required(true).multiple(true).args(["keep_last", "older_than"])))]
+    Prune {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
         db_path: Option<PathBuf>,
-        item_id: Option<u32>,
-        item_path: Option<String>,
+
+        /// Only prune the scans of this root
+        #[arg(long)]
         root_id: Option<u32>,
-        format: String,
-    ) -> Result<(), FsPulseError> {
-        let db = Database::new(db_path)?;
-        let format: ReportFormat = format.parse()?;
 
-        Reports::report_items(&db, item_id, item_path, root_id, format)?;
-        Ok(())
-    }
+        /// Keep this many of each root's most recent scans
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        keep_last: Option<u32>,
 
-    /// Handler for `report changes`
-    fn handle_report_changes(
+        /// Keep scans run more recently than this, such as "90d" or "12w". With --keep-last,
+        /// only scans outside both limits are removed
+        #[arg(long, value_name = "DURATION", value_parser = Utils::parse_interval)]
+        older_than: Option<i64>,
+
+        /// Compact the database afterwards, giving the freed space back to the file system
+        #[arg(long)]
+        vacuum: bool,
+
+        /// Report what would be removed without removing anything
+        #[arg(long, conflicts_with = "vacuum")]
+        dry_run: bool,
+    },
+
+    /// Encrypt the database, change its passphrase, or decrypt it. The database is opened
+    /// with its current passphrase, if any, and must not be in use by another process.
+    /// Needs a build with the `sqlcipher` feature
+    Rekey {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
         db_path: Option<PathBuf>,
-        change_id: Option<u32>,
-        item_id: Option<u32>,
-        scan_id: Option<u32>,
-        format: String,
-    ) -> Result<(), FsPulseError> {
-        let db = Database::new(db_path)?;
-        let format: ReportFormat = format.parse()?;
 
-        Reports::report_changes(&db, change_id, item_id, scan_id, format)?;
-        Ok(())
+        /// The new passphrase
+        #[arg(long, value_name = "PASSPHRASE", conflicts_with_all = ["new_key_file", "decrypt"], required_unless_present_any = ["new_key_file", "decrypt"])]
+        new_passphrase: Option<String>,
+
+        /// Read the new passphrase from the first line of this file
+        #[arg(long, value_name = "PATH", conflicts_with = "decrypt")]
+        new_key_file: Option<PathBuf>,
+
+        /// Store the database unencrypted
+        #[arg(long)]
+        decrypt: bool,
+    },
+
+    /// Manage the database itself
+    Db {
+        #[command(subcommand)]
+        action: DbAction,
+    },
+
+    /// Find roots registered more than once under different paths (a trailing separator,
+    /// a symlinked alias, or different case) and merge each set into a single root
+    DedupRoots {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Report duplicate roots without merging them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Compare the latest scans of two roots which should hold the same content (e.g. the
+    /// blue and green copies of a deployment) by paths relative to each root
+    CompareRoots {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The first root (A), as a root id or path
+        root_a: String,
+
+        /// The second root (B), as a root id or path
+        root_b: String,
+    },
+
+    /// Predict how much work scanning a tree would be: how many items it holds, and how
+    /// many bytes shallow, delta deep and full deep scans would hash and for how long.
+    /// Walks the tree without recording anything and reads a sample of its files
+    Estimate {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The tree to estimate, as a root id or a path. The path needn't have been scanned
+        root: String,
+
+        /// Stop walking the tree after this many seconds. The rest of a known root is
+        /// extrapolated from its last scan
+        #[arg(long, default_value_t = 10)]
+        walk_secs: u64,
+
+        /// Read at most this many MiB of sample files to measure the hashing rate
+        #[arg(long, default_value_t = 256)]
+        sample_mib: u64,
+    },
+
+    /// Compare a scan with another scan of the same root, to see what changed between any
+    /// two scans, with a tar archive (optionally gzip compressed) to check that a backup
+    /// made from the root holds what was on disk when it was scanned, or with a container
+    /// image to find how a container's filesystem has drifted from the image it was started from
+    Compare {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The scan to compare (A): a scan id, ROOT@NUMBER or HEAD~N
+        #[arg(long, alias = "scan")]
+        scan_a: String,
+
+        /// The scan to compare with (B), of the same root as A
+        #[arg(long, required_unless_present_any = ["archive", "image"], conflicts_with_all = ["archive", "image", "strip_components", "hash"])]
+        scan_b: Option<String>,
+
+        /// The archive to compare (B), whose member paths should be relative to the root
+        #[arg(long, conflicts_with = "image")]
+        archive: Option<PathBuf>,
+
+        /// The container image to compare (B): an OCI image layout directory, or the mount
+        /// point of an overlay (such as a container's root filesystem) whose lower layers
+        /// are the image. A is then usually a scan of the container's root filesystem
+        #[arg(long, conflicts_with = "strip_components")]
+        image: Option<PathBuf>,
+
+        /// Remove this many leading components from member paths, as with tar's option of
+        /// the same name
+        #[arg(long, default_value_t = 0)]
+        strip_components: usize,
+
+        /// Hash the content of each file in the archive or image to compare it with the scan's hashes
+        #[arg(long)]
+        hash: bool,
+    },
+
+    /// Ignore a root's items matching a pattern, along with everything beneath matching
+    /// directories. Ignored items keep their history but no longer appear in reports, and
+    /// later scans neither update them nor record changes to them
+    Ignore {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root whose items are ignored
+        #[arg(long)]
+        root_id: u32,
+
+        /// A glob matched against paths relative to the root (e.g. "build/*.o"). A pattern
+        /// without a separator matches an item's name at any depth (e.g. "node_modules")
+        pattern: String,
+
+        /// Stop ignoring the matching items. They're picked up again by the next scan
+        #[arg(long)]
+        undo: bool,
+
+        /// Count the matching items without changing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Manage policies for scanning roots periodically, such as a shallow scan every hour
+    /// and a deep scan every Sunday. Scheduled scans are run by `scan --due`
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleAction,
+    },
+
+    /// Manage the users expected to own a root's items, which `report ownership` checks
+    Owners {
+        #[command(subcommand)]
+        action: OwnersAction,
+    },
+
+    /// Manage groups of roots which together hold one dataset (such as a collection spread
+    /// across volumes), which `report group` reports on as one
+    Group {
+        #[command(subcommand)]
+        action: GroupAction,
+    },
+
+    /// Manage containers: parent directories, such as /home or a folder of datasets, whose
+    /// immediate subdirectories are each registered and scanned as a root of their own
+    Container {
+        #[command(subcommand)]
+        action: ContainerAction,
+    },
+
+    /// Export a scan's changes to a file for reviewers to annotate offline, and import
+    /// their acknowledgements and comments back
+    Review {
+        #[command(subcommand)]
+        action: ReviewAction,
+    },
+
+    /// Generate reports.
+    Report {
+        #[command(subcommand)]
+        report_type: ReportType,
+    },
+}
+
+/// Maintenance of the database.
+#[derive(Subcommand)]
+pub enum DbAction {
+    /// Upgrade the database's schema to this version of fspulse. Every command upgrades it
+    /// when it opens it, unless it can't be written, in which case reports read it through
+    /// an upgraded copy and leave it as it is
+    Upgrade {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+    },
+}
+
+/// Changes to the allowed owners of a root.
+#[derive(Subcommand)]
+pub enum OwnersAction {
+    /// Allow users to own a root's items. Once a root has allowed owners, items owned by
+    /// anyone else are reported
+    Allow {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root whose items the users may own
+        #[arg(long)]
+        root_id: u32,
+
+        /// User names or ids
+        #[arg(required = true)]
+        users: Vec<String>,
+    },
+
+    /// No longer allow users to own a root's items
+    Disallow {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root whose items the users may no longer own
+        #[arg(long)]
+        root_id: u32,
+
+        /// User names or ids
+        #[arg(required = true)]
+        users: Vec<String>,
+    },
+
+    /// List the users allowed to own a root's items
+    List {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root whose allowed owners to list
+        #[arg(long)]
+        root_id: u32,
+    },
+}
+
+/// Changes to groups of roots.
+#[derive(Subcommand)]
+pub enum GroupAction {
+    /// Add roots to a group, creating the group if needed
+    Add {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The name of the group
+        #[arg(long)]
+        name: String,
+
+        /// Root ids
+        #[arg(required = true)]
+        root_ids: Vec<u32>,
+    },
+
+    /// Remove roots from a group, or the whole group if no roots are given
+    Remove {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The name of the group
+        #[arg(long)]
+        name: String,
+
+        /// Root ids
+        root_ids: Vec<u32>,
+    },
+
+    /// List groups and their roots
+    List {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+    },
+}
+
+/// Changes to scan schedules.
+#[derive(Subcommand)]
+pub enum ContainerAction {
+    /// Add a container and register a root for each of its subdirectories
+    Add {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The parent directory
+        path: String,
+    },
+
+    /// Stop tracking a container. Its roots and their history are kept
+    Remove {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The container, by id or path
+        container: String,
+    },
+
+    /// Register roots for new subdirectories of each container, and archive the roots whose
+    /// subdirectory is gone. `scan --all` does this before scanning
+    Sync {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+    },
+
+    /// List containers and their roots
+    List {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ScheduleAction {
+    /// Schedule scans of a root, replacing its existing schedule for scans of that kind
+    Set {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root to scan
+        #[arg(long)]
+        root_id: u32,
+
+        /// How thorough the scan is: shallow (metadata only), deep (hash new and changed
+        /// files) or verify (hash every file)
+        #[arg(long, value_parser = ["shallow", "deep", "verify"])]
+        kind: String,
+
+        /// Time between scans, such as 90m, 6h, 7d or 4w
+        #[arg(long, value_parser = Utils::parse_interval)]
+        every: i64,
+
+        /// Local time of the first scan, as "YYYY-MM-DD HH:MM". Later scans are due every
+        /// interval after it. Defaults to now
+        #[arg(long)]
+        starting: Option<String>,
+    },
+
+    /// Stop scheduling scans of a kind for a root
+    Remove {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root whose schedule is removed
+        #[arg(long)]
+        root_id: u32,
+
+        /// The kind of scan no longer scheduled
+        #[arg(long, value_parser = ["shallow", "deep", "verify"])]
+        kind: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ReviewAction {
+    /// Write a scan's changes to a review file. Reviewers fill in the acknowledged,
+    /// comment and reviewer columns
+    Export {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The scan whose changes are exported: a scan id, ROOT@NUMBER or HEAD~N
+        #[arg(long)]
+        scan_id: String,
+
+        /// The review file to write
+        #[arg(long)]
+        file: PathBuf,
+
+        /// The format of the review file. Defaults to the file's extension
+        #[arg(long, value_parser = ["csv", "json"])]
+        format: Option<String>,
+    },
+
+    /// Record the acknowledgements, comments and reviewers from a review file
+    Import {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The review file to read
+        #[arg(long)]
+        file: PathBuf,
+
+        /// The format of the review file. Defaults to the file's extension
+        #[arg(long, value_parser = ["csv", "json"])]
+        format: Option<String>,
+
+        /// Report what would be recorded without recording it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Acknowledge a scan's added and modified files that package installs and upgrades
+    /// explain, by cross-referencing them with the package manager's file lists, and list
+    /// the changes to packaged files that nothing explains
+    Packages {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The scan whose changes are explained: a scan id, ROOT@NUMBER or HEAD~N
+        #[arg(long)]
+        scan_id: String,
+
+        /// The package manager to consult. Defaults to the one this system uses
+        #[arg(long, value_parser = ["dpkg", "rpm"])]
+        manager: Option<String>,
+
+        /// Report what would be acknowledged without recording it
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Available report types.
+#[derive(Subcommand)]
+pub enum ReportType {
+    /// Reports on "roots" which have been scanned in the past
+    Roots {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Show details of the root with the specified id
+        #[arg(long, conflicts_with = "root_path")]
+        root_id: Option<u32>,
+
+        /// Show details of the root with the specified path
+        #[arg(long, conflicts_with = "root_id")]
+        root_path: Option<String>,
+
+        /// Report format (csv, table).
+        #[arg(long, default_value = "table", value_parser = ["csv", "table"])]
+        format: String,
+    },
+
+    /// Summarizes the health of every root (or the specified roots): when each was last
+    /// scanned, which haven't been scanned recently, size, and recent changes
+    Summary {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Only summarize these roots (may be repeated)
+        #[arg(long)]
+        root_id: Vec<u32>,
+
+        /// Warn about roots which haven't completed a scan in this many days
+        #[arg(long, default_value_t = 7)]
+        stale_days: u32,
+
+        /// Count changes recorded in this many days
+        #[arg(long, default_value_t = 7)]
+        change_days: u32,
+    },
+
+    /// Counts each root's scans and the changes they found per day or week, to show how
+    /// activity trends over time
+    Activity {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Only report on these roots (may be repeated)
+        #[arg(long)]
+        root_id: Vec<u32>,
+
+        /// The length of each period (day, week). Periods are in local time and weeks start on Monday
+        #[arg(long, default_value = "day", value_parser = ["day", "week"])]
+        by: String,
+
+        /// How many periods to report, ending with the current one
+        #[arg(long, default_value_t = 14, value_parser = clap::value_parser!(u32).range(1..=3660))]
+        periods: u32,
+
+        /// Report format (table, json).
+        #[arg(long, default_value = "table", value_parser = ["table", "json"])]
+        format: String,
+    },
+
+    /// Reports on scans.
+    Scans {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Filter by scan (a scan id, ROOT@NUMBER or HEAD~N).
+        #[arg(long, conflicts_with = "last")]
+        scan_id: Option<String>,
+
+        /// Show last N scans (default: 10)
+        #[arg(long, default_value_t = 10, conflicts_with = "scan_id")]
+        last: u32,
+
+        /// Report format (csv, table).
+        #[arg(long, default_value = "table", value_parser = ["csv", "table"])]
+        format: String,
+    },
+
+    /// Reports on items.
+    Items {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Show a specific Item
+        #[arg(long, conflicts_with_all = ["item_path", "root_id", "search"])]
+        item_id: Option<u32>,
+
+        /// Show all items with a specific path (an item may appear in multiple roots
+        /// (in the case where one root is a subdirectory of another)
+        #[arg(long, conflicts_with_all = ["item_id", "root_id", "search"])]
+        item_path: Option<String>,
+
+        /// Shows the items seen on the most recent scan of the specified root
+        #[arg(long, conflicts_with_all = ["item_id", "item_path", "search"])]
+        root_id: Option<u32>,
+
+        /// Show items whose path contains the text, ignoring case
+        #[arg(long, conflicts_with_all = ["item_id", "item_path", "root_id"])]
+        search: Option<String>,
+
+        /// With --search, rank items by how closely their path resembles the text rather
+        /// than requiring an exact substring (shows 50 results unless --limit is given)
+        #[arg(long, requires = "search")]
+        fuzzy: bool,
+
+        /// With --search, show at most this many items
+        #[arg(long, requires = "search")]
+        limit: Option<u32>,
+
+        /// With --root-id, show the root's items as they were at a past scan: a scan id,
+        /// ROOT@NUMBER or HEAD~N, or a date ("YYYY-MM-DD" or "YYYY-MM-DD HH:MM") for the
+        /// last scan completed by then
+        #[arg(long, requires = "root_id")]
+        as_of: Option<String>,
+
+        /// With --root-id, show only the items at or beneath this path, given relative to
+        /// the root or in full
+        #[arg(long, requires = "root_id")]
+        path_prefix: Option<String>,
+
+        /// Report format (csv, table, tree).
+        #[arg(long, default_value = "table", value_parser = ["csv", "table", "tree"])]
+        format: String,
+    },
+
+    /// Reports items with implausible modification times: in the future relative to the
+    /// scan that recorded them, or before the Unix epoch. Uses each root's most recent scan
+    Timestamps {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Only report on the root with the specified id
+        #[arg(long)]
+        root_id: Option<u32>,
+
+        /// Seconds past the start of the scan before a timestamp counts as in the future.
+        /// Allows for files modified while the scan was running
+        #[arg(long, default_value_t = 300)]
+        tolerance: u64,
+    },
+
+    /// Reports symlinks whose target was missing when they were scanned. Uses each root's
+    /// most recent scan
+    Symlinks {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Only report on the root with the specified id
+        #[arg(long)]
+        root_id: Option<u32>,
+    },
+
+    /// Reports a group of roots as one dataset: the latest scan of each, and their items
+    /// together or the files among them with matching hashes
+    Group {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The name of the group
+        #[arg(long)]
+        name: String,
+
+        /// List files whose hashes match another file in the group, in any of its roots,
+        /// instead of every item
+        #[arg(long)]
+        duplicates: bool,
+    },
+
+    /// Reports sets of identical files, matched by hash and size, in a root's latest scan,
+    /// with the space taken by the extra copies
+    Duplicates {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root to report on
+        #[arg(long, alias = "root-path-id")]
+        root_id: u32,
+
+        /// Report format (table, json, fdupes, jdupes). fdupes and jdupes list the sets as
+        /// those tools do, for tools that act on their output
+        #[arg(long, default_value = "table", value_parser = ["table", "json", "fdupes", "jdupes"])]
+        format: String,
+    },
+
+    /// Reports a root's deleted items with the scans they disappeared between: the last
+    /// scan that found each item and the scan that recorded its deletion
+    Deletions {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root to report on
+        #[arg(long)]
+        root_id: u32,
+
+        /// Only report deletions recorded by this scan or later (a scan id, ROOT@NUMBER or HEAD~N)
+        #[arg(long)]
+        from_scan: Option<String>,
+
+        /// Only report deletions recorded by this scan or earlier (a scan id, ROOT@NUMBER or HEAD~N)
+        #[arg(long)]
+        to_scan: Option<String>,
+    },
+
+    /// Reports where duplicated content went across a range of scans: content that moved,
+    /// was copied and then deleted from where it was, was copied and left behind, or had
+    /// copies removed. Finds copy-then-delete reorganizations and space taken by leftover copies
+    Migrations {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root to report on
+        #[arg(long)]
+        root_id: u32,
+
+        /// Only follow content from this scan on (a scan id, ROOT@NUMBER or HEAD~N). Defaults
+        /// to the scan after the root's first
+        #[arg(long)]
+        from_scan: Option<String>,
+
+        /// Only follow content up to this scan (a scan id, ROOT@NUMBER or HEAD~N)
+        #[arg(long)]
+        to_scan: Option<String>,
+    },
+
+    /// Reports changes of owner or group to a root's items across a range of scans, and
+    /// items in its most recent scan owned by users other than its allowed owners
+    Ownership {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root to report on
+        #[arg(long)]
+        root_id: u32,
+
+        /// Only report changes recorded by this scan or later (a scan id, ROOT@NUMBER or HEAD~N)
+        #[arg(long)]
+        from_scan: Option<String>,
+
+        /// Only report changes recorded by this scan or earlier (a scan id, ROOT@NUMBER or HEAD~N)
+        #[arg(long)]
+        to_scan: Option<String>,
+    },
+
+    /// Reports how many items, and how many bytes of files, each user or group held in a
+    /// root as of a scan, and how that changed since the root's previous completed scan.
+    /// Answers "whose data is filling this server"
+    Usage {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root to report on
+        #[arg(long)]
+        root_id: u32,
+
+        /// Add up items by the user owning them or by their group
+        #[arg(long, default_value = "user", value_parser = ["user", "group"])]
+        by: String,
+
+        /// The scan to report as of (a scan id, ROOT@NUMBER or HEAD~N). Defaults to the
+        /// root's latest completed scan
+        #[arg(long)]
+        scan_id: Option<String>,
+    },
+
+    /// Reports files whose content hasn't been verified (read and hashed by a hashing scan)
+    /// recently, oldest first. Useful for planning verification a portion at a time
+    Unverified {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Only report on the root with the specified id
+        #[arg(long)]
+        root_id: Option<u32>,
+
+        /// Report files not verified in this many days (0 reports every file)
+        #[arg(long, default_value_t = 30)]
+        older_than: u32,
+
+        /// Report at most this many files
+        #[arg(long)]
+        limit: Option<u32>,
+    },
+
+    /// Reports files kept longer than a retention period, oldest first, for enforcing
+    /// data-retention policies such as "no exports older than 90 days"
+    Retention {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// The root to report on
+        #[arg(long)]
+        root_id: u32,
+
+        /// The retention period, in days
+        #[arg(long)]
+        older_than: u32,
+
+        /// Only report files at or beneath this path, relative to the root or absolute
+        /// (may be repeated)
+        #[arg(long)]
+        under: Vec<String>,
+
+        /// Measure age from the file's modification time (mtime) or from the scan that first
+        /// found it (first-seen)
+        #[arg(long, default_value = "mtime", value_parser = ["mtime", "first-seen"])]
+        by: String,
+    },
+
+    /// Reports the runs of `verify` and the files they found whose content no longer matched
+    /// its hash though its size and modification time hadn't changed
+    Bitrot {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Only report on the root with the specified id
+        #[arg(long)]
+        root_id: Option<u32>,
+    },
+
+    /// Reports the audit log: each operation which changed the database, when it was run,
+    /// by whom and with what arguments, oldest first
+    Audit {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Only report this operation, such as "scan" or "schedule set"
+        #[arg(long)]
+        operation: Option<String>,
+
+        /// Report only this many of the most recent operations
+        #[arg(long)]
+        limit: Option<u32>,
+    },
+
+    /// Reports on changes.
+    Changes {
+        /// Specifies the directory where the database is stored.
+        /// If omitted, uses $FSPULSE_DB, then the nearest `.fspulse` directory above the
+        /// current directory, then an existing database in the home directory, and finally
+        /// the platform data directory. The database file will always be named "fspulse.db".
+        #[arg(long)]
+        db_path: Option<PathBuf>,
+
+        /// Filter by change ID.
+        #[arg(long, conflicts_with_all = ["item_id", "scan_id"])]
+        change_id: Option<u32>,
+
+        /// Filter by item ID (shows all changes affecting the item).
+        #[arg(long, conflicts_with_all = ["change_id", "scan_id"])]
+        item_id: Option<u32>,        
+
+        /// Filter by scan (a scan id, ROOT@NUMBER or HEAD~N; shows all changes recorded in this scan).
+        #[arg(long, conflicts_with_all = ["change_id", "item_id"])]
+        scan_id: Option<String>,
+
+        /// Show only changes of these types, separated by commas (e.g. `--only add,delete`)
+        #[arg(long, value_delimiter = ',', conflicts_with = "change_id",
+            value_parser = ["add", "modify", "delete", "type-change", "move"])]
+        only: Vec<String>,
+
+        /// With --scan-id, show only changes to items at or beneath this path, given relative
+        /// to the root or in full
+        #[arg(long, requires = "scan_id")]
+        path_prefix: Option<String>,
+
+        /// Show only modifications to file content, whether or not the file's metadata changed
+        /// too. Only scans which hash can tell that content changed
+        #[arg(long, conflicts_with_all = ["change_id", "only", "metadata_only"])]
+        content_only: bool,
+
+        /// Show only modifications to metadata alone, such as files touched without their
+        /// content changing
+        #[arg(long, conflicts_with_all = ["change_id", "only"])]
+        metadata_only: bool,
+
+        /// Report format (csv, table, tree, git - tree and git only valid with scan-id).
+        /// git lists each change with its status letter, then a diffstat by directory
+        #[arg(long, default_value = "table", value_parser = ["csv", "table", "tree", "git"])]
+        format: String,
+
+        /// Print only the absolute path of each changed item, one per line
+        #[arg(long, conflicts_with = "format")]
+        paths_only: bool,
+
+        /// End each path with a NUL instead of a newline, for use with `xargs -0`
+        #[arg(long, requires = "paths_only")]
+        print0: bool,
+
+        /// In a tree, show only changed files, under the directories leading to them
+        #[arg(long, requires = "scan_id", conflicts_with_all = ["dirs_only", "paths_only"])]
+        files_only: bool,
+
+        /// In a tree, show only the directories holding changes, with a count of each type of change in each
+        #[arg(long, requires = "scan_id", conflicts_with_all = ["files_only", "paths_only"])]
+        dirs_only: bool,
+
+        /// With --dirs-only, roll each change up to the directory this many levels below the root,
+        /// counting it there instead of in the directory directly holding it
+        #[arg(long, requires = "dirs_only", value_parser = clap::value_parser!(u32).range(1..))]
+        depth: Option<u32>,
+    },
+}
+
+impl Command {
+    /// The name the command's running time is shown under by `--timings`
+    fn timing_phase(&self) -> &'static str {
+        match self {
+            Command::Scan { .. } => "scan",
+            Command::Report { .. } => "report",
+            _ => "command",
+        }
+    }
+}
+
+impl Cli {
+    pub fn handle_command_line() -> Result<(), FsPulseError>{
+        let args = Cli::parse();
+        let prompts = PromptPolicy::from_flags(args.yes, args.non_interactive);
+        Reports::set_width(args.width.map(|width| width as usize));
+        Reports::set_max_path_width((!args.full_paths).then(|| {
+            args.max_path_width.map_or_else(Reports::default_max_path_width, |max_width| max_width as usize)
+        }));
+        Reports::set_escape_paths(args.escape_paths);
+        Database::set_passphrase(match (args.db_passphrase, &args.db_key_file) {
+            (Some(passphrase), _) => Some(passphrase),
+            (None, Some(key_file)) => Some(Database::read_key_file(key_file)?),
+            (None, None) => None,
+        });
+        Reports::set_relative_paths(args.relative);
+
+        let config = Config::load(args.config.as_deref())?;
+        Database::set_configured_folder(config.db_path.clone());
+
+        if args.timings {
+            Timings::enable();
+        }
+        let _timings = Timings::print_on_exit();
+        let _command = Timings::start(args.command.timing_phase());
+        
+        match args.command {
+            Command::Init { root_path } => {
+                info!("Running init with root_path: {:?}", root_path);
+                Self::handle_init(root_path)?;
+            }
+            Command::Scan { db_path, root_id, root_path, last, all, due, jobs, hash_threads, hash, deep_delta, hash_algo, validate, confirm_hashes_over, allow_empty, progress_json, quiet, summary_line, dir_mtime, record_exclusions, budget, write_checksums, expect_frozen, tune_hashing, sample_over, sample_chunk, sample_stride, similarity_under, offline_files, max_db_growth, batch_size, exclude, respect_gitignore, include } => {
+                let options = ScanOptions {
+                    hash: hash || deep_delta,
+                    hash_delta: deep_delta,
+                    hash_algo: hash_algo.map(|algo| algo.parse()).transpose()?,
+                    validate,
+                    allow_empty,
+                    confirm_threshold: confirm_hashes_over,
+                    prompts,
+                    progress_json,
+                    ignore_dir_mtime: dir_mtime == "ignore",
+                    record_exclusions,
+                    budget_secs: budget,
+                    write_checksums,
+                    expect_frozen,
+                    tune_hashing,
+                    hash_threads: hash_threads.map(|threads| threads as usize),
+                    sample_threshold: sample_over,
+                    sampling: Sampling { chunk_size: sample_chunk, stride: sample_stride },
+                    similarity_threshold: similarity_under,
+                    max_db_growth,
+                    offline_files: match offline_files.as_str() {
+                        "skip" => OfflinePolicy::Skip,
+                        "hydrate" => OfflinePolicy::Hydrate,
+                        _ => OfflinePolicy::Record,
+                    },
+                    quiet,
+                    no_report: false,
+                    summary_line,
+                    batch_size: batch_size.map(|batch_size| batch_size as usize),
+                };
+                let rules = ExcludeRules::new(exclude, include, respect_gitignore);
+                info!(
+                    "Running scan with db_path: {:?}, root_id: {:?}, root_path: {:?}, last: {}, all: {}, due: {}, jobs: {}, options: {:?}, rules: {:?}",
+                    db_path, root_id, root_path, last, all, due, jobs, options, rules
+                );
+                Self::handle_scan(db_path, root_id, root_path, last, all, due, jobs, options, &rules, &config)?;
+            }
+            Command::Search { db_path, query, limit, min_changes, within_days, content_changed, type_changed } => {
+                info!(
+                    "Running search with db_path: {:?}, query: {:?}, limit: {:?}, min_changes: {:?}, within_days: {:?}, content_changed: {}, type_changed: {}",
+                    db_path, query, limit, min_changes, within_days, content_changed, type_changed
+                );
+                let filter = ChangeFilter { min_changes, within_days, content_changed, type_changed };
+                Self::handle_search(db_path, query.join(" "), filter, limit)?;
+            }
+            Command::History { db_path, item, format } => {
+                info!("Running history with db_path: {:?}, item: {}, format: {}", db_path, item, format);
+                Self::handle_history(db_path, item, format)?;
+            }
+            Command::Watch { db_path, root_id, root_path, settle_secs, max_wait_secs, hash, hash_algo, exclude, respect_gitignore, include } => {
+                let options = ScanOptions {
+                    hash,
+                    hash_delta: hash,
+                    hash_algo: hash_algo.map(|algo| algo.parse()).transpose()?,
+                    prompts,
+                    ..Default::default()
+                };
+                let rules = ExcludeRules::new(exclude, include, respect_gitignore);
+                info!(
+                    "Running watch with db_path: {:?}, root_id: {:?}, root_path: {:?}, settle_secs: {}, max_wait_secs: {}, options: {:?}, rules: {:?}",
+                    db_path, root_id, root_path, settle_secs, max_wait_secs, options, rules
+                );
+                Self::handle_watch(db_path, root_id, root_path, settle_secs, max_wait_secs, options, &rules, &config)?;
+            }
+            Command::Verify { db_path, root_id, limit, hash_threads } => {
+                info!(
+                    "Running verify with db_path: {:?}, root_id: {}, limit: {:?}, hash_threads: {}",
+                    db_path, root_id, limit, hash_threads
+                );
+                Self::handle_verify(db_path, root_id, limit, hash_threads)?;
+            }
+            Command::Repair { db_path, root_id, from, baseline, dry_run } => {
+                info!(
+                    "Running repair with db_path: {:?}, root_id: {}, from: {:?}, baseline: {:?}, dry_run: {}",
+                    db_path, root_id, from, baseline, dry_run
+                );
+                Self::handle_repair(db_path, root_id, from, baseline, dry_run)?;
+            }
+            Command::Check { db_path, root, hash, deep_delta, fail_on, quiet } => {
+                let options = ScanOptions {
+                    hash: hash || deep_delta,
+                    hash_delta: deep_delta,
+                    prompts,
+                    quiet,
+                    ..ScanOptions::default()
+                };
+                let fail_on = Self::parse_change_types(&fail_on);
+                info!(
+                    "Running check with db_path: {:?}, root: {}, fail_on: {:?}, options: {:?}",
+                    db_path, root, fail_on, options
+                );
+                Self::handle_check(db_path, root, options, &fail_on, &config)?;
+            }
+            Command::BackfillHashes { db_path, root_id, order, limit, budget, max_rate, hash_algo, hash_threads } => {
+                let options = BackfillOptions {
+                    order: match order.as_str() {
+                        "largest" => BackfillOrder::Largest,
+                        "path" => BackfillOrder::Path,
+                        _ => BackfillOrder::Smallest,
+                    },
+                    limit,
+                    budget_secs: budget,
+                    max_rate,
+                    hash_algo: hash_algo.map(|algo| algo.parse()).transpose()?,
+                    hash_threads: hash_threads as usize,
+                };
+                info!(
+                    "Running backfill-hashes with db_path: {:?}, root_id: {:?}, options: {:?}",
+                    db_path, root_id, options
+                );
+                Self::handle_backfill_hashes(db_path, root_id, &options, &config)?;
+            }
+            Command::Selftest { keep } => {
+                info!("Running selftest with keep: {}", keep);
+                SelfTest::run(keep)?;
+            }
+            Command::Gc { db_path, dry_run } => {
+                info!("Running gc with db_path: {:?}, dry_run: {}", db_path, dry_run);
+                Self::handle_gc(db_path, dry_run)?;
+            }
+            Command::Prune { db_path, root_id, keep_last, older_than, vacuum, dry_run } => {
+                info!(
+                    "Running prune with db_path: {:?}, root_id: {:?}, keep_last: {:?}, older_than: {:?}, vacuum: {}, dry_run: {}",
+                    db_path, root_id, keep_last, older_than, vacuum, dry_run
+                );
+                let policy = PrunePolicy { root_id: root_id.map(i64::from), keep_last, older_than };
+                Self::handle_prune(db_path, &policy, vacuum, dry_run, prompts)?;
+            }
+            Command::Rekey { db_path, new_passphrase, new_key_file, decrypt } => {
+                // Passphrases are kept out of the log
+                info!(
+                    "Running rekey with db_path: {:?}, new_key_file: {:?}, decrypt: {}",
+                    db_path, new_key_file, decrypt
+                );
+                let new_passphrase = match (new_passphrase, new_key_file) {
+                    (Some(passphrase), _) => Some(passphrase),
+                    (None, Some(key_file)) => Some(Database::read_key_file(&key_file)?),
+                    (None, None) => None,
+                };
+                Self::handle_rekey(db_path, new_passphrase)?;
+            }
+            Command::Db { action } => match action {
+                DbAction::Upgrade { db_path } => {
+                    info!("Upgrading database with db_path: {:?}", db_path);
+                    Self::handle_db_upgrade(db_path)?;
+                }
+            },
+            Command::CompareRoots { db_path, root_a, root_b } => {
+                info!("Running compare-roots with db_path: {:?}, root_a: {}, root_b: {}", db_path, root_a, root_b);
+                Self::handle_compare_roots(db_path, root_a, root_b)?;
+            }
+            Command::Estimate { db_path, root, walk_secs, sample_mib } => {
+                info!("Running estimate with db_path: {:?}, root: {}, walk_secs: {}, sample_mib: {}", db_path, root, walk_secs, sample_mib);
+                Self::handle_estimate(db_path, root, walk_secs, sample_mib)?;
+            }
+            Command::Compare { db_path, scan_a, scan_b, archive, image, strip_components, hash } => {
+                info!(
+                    "Running compare with db_path: {:?}, scan_a: {}, scan_b: {:?}, archive: {:?}, image: {:?}, strip_components: {}, hash: {}",
+                    db_path, scan_a, scan_b, archive, image, strip_components, hash
+                );
+                Self::handle_compare(db_path, scan_a, scan_b, archive, image, strip_components, hash)?;
+            }
+            Command::Ignore { db_path, root_id, pattern, undo, dry_run } => {
+                info!(
+                    "Running ignore with db_path: {:?}, root_id: {}, pattern: {}, undo: {}, dry_run: {}",
+                    db_path, root_id, pattern, undo, dry_run
+                );
+                Self::handle_ignore(db_path, root_id, pattern, undo, dry_run)?;
+            }
+            Command::DedupRoots { db_path, dry_run } => {
+                info!("Running dedup-roots with db_path: {:?}, dry_run: {}", db_path, dry_run);
+                Self::handle_dedup_roots(db_path, dry_run, prompts)?;
+            }
+            Command::Report { report_type } => match report_type {
+                ReportType::Roots { db_path, root_id, root_path, format } => {
+                    info!(
+                        "Generating roots report with db_path: {:?}, root_id: {:?}, root_path: {:?}, format: {}",
+                        db_path, root_id, root_path, format
+                    );
+                    Self::handle_report_roots(db_path, root_id, root_path, format)?;
+                }
+                ReportType::Summary { db_path, root_id, stale_days, change_days } => {
+                    info!(
+                        "Generating summary report with db_path: {:?}, root_id: {:?}, stale_days: {}, change_days: {}",
+                        db_path, root_id, stale_days, change_days
+                    );
+                    Self::handle_report_summary(db_path, root_id, stale_days, change_days)?;
+                }
+                ReportType::Scans { db_path, scan_id, last, format } => {
+                    info!(
+                        "Generating scans report with db_path: {:?}, scan_id: {:?}, last: {}, format: {}",
+                        db_path, scan_id, last, format
+                    );
+                    Self::handle_report_scans(db_path, scan_id, last, format)?;
+                }
+                ReportType::Items { db_path, item_id, item_path, root_id, search, fuzzy, limit, as_of, path_prefix, format } => {
+                    info!(
+                        "Generating items report with db_path: {:?}, item_id: {:?}, item_path: {:?}, root_id: {:?}, search: {:?}, fuzzy: {}, limit: {:?}, as_of: {:?}, path_prefix: {:?}, format: {}",
+                        db_path, item_id, item_path, root_id, search, fuzzy, limit, as_of, path_prefix, format
+                    );
+                    Self::handle_report_items(db_path, item_id, item_path, root_id, search, fuzzy, limit, as_of, path_prefix, format)?;
+                }
+                ReportType::Timestamps { db_path, root_id, tolerance } => {
+                    info!(
+                        "Generating timestamps report with db_path: {:?}, root_id: {:?}, tolerance: {}",
+                        db_path, root_id, tolerance
+                    );
+                    Self::handle_report_timestamps(db_path, root_id, tolerance)?;
+                }
+                ReportType::Symlinks { db_path, root_id } => {
+                    info!(
+                        "Generating symlinks report with db_path: {:?}, root_id: {:?}",
+                        db_path, root_id
+                    );
+                    Self::handle_report_symlinks(db_path, root_id)?;
+                }
+                ReportType::Activity { db_path, root_id, by, periods, format } => {
+                    info!(
+                        "Generating activity report with db_path: {:?}, root_id: {:?}, by: {}, periods: {}, format: {}",
+                        db_path, root_id, by, periods, format
+                    );
+                    Self::handle_report_activity(db_path, root_id, by, periods, format)?;
+                }
+                ReportType::Group { db_path, name, duplicates } => {
+                    info!("Generating group report with db_path: {:?}, name: {}, duplicates: {}", db_path, name, duplicates);
+                    Self::handle_report_group(db_path, name, duplicates)?;
+                }
+                ReportType::Duplicates { db_path, root_id, format } => {
+                    info!("Generating duplicates report with db_path: {:?}, root_id: {}, format: {}", db_path, root_id, format);
+                    Self::handle_report_duplicates(db_path, root_id, format)?;
+                }
+                ReportType::Deletions { db_path, root_id, from_scan, to_scan } => {
+                    info!(
+                        "Generating deletions report with db_path: {:?}, root_id: {}, from_scan: {:?}, to_scan: {:?}",
+                        db_path, root_id, from_scan, to_scan
+                    );
+                    Self::handle_report_deletions(db_path, root_id, from_scan, to_scan)?;
+                }
+                ReportType::Migrations { db_path, root_id, from_scan, to_scan } => {
+                    info!(
+                        "Generating migrations report with db_path: {:?}, root_id: {}, from_scan: {:?}, to_scan: {:?}",
+                        db_path, root_id, from_scan, to_scan
+                    );
+                    Self::handle_report_migrations(db_path, root_id, from_scan, to_scan)?;
+                }
+                ReportType::Ownership { db_path, root_id, from_scan, to_scan } => {
+                    info!(
+                        "Generating ownership report with db_path: {:?}, root_id: {}, from_scan: {:?}, to_scan: {:?}",
+                        db_path, root_id, from_scan, to_scan
+                    );
+                    Self::handle_report_ownership(db_path, root_id, from_scan, to_scan)?;
+                }
+                ReportType::Usage { db_path, root_id, by, scan_id } => {
+                    info!(
+                        "Generating usage report with db_path: {:?}, root_id: {}, by: {}, scan_id: {:?}",
+                        db_path, root_id, by, scan_id
+                    );
+                    Self::handle_report_usage(db_path, root_id, by, scan_id)?;
+                }
+                ReportType::Unverified { db_path, root_id, older_than, limit } => {
+                    info!(
+                        "Generating unverified report with db_path: {:?}, root_id: {:?}, older_than: {}, limit: {:?}",
+                        db_path, root_id, older_than, limit
+                    );
+                    Self::handle_report_unverified(db_path, root_id, older_than, limit)?;
+                }
+                ReportType::Retention { db_path, root_id, older_than, under, by } => {
+                    info!(
+                        "Generating retention report with db_path: {:?}, root_id: {}, older_than: {}, under: {:?}, by: {}",
+                        db_path, root_id, older_than, under, by
+                    );
+                    Self::handle_report_retention(db_path, root_id, older_than, under, by)?;
+                }
+                ReportType::Bitrot { db_path, root_id } => {
+                    info!("Generating bitrot report with db_path: {:?}, root_id: {:?}", db_path, root_id);
+                    Self::handle_report_bitrot(db_path, root_id)?;
+                }
+                ReportType::Audit { db_path, operation, limit } => {
+                    info!(
+                        "Generating audit report with db_path: {:?}, operation: {:?}, limit: {:?}",
+                        db_path, operation, limit
+                    );
+                    Self::handle_report_audit(db_path, operation, limit)?;
+                }
+                ReportType::Changes { db_path, change_id, item_id, scan_id, only, path_prefix, content_only, metadata_only, format, paths_only, print0, files_only, dirs_only, depth } => {
+                    let only = Self::parse_change_types(&only);
+                    let modify_kind = match (content_only, metadata_only) {
+                        (true, _) => Some(ModifyKind::Content),
+                        (_, true) => Some(ModifyKind::Metadata),
+                        _ => None,
+                    };
+                    info!(
+                        "Generating changes report with db_path: {:?}, change_id: {:?}, item_id: {:?}, scan_id: {:?}, only: {:?}, path_prefix: {:?}, modify_kind: {:?}, format: {}, paths_only: {}, print0: {}, files_only: {}, dirs_only: {}, depth: {:?}",
+                        db_path, change_id, item_id, scan_id, only, path_prefix, modify_kind, format, paths_only, print0, files_only, dirs_only, depth
+                    );
+                    Self::handle_report_changes(db_path, change_id, item_id, scan_id, only, path_prefix, modify_kind, format, paths_only, print0, files_only, dirs_only, depth)?;
+                }
+            },
+            Command::Schedule { action } => match action {
+                ScheduleAction::Set { db_path, root_id, kind, every, starting } => {
+                    info!(
+                        "Setting schedule with db_path: {:?}, root_id: {}, kind: {}, every: {}, starting: {:?}",
+                        db_path, root_id, kind, every, starting
+                    );
+                    Self::handle_schedule_set(db_path, root_id, kind, every, starting)?;
+                }
+                ScheduleAction::Remove { db_path, root_id, kind } => {
+                    info!("Removing schedule with db_path: {:?}, root_id: {}, kind: {}", db_path, root_id, kind);
+                    Self::handle_schedule_remove(db_path, root_id, kind)?;
+                }
+            },
+            Command::Owners { action } => match action {
+                OwnersAction::Allow { db_path, root_id, users } => {
+                    info!("Allowing owners with db_path: {:?}, root_id: {}, users: {:?}", db_path, root_id, users);
+                    Self::handle_owners_allow(db_path, root_id, users, true)?;
+                }
+                OwnersAction::Disallow { db_path, root_id, users } => {
+                    info!("Disallowing owners with db_path: {:?}, root_id: {}, users: {:?}", db_path, root_id, users);
+                    Self::handle_owners_allow(db_path, root_id, users, false)?;
+                }
+                OwnersAction::List { db_path, root_id } => {
+                    info!("Listing owners with db_path: {:?}, root_id: {}", db_path, root_id);
+                    Self::handle_owners_list(db_path, root_id)?;
+                }
+            },
+            Command::Group { action } => match action {
+                GroupAction::Add { db_path, name, root_ids } => {
+                    info!("Adding to group with db_path: {:?}, name: {}, root_ids: {:?}", db_path, name, root_ids);
+                    Self::handle_group_add(db_path, name, root_ids)?;
+                }
+                GroupAction::Remove { db_path, name, root_ids } => {
+                    info!("Removing from group with db_path: {:?}, name: {}, root_ids: {:?}", db_path, name, root_ids);
+                    Self::handle_group_remove(db_path, name, root_ids)?;
+                }
+                GroupAction::List { db_path } => {
+                    info!("Listing groups with db_path: {:?}", db_path);
+                    Self::handle_group_list(db_path)?;
+                }
+            },
+            Command::Container { action } => match action {
+                ContainerAction::Add { db_path, path } => {
+                    info!("Adding container with db_path: {:?}, path: {}", db_path, path);
+                    Self::handle_container_add(db_path, path)?;
+                }
+                ContainerAction::Remove { db_path, container } => {
+                    info!("Removing container with db_path: {:?}, container: {}", db_path, container);
+                    Self::handle_container_remove(db_path, container)?;
+                }
+                ContainerAction::Sync { db_path } => {
+                    info!("Syncing containers with db_path: {:?}", db_path);
+                    Self::handle_container_sync(db_path)?;
+                }
+                ContainerAction::List { db_path } => {
+                    info!("Listing containers with db_path: {:?}", db_path);
+                    Self::handle_container_list(db_path)?;
+                }
+            },
+            Command::Review { action } => match action {
+                ReviewAction::Export { db_path, scan_id, file, format } => {
+                    info!(
+                        "Exporting review with db_path: {:?}, scan_id: {}, file: {:?}, format: {:?}",
+                        db_path, scan_id, file, format
+                    );
+                    Self::handle_review_export(db_path, scan_id, file, format)?;
+                }
+                ReviewAction::Import { db_path, file, format, dry_run } => {
+                    info!(
+                        "Importing review with db_path: {:?}, file: {:?}, format: {:?}, dry_run: {}",
+                        db_path, file, format, dry_run
+                    );
+                    Self::handle_review_import(db_path, file, format, dry_run)?;
+                }
+                ReviewAction::Packages { db_path, scan_id, manager, dry_run } => {
+                    info!(
+                        "Explaining changes by packages with db_path: {:?}, scan_id: {}, manager: {:?}, dry_run: {}",
+                        db_path, scan_id, manager, dry_run
+                    );
+                    Self::handle_review_packages(db_path, scan_id, manager, dry_run)?;
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `init` command.
+    fn handle_init(root_path: Option<String>) -> Result<(), FsPulseError> {
+        let root_path = root_path.unwrap_or_else(|| ".".to_string());
+        let root_path_buf = Root::validate_and_canonicalize_path(&root_path)?;
+
+        let db_folder = root_path_buf.join(PROJECT_DIR_NAME);
+        if db_folder.exists() {
+            return Err(FsPulseError::Error(format!("'{}' is already initialized", root_path_buf.display())));
+        }
+        fs::create_dir(&db_folder)?;
+
+        let db = Database::new(Some(db_folder.clone()))?;
+        AuditEntry::record(&db, "init")?;
+        let root = Root::create(&db, &root_path_buf.to_string_lossy())?;
+
+        println!("Initialized fspulse database in '{}' (Root Id {})", db_folder.display(), root.id());
+        Ok(())
+    }
+
+    /// Handler for `scan` command.
+    #[allow(clippy::too_many_arguments)]
+    fn handle_scan(
+        db_path: Option<PathBuf>,
+        root_id: Option<u32>,
+        root_path: Option<String>,
+        last: bool,
+        all: bool,
+        due: bool,
+        jobs: u32,
+        options: ScanOptions,
+        rules: &ExcludeRules,
+        config: &Config,
+    ) -> Result<(), FsPulseError> {
+        let mut db = Database::new(db_path)?;
+        AuditEntry::record(&db, "scan")?;
+
+        if due {
+            return do_scan_due(&mut db, options, config);
+        }
+
+        if all {
+            return do_scan_all(&mut db, jobs as usize, options, rules, config);
+        }
+
+        // Within an initialized tree, scan the tree when no root was specified
+        let root_path = match (root_id, &root_path, last, db.project_root()) {
+            (None, None, false, Some(project_root)) => Some(project_root.to_string_lossy().into_owned()),
+            _ => root_path,
+        };
+
+        do_scan_machine(&mut db, root_id, root_path, last, options, rules, config)?;
+
+
+        Ok(())
+    }
+
+    /// Handler for `search` command.
+    fn handle_search(db_path: Option<PathBuf>, query: String, filter: ChangeFilter, limit: Option<u32>) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+
+        Reports::report_search(&db, &query, &filter, limit)
+    }
+
+    /// Handler for `history` command.
+    fn handle_history(db_path: Option<PathBuf>, item: String, format: String) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        let format: ReportFormat = format.parse()?;
+
+        let histories = Reports::history(&db, &item)?;
+        Reports::print_history(&histories, format)
+    }
+
+    /// Handler for `watch` command.
+    #[allow(clippy::too_many_arguments)]
+    fn handle_watch(
+        db_path: Option<PathBuf>,
+        root_id: Option<u32>,
+        root_path: Option<String>,
+        settle_secs: u64,
+        max_wait_secs: u64,
+        options: ScanOptions,
+        rules: &ExcludeRules,
+        config: &Config,
+    ) -> Result<(), FsPulseError> {
+        let mut db = Database::new(db_path)?;
+        AuditEntry::record(&db, "watch")?;
+
+        let root = match (root_id, root_path) {
+            (Some(root_id), _) => Root::get_by_id(&db, root_id.into())?
+                .ok_or_else(|| FsPulseError::Error(format!("Root id {} not found", root_id)))?,
+            (None, Some(root_path)) => {
+                let root_path_buf = Root::validate_and_canonicalize_path(&root_path)?;
+                match Root::get_by_directory(&db, &root_path_buf)? {
+                    Some(root) => root,
+                    None => Root::create(&db, &root_path_buf.to_string_lossy())?,
+                }
+            },
+            (None, None) => unreachable!("clap requires a root"),
+        };
+
+        Watch::run(&mut db, &root, Duration::from_secs(settle_secs), Duration::from_secs(max_wait_secs), options, rules, config)
+    }
+
+    /// Handler for `verify` command.
+    fn handle_verify(db_path: Option<PathBuf>, root_id: u32, limit: Option<u32>, hash_threads: u32) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        AuditEntry::record(&db, "verify")?;
+
+        let root = Root::get_by_id(&db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        let (verification, mismatches) = Verification::run(&db, &root, limit, hash_threads as usize)?;
+        Reports::print_verification(&root, &verification, mismatches)?;
+
+        match verification.files_mismatched() {
+            0 => Ok(()),
+            count => Err(FsPulseError::ContentMismatch(format!(
+                "{} file(s) in '{}' don't match their hashes", count, root.path()))),
+        }
+    }
+
+    /// Handler for `repair` command.
+    fn handle_repair(db_path: Option<PathBuf>, root_id: u32, from: PathBuf, baseline: Option<String>, dry_run: bool) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        if !dry_run {
+            AuditEntry::record(&db, "repair")?;
+        }
+
+        let root = Root::get_by_id(&db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+        let baseline = match baseline {
+            Some(baseline) => Scan::find_by_ref(&db, &baseline)?,
+            None => Scan::get_latest_for_root(&db, root.id())?
+                .filter(|scan| scan.state() == ScanState::Completed)
+                .ok_or_else(|| FsPulseError::Error(format!("'{}' has no completed scan to repair it to", root.path())))?,
+        };
+
+        let repaired = Repairs::run(&db, &root, &baseline, &from, dry_run)?;
+        for file in &repaired {
+            let path = file.path.to_string_lossy();
+            let path = Reports::show_path(&path);
+            match &file.failure {
+                None if dry_run => println!("Would restore {} file '{}'", file.damage.as_str(), path),
+                None => println!("Restored {} file '{}'", file.damage.as_str(), path),
+                Some(failure) => println!("Couldn't restore {} file '{}': {}", file.damage.as_str(), path, failure),
+            }
+        }
+
+        let failed = repaired.iter().filter(|file| file.failure.is_some()).count();
+        println!(
+            "{} {} of {} damaged file(s) in '{}' as of Scan Id {}",
+            if dry_run { "Could restore" } else { "Restored" }, repaired.len() - failed, repaired.len(), root.path(), baseline.id(),
+        );
+
+        match failed {
+            0 => Ok(()),
+            count => Err(FsPulseError::Error(format!("{} file(s) in '{}' couldn't be restored", count, root.path()))),
+        }
+    }
+
+    /// Handler for `check` command.
+    fn handle_check(db_path: Option<PathBuf>, root: String, options: ScanOptions, fail_on: &[ChangeType], config: &Config) -> Result<(), FsPulseError> {
+        let mut db = Database::new(db_path)?;
+        AuditEntry::record(&db, "check")?;
+
+        // A path which isn't a root yet is registered by the scan
+        let (root_id, root_path) = match Root::find_by_id_or_path(&db, &root) {
+            Ok(known) => (Some(known.id() as u32), None),
+            Err(_) => (None, Some(root.clone())),
+        };
+        do_scan_machine(&mut db, root_id, root_path, false, options, &ExcludeRules::default(), config)?;
+
+        let root = Root::find_by_id_or_path(&db, &root)?;
+        let scan = Scan::get_latest_for_root(&db, root.id())?
+            .filter(|scan| scan.state() == ScanState::Completed)
+            .ok_or_else(|| FsPulseError::Error(format!("The scan of '{}' didn't complete", root.path())))?;
+
+        if !scan.has_completed_predecessor(&db)? {
+            println!("Recorded the baseline of '{}'", root.path());
+            return Ok(());
+        }
+
+        let counts = scan.change_counts();
+        let changes: i64 = [ChangeType::Add, ChangeType::Modify, ChangeType::Delete, ChangeType::TypeChange, ChangeType::Move]
+            .into_iter()
+            .filter(|change_type| fail_on.contains(change_type))
+            .map(|change_type| counts.count_of(change_type))
+            .sum();
+        match changes {
+            0 => Ok(()),
+            changes => Err(FsPulseError::ChangesDetected(format!(
+                "'{}' has {} changes in Scan Id {}", root.path(), changes, scan.id()))),
+        }
+    }
+
+    /// Handler for `backfill-hashes` command.
+    fn handle_backfill_hashes(db_path: Option<PathBuf>, root_id: Option<u32>, options: &BackfillOptions, config: &Config) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        AuditEntry::record(&db, "backfill-hashes")?;
+
+        let roots = match root_id {
+            Some(root_id) => vec![Root::get_by_id(&db, root_id.into())?
+                .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?],
+            None => {
+                let mut roots = Vec::new();
+                Root::for_each_root(&db, |root| {
+                    roots.push(root.clone());
+                    Ok(())
+                })?;
+                roots
+            },
+        };
+
+        // The budget is shared by all the roots
+        let deadline = options.budget_secs.map(|secs| Instant::now() + Duration::from_secs(secs as u64));
+
+        for root in roots {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                println!("Out of time before '{}' was reached", root.path());
+                continue;
+            }
+
+            let hash_algo = match options.hash_algo.or_else(|| config.hash_algo_for(root.path())) {
+                Some(hash_algo) => hash_algo,
+                None => Scan::last_hash_algo(&db, root.id())?.unwrap_or_default(),
+            };
+
+            let backfill = Backfill::run(&db, &root, options, deadline, hash_algo)?;
+            println!(
+                "Backfilled '{}' ({}): {} files hashed ({}), {} skipped, {} still without a hash",
+                root.path(), hash_algo, backfill.files_hashed, Utils::format_bytes(backfill.bytes_hashed),
+                backfill.files_skipped, backfill.files_remaining,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `gc` command.
+    fn handle_gc(db_path: Option<PathBuf>, dry_run: bool) -> Result<(), FsPulseError> {
+        let mut db = Database::new(db_path)?;
+        if !dry_run {
+            AuditEntry::record(&db, "gc")?;
+        }
+
+        Gc::do_gc(&mut db, dry_run)
+    }
+
+    /// Handler for `prune` command.
+    fn handle_prune(db_path: Option<PathBuf>, policy: &PrunePolicy, vacuum: bool, dry_run: bool, prompts: PromptPolicy) -> Result<(), FsPulseError> {
+        let mut db = Database::new(db_path)?;
+        if !dry_run {
+            AuditEntry::record(&db, "prune")?;
+        }
+
+        if let Some(root_id) = policy.root_id {
+            Root::get_by_id(&db, root_id)?
+                .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+        }
+
+        Prune::do_prune(&mut db, policy, dry_run, vacuum, prompts)
+    }
+
+    /// Handler for `rekey` command.
+    fn handle_rekey(db_path: Option<PathBuf>, new_passphrase: Option<String>) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        AuditEntry::record(&db, "rekey")?;
+        let path = db.path().to_string();
+
+        db.rekey(new_passphrase.as_deref())?;
+        match new_passphrase {
+            Some(_) => println!("Encrypted '{}' with the new passphrase", path),
+            None => println!("Decrypted '{}'", path),
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `db upgrade`
+    fn handle_db_upgrade(db_path: Option<PathBuf>) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+
+        match db.upgraded_from() {
+            Some(version) => {
+                AuditEntry::record(&db, "db upgrade")?;
+                println!("Upgraded '{}' from schema version {} to {}", db.path(), version, SCHEMA_VERSION);
+            },
+            None => println!("'{}' is already at schema version {}", db.path(), SCHEMA_VERSION),
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `dedup-roots` command.
+    fn handle_dedup_roots(db_path: Option<PathBuf>, dry_run: bool, prompts: PromptPolicy) -> Result<(), FsPulseError> {
+        let mut db = Database::new(db_path)?;
+        if !dry_run {
+            AuditEntry::record(&db, "dedup-roots")?;
+        }
+
+        Root::dedup_roots(&mut db, dry_run, prompts)
+    }
+
+    /// Handler for `compare-roots` command.
+    fn handle_compare_roots(db_path: Option<PathBuf>, root_a: String, root_b: String) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+
+        let root_a = Root::find_by_id_or_path(&db, &root_a)?;
+        let root_b = Root::find_by_id_or_path(&db, &root_b)?;
+
+        Reports::report_compare_roots(&db, &root_a, &root_b)
+    }
+
+    /// Handler for `estimate` command.
+    fn handle_estimate(db_path: Option<PathBuf>, root: String, walk_secs: u64, sample_mib: u64) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+
+        // A path which isn't a root yet is estimated without history
+        let (root_path, root_id) = match Root::find_by_id_or_path(&db, &root) {
+            Ok(root) => (PathBuf::from(root.path()), Some(root.id())),
+            Err(_) => (Root::validate_and_canonicalize_path(&root)?, None),
+        };
+
+        let estimate = Estimate::run(&db, &root_path, root_id, Duration::from_secs(walk_secs), sample_mib.saturating_mul(1024 * 1024))?;
+
+        Reports::report_estimate(&root_path, root_id, &estimate)
+    }
+
+    /// Handler for `compare` command.
+    fn handle_compare(
+        db_path: Option<PathBuf>,
+        scan_a: String,
+        scan_b: Option<String>,
+        archive: Option<PathBuf>,
+        image: Option<PathBuf>,
+        strip_components: usize,
+        hash: bool,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+
+        let scan_a = Scan::find_by_ref(&db, &scan_a)?;
+
+        match (scan_b, archive, image) {
+            (Some(scan_b), _, _) => {
+                let scan_b = Scan::find_by_ref(&db, &scan_b)?;
+                Reports::report_compare_scans(&db, &scan_a, &scan_b)
+            },
+            (None, Some(archive), _) => {
+                let members = Archive::read_members(&archive, strip_components, hash)?;
+                Reports::report_compare_archive(&db, &scan_a, &archive, members, hash)
+            },
+            (None, None, Some(image)) => {
+                let members = Image::read_members(&image, hash)?;
+                Reports::report_compare_archive(&db, &scan_a, &image, members, hash)
+            },
+            (None, None, None) => Err(FsPulseError::Error("One of --scan-b, --archive or --image is required".to_string())),
+        }
+    }
+
+    /// Handler for `ignore` command.
+    fn handle_ignore(db_path: Option<PathBuf>, root_id: u32, pattern: String, undo: bool, dry_run: bool) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        if !dry_run {
+            AuditEntry::record(&db, "ignore")?;
+        }
+
+        let root = Root::get_by_id(&db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        let count = Item::set_ignored(&db, root.id(), &pattern, !undo, dry_run)?;
+        let action = match (undo, dry_run) {
+            (false, false) => "Ignored",
+            (false, true) => "Would ignore",
+            (true, false) => "Stopped ignoring",
+            (true, true) => "Would stop ignoring",
+        };
+
+        println!("{} {} items matching '{}' in '{}'{}", action, count, pattern, root.path(), if dry_run { " (dry run)" } else { "" });
+        Ok(())
+    }
+
+    /// Handler for `schedule set`
+    fn handle_schedule_set(
+        db_path: Option<PathBuf>,
+        root_id: u32,
+        kind: String,
+        every: i64,
+        starting: Option<String>,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        AuditEntry::record(&db, "schedule set")?;
+        let kind: ScanKind = kind.parse()?;
+
+        let root = Root::get_by_id(&db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        let anchor = match starting {
+            Some(starting) => NaiveDateTime::parse_from_str(&starting, "%Y-%m-%d %H:%M")
+                .ok()
+                .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+                .ok_or_else(|| FsPulseError::Error(format!("Invalid start time '{}'. Use \"YYYY-MM-DD HH:MM\"", starting)))?
+                .timestamp(),
+            None => Utc::now().timestamp(),
+        };
+
+        Schedule::set(&db, root.id(), kind, every, anchor)?;
+        println!(
+            "Scheduled a {} scan of '{}' every {}, starting {}",
+            kind, root.path(), Utils::format_interval(every), Utils::format_db_time_short(anchor),
+        );
+        Ok(())
+    }
+
+    /// Handler for `schedule remove`
+    fn handle_schedule_remove(db_path: Option<PathBuf>, root_id: u32, kind: String) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        AuditEntry::record(&db, "schedule remove")?;
+        let kind: ScanKind = kind.parse()?;
+
+        if !Schedule::remove(&db, root_id.into(), kind)? {
+            return Err(FsPulseError::Error(format!("Root Id {} has no {} schedule", root_id, kind)));
+        }
+
+        println!("Removed the {} schedule of Root Id {}", kind, root_id);
+        Ok(())
+    }
+
+    /// Handler for `owners allow` and `owners disallow`
+    fn handle_owners_allow(db_path: Option<PathBuf>, root_id: u32, users: Vec<String>, allow: bool) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        AuditEntry::record(&db, if allow { "owners allow" } else { "owners disallow" })?;
+
+        let root = Root::get_by_id(&db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        // Resolve every user before changing anything
+        let uids = users.iter()
+            .map(|user| Owners::resolve_user(user))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for uid in uids {
+            let name = Owners::user_name(Some(uid));
+            if allow {
+                if Owners::allow(&db, root.id(), uid)? {
+                    println!("Allowed {} to own items of '{}'", name, root.path());
+                } else {
+                    println!("{} is already allowed to own items of '{}'", name, root.path());
+                }
+            } else if Owners::disallow(&db, root.id(), uid)? {
+                println!("{} is no longer allowed to own items of '{}'", name, root.path());
+            } else {
+                println!("{} wasn't allowed to own items of '{}'", name, root.path());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `owners list`
+    fn handle_owners_list(db_path: Option<PathBuf>, root_id: u32) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+
+        let root = Root::get_by_id(&db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        let allowed = Owners::allowed(&db, root.id())?;
+        if allowed.is_empty() {
+            println!("Any user may own items of '{}'", root.path());
+        } else {
+            println!("Allowed owners of items of '{}':", root.path());
+            for uid in allowed {
+                println!("    {} ({})", Owners::user_name(Some(uid)), uid);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `group add`
+    fn handle_group_add(db_path: Option<PathBuf>, name: String, root_ids: Vec<u32>) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        AuditEntry::record(&db, "group add")?;
+
+        // Find every root before changing anything
+        let roots = root_ids.iter()
+            .map(|root_id| Root::get_by_id(&db, (*root_id).into())?
+                .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id))))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for root in roots {
+            if RootGroups::add(&db, &name, root.id())? {
+                println!("Added '{}' to group '{}'", root.path(), name);
+            } else {
+                println!("'{}' is already in group '{}'", root.path(), name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `group remove`
+    fn handle_group_remove(db_path: Option<PathBuf>, name: String, root_ids: Vec<u32>) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        AuditEntry::record(&db, "group remove")?;
+
+        if root_ids.is_empty() {
+            match RootGroups::remove_all(&db, &name)? {
+                0 => println!("Group '{}' not found", name),
+                count => println!("Removed group '{}' ({} roots)", name, count),
+            }
+            return Ok(());
+        }
+
+        for root_id in root_ids {
+            if RootGroups::remove(&db, &name, root_id.into())? {
+                println!("Removed Root Id {} from group '{}'", root_id, name);
+            } else {
+                println!("Root Id {} wasn't in group '{}'", root_id, name);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `group list`
+    fn handle_group_list(db_path: Option<PathBuf>) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+
+        let groups = RootGroups::all(&db)?;
+        if groups.is_empty() {
+            println!("No groups. Use 'fspulse group add' to create one");
+        }
+
+        for (name, root_ids) in groups {
+            println!("{}:", name);
+            for root_id in root_ids {
+                match Root::get_by_id(&db, root_id)? {
+                    Some(root) => println!("    {} ({})", root.path(), root_id),
+                    None => println!("    Root Id {} (not found)", root_id),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `container add`
+    fn handle_container_add(db_path: Option<PathBuf>, path: String) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        AuditEntry::record(&db, "container add")?;
+
+        let (container, added) = Container::add(&db, &path)?;
+        if added {
+            println!("Added container '{}' (Container Id {})", container.path(), container.id());
+        } else {
+            println!("'{}' is already a container (Container Id {})", container.path(), container.id());
+        }
+
+        for change in container.sync(&db)? {
+            change.print(&container);
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `container remove`
+    fn handle_container_remove(db_path: Option<PathBuf>, container: String) -> Result<(), FsPulseError> {
+        let mut db = Database::new(db_path)?;
+        AuditEntry::record(&db, "container remove")?;
+
+        let container = Container::find_by_id_or_path(&db, &container)?;
+        let path = container.path().to_string();
+        let count = container.remove(&mut db)?;
+        println!("Removed container '{}'. Its {} roots are kept", path, count);
+
+        Ok(())
+    }
+
+    /// Handler for `container sync`
+    fn handle_container_sync(db_path: Option<PathBuf>) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        AuditEntry::record(&db, "container sync")?;
+
+        Container::sync_all(&db)
+    }
+
+    /// Handler for `container list`
+    fn handle_container_list(db_path: Option<PathBuf>) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+
+        let containers = Container::all(&db)?;
+        if containers.is_empty() {
+            println!("No containers. Use 'fspulse container add' to add one");
+        }
+
+        for container in containers {
+            println!("{} ({}):", container.path(), container.id());
+            for (root, archived_at) in container.roots(&db)? {
+                match archived_at {
+                    Some(archived_at) => println!(
+                        "    {} ({}, archived {})", root.path(), root.id(), Utils::format_db_time_short(archived_at)),
+                    None => println!("    {} ({})", root.path(), root.id()),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Handler for `review export`
+    fn handle_review_export(db_path: Option<PathBuf>, scan_id: String, file: PathBuf, format: Option<String>) -> Result<(), FsPulseError> {
+        let db = Database::new(db_path)?;
+        let format = ReviewFormat::for_file(format.as_deref(), &file)?;
+
+        let scan_id = Scan::id_by_ref(&db, &scan_id)?;
+
+        let count = Review::export(&db, scan_id, &file, format)?;
+        println!("Exported {} change(s) from Scan Id {} to '{}'", count, scan_id, file.display());
+        Ok(())
+    }
+
+    /// Handler for `review import`
+    fn handle_review_import(db_path: Option<PathBuf>, file: PathBuf, format: Option<String>, dry_run: bool) -> Result<(), FsPulseError> {
+        let mut db = Database::new(db_path)?;
+        if !dry_run {
+            AuditEntry::record(&db, "review import")?;
+        }
+        let format = ReviewFormat::for_file(format.as_deref(), &file)?;
+
+        let summary = Review::import(&mut db, &file, format, dry_run)?;
+        println!(
+            "{} {} change(s), {} unchanged",
+            if dry_run { "Would update" } else { "Updated" }, summary.updated, summary.unchanged,
+        );
+        if summary.mismatched > 0 {
+            println!(
+                "Skipped {} record(s) whose change doesn't exist or refers to a different item. Was the file exported from another database?",
+                summary.mismatched,
+            );
+        }
+        Ok(())
+    }
+
+    /// Handler for `review packages`
+    fn handle_review_packages(db_path: Option<PathBuf>, scan_id: String, manager: Option<String>, dry_run: bool) -> Result<(), FsPulseError> {
+        let mut db = Database::new(db_path)?;
+        if !dry_run {
+            AuditEntry::record(&db, "review packages")?;
+        }
+
+        let manager = match manager.as_deref() {
+            Some("rpm") => PackageManager::Rpm,
+            Some(_) => PackageManager::Dpkg,
+            None => PackageManager::detect()
+                .ok_or_else(|| FsPulseError::Error("No package manager found. Use --manager to name one".to_string()))?,
+        };
+        let scan = Scan::find_by_ref(&db, &scan_id)?;
+
+        let summary = Packages::explain_scan(&mut db, &scan, manager, dry_run)?;
+        println!(
+            "{} {} change(s) to packaged files in Scan Id {} as explained by {}. {} unexplained, {} to files no package installed",
+            if dry_run { "Would acknowledge" } else { "Acknowledged" }, summary.explained, scan.id(), manager.as_str(),
+            summary.unexplained.len(), summary.unpackaged,
+        );
+        for change in &summary.unexplained {
+            println!("  {} {} ({})", change.change_type, Reports::show_path(&change.path.to_string_lossy()), change.package);
+        }
+        Ok(())
+    }
+
+    /// Handler for `report paths`
+    fn handle_report_roots(
+        db_path: Option<PathBuf>,
+        root_id: Option<u32>,
+        root_path: Option<String>,
+        format: String,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        format.parse::<ReportFormat>()?;
+        
+        let report = Reports::roots(&db, root_id, root_path)?;
+        Reports::print_roots(&report)?;
+        Ok(())
+    }
+
+    /// Handler for `report summary`
+    fn handle_report_summary(
+        db_path: Option<PathBuf>,
+        root_ids: Vec<u32>,
+        stale_days: u32,
+        change_days: u32,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+
+        let summary = Reports::summary(&db, &root_ids, stale_days, change_days)?;
+        Reports::print_summary(&summary)?;
+        Ok(())
+    }
+
+    /// Handler for `report scans`
+    fn handle_report_scans(
+        db_path: Option<PathBuf>,
+        scan_id: Option<String>,
+        last: u32,
+        format: String,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        format.parse::<ReportFormat>()?;
+        let scan_id = scan_id.map(|scan_id| Scan::id_by_ref(&db, &scan_id)).transpose()?;
+
+        match scan_id {
+            Some(scan_id) => {
+                let report = Reports::scan(&db, scan_id)?;
+                Reports::print_scan(report.as_ref())?;
+                if let Some(report) = &report {
+                    Reports::print_scan_paths(report)?;
+                }
+            },
+            None => Reports::print_scans(&Reports::scans(&db, last)?)?,
+        }
+        Ok(())
+    }
+
+    /// Handler for `report items`
+    #[allow(clippy::too_many_arguments)]
+    fn handle_report_items(
+        db_path: Option<PathBuf>,
+        item_id: Option<u32>,
+        item_path: Option<String>,
+        root_id: Option<u32>,
+        search: Option<String>,
+        fuzzy: bool,
+        limit: Option<u32>,
+        as_of: Option<String>,
+        path_prefix: Option<String>,
+        format: String,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        let format: ReportFormat = format.parse()?;
+
+        let as_of = match (as_of, root_id) {
+            (Some(as_of), Some(root_id)) => Some(Self::scan_as_of(&db, root_id.into(), &as_of)?),
+            _ => None,
+        };
+
+        let path_prefix = match (path_prefix, root_id) {
+            (Some(path_prefix), Some(root_id)) => Root::get_by_id(&db, root_id.into())?
+                .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?
+                .relative_path_prefix(&path_prefix)?,
+            _ => None,
+        };
+
+        let report = Reports::items(&db, item_id, item_path, root_id, search, fuzzy, limit, as_of, path_prefix)?;
+        Reports::print_items(&report, format)?;
+        Ok(())
+    }
+
+    /// The id of the scan given to `--as-of`: a scan reference, or a date or time meaning
+    /// the root's last scan completed by then. A date alone means the end of that day
+    fn scan_as_of(db: &Database, root_id: i64, as_of: &str) -> Result<i64, FsPulseError> {
+        let naive = NaiveDateTime::parse_from_str(as_of, "%Y-%m-%d %H:%M").ok()
+            .or_else(|| NaiveDate::parse_from_str(as_of, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(23, 59, 59)));
+
+        let Some(naive) = naive else {
+            return Scan::id_by_ref(db, as_of);
+        };
+
+        let time = Local.from_local_datetime(&naive).earliest()
+            .ok_or_else(|| FsPulseError::Error(format!("Invalid time '{}'", as_of)))?
+            .timestamp();
+
+        let scan = Scan::get_latest_completed_at(db, root_id, time)?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} has no scan completed by {}", root_id, as_of)))?;
+
+        Ok(scan.id())
+    }
+
+    /// Handler for `report timestamps`
+    fn handle_report_timestamps(
+        db_path: Option<PathBuf>,
+        root_id: Option<u32>,
+        tolerance: u64,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+
+        Reports::report_timestamps(&db, root_id, tolerance)?;
+        Ok(())
+    }
+
+    /// Handler for `report symlinks`
+    fn handle_report_symlinks(
+        db_path: Option<PathBuf>,
+        root_id: Option<u32>,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+
+        Reports::report_symlinks(&db, root_id)?;
+        Ok(())
+    }
+
+    /// Handler for `report activity`
+    fn handle_report_activity(
+        db_path: Option<PathBuf>,
+        root_ids: Vec<u32>,
+        by: String,
+        periods: u32,
+        format: String,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        let period: ChangePeriod = by.parse()?;
+        let format: ReportFormat = format.parse()?;
+
+        Reports::report_activity(&db, &root_ids, period, periods, format)?;
+        Ok(())
+    }
+
+    /// Handler for `report group`
+    fn handle_report_group(db_path: Option<PathBuf>, name: String, duplicates: bool) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+
+        let roots = Reports::group_roots(&db, &name)?;
+        Reports::print_group_roots(&name, &roots)?;
+
+        if duplicates {
+            let sets = Reports::group_duplicates(&db, &name)?;
+            Reports::print_group_duplicates(&name, &sets)?;
+        } else {
+            Reports::print_group_items(&db, &name)?;
+        }
+        Ok(())
+    }
+
+    /// Handler for `report duplicates`
+    fn handle_report_duplicates(db_path: Option<PathBuf>, root_id: u32, format: String) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        let format: ReportFormat = format.parse()?;
+
+        let root = Root::get_by_id(&db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        let sets = Reports::duplicates(&db, &root)?;
+        Reports::print_duplicates(&root, &sets, format)?;
+        Ok(())
+    }
+
+    /// Handler for `report deletions`
+    fn handle_report_deletions(
+        db_path: Option<PathBuf>,
+        root_id: u32,
+        from_scan: Option<String>,
+        to_scan: Option<String>,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        let from_scan = from_scan.map(|scan| Scan::id_by_ref(&db, &scan)).transpose()?;
+        let to_scan = to_scan.map(|scan| Scan::id_by_ref(&db, &scan)).transpose()?;
+
+        Reports::report_deletions(&db, root_id, from_scan, to_scan)?;
+        Ok(())
+    }
+
+    /// Handler for `report migrations`
+    fn handle_report_migrations(
+        db_path: Option<PathBuf>,
+        root_id: u32,
+        from_scan: Option<String>,
+        to_scan: Option<String>,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        let from_scan = from_scan.map(|scan| Scan::id_by_ref(&db, &scan)).transpose()?;
+        let to_scan = to_scan.map(|scan| Scan::id_by_ref(&db, &scan)).transpose()?;
+
+        Reports::report_migrations(&db, root_id, from_scan, to_scan)?;
+        Ok(())
+    }
+
+    /// Handler for `report ownership`
+    fn handle_report_ownership(
+        db_path: Option<PathBuf>,
+        root_id: u32,
+        from_scan: Option<String>,
+        to_scan: Option<String>,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        let from_scan = from_scan.map(|scan| Scan::id_by_ref(&db, &scan)).transpose()?;
+        let to_scan = to_scan.map(|scan| Scan::id_by_ref(&db, &scan)).transpose()?;
+
+        Reports::report_ownership(&db, root_id, from_scan, to_scan)?;
+        Ok(())
+    }
+
+    /// Handler for `report usage`
+    fn handle_report_usage(
+        db_path: Option<PathBuf>,
+        root_id: u32,
+        by: String,
+        scan_id: Option<String>,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        let by: UsageBy = by.parse()?;
+        let scan_id = scan_id.map(|scan| Scan::id_by_ref(&db, &scan)).transpose()?;
+
+        Reports::report_usage(&db, root_id, scan_id, by)?;
+        Ok(())
+    }
+
+    /// Handler for `report retention`
+    fn handle_report_retention(
+        db_path: Option<PathBuf>,
+        root_id: u32,
+        older_than: u32,
+        under: Vec<String>,
+        by: String,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        let basis: AgeBasis = by.parse()?;
+
+        Reports::report_retention(&db, root_id, older_than, &under, basis)?;
+        Ok(())
+    }
+
+    /// Handler for `report unverified`
+    fn handle_report_unverified(
+        db_path: Option<PathBuf>,
+        root_id: Option<u32>,
+        older_than: u32,
+        limit: Option<u32>,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+
+        Reports::report_unverified(&db, root_id, older_than, limit)?;
+        Ok(())
+    }
+
+    /// Handler for `report bitrot`
+    fn handle_report_bitrot(db_path: Option<PathBuf>, root_id: Option<u32>) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+
+        Reports::report_bitrot(&db, root_id)?;
+        Ok(())
+    }
+
+    /// Handler for `report audit`
+    fn handle_report_audit(db_path: Option<PathBuf>, operation: Option<String>, limit: Option<u32>) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+
+        let entries = Reports::audit(&db, operation.as_deref(), limit)?;
+        Reports::print_audit(&entries)?;
+        Ok(())
+    }
+
+    /// Handler for `report changes`
+    #[allow(clippy::too_many_arguments)]
+    fn handle_report_changes(
+        db_path: Option<PathBuf>,
+        change_id: Option<u32>,
+        item_id: Option<u32>,
+        scan_id: Option<String>,
+        change_types: Vec<ChangeType>,
+        path_prefix: Option<String>,
+        modify_kind: Option<ModifyKind>,
+        format: String,
+        paths_only: bool,
+        print0: bool,
+        files_only: bool,
+        dirs_only: bool,
+        depth: Option<u32>,
+    ) -> Result<(), FsPulseError> {
+        let db = Database::open_for_reading(db_path)?;
+        let scan = scan_id.map(|scan_id| Scan::find_by_ref(&db, &scan_id)).transpose()?;
+
+        let path_prefix = match (path_prefix, &scan) {
+            (Some(path_prefix), Some(scan)) => Root::get_by_id(&db, scan.root_id())?
+                .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", scan.root_id())))?
+                .relative_path_prefix(&path_prefix)?,
+            _ => None,
+        };
+        let selection = ChangeSelection { change_types, path_prefix, modify_kind };
+        let scan_id = scan.map(|scan| scan.id());
+
+        if paths_only {
+            let terminator = if print0 { b'\0' } else { b'\n' };
+            Reports::report_changed_paths(&db, change_id, item_id, scan_id, &selection, terminator)?;
+            return Ok(());
+        }
+
+        let format: ReportFormat = format.parse()?;
+        let tree_dirs = match (files_only, dirs_only, depth) {
+            (true, _, _) => TreeDirs::Hide,
+            (_, true, Some(depth)) => TreeDirs::RollUp(depth as usize),
+            (_, true, None) => TreeDirs::Only,
+            _ => TreeDirs::All,
+        };
+
+        if tree_dirs != TreeDirs::All && format != ReportFormat::Tree {
+            return Err(FsPulseError::Error("--files-only and --dirs-only are only valid with --format tree".to_string()));
+        }
+
+        let report = Reports::changes(&db, change_id, item_id, scan_id, &selection)?;
+        Reports::print_changes(&report, format, tree_dirs)?;
+        Ok(())
+    }
+
+    /// Change types as named on the command line, which clap has already checked
+    fn parse_change_types(names: &[String]) -> Vec<ChangeType> {
+        names.iter()
+            .map(|name| match name.as_str() {
+                "add" => ChangeType::Add,
+                "modify" => ChangeType::Modify,
+                "delete" => ChangeType::Delete,
+                "type-change" => ChangeType::TypeChange,
+                _ => ChangeType::Move,
+            })
+            .collect()
     }
 }
\ No newline at end of file
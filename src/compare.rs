@@ -0,0 +1,284 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::archive::ArchiveMember;
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::items::{Item, ItemType};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DifferenceKind {
+    OnlyInA,
+    OnlyInB,
+    TypeDiffers,
+    SizeDiffers,
+    ContentDiffers,
+    ModifiedDiffers,
+}
+
+impl fmt::Display for DifferenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            DifferenceKind::OnlyInA => "Only in A",
+            DifferenceKind::OnlyInB => "Only in B",
+            DifferenceKind::TypeDiffers => "Type differs",
+            DifferenceKind::SizeDiffers => "Size differs",
+            DifferenceKind::ContentDiffers => "Content differs",
+            DifferenceKind::ModifiedDiffers => "Modified time differs",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// A path, relative to both roots, whose item differs between them
+#[derive(Clone, Debug)]
+pub struct Difference {
+    pub path: String,
+    pub kind: DifferenceKind,
+    pub size_a: Option<i64>,
+    pub size_b: Option<i64>,
+}
+
+/// How the items of two roots compare, counted by path
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ComparisonTotals {
+    pub identical: i64,
+    pub only_in_a: i64,
+    pub only_in_b: i64,
+    pub differing: i64,
+    /// Files of the same size which couldn't be compared by content because at least one
+    /// of them has no hash
+    pub unhashed: i64,
+    /// Items present at both scans whose state at either isn't known, because they were
+    /// added again or changed type after both
+    pub unknown: i64,
+}
+
+pub struct Compare {
+    // No fields
+}
+
+impl Compare {
+    /// Compares the current items of two roots by their paths relative to each root,
+    /// calling `func` with each path that differs. Modification times are not compared,
+    /// since copies of the same content rarely share them
+    pub fn for_each_difference<F>(db: &Database, root_id_a: i64, root_id_b: i64, mut func: F) -> Result<ComparisonTotals, FsPulseError>
+    where
+        F: FnMut(&Difference) -> Result<(), FsPulseError>,
+    {
+        let mut stmt = db.conn.prepare(
+            "SELECT COALESCE(a.path, b.path) AS path, a.item_type, b.item_type, a.file_size, b.file_size, a.file_hash, b.file_hash, a.hash_algo, b.hash_algo
+                FROM (SELECT path, item_type, file_size, file_hash, hash_algo FROM items WHERE root_id = ?1 AND is_tombstone = 0 AND is_ignored = 0) a
+                FULL OUTER JOIN (SELECT path, item_type, file_size, file_hash, hash_algo FROM items WHERE root_id = ?2 AND is_tombstone = 0 AND is_ignored = 0) b
+                    ON a.path = b.path
+                ORDER BY path ASC"
+        )?;
+
+        let mut rows = stmt.query([root_id_a, root_id_b])?;
+        let mut totals = ComparisonTotals::default();
+
+        while let Some(row) = rows.next()? {
+            let path: String = row.get(0)?;
+            let type_a: Option<String> = row.get(1)?;
+            let type_b: Option<String> = row.get(2)?;
+            let size_a: Option<i64> = row.get(3)?;
+            let size_b: Option<i64> = row.get(4)?;
+            let hash_a: Option<String> = row.get(5)?;
+            let hash_b: Option<String> = row.get(6)?;
+            let algo_a: Option<String> = row.get(7)?;
+            let algo_b: Option<String> = row.get(8)?;
+
+            let kind = match (&type_a, &type_b) {
+                (Some(_), None) => Some(DifferenceKind::OnlyInA),
+                (None, Some(_)) => Some(DifferenceKind::OnlyInB),
+                (Some(a), Some(b)) if a != b => Some(DifferenceKind::TypeDiffers),
+                _ if size_a != size_b => Some(DifferenceKind::SizeDiffers),
+                // Hashes taken with different algorithms can't be compared
+                _ => match (&hash_a, &hash_b) {
+                    (Some(a), Some(b)) if algo_a == algo_b && a != b => Some(DifferenceKind::ContentDiffers),
+                    (Some(_), Some(_)) if algo_a == algo_b => None,
+                    // Only files are hashed
+                    _ if size_a.is_some() => {
+                        totals.unhashed += 1;
+                        None
+                    },
+                    _ => None,
+                },
+            };
+
+            match kind {
+                Some(DifferenceKind::OnlyInA) => totals.only_in_a += 1,
+                Some(DifferenceKind::OnlyInB) => totals.only_in_b += 1,
+                Some(_) => totals.differing += 1,
+                None => totals.identical += 1,
+            }
+
+            if let Some(kind) = kind {
+                func(&Difference { path, kind, size_a, size_b })?;
+            }
+        }
+
+        Ok(totals)
+    }
+
+    /// Compares the items present in a scan (A) with the members of an archive (B) by
+    /// their paths relative to the root, calling `func` with each path that differs.
+    /// Files are compared by size, then modification time to the second (the precision
+    /// tar keeps), then content if both have an md5 (members are hashed with md5). Items are
+    /// compared as they were last recorded, which is exactly the scan only if it's the
+    /// root's latest
+    pub fn for_each_archive_difference<F>(db: &Database, scan_id: i64, members: Vec<ArchiveMember>, mut func: F) -> Result<ComparisonTotals, FsPulseError>
+    where
+        F: FnMut(&Difference) -> Result<(), FsPulseError>,
+    {
+        let mut members: BTreeMap<String, ArchiveMember> = members.into_iter()
+            .map(|member| (member.path.clone(), member))
+            .collect();
+
+        // Items seen by the scan: those seen by it or a later scan, less those first added later
+        let mut stmt = db.conn.prepare(
+            "SELECT i.path, i.item_type, i.file_size, i.last_modified, CASE WHEN i.hash_algo = 'md5' THEN i.file_hash END
+                FROM items i
+                JOIN scans s ON s.id = ?1
+                WHERE i.root_id = s.root_id AND i.is_ignored = 0 AND i.last_scan_id >= ?1
+                    AND (SELECT MIN(scan_id) FROM changes WHERE item_id = i.id AND change_type = 'A') <= ?1
+                ORDER BY i.path ASC"
+        )?;
+
+        let mut rows = stmt.query([scan_id])?;
+        let mut totals = ComparisonTotals::default();
+        let mut differences = Vec::new();
+
+        while let Some(row) = rows.next()? {
+            let path: String = row.get(0)?;
+            let item_type: String = row.get(1)?;
+            let size_a: Option<i64> = row.get(2)?;
+            let modified_a: Option<i64> = row.get(3)?;
+            let hash_a: Option<String> = row.get(4)?;
+
+            let Some(member) = members.remove(&path) else {
+                totals.only_in_a += 1;
+                differences.push(Difference { path, kind: DifferenceKind::OnlyInA, size_a, size_b: None });
+                continue;
+            };
+
+            let is_file = item_type == ItemType::File.as_str();
+            let kind = if item_type != member.item_type.as_str() {
+                Some(DifferenceKind::TypeDiffers)
+            } else if !is_file {
+                None
+            } else if size_a != member.size {
+                Some(DifferenceKind::SizeDiffers)
+            } else if modified_a.is_some() && member.modified.is_some() && modified_a != member.modified {
+                Some(DifferenceKind::ModifiedDiffers)
+            } else {
+                match (&hash_a, &member.hash) {
+                    (Some(a), Some(b)) if a != b => Some(DifferenceKind::ContentDiffers),
+                    (Some(_), Some(_)) => None,
+                    _ => {
+                        totals.unhashed += 1;
+                        None
+                    },
+                }
+            };
+
+            match kind {
+                Some(kind) => {
+                    totals.differing += 1;
+                    differences.push(Difference { path, kind, size_a, size_b: member.size });
+                },
+                None => totals.identical += 1,
+            }
+        }
+
+        for (path, member) in members {
+            totals.only_in_b += 1;
+            differences.push(Difference { path, kind: DifferenceKind::OnlyInB, size_a: None, size_b: member.size });
+        }
+
+        differences.sort_by(|a, b| a.path.cmp(&b.path));
+        for difference in &differences {
+            func(difference)?;
+        }
+
+        Ok(totals)
+    }
+
+    /// Compares the items present at two scans of the same root (A and B) by path, calling
+    /// `func` with each path that differs. Each scan's items are rebuilt as they were when it
+    /// ran, so neither need be the root's latest. Files are compared by size, then
+    /// modification time, then content if both have a hash, as a scan would compare them
+    pub fn for_each_scan_difference<F>(db: &Database, root_id: i64, scan_id_a: i64, scan_id_b: i64, mut func: F) -> Result<ComparisonTotals, FsPulseError>
+    where
+        F: FnMut(&Difference) -> Result<(), FsPulseError>,
+    {
+        let mut items_b: BTreeMap<String, Item> = Item::items_as_of(db, root_id, scan_id_b)?
+            .into_iter()
+            .map(|item| (item.path().to_owned(), item))
+            .collect();
+
+        let mut totals = ComparisonTotals::default();
+        let mut differences = Vec::new();
+
+        for item_a in Item::items_as_of(db, root_id, scan_id_a)? {
+            let path = item_a.path().to_owned();
+            let (size_a, modified_a, hash_a) = (item_a.file_size(), item_a.last_modified(), item_a.file_hash());
+
+            let Some(item_b) = items_b.remove(&path) else {
+                totals.only_in_a += 1;
+                differences.push(Difference { path, kind: DifferenceKind::OnlyInA, size_a, size_b: None });
+                continue;
+            };
+            let size_b = item_b.file_size();
+
+            // An item replaced after a scan is known to have been present, but not what it was
+            let (type_a, type_b) = (item_a.item_type(), item_b.item_type());
+            if type_a == "?" && type_b == "?" {
+                totals.unknown += 1;
+                continue;
+            }
+
+            let kind = if type_a != type_b {
+                Some(DifferenceKind::TypeDiffers)
+            } else if type_a == ItemType::Symlink.as_str() && item_a.symlink_target() != item_b.symlink_target() {
+                Some(DifferenceKind::ContentDiffers)
+            } else if type_a != ItemType::File.as_str() {
+                None
+            } else if size_a != size_b {
+                Some(DifferenceKind::SizeDiffers)
+            } else if modified_a != item_b.last_modified() {
+                Some(DifferenceKind::ModifiedDiffers)
+            } else {
+                let same_algo = item_a.hash_algo() == item_b.hash_algo();
+                match (hash_a, item_b.file_hash()) {
+                    (Some(a), Some(b)) if same_algo && a != b => Some(DifferenceKind::ContentDiffers),
+                    (Some(_), Some(_)) if same_algo => None,
+                    _ => {
+                        totals.unhashed += 1;
+                        None
+                    },
+                }
+            };
+
+            match kind {
+                Some(kind) => {
+                    totals.differing += 1;
+                    differences.push(Difference { path, kind, size_a, size_b });
+                },
+                None => totals.identical += 1,
+            }
+        }
+
+        for (path, item_b) in items_b {
+            totals.only_in_b += 1;
+            differences.push(Difference { path, kind: DifferenceKind::OnlyInB, size_a: None, size_b: item_b.file_size() });
+        }
+
+        differences.sort_by(|a, b| a.path.cmp(&b.path));
+        for difference in &differences {
+            func(difference)?;
+        }
+
+        Ok(totals)
+    }
+}
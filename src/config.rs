@@ -0,0 +1,186 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde::{Deserialize, Deserializer};
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::excludes::ExcludeRules;
+use crate::hash::HashAlgo;
+use crate::notify::Severity;
+
+/// The folder holding the config file, within the platform config directory (e.g. `~/.config`)
+const CONFIG_DIR_NAME: &str = "fspulse";
+
+const CONFIG_FILENAME: &str = "config.toml";
+
+/// Defaults read from the config file. Command line options take precedence over them
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The folder holding the database, used when neither --db-path, $FSPULSE_DB nor a
+    /// project database says otherwise
+    pub db_path: Option<PathBuf>,
+    /// The algorithm roots are hashed with, unless --hash-algo is given
+    #[serde(deserialize_with = "deserialize_hash_algo")]
+    pub hash_algo: Option<HashAlgo>,
+    pub exclude: Vec<String>,
+    pub include: Vec<String>,
+    pub respect_gitignore: bool,
+    /// Options for the roots at particular paths, given as `[[root]]` tables
+    #[serde(rename = "root")]
+    pub roots: Vec<RootConfig>,
+    /// The notifiers run for roots which neither they nor their groups route elsewhere
+    pub notify: Vec<String>,
+    /// Commands run when a scan finds changes, given as `[[notifier]]` tables
+    #[serde(rename = "notifier")]
+    pub notifiers: Vec<NotifierConfig>,
+    /// Notification routing for root groups, given as `[[group]]` tables
+    #[serde(rename = "group")]
+    pub groups: Vec<GroupConfig>,
+}
+
+/// Options for one root. Its algorithm replaces the default, while its exclude and include
+/// globs are added to the defaults
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RootConfig {
+    pub path: PathBuf,
+    #[serde(deserialize_with = "deserialize_hash_algo")]
+    pub hash_algo: Option<HashAlgo>,
+    pub exclude: Vec<String>,
+    pub include: Vec<String>,
+    pub respect_gitignore: Option<bool>,
+    /// The notifiers run for the root, in place of those of its groups and the default
+    pub notify: Option<Vec<String>>,
+}
+
+/// A command run when a scan finds changes at least as serious as `min_severity`
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifierConfig {
+    pub name: String,
+    pub command: String,
+    pub min_severity: Severity,
+}
+
+/// The notifiers run for the roots in a group
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GroupConfig {
+    pub name: String,
+    pub notify: Vec<String>,
+}
+
+fn deserialize_hash_algo<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<HashAlgo>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|algo| algo.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+impl Config {
+    /// Reads the config file at `path` or, without one, the config file of the project the
+    /// current directory is in, then the default config file, if there is one. A file named
+    /// with --config must exist
+    pub fn load(path: Option<&Path>) -> Result<Self, FsPulseError> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match Self::project_path()?.or_else(Self::default_path).filter(|path| path.is_file()) {
+                Some(path) => path,
+                None => return Ok(Config::default()),
+            },
+        };
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|error| FsPulseError::Error(format!("Can't read config file '{}': {}", path.display(), error)))?;
+        let mut config: Config = toml::from_str(&contents)
+            .map_err(|error| FsPulseError::Error(format!("Invalid config file '{}': {}", path.display(), error)))?;
+        info!("Using config file: {}", path.display());
+
+        // Root paths are matched against the canonical paths roots are registered with
+        for root in &mut config.roots {
+            if root.path.as_os_str().is_empty() {
+                return Err(FsPulseError::Error(format!("A [[root]] in config file '{}' has no path", path.display())));
+            }
+            if let Ok(canonical) = fs::canonicalize(&root.path) {
+                root.path = canonical;
+            }
+        }
+
+        for notifier in &config.notifiers {
+            if notifier.name.is_empty() || notifier.command.is_empty() {
+                return Err(FsPulseError::Error(format!(
+                    "A [[notifier]] in config file '{}' needs both a name and a command", path.display())));
+            }
+        }
+
+        let routes = config.notify.iter()
+            .chain(config.roots.iter().flat_map(|root| root.notify.iter().flatten()))
+            .chain(config.groups.iter().flat_map(|group| &group.notify));
+        for name in routes {
+            if !config.notifiers.iter().any(|notifier| &notifier.name == name) {
+                return Err(FsPulseError::Error(format!("Config file '{}' names notifier '{}', which isn't defined", path.display(), name)));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// The config file in the nearest `.fspulse` folder, found the way the project database
+    /// is, if it has one
+    fn project_path() -> Result<Option<PathBuf>, FsPulseError> {
+        Ok(Database::find_project_db_folder(&env::current_dir()?)
+            .map(|folder| folder.join(CONFIG_FILENAME))
+            .filter(|path| path.is_file()))
+    }
+
+    /// Where the config file is looked for when --config isn't given
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR_NAME).join(CONFIG_FILENAME))
+    }
+
+    fn root(&self, root_path: &str) -> Option<&RootConfig> {
+        self.roots.iter().find(|root| root.path == Path::new(root_path))
+    }
+
+    /// The algorithm the config hashes the root at the path with, if it names one
+    pub fn hash_algo_for(&self, root_path: &str) -> Option<HashAlgo> {
+        self.root(root_path).and_then(|root| root.hash_algo).or(self.hash_algo)
+    }
+
+    /// The notifiers routed to for the root at the path, which is in the named groups. A
+    /// root's own notifiers replace those of its groups, which replace the default ones
+    pub fn notifiers_for(&self, root_path: &str, groups: &[String]) -> Vec<&NotifierConfig> {
+        let names: Vec<&String> = match self.root(root_path).and_then(|root| root.notify.as_ref()) {
+            Some(names) => names.iter().collect(),
+            None => {
+                let group_names: Vec<&String> = self.groups.iter()
+                    .filter(|group| groups.contains(&group.name))
+                    .flat_map(|group| &group.notify)
+                    .collect();
+                if group_names.is_empty() { self.notify.iter().collect() } else { group_names }
+            }
+        };
+
+        self.notifiers.iter()
+            .filter(|notifier| names.contains(&&notifier.name))
+            .collect()
+    }
+
+    /// The rules a new scan of the root at the path excludes paths by. Rules given on the
+    /// command line replace those in the config
+    pub fn rules_for(&self, root_path: &str, command_line: &ExcludeRules) -> ExcludeRules {
+        if !command_line.is_empty() {
+            return command_line.clone();
+        }
+
+        let root = self.root(root_path);
+        let exclude = self.exclude.iter().chain(root.into_iter().flat_map(|root| &root.exclude)).cloned().collect();
+        let include = self.include.iter().chain(root.into_iter().flat_map(|root| &root.include)).cloned().collect();
+        let respect_gitignore = root.and_then(|root| root.respect_gitignore).unwrap_or(self.respect_gitignore);
+
+        ExcludeRules::new(exclude, include, respect_gitignore)
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use rusqlite::OptionalExtension;
+
+use crate::capabilities::PROBE_DIR_PREFIX;
+use crate::database::{Database, PROJECT_DIR_NAME};
+use crate::error::FsPulseError;
+use crate::roots::Root;
+use crate::utils::Utils;
+
+/// A parent directory, such as /home or a folder of datasets, whose immediate subdirectories
+/// are each registered and scanned as a root of their own. Syncing the container registers
+/// roots for new subdirectories and archives those whose subdirectory is gone
+pub struct Container {
+    id: i64,
+    path: String,
+}
+
+/// What syncing a container changed
+pub enum ContainerChange {
+    /// A root was registered for a new subdirectory, or an existing root was linked to it
+    Added(Root),
+    /// The root's subdirectory is gone, so it's no longer scanned. Its history is kept
+    Archived(Root),
+    /// The subdirectory of an archived root is back
+    Restored(Root),
+}
+
+impl Container {
+    /// Adds the directory as a container. Returns the container and whether it's new
+    pub fn add(db: &Database, path_arg: &str) -> Result<(Self, bool), FsPulseError> {
+        let path = Root::validate_and_canonicalize_path(path_arg)?.to_string_lossy().into_owned();
+
+        if let Some(container) = Self::get_by_path(db, &path)? {
+            return Ok((container, false));
+        }
+
+        let id = db.conn.query_row(
+            "INSERT INTO containers (path) VALUES (?) RETURNING id",
+            [&path],
+            |row| row.get(0),
+        )?;
+
+        Ok((Container { id, path }, true))
+    }
+
+    /// Stops tracking the container. Its roots and their history are kept. Returns the
+    /// number of roots it held
+    pub fn remove(self, db: &mut Database) -> Result<usize, FsPulseError> {
+        let tx = db.conn.transaction()?;
+        let unlinked = tx.execute("DELETE FROM container_roots WHERE container_id = ?", [self.id])?;
+        tx.execute("DELETE FROM containers WHERE id = ?", [self.id])?;
+        tx.commit()?;
+
+        Ok(unlinked)
+    }
+
+    pub fn get_by_path(db: &Database, path: &str) -> Result<Option<Self>, FsPulseError> {
+        let container = db.conn.query_row(
+            "SELECT id, path FROM containers WHERE path = ?",
+            [path],
+            |row| Ok(Container { id: row.get(0)?, path: row.get(1)? }),
+        ).optional()?;
+
+        Ok(container)
+    }
+
+    /// Finds a container given on the command line as either an id or a path
+    pub fn find_by_id_or_path(db: &Database, arg: &str) -> Result<Self, FsPulseError> {
+        let by_id = match arg.parse::<i64>() {
+            Ok(id) => db.conn.query_row(
+                "SELECT id, path FROM containers WHERE id = ?",
+                [id],
+                |row| Ok(Container { id: row.get(0)?, path: row.get(1)? }),
+            ).optional()?,
+            Err(_) => None,
+        };
+
+        let by_path = match by_id {
+            Some(container) => Some(container),
+            None => match Root::validate_and_canonicalize_path(arg) {
+                Ok(path) => Self::get_by_path(db, &path.to_string_lossy())?,
+                Err(_) => Self::get_by_path(db, arg)?,
+            },
+        };
+
+        by_path.ok_or_else(|| FsPulseError::Error(format!("Container '{}' not found", arg)))
+    }
+
+    pub fn all(db: &Database) -> Result<Vec<Self>, FsPulseError> {
+        let mut stmt = db.conn.prepare("SELECT id, path FROM containers ORDER BY id ASC")?;
+        let rows = stmt.query_map([], |row| Ok(Container { id: row.get(0)?, path: row.get(1)? }))?;
+
+        let mut containers = Vec::new();
+        for row in rows {
+            containers.push(row?);
+        }
+
+        Ok(containers)
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The container's roots, in path order, with when each was archived
+    pub fn roots(&self, db: &Database) -> Result<Vec<(Root, Option<i64>)>, FsPulseError> {
+        let mut stmt = db.conn.prepare(
+            "SELECT cr.root_id, cr.archived_at FROM container_roots cr
+                JOIN roots r ON r.id = cr.root_id
+                WHERE cr.container_id = ?
+                ORDER BY r.path ASC"
+        )?;
+        let rows = stmt.query_map([self.id], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<i64>>(1)?)))?;
+
+        let mut roots = Vec::new();
+        for row in rows {
+            let (root_id, archived_at) = row?;
+            if let Some(root) = Root::get_by_id(db, root_id)? {
+                roots.push((root, archived_at));
+            }
+        }
+
+        Ok(roots)
+    }
+
+    /// Registers a root for each subdirectory without one, and archives the roots whose
+    /// subdirectory is gone. Symlinks, per-project database folders and probe folders
+    /// aren't taken to be subdirectories
+    pub fn sync(&self, db: &Database) -> Result<Vec<ContainerChange>, FsPulseError> {
+        let mut changes = Vec::new();
+        let mut present = HashSet::new();
+
+        // Subdirectories are registered in name order
+        let mut entries = fs::read_dir(&self.path)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let file_name = entry.file_name();
+            if !entry.file_type()?.is_dir()
+                || file_name == PROJECT_DIR_NAME
+                || file_name.to_string_lossy().starts_with(PROBE_DIR_PREFIX)
+            {
+                continue;
+            }
+
+            let dir_path = entry.path();
+            let root = match Root::get_by_directory(db, &dir_path)? {
+                Some(root) => root,
+                None => Root::create(db, &dir_path.to_string_lossy())?,
+            };
+            present.insert(root.id());
+
+            let linked = db.conn.execute(
+                "INSERT OR IGNORE INTO container_roots (container_id, root_id) VALUES (?, ?)",
+                (self.id, root.id()),
+            )?;
+            if linked > 0 {
+                changes.push(ContainerChange::Added(root));
+            }
+        }
+
+        let now = Utils::system_time_to_db_time(SystemTime::now());
+        for (root, archived_at) in self.roots(db)? {
+            let exists = present.contains(&root.id()) || Path::new(root.path()).is_dir();
+
+            match (exists, archived_at) {
+                (false, None) => {
+                    db.conn.execute("UPDATE container_roots SET archived_at = ? WHERE root_id = ?", (now, root.id()))?;
+                    changes.push(ContainerChange::Archived(root));
+                },
+                (true, Some(_)) => {
+                    db.conn.execute("UPDATE container_roots SET archived_at = NULL WHERE root_id = ?", [root.id()])?;
+                    changes.push(ContainerChange::Restored(root));
+                },
+                _ => {},
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Syncs every container, printing what changed
+    pub fn sync_all(db: &Database) -> Result<(), FsPulseError> {
+        for container in Self::all(db)? {
+            for change in container.sync(db)? {
+                change.print(&container);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The ids of roots whose subdirectory was found to be gone
+    pub fn archived_root_ids(db: &Database) -> Result<HashSet<i64>, FsPulseError> {
+        let mut stmt = db.conn.prepare("SELECT root_id FROM container_roots WHERE archived_at IS NOT NULL")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+
+        let mut root_ids = HashSet::new();
+        for row in rows {
+            root_ids.insert(row?);
+        }
+
+        Ok(root_ids)
+    }
+}
+
+impl ContainerChange {
+    pub fn print(&self, container: &Container) {
+        match self {
+            ContainerChange::Added(root) => println!(
+                "Added '{}' (Root Id {}) from container '{}'", root.path(), root.id(), container.path()),
+            ContainerChange::Archived(root) => println!(
+                "Archived '{}' (Root Id {}): it's gone from container '{}'", root.path(), root.id(), container.path()),
+            ContainerChange::Restored(root) => println!(
+                "Restored '{}' (Root Id {}): it's back in container '{}'", root.path(), root.id(), container.path()),
+        }
+    }
+}
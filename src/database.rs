@@ -1,24 +1,52 @@
 use log::info;
-use rusqlite::{Connection, OptionalExtension, Result};
-use std::path::PathBuf;
+use rusqlite::{Connection, DatabaseName, OptionalExtension, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 use crate::error::FsPulseError;
-use crate::schema::CREATE_SCHEMA_SQL;
+use crate::schema::{CREATE_SCHEMA_SQL, UPGRADE_SCHEMA_SQL};
+use crate::timings::Timings;
 
 const DB_FILENAME: &str = "fspulse.db";
-const SCHEMA_VERSION: &str = "2";
+const DB_ENV_VAR: &str = "FSPULSE_DB";
+const DB_PASSPHRASE_ENV_VAR: &str = "FSPULSE_DB_PASSPHRASE";
+const DATA_DIR_NAME: &str = "fspulse";
+pub const PROJECT_DIR_NAME: &str = ".fspulse";
+pub const SCHEMA_VERSION: &str = "34";
+const BUSY_TIMEOUT: Duration = Duration::from_secs(60);
+/// Number of writes `batched` commits at once when it isn't told otherwise
+pub const DEFAULT_WRITE_BATCH_SIZE: usize = 1000;
+
+/// The passphrase given on the command line, which takes precedence over $FSPULSE_DB_PASSPHRASE
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+
+/// The database folder named by the config file
+static CONFIGURED_FOLDER: Mutex<Option<PathBuf>> = Mutex::new(None);
 
 pub struct Database {
     pub conn: Connection,
-    #[allow(dead_code)]
     path: String,
+    upgraded_from: Option<String>,
 }
 
 impl Database {
     pub fn new(db_path: Option<PathBuf>) -> Result<Self, FsPulseError>
     {
-        let mut db_path = db_path
-            .or_else(dirs::home_dir)
-            .ok_or_else(|| FsPulseError::Error("Could not determine home directory".to_string()))?;
+        Self::open(db_path, false)
+    }
+
+    /// Opens the database for commands that only read it. A database with an older schema
+    /// is upgraded in place like any other, unless it can't be written, such as a read-only
+    /// backup. That one is read through an upgraded copy in memory, and nothing written to
+    /// the copy is kept
+    pub fn open_for_reading(db_path: Option<PathBuf>) -> Result<Self, FsPulseError> {
+        Self::open(db_path, true)
+    }
+
+    fn open(db_path: Option<PathBuf>, copy_if_read_only: bool) -> Result<Self, FsPulseError> {
+        let mut db_path = Self::resolve_db_folder(db_path)?;
 
         if !db_path.is_dir() {
             return Err(FsPulseError::Error(format!(
@@ -31,23 +59,273 @@ impl Database {
 
         // Attempt to open the database
         info!("Opening database: {}", db_path.display());
-        let conn = Connection::open(&db_path).map_err(FsPulseError::Database)?;
+        let _timer = Timings::start("open database");
+        let conn = Self::open_connection(&db_path)?;
+        let stored_version = Self::stored_schema_version(&conn)?;
+
+        let conn = match stored_version.as_deref() {
+            Some(version) if version != SCHEMA_VERSION && copy_if_read_only && conn.is_readonly(DatabaseName::Main)? => {
+                if Self::is_newer_version(version) {
+                    return Err(Self::no_upgrade_error(version));
+                }
+                // SQLCipher builds don't make the copy, since copying an encrypted database
+                // into memory isn't known to work, so it has to be upgraded where it is
+                if cfg!(feature = "sqlcipher") {
+                    return Err(FsPulseError::Error(format!(
+                        "The database has schema version {} and can't be written, so it can't be upgraded. Make it writable and run `fspulse db upgrade`",
+                        version)));
+                }
+                eprintln!(
+                    "Note: The database has schema version {} and can't be written, so it's read from an upgraded copy",
+                    version
+                );
+                Self::copy_to_memory(&conn)?
+            },
+            _ => conn,
+        };
+        Timings::trace_sql(&conn);
+
+        let mut db = Self { conn, path: db_path.to_string_lossy().into_owned(), upgraded_from: None };
 
-        let db = Self { conn, path: db_path.to_string_lossy().into_owned() };
-        
         // Ensure schema is current
-        db.ensure_schema()?;
+        db.ensure_schema(stored_version)?;
 
         Ok(db)
     }
 
-    #[allow(dead_code)]
+    /// Opens an additional connection to the same database, for use by another thread.
+    /// Connections wait on each other's locks rather than failing immediately
+    pub fn reopen(&self) -> Result<Self, FsPulseError> {
+        let conn = Self::open_connection(Path::new(&self.path))?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        Timings::trace_sql(&conn);
+
+        Ok(Self { conn, path: self.path.clone(), upgraded_from: None })
+    }
+
+    /// Sets the passphrase used to open an encrypted database
+    pub fn set_passphrase(passphrase: Option<String>) {
+        *PASSPHRASE.lock().unwrap() = passphrase;
+    }
+
+    /// Sets the database folder named by the config file, used when no other setting says
+    /// where the database is
+    pub fn set_configured_folder(folder: Option<PathBuf>) {
+        *CONFIGURED_FOLDER.lock().unwrap() = folder;
+    }
+
+    /// Reads a passphrase from the first line of a key file
+    pub fn read_key_file(path: &Path) -> Result<String, FsPulseError> {
+        let contents = fs::read_to_string(path)?;
+        let passphrase = contents.lines().next().unwrap_or_default();
+
+        if passphrase.is_empty() {
+            return Err(FsPulseError::Error(format!("Key file '{}' holds no passphrase", path.display())));
+        }
+
+        Ok(passphrase.to_string())
+    }
+
+    fn passphrase() -> Option<String> {
+        PASSPHRASE.lock().unwrap().clone()
+            .or_else(|| env::var(DB_PASSPHRASE_ENV_VAR).ok().filter(|p| !p.is_empty()))
+    }
+
+    /// Opens a connection, keying it with the passphrase if there is one. SQLCipher only
+    /// finds out whether the key is right when the database is first read, so it's read here
+    fn open_connection(path: &Path) -> Result<Connection, FsPulseError> {
+        let conn = Connection::open(path)?;
+
+        if let Some(passphrase) = Self::passphrase() {
+            if !cfg!(feature = "sqlcipher") {
+                return Err(FsPulseError::Error(
+                    "This build of fspulse can't open encrypted databases. Build it with `--features sqlcipher`".to_string()));
+            }
+            conn.pragma_update(None, "key", &passphrase)?;
+        }
+
+        match conn.query_row("SELECT count(*) FROM sqlite_master", [], |_row| Ok(())) {
+            Err(rusqlite::Error::SqliteFailure(error, _)) if error.code == rusqlite::ErrorCode::NotADatabase => {
+                Err(FsPulseError::Error(format!(
+                    "Couldn't read database '{}'. If it's encrypted, the passphrase is missing or wrong",
+                    path.display())))
+            },
+            result => result.map(|_| conn).map_err(FsPulseError::Database),
+        }
+    }
+
+    /// Writes the database out encrypted with `new_passphrase`, or unencrypted if there's
+    /// none, and replaces it. The database must not be in use by another process
+    pub fn rekey(self, new_passphrase: Option<&str>) -> Result<(), FsPulseError> {
+        if !cfg!(feature = "sqlcipher") {
+            return Err(FsPulseError::Error(
+                "This build of fspulse can't encrypt databases. Build it with `--features sqlcipher`".to_string()));
+        }
+
+        let rekeyed_path = format!("{}.rekey", self.path);
+        if Path::new(&rekeyed_path).exists() {
+            fs::remove_file(&rekeyed_path)?;
+        }
+
+        // Fold the write-ahead log into the database so that the export is complete
+        self.conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_row| Ok(()))?;
+        self.conn.execute("ATTACH DATABASE ?1 AS rekeyed KEY ?2", (&rekeyed_path, new_passphrase.unwrap_or("")))?;
+        self.conn.query_row("SELECT sqlcipher_export('rekeyed')", [], |_row| Ok(()))?;
+        self.conn.execute("DETACH DATABASE rekeyed", [])?;
+
+        let Self { conn, path, .. } = self;
+        conn.close().map_err(|(_conn, err)| FsPulseError::Database(err))?;
+
+        for suffix in ["-wal", "-shm"] {
+            let side_path = format!("{}{}", path, suffix);
+            if Path::new(&side_path).exists() {
+                fs::remove_file(&side_path)?;
+            }
+        }
+        fs::rename(&rekeyed_path, &path)?;
+
+        Ok(())
+    }
+
+    /// Prepares the database for concurrent writers. Write-ahead logging lets readers
+    /// proceed while another connection writes, and the setting persists in the database file
+    pub fn enable_concurrency(&self) -> Result<(), FsPulseError> {
+        self.conn.busy_timeout(BUSY_TIMEOUT)?;
+        self.conn.query_row("PRAGMA journal_mode = WAL", [], |_row| Ok(()))?;
+        Ok(())
+    }
+
+    /// Runs `write`, committing what it writes once for every `batch_size` writes it counts
+    /// with `WriteBatch::wrote` rather than once for each. SQLite waits for each commit to
+    /// reach the disk, so committing many small writes one at a time is slow. Writes which
+    /// must be all or nothing go in a savepoint, which nests in the batch's transaction.
+    /// Each transaction is begun immediately, taking the write lock before anything is
+    /// read, so a concurrent writer is waited for rather than invalidating what was read.
+    /// What `write` wrote before failing is committed, as it would have been one write at a time
+    pub fn batched<T>(&mut self, batch_size: usize, write: impl FnOnce(&mut Self, &mut WriteBatch) -> Result<T, FsPulseError>) -> Result<T, FsPulseError> {
+        let mut batch = WriteBatch { size: batch_size.max(1), pending: 0 };
+        self.conn.execute_batch("BEGIN IMMEDIATE")?;
+
+        let result = write(self, &mut batch);
+
+        // A batch which failed to commit may have left no transaction open
+        if !self.conn.is_autocommit() {
+            if let Err(error) = self.conn.execute_batch("COMMIT") {
+                let _ = self.conn.execute_batch("ROLLBACK");
+                return result.and(Err(error.into()));
+            }
+        }
+
+        result
+    }
+
+    /// Determines the folder holding the database. In order of precedence:
+    /// 1. The folder passed on the command line
+    /// 2. The folder named by the FSPULSE_DB environment variable
+    /// 3. A `.fspulse` folder in the current directory or any of its ancestors
+    /// 4. The folder named by the config file's `db_path`
+    /// 5. The user's home directory, if it already contains a database (legacy location)
+    /// 6. The platform data directory (e.g. `~/.local/share/fspulse`), created if needed
+    fn resolve_db_folder(db_path: Option<PathBuf>) -> Result<PathBuf, FsPulseError> {
+        if let Some(db_path) = db_path {
+            info!("Using database folder from command line: {}", db_path.display());
+            return Ok(db_path);
+        }
+
+        if let Some(env_path) = env::var_os(DB_ENV_VAR).filter(|p| !p.is_empty()) {
+            let env_path = PathBuf::from(env_path);
+            info!("Using database folder from {}: {}", DB_ENV_VAR, env_path.display());
+            return Ok(env_path);
+        }
+
+        if let Some(project_path) = Self::find_project_db_folder(&env::current_dir()?) {
+            info!("Using project database folder: {}", project_path.display());
+            return Ok(project_path);
+        }
+
+        if let Some(config_path) = CONFIGURED_FOLDER.lock().unwrap().clone() {
+            info!("Using database folder from the config file: {}", config_path.display());
+            return Ok(config_path);
+        }
+
+        if let Some(home_path) = dirs::home_dir().filter(|p| p.join(DB_FILENAME).is_file()) {
+            info!("Using database in home directory: {}", home_path.display());
+            return Ok(home_path);
+        }
+
+        let data_path = dirs::data_dir()
+            .ok_or_else(|| FsPulseError::Error("Could not determine a location for the database".to_string()))?
+            .join(DATA_DIR_NAME);
+        fs::create_dir_all(&data_path)?;
+        info!("Using database folder in data directory: {}", data_path.display());
+
+        Ok(data_path)
+    }
+
+    /// Walks up from `start` looking for a `.fspulse` folder, the way git looks for `.git`
+    pub fn find_project_db_folder(start: &Path) -> Option<PathBuf> {
+        start.ancestors()
+            .map(|dir| dir.join(PROJECT_DIR_NAME))
+            .find(|candidate| candidate.is_dir())
+    }
+
     pub fn path(&self) -> &str {
         &self.path
     }
 
-    fn ensure_schema(&self) -> Result<(), FsPulseError> {
-        let table_exists: bool = self.conn
+    /// The schema version the database had before opening it upgraded it, if it did
+    pub fn upgraded_from(&self) -> Option<&str> {
+        self.upgraded_from.as_deref()
+    }
+
+    /// The bytes the database takes up on disk, including its write-ahead log
+    pub fn size(&self) -> u64 {
+        let wal_path = format!("{}-wal", self.path);
+
+        [self.path.as_str(), wal_path.as_str()].iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    /// The bytes available on the device holding the database, where the platform can tell
+    #[cfg(unix)]
+    #[allow(clippy::unnecessary_cast)] // The field types differ between platforms
+    pub fn free_space(&self) -> Option<u64> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let db_folder = Path::new(&self.path).parent()?;
+        let db_folder = CString::new(db_folder.as_os_str().as_bytes()).ok()?;
+
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        if unsafe { libc::statvfs(db_folder.as_ptr(), &mut stat) } != 0 {
+            return None;
+        }
+
+        Some((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+    }
+
+    #[cfg(not(unix))]
+    pub fn free_space(&self) -> Option<u64> {
+        None
+    }
+
+    /// If this is a project database (stored in a `.fspulse` folder), returns the
+    /// directory containing that folder
+    pub fn project_root(&self) -> Option<&Path> {
+        let db_folder = Path::new(&self.path).parent()?;
+
+        if db_folder.file_name()? == PROJECT_DIR_NAME {
+            db_folder.parent()
+        } else {
+            None
+        }
+    }
+
+    /// The schema version recorded in the database, or None if it has no schema yet
+    fn stored_schema_version(conn: &Connection) -> Result<Option<String>, FsPulseError> {
+        let table_exists: bool = conn
             .query_row(
                 "SELECT count(*) FROM sqlite_master WHERE type='table' AND name='meta'",
                 [],
@@ -57,28 +335,95 @@ impl Database {
             .unwrap_or(false);
 
         if !table_exists {
-            return self.create_schema();
+            return Ok(None);
         }
 
-        // Get the stored schema version
-        let stored_version: Option<String> = self.conn
-            .query_row(
-                "SELECT value FROM meta WHERE key = 'schema_version'",
-                [],
-                |row| row.get(0),
-            )
-            .optional()?;
+        conn.query_row("SELECT value FROM meta WHERE key = 'schema_version'", [], |row| row.get(0))
+            .optional()?
+            .map(Some)
+            .ok_or_else(|| FsPulseError::Error("Schema version missing".to_string()))
+    }
 
-            match stored_version.as_deref() {
-                Some(SCHEMA_VERSION) => Ok(()), // Schema is up to date
-                Some(_) => Err(FsPulseError::Error("Schema version mismatch".to_string())),
-                None => Err(FsPulseError::Error("Schema version missing".to_string())),
-            }
+    /// Copies the database into a new in-memory database, which lasts as long as the
+    /// returned connection. The database itself is only read
+    fn copy_to_memory(conn: &Connection) -> Result<Connection, FsPulseError> {
+        let name = format!("file:fspulse-copy-{}?mode=memory&cache=shared", std::process::id());
+        let copy = Connection::open(&name)?;
+        conn.execute("VACUUM INTO ?", [&name])?;
+
+        Ok(copy)
+    }
+
+    fn ensure_schema(&mut self, stored_version: Option<String>) -> Result<(), FsPulseError> {
+        match stored_version {
+            None => self.create_schema(),
+            Some(version) if version == SCHEMA_VERSION => Ok(()), // Schema is up to date
+            Some(version) => {
+                self.upgrade_schema(&version)?;
+                self.upgraded_from = Some(version);
+                Ok(())
+            },
+        }
+    }
+
+    /// Applies each upgrade step in turn until the schema reaches the current version.
+    /// Every step runs in its own transaction and records the version it produces
+    fn upgrade_schema(&self, stored_version: &str) -> Result<(), FsPulseError> {
+        let mut version = stored_version;
+
+        while version != SCHEMA_VERSION {
+            let (next_version, upgrade_sql) = UPGRADE_SCHEMA_SQL
+                .iter()
+                .find(|(from, _, _)| *from == version)
+                .map(|(_, to, sql)| (*to, *sql))
+                .ok_or_else(|| Self::no_upgrade_error(version))?;
+
+            info!("Upgrading database schema from version {} to {}", version, next_version);
+            self.conn.execute_batch(upgrade_sql)?;
+            version = next_version;
+        }
+
+        Ok(())
     }
     
+    /// Whether the schema version is from a newer version of fspulse than this one
+    fn is_newer_version(version: &str) -> bool {
+        version.parse::<u32>().ok()
+            .zip(SCHEMA_VERSION.parse::<u32>().ok())
+            .is_some_and(|(version, current)| version > current)
+    }
+
+    fn no_upgrade_error(version: &str) -> FsPulseError {
+        if Self::is_newer_version(version) {
+            FsPulseError::Error(format!(
+                "The database has schema version {}, from a newer version of fspulse than this one (schema version {}). Upgrade fspulse to use it",
+                version, SCHEMA_VERSION))
+        } else {
+            FsPulseError::Error(format!("Schema version mismatch: no upgrade from version {}", version))
+        }
+    }
+
     fn create_schema(&self) -> Result<(), FsPulseError> {
         self.conn.execute_batch(CREATE_SCHEMA_SQL)?;
         Ok(())
     }
 }
-    
\ No newline at end of file
+    
+
+/// Counts the writes made within `Database::batched`, committing them a batch at a time
+pub struct WriteBatch {
+    size: usize,
+    pending: usize,
+}
+
+impl WriteBatch {
+    /// Counts a write, committing the batch and beginning the next once it's full
+    pub fn wrote(&mut self, db: &Database) -> Result<(), FsPulseError> {
+        self.pending += 1;
+        if self.pending >= self.size {
+            db.conn.execute_batch("COMMIT; BEGIN IMMEDIATE")?;
+            self.pending = 0;
+        }
+        Ok(())
+    }
+}
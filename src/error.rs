@@ -10,7 +10,48 @@ pub enum FsPulseError {
     #[error("Database error: {0}")]
     Database(#[from] RusqliteError), // Converts rusqlite::Error automatically
 
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error("Watch error: {0}")]
+    Watch(#[from] notify::Error),
+
     #[error("Error: {0}")]
     Error(String), // Allows custom application errors
+
+    #[error("Frozen root changed: {0}")]
+    FrozenRootChanged(String), // A scan with --expect-frozen found changes
+
+    #[error("Content mismatch: {0}")]
+    ContentMismatch(String), // Verify found files whose content changed while their metadata didn't
+
+    #[error("Changes detected: {0}")]
+    ChangesDetected(String), // Check found changes of the types it fails on
+}
+
+impl FsPulseError {
+    /// The process exit code for the error. Changes to a frozen root, possible corruption and
+    /// changes found by `check` get their own codes so that scripts can tell them apart from
+    /// failures to scan
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            FsPulseError::FrozenRootChanged(_) => 3,
+            FsPulseError::ContentMismatch(_) => 4,
+            FsPulseError::ChangesDetected(_) => 5,
+            _ => 1,
+        }
+    }
+
+    /// Whether the error came from SQLite running out of room on the disk. SQLite rolls
+    /// back the write that failed, so the database is left as it was before it
+    pub fn is_disk_full(&self) -> bool {
+        matches!(
+            self,
+            FsPulseError::Database(RusqliteError::SqliteFailure(error, _)) if error.code == rusqlite::ErrorCode::DiskFull
+        )
+    }
 }
 
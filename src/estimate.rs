@@ -0,0 +1,336 @@
+use md5::{Digest, Md5};
+use rusqlite::OptionalExtension;
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::capabilities::{FsCapabilities, PROBE_DIR_PREFIX};
+use crate::checksums::CHECKSUM_FILE_NAME;
+use crate::database::{Database, PROJECT_DIR_NAME};
+use crate::error::FsPulseError;
+use crate::scans::ScanState;
+use crate::utils::Utils;
+
+/// How many files are read to measure how fast files can be hashed
+const SAMPLE_FILES: usize = 16;
+
+/// Longest time spent reading sample files
+const SAMPLE_TIME_LIMIT: Duration = Duration::from_secs(5);
+
+/// Files at least this large are preferred as samples, since opening a small file costs
+/// more than reading it and would understate the hashing rate
+const SAMPLE_MIN_SIZE: u64 = 1024 * 1024;
+
+/// An item as the last scan recorded it
+struct RecordedItem {
+    size: Option<i64>,
+    modified: Option<i64>,
+    hashed: bool,
+    tombstone: bool,
+    ignored: bool,
+}
+
+/// A uniformly random selection of up to SAMPLE_FILES paths from those offered
+#[derive(Default)]
+struct Reservoir {
+    paths: Vec<PathBuf>,
+    offered: u64,
+}
+
+impl Reservoir {
+    fn offer(&mut self, path: PathBuf, rng: &mut u64) {
+        self.offered += 1;
+        if self.paths.len() < SAMPLE_FILES {
+            self.paths.push(path);
+        } else {
+            let slot = (Estimate::next_random(rng) % self.offered) as usize;
+            if slot < SAMPLE_FILES {
+                self.paths[slot] = path;
+            }
+        }
+    }
+}
+
+/// The counts recorded by a root's latest completed scan
+#[derive(Copy, Clone, Debug)]
+pub struct ScanHistory {
+    pub scan_id: i64,
+    pub files: i64,
+    pub folders: i64,
+    pub bytes: i64,
+}
+
+/// What one kind of scan would hash
+#[derive(Clone, Debug)]
+pub struct ScanKindEstimate {
+    pub kind: &'static str,
+    pub option: &'static str,
+    pub files: i64,
+    pub bytes: i64,
+    pub hash_time: Option<Duration>,
+}
+
+/// A prediction of what scanning a tree would involve, made by walking it without
+/// recording anything and reading a sample of its files
+#[derive(Clone, Debug, Default)]
+pub struct Estimate {
+    pub files: i64,
+    pub folders: i64,
+    pub other_items: i64,
+    /// Total size of the files
+    pub bytes: i64,
+    /// Files a delta hashing scan (--deep-delta) would hash: new files, files whose size
+    /// or modification time changed, and files without a hash
+    pub delta_files: i64,
+    pub delta_bytes: i64,
+    /// Whether the whole tree was walked within the time limit
+    pub complete: bool,
+    pub walk_time: Duration,
+    pub sampled_bytes: u64,
+    pub sampled_files: usize,
+    pub sample_time: Duration,
+    pub history: Option<ScanHistory>,
+    /// Whether the counts were scaled up from a partial walk using the history
+    pub extrapolated: bool,
+}
+
+impl Estimate {
+    /// Walks the tree at `root_path` for at most `walk_limit`, comparing what it finds with
+    /// the items recorded for `root_id` if the root is known, then reads up to
+    /// `sample_limit` bytes from a random sample of its files. If the walk doesn't finish,
+    /// the counts are scaled up to the size of the root's latest completed scan
+    pub fn run(db: &Database, root_path: &Path, root_id: Option<i64>, walk_limit: Duration, sample_limit: u64) -> Result<Self, FsPulseError> {
+        let mut estimate = Estimate {
+            history: match root_id {
+                Some(root_id) => Self::history(db, root_id)?,
+                None => None,
+            },
+            ..Default::default()
+        };
+
+        let mtime_tolerance = match root_id {
+            Some(root_id) => FsCapabilities::get_for_root(db, root_id)?.unwrap_or_default().mtime_tolerance(),
+            None => 0,
+        };
+
+        let mut stmt = db.conn.prepare(
+            "SELECT file_size, last_modified, file_hash IS NOT NULL AND hash_in_flux = 0, is_tombstone, is_ignored
+                FROM items
+                WHERE root_id = ? AND path = ?"
+        )?;
+
+        let mut large_samples = Reservoir::default();
+        let mut small_samples = Reservoir::default();
+        let mut rng = SystemTime::now().duration_since(UNIX_EPOCH).map_or(1, |d| d.as_nanos() as u64) | 1;
+
+        let started = Instant::now();
+        let mut dirs = vec![root_path.to_path_buf()];
+        estimate.complete = true;
+
+        'walk: while let Some(dir_path) = dirs.pop() {
+            for item in fs::read_dir(&dir_path)? {
+                if started.elapsed() >= walk_limit {
+                    estimate.complete = false;
+                    break 'walk;
+                }
+
+                let item = item?;
+                let file_name = item.file_name();
+                let file_type = item.file_type()?;
+
+                // Skipped just as a scan skips them
+                if file_type.is_dir() && (file_name == PROJECT_DIR_NAME || file_name.to_string_lossy().starts_with(PROBE_DIR_PREFIX)) {
+                    continue;
+                }
+                if file_type.is_file() && file_name == CHECKSUM_FILE_NAME {
+                    continue;
+                }
+
+                let metadata = item.metadata()?;
+                let item_path = item.path();
+                let relative_path = item_path.strip_prefix(root_path).unwrap_or(&item_path).to_string_lossy().into_owned();
+
+                let recorded = match root_id {
+                    Some(root_id) => stmt.query_row(
+                        (root_id, &relative_path),
+                        |row| Ok(RecordedItem {
+                            size: row.get(0)?,
+                            modified: row.get(1)?,
+                            hashed: row.get(2)?,
+                            tombstone: row.get(3)?,
+                            ignored: row.get(4)?,
+                        }),
+                    ).optional()?,
+                    None => None,
+                };
+
+                // Scans pass over ignored items, and everything beneath ignored directories
+                if recorded.as_ref().is_some_and(|recorded| recorded.ignored) {
+                    continue;
+                }
+
+                if metadata.is_dir() {
+                    estimate.folders += 1;
+                    dirs.push(item_path);
+                } else if metadata.is_file() {
+                    let size = metadata.len() as i64;
+                    let modified = metadata.modified().ok().map(Utils::system_time_to_db_time);
+
+                    estimate.files += 1;
+                    estimate.bytes += size;
+
+                    let unchanged = recorded.is_some_and(|recorded| {
+                        let same_time = match (recorded.modified, modified) {
+                            (Some(recorded), Some(current)) => (recorded - current).abs() <= mtime_tolerance,
+                            (recorded, current) => recorded == current,
+                        };
+                        recorded.hashed && !recorded.tombstone && recorded.size == Some(size) && same_time
+                    });
+                    if !unchanged {
+                        estimate.delta_files += 1;
+                        estimate.delta_bytes += size;
+                    }
+
+                    if metadata.len() >= SAMPLE_MIN_SIZE {
+                        large_samples.offer(item_path, &mut rng);
+                    } else if metadata.len() > 0 {
+                        small_samples.offer(item_path, &mut rng);
+                    }
+                } else {
+                    estimate.other_items += 1;
+                }
+            }
+        }
+
+        estimate.walk_time = started.elapsed();
+
+        let samples = if large_samples.paths.is_empty() { small_samples.paths } else { large_samples.paths };
+        estimate.sample(&samples, sample_limit);
+
+        if !estimate.complete {
+            estimate.extrapolate();
+        }
+
+        Ok(estimate)
+    }
+
+    /// What shallow, delta deep and full deep scans would each hash
+    pub fn by_scan_kind(&self) -> Vec<ScanKindEstimate> {
+        [
+            ("Shallow", "(default)", 0, 0),
+            ("Delta deep", "--deep-delta", self.delta_files, self.delta_bytes),
+            ("Full deep", "--hash", self.files, self.bytes),
+        ]
+        .into_iter()
+        .map(|(kind, option, files, bytes)| ScanKindEstimate {
+            kind,
+            option,
+            files,
+            bytes,
+            hash_time: self.hash_time(bytes),
+        })
+        .collect()
+    }
+
+    /// Bytes hashed per second, as measured by reading the sample
+    pub fn hash_rate(&self) -> Option<f64> {
+        let secs = self.sample_time.as_secs_f64();
+        (self.sampled_bytes > 0 && secs > 0.0).then(|| self.sampled_bytes as f64 / secs)
+    }
+
+    /// How long hashing `bytes` should take at the measured rate
+    pub fn hash_time(&self, bytes: i64) -> Option<Duration> {
+        self.hash_rate().map(|rate| Duration::from_secs_f64(bytes as f64 / rate))
+    }
+
+    /// Reads and hashes up to `limit` bytes, spread across the sample files
+    fn sample(&mut self, samples: &[PathBuf], limit: u64) {
+        if samples.is_empty() || limit == 0 {
+            return;
+        }
+
+        let per_file = (limit / samples.len() as u64).max(1);
+        let started = Instant::now();
+        let mut buffer = [0; 8192];
+
+        for path in samples {
+            if started.elapsed() >= SAMPLE_TIME_LIMIT {
+                break;
+            }
+
+            // Files which can't be read are left out of the measurement
+            let Ok(file) = File::open(path) else {
+                continue;
+            };
+
+            let mut reader = file.take(per_file);
+            let mut hasher = Md5::new();
+
+            while let Ok(bytes_read) = reader.read(&mut buffer) {
+                if bytes_read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..bytes_read]);
+                self.sampled_bytes += bytes_read as u64;
+            }
+
+            hasher.finalize();
+            self.sampled_files += 1;
+        }
+
+        self.sample_time = started.elapsed();
+    }
+
+    /// Scales the counts from a partial walk up to the size of the latest completed scan,
+    /// assuming the rest of the tree looks like the part that was walked
+    fn extrapolate(&mut self) {
+        let Some(history) = self.history else {
+            return;
+        };
+
+        if self.files == 0 || history.files <= self.files {
+            return;
+        }
+
+        let file_scale = history.files as f64 / self.files as f64;
+        let byte_scale = if self.bytes > 0 { history.bytes as f64 / self.bytes as f64 } else { file_scale };
+
+        self.delta_files = (self.delta_files as f64 * file_scale) as i64;
+        self.delta_bytes = (self.delta_bytes as f64 * byte_scale) as i64;
+        self.files = history.files;
+        self.folders = self.folders.max(history.folders);
+        self.bytes = history.bytes.max(self.bytes);
+        self.extrapolated = true;
+    }
+
+    fn history(db: &Database, root_id: i64) -> Result<Option<ScanHistory>, FsPulseError> {
+        let history = db.conn.query_row(
+            "SELECT s.id, COALESCE(s.file_count, 0), COALESCE(s.folder_count, 0),
+                    (SELECT COALESCE(SUM(file_size), 0) FROM items WHERE last_scan_id = s.id AND is_tombstone = 0 AND item_type = 'F')
+                FROM scans s
+                WHERE s.root_id = ? AND s.state = ?
+                ORDER BY s.id DESC
+                LIMIT 1",
+            (root_id, ScanState::Completed.as_i64()),
+            |row| Ok(ScanHistory {
+                scan_id: row.get(0)?,
+                files: row.get(1)?,
+                folders: row.get(2)?,
+                bytes: row.get(3)?,
+            }),
+        )
+        .optional()?;
+
+        Ok(history)
+    }
+
+    fn next_random(state: &mut u64) -> u64 {
+        // xorshift64
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+}
@@ -0,0 +1,507 @@
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::scans::ExclusionReason;
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The files whose rules are followed with --respect-gitignore, in increasing precedence
+const IGNORE_FILE_NAMES: &[&str] = &[".gitignore", ".ignore"];
+
+const RULE_KIND_EXCLUDE: &str = "exclude";
+const RULE_KIND_GITIGNORE: &str = "gitignore";
+const RULE_KIND_INCLUDE: &str = "include";
+
+/// The rules a scan excludes paths by: globs given with --exclude, whether the
+/// `.gitignore` and `.ignore` files found in the tree are followed, and globs given with
+/// --include for paths to scan even so. They're stored with the scan rather than the root,
+/// so that a resumed scan follows the rules it was started with, and a one-off scan with
+/// rules of its own leaves later scans as they were
+#[derive(Clone, Debug, Default)]
+pub struct ExcludeRules {
+    patterns: Vec<String>,
+    includes: Vec<String>,
+    respect_gitignore: bool,
+}
+
+impl ExcludeRules {
+    pub fn new(patterns: Vec<String>, includes: Vec<String>, respect_gitignore: bool) -> Self {
+        ExcludeRules { patterns: Self::trim(patterns), includes: Self::trim(includes), respect_gitignore }
+    }
+
+    fn trim(patterns: Vec<String>) -> Vec<String> {
+        patterns.iter()
+            .map(|pattern| pattern.trim_matches('/').to_string())
+            .filter(|pattern| !pattern.is_empty())
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty() && self.includes.is_empty() && !self.respect_gitignore
+    }
+
+    pub fn store(&self, db: &Database, scan_id: i64) -> Result<(), FsPulseError> {
+        let mut stmt = db.conn.prepare("INSERT INTO scan_exclude_rules (scan_id, kind, pattern) VALUES (?, ?, ?)")?;
+
+        for pattern in &self.patterns {
+            stmt.execute((scan_id, RULE_KIND_EXCLUDE, pattern))?;
+        }
+        if self.respect_gitignore {
+            stmt.execute((scan_id, RULE_KIND_GITIGNORE, None::<String>))?;
+        }
+        for pattern in &self.includes {
+            stmt.execute((scan_id, RULE_KIND_INCLUDE, pattern))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn for_scan(db: &Database, scan_id: i64) -> Result<Self, FsPulseError> {
+        let mut stmt = db.conn.prepare("SELECT kind, pattern FROM scan_exclude_rules WHERE scan_id = ? ORDER BY id")?;
+        let mut rows = stmt.query([scan_id])?;
+        let mut rules = ExcludeRules::default();
+
+        while let Some(row) = rows.next()? {
+            let kind: String = row.get(0)?;
+            match (kind.as_str(), row.get::<_, Option<String>>(1)?) {
+                (RULE_KIND_EXCLUDE, Some(pattern)) => rules.patterns.push(pattern),
+                (RULE_KIND_GITIGNORE, _) => rules.respect_gitignore = true,
+                (RULE_KIND_INCLUDE, Some(pattern)) => rules.includes.push(pattern),
+                _ => {},
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// The rules as they'd be given on the command line
+    pub fn describe(&self) -> Vec<String> {
+        let mut descriptions: Vec<String> = self.patterns.iter()
+            .map(|pattern| format!("--exclude '{}'", pattern))
+            .collect();
+        if self.respect_gitignore {
+            descriptions.push("--respect-gitignore".to_string());
+        }
+        descriptions.extend(self.includes.iter().map(|pattern| format!("--include '{}'", pattern)));
+        descriptions
+    }
+
+    /// Prepares to check paths in the tree at `root_path` against the rules
+    pub fn matcher(&self, root_path: &Path) -> ExcludeMatcher {
+        ExcludeMatcher {
+            globs: self.patterns.iter().map(|pattern| PathGlob::new(pattern)).collect(),
+            includes: self.includes.iter().map(|pattern| PathGlob::new(pattern)).collect(),
+            gitignores: self.respect_gitignore.then(|| Gitignores::new(root_path)),
+        }
+    }
+}
+
+/// Checks paths against a scan's rules
+pub struct ExcludeMatcher {
+    globs: Vec<PathGlob>,
+    includes: Vec<PathGlob>,
+    gitignores: Option<Gitignores>,
+}
+
+impl ExcludeMatcher {
+    /// Why the path, relative to the root, is excluded, if it is. A path an include glob
+    /// matches isn't excluded
+    pub fn check(&mut self, relative_path: &Path, is_dir: bool) -> Option<ExclusionReason> {
+        let path = slash_path(relative_path);
+
+        let reason = if self.globs.iter().any(|glob| glob.matches(&path)) {
+            Some(ExclusionReason::Excluded)
+        } else {
+            let ignored = self.gitignores.as_mut()
+                .is_some_and(|gitignores| gitignores.is_ignored(relative_path, is_dir));
+            ignored.then_some(ExclusionReason::Gitignore)
+        };
+
+        reason.filter(|_| !self.includes.iter().any(|glob| glob.matches(&path)))
+    }
+}
+
+/// An --exclude glob, which like `fspulse ignore` matches the path relative to the root or,
+/// for a pattern without a separator, an item's name at any depth
+struct PathGlob {
+    glob: Glob,
+    by_name: bool,
+}
+
+impl PathGlob {
+    fn new(pattern: &str) -> Self {
+        PathGlob { glob: Glob::new(pattern, true), by_name: !pattern.contains('/') }
+    }
+
+    fn matches(&self, path: &str) -> bool {
+        if self.by_name {
+            self.glob.matches(path.rsplit('/').next().unwrap_or(path))
+        } else {
+            self.glob.matches(path)
+        }
+    }
+}
+
+/// The rules of the ignore files in the directories above the last path checked, read as
+/// they're needed. Only that one chain of directories is kept, so a depth-first walk reads
+/// each directory's files once while memory grows with the depth of the tree
+struct Gitignores {
+    root_path: PathBuf,
+    /// Each directory from the root down, with its rules
+    ancestors: Vec<(PathBuf, Vec<GitignoreRule>)>,
+}
+
+impl Gitignores {
+    fn new(root_path: &Path) -> Self {
+        Gitignores { root_path: root_path.to_path_buf(), ancestors: Vec::new() }
+    }
+
+    /// Whether the ignore files of the directories above the path ignore it. A file in a
+    /// deeper directory takes precedence, and within a file the last rule that matches does
+    fn is_ignored(&mut self, relative_path: &Path, is_dir: bool) -> bool {
+        let mut dirs: Vec<&Path> = relative_path.ancestors().skip(1).collect();
+        dirs.reverse();
+
+        // Directories the walk has left are dropped, and those it's entered are read
+        let kept = self.ancestors.iter().zip(&dirs)
+            .take_while(|((dir, _), wanted)| dir == *wanted)
+            .count();
+        self.ancestors.truncate(kept);
+        for dir in &dirs[kept..] {
+            self.ancestors.push((dir.to_path_buf(), GitignoreRule::read_dir(&self.root_path.join(dir))));
+        }
+
+        for (dir, rules) in self.ancestors.iter().rev() {
+            let path = slash_path(relative_path.strip_prefix(dir).unwrap_or(relative_path));
+            if let Some(rule) = rules.iter().rev().find(|rule| rule.matches(&path, is_dir)) {
+                return !rule.negated;
+            }
+        }
+
+        false
+    }
+}
+
+/// One line of an ignore file
+struct GitignoreRule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+}
+
+impl GitignoreRule {
+    /// Reads the rules of the ignore files in a directory. Files that can't be read have none
+    fn read_dir(dir: &Path) -> Vec<GitignoreRule> {
+        IGNORE_FILE_NAMES.iter()
+            .filter_map(|name| fs::read_to_string(dir.join(name)).ok())
+            .flat_map(|contents| contents.lines().filter_map(Self::parse).collect::<Vec<_>>())
+            .collect()
+    }
+
+    fn parse(line: &str) -> Option<GitignoreRule> {
+        // Trailing spaces are dropped, except one escaped with a backslash
+        let line = line.trim_end_matches('\r');
+        let trimmed = line.trim_end_matches(' ');
+        let escaped = trimmed.len() < line.len()
+            && trimmed.chars().rev().take_while(|c| *c == '\\').count() % 2 == 1;
+        let line = if escaped { &line[..trimmed.len() + 1] } else { trimmed };
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        if line.is_empty() {
+            return None;
+        }
+
+        // A pattern with a separator is anchored to the ignore file's directory. One
+        // without matches at any depth beneath it
+        let pattern = if line.contains('/') {
+            line.trim_start_matches('/').to_string()
+        } else {
+            format!("**/{}", line)
+        };
+
+        Some(GitignoreRule { glob: Glob::new(&pattern, false), negated, dir_only })
+    }
+
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && self.glob.matches(path)
+    }
+}
+
+#[derive(Debug)]
+enum Token {
+    Char(char),
+    /// `?`: any one character other than a separator
+    Any,
+    /// `*`: any run of characters, which crosses separators only in an --exclude glob
+    Star,
+    /// `**` as a whole path component: any run of characters, separators included
+    DoubleStar,
+    /// `**/`: any number of leading directories, including none
+    Dirs,
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+/// A glob matched against paths with `/` separators. `*`, `?`, `[...]` and `\` escapes have
+/// their usual meanings. An unclosed `[` is taken literally
+struct Glob {
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    fn new(pattern: &str, star_crosses_separator: bool) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            match chars[i] {
+                '\\' if i + 1 < chars.len() => {
+                    tokens.push(Token::Char(chars[i + 1]));
+                    i += 2;
+                },
+                '*' if chars.get(i + 1) == Some(&'*') && !star_crosses_separator
+                    && (i == 0 || chars[i - 1] == '/') =>
+                {
+                    match chars.get(i + 2) {
+                        Some('/') => {
+                            tokens.push(Token::Dirs);
+                            i += 3;
+                        },
+                        None => {
+                            tokens.push(Token::DoubleStar);
+                            i += 2;
+                        },
+                        Some(_) => {
+                            tokens.push(Token::Star);
+                            i += 2;
+                        },
+                    }
+                },
+                '*' => {
+                    tokens.push(if star_crosses_separator { Token::DoubleStar } else { Token::Star });
+                    i += 1;
+                },
+                '?' => {
+                    tokens.push(Token::Any);
+                    i += 1;
+                },
+                '[' => match Self::parse_class(&chars[i + 1..]) {
+                    Some((token, len)) => {
+                        tokens.push(token);
+                        i += len + 1;
+                    },
+                    None => {
+                        tokens.push(Token::Char('['));
+                        i += 1;
+                    },
+                },
+                c => {
+                    tokens.push(Token::Char(c));
+                    i += 1;
+                },
+            }
+        }
+
+        Glob { tokens }
+    }
+
+    /// Parses a class following its `[`, returning it and the characters it took up
+    /// through its closing `]`
+    fn parse_class(chars: &[char]) -> Option<(Token, usize)> {
+        let mut i = 0;
+        let negated = matches!(chars.first(), Some('!') | Some('^'));
+        if negated {
+            i += 1;
+        }
+
+        let mut ranges = Vec::new();
+        let mut first = true;
+        while i < chars.len() {
+            let c = chars[i];
+            if c == ']' && !first {
+                return Some((Token::Class { negated, ranges }, i + 1));
+            }
+            first = false;
+
+            if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|end| *end != ']') {
+                ranges.push((c, chars[i + 2]));
+                i += 3;
+            } else {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+
+        None
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        Self::matches_from(&self.tokens, &text)
+    }
+
+    fn matches_from(tokens: &[Token], text: &[char]) -> bool {
+        let Some((token, rest)) = tokens.split_first() else {
+            return text.is_empty();
+        };
+
+        match token {
+            Token::Char(c) => text.first() == Some(c) && Self::matches_from(rest, &text[1..]),
+            Token::Any => text.first().is_some_and(|c| *c != '/') && Self::matches_from(rest, &text[1..]),
+            Token::Class { negated, ranges } => text.first().is_some_and(|c| {
+                *c != '/' && ranges.iter().any(|(start, end)| (start..=end).contains(&c)) != *negated
+            }) && Self::matches_from(rest, &text[1..]),
+            Token::Star => {
+                let run = text.iter().position(|c| *c == '/').unwrap_or(text.len());
+                (0..=run).any(|len| Self::matches_from(rest, &text[len..]))
+            },
+            Token::DoubleStar => (0..=text.len()).any(|len| Self::matches_from(rest, &text[len..])),
+            Token::Dirs => (0..=text.len())
+                .filter(|len| *len == 0 || text[len - 1] == '/')
+                .any(|len| Self::matches_from(rest, &text[len..])),
+        }
+    }
+}
+
+/// A relative path with `/` separators on every platform
+fn slash_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn rule(line: &str) -> GitignoreRule {
+        GitignoreRule::parse(line).unwrap_or_else(|| panic!("'{}' isn't a rule", line))
+    }
+
+    #[test]
+    fn name_patterns_match_at_any_depth() {
+        let rule = rule("*.log");
+        assert!(rule.matches("a.log", false));
+        assert!(rule.matches("x/y/a.log", false));
+        assert!(!rule.matches("a.log.gz", false));
+        assert!(!rule.matches("a.log/b", false));
+    }
+
+    #[test]
+    fn patterns_with_a_separator_are_anchored() {
+        assert!(rule("/build").matches("build", true));
+        assert!(!rule("/build").matches("src/build", true));
+        assert!(rule("doc/*.txt").matches("doc/a.txt", false));
+        assert!(!rule("doc/*.txt").matches("doc/sub/a.txt", false));
+        assert!(!rule("doc/*.txt").matches("x/doc/a.txt", false));
+    }
+
+    #[test]
+    fn trailing_slash_matches_only_directories() {
+        let rule = rule("cache/");
+        assert!(rule.matches("cache", true));
+        assert!(rule.matches("x/cache", true));
+        assert!(!rule.matches("cache", false));
+    }
+
+    #[test]
+    fn double_star_spans_directories() {
+        assert!(rule("**/logs").matches("logs", true));
+        assert!(rule("**/logs").matches("a/b/logs", true));
+        assert!(rule("a/**/b").matches("a/b", false));
+        assert!(rule("a/**/b").matches("a/x/y/b", false));
+        assert!(!rule("a/**/b").matches("a/xb", false));
+        assert!(rule("logs/**").matches("logs/a/b", false));
+        assert!(!rule("logs/**").matches("logs", true));
+        // Elsewhere, ** is two stars, which don't cross a separator
+        assert!(rule("/a**b").matches("axyb", false));
+        assert!(!rule("/a**b").matches("ax/yb", false));
+    }
+
+    #[test]
+    fn classes_can_be_negated() {
+        let rule = rule("file[!0-9].txt");
+        assert!(rule.matches("filea.txt", false));
+        assert!(!rule.matches("file1.txt", false));
+        assert!(!rule.matches("file/.txt", false));
+        assert!(Glob::new("[^a]", false).matches("b"));
+        assert!(Glob::new("[]]", false).matches("]"));
+        assert!(Glob::new("[a-", false).matches("[a-"));
+    }
+
+    #[test]
+    fn comments_blanks_and_escapes() {
+        assert!(GitignoreRule::parse("# comment").is_none());
+        assert!(GitignoreRule::parse("   ").is_none());
+        assert!(GitignoreRule::parse("!").is_none());
+        assert!(rule("\\#name").matches("#name", false));
+        assert!(!rule("\\!name").negated);
+        assert!(rule("\\!name").matches("!name", false));
+        assert!(rule("!name").negated);
+    }
+
+    #[test]
+    fn trailing_spaces_are_trimmed_unless_escaped() {
+        assert!(rule("name  ").matches("name", false));
+        assert!(rule("name\\ ").matches("name ", false));
+        assert!(rule("name\\  ").matches("name ", false));
+        assert!(!rule("name\\  ").matches("name  ", false));
+        // An escaped backslash doesn't escape the space after it
+        assert!(rule("name\\\\ ").matches("name\\", false));
+        assert!(rule("name\r").matches("name", false));
+    }
+
+    #[test]
+    fn exclude_globs_cross_separators() {
+        assert!(PathGlob::new("*.tmp").matches("a/b/c.tmp"));
+        assert!(PathGlob::new("cache/*").matches("cache/a/b"));
+        assert!(!PathGlob::new("cache/*").matches("x/cache/a"));
+    }
+
+    #[test]
+    fn deeper_ignore_files_take_precedence() {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos();
+        let root = std::env::temp_dir().join(format!("fspulse-excludes-{}-{}", process::id(), nanos));
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::create_dir_all(root.join("c")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n!keep.log\n").unwrap();
+        fs::write(root.join("a/.gitignore"), "keep.log\n").unwrap();
+        fs::write(root.join("a/b/.ignore"), "!*.log\n").unwrap();
+        fs::write(root.join("c/.gitignore"), "*.log\n!*.log\nkeep.log\n").unwrap();
+
+        let mut gitignores = Gitignores::new(&root);
+        let mut ignored = |path: &str| (gitignores.is_ignored(Path::new(path), false), gitignores.ancestors.len());
+
+        assert_eq!(ignored("x.log"), (true, 1));
+        assert_eq!(ignored("keep.log"), (false, 1));
+        // a/.gitignore ignores keep.log again beneath it
+        assert_eq!(ignored("a/keep.log"), (true, 2));
+        assert_eq!(ignored("a/x.log"), (true, 2));
+        // a/b/.ignore brings every log back
+        assert_eq!(ignored("a/b/keep.log"), (false, 3));
+        assert_eq!(ignored("a/b/x.log"), (false, 3));
+        // Within a file the last rule that matches wins. Once the walk leaves a, its rules
+        // are dropped
+        assert_eq!(ignored("c/x.log"), (false, 2));
+        assert_eq!(ignored("c/keep.log"), (true, 2));
+        assert_eq!(ignored("a/b/x.log"), (false, 3));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+use crate::database::Database;
+use crate::error::FsPulseError;
+
+// Each kind of orphaned row, as (description, table, condition identifying the orphans).
+// Order matters: removing scans and items orphans their changes, which are removed after
+const ORPHANS: &[(&str, &str, &str)] = &[
+    ("Scans without a root", "scans", "root_id NOT IN (SELECT id FROM roots)"),
+    ("Items without a root", "items", "root_id NOT IN (SELECT id FROM roots)"),
+    ("Schedules without a root", "schedules", "root_id NOT IN (SELECT id FROM roots)"),
+    ("Allowed owners without a root", "allowed_owners", "root_id NOT IN (SELECT id FROM roots)"),
+    ("Group members without a root", "root_groups", "root_id NOT IN (SELECT id FROM roots)"),
+    ("Container roots without a root", "container_roots", "root_id NOT IN (SELECT id FROM roots)"),
+    ("Verifications without a root", "verifications", "root_id NOT IN (SELECT id FROM roots)"),
+    ("Container roots without a container", "container_roots", "container_id NOT IN (SELECT id FROM containers)"),
+    ("Exclusions without a scan", "scan_exclusions", "scan_id NOT IN (SELECT id FROM scans)"),
+    ("Exclusion counts without a scan", "scan_exclusion_counts", "scan_id NOT IN (SELECT id FROM scans)"),
+    ("Exclude rules without a scan", "scan_exclude_rules", "scan_id NOT IN (SELECT id FROM scans)"),
+    ("Unchecked directories without a scan", "unchecked_dirs", "scan_id NOT IN (SELECT id FROM scans)"),
+    ("Changes without a scan", "changes", "scan_id NOT IN (SELECT id FROM scans)"),
+    ("Changes without an item", "changes", "item_id NOT IN (SELECT id FROM items)"),
+    ("Verification mismatches without a verification", "verification_mismatches", "verification_id NOT IN (SELECT id FROM verifications)"),
+    ("Verification mismatches without an item", "verification_mismatches", "item_id NOT IN (SELECT id FROM items)"),
+];
+
+pub struct Gc {
+    // No fields
+}
+
+impl Gc {
+    /// Removes rows which refer to roots, scans or items that no longer exist. Such rows
+    /// can be left behind by older versions or by crashes. With `dry_run`, the rows are
+    /// counted but the database is left unchanged
+    pub fn do_gc(db: &mut Database, dry_run: bool) -> Result<(), FsPulseError> {
+        let tx = db.conn.transaction()?;
+        let mut total = 0;
+
+        // Orphans can refer to each other (e.g. the items and changes of a missing root refer
+        // to its scans), so foreign keys are checked once every step has run
+        tx.execute_batch("PRAGMA defer_foreign_keys = ON")?;
+
+        for (description, table, condition) in ORPHANS {
+            let removed = tx.execute(&format!("DELETE FROM {} WHERE {}", table, condition), [])?;
+            println!("{}: {}", description, removed);
+            total += removed;
+        }
+
+        // A dry run performs the same deletes so that rows orphaned by earlier steps are
+        // counted, then discards them
+        if dry_run {
+            tx.rollback()?;
+            println!("Would remove {} orphaned rows (dry run)", total);
+        } else {
+            tx.commit()?;
+            println!("Removed {} orphaned rows", total);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+
+/// Named sets of roots which together hold one dataset, such as a collection spread across
+/// /mnt/disk1 and /mnt/disk2. Each root is still scanned on its own; reports on a group
+/// combine the latest scans of its roots
+pub struct RootGroups {
+    // No fields
+}
+
+impl RootGroups {
+    /// Adds a root to the group, creating the group if needed. Returns false if the root
+    /// was already in the group
+    pub fn add(db: &Database, name: &str, root_id: i64) -> Result<bool, FsPulseError> {
+        let added = db.conn.execute(
+            "INSERT OR IGNORE INTO root_groups (name, root_id) VALUES (?, ?)",
+            (name, root_id),
+        )?;
+
+        Ok(added > 0)
+    }
+
+    /// Removes a root from the group. Returns false if the root wasn't in the group
+    pub fn remove(db: &Database, name: &str, root_id: i64) -> Result<bool, FsPulseError> {
+        let removed = db.conn.execute(
+            "DELETE FROM root_groups WHERE name = ? AND root_id = ?",
+            (name, root_id),
+        )?;
+
+        Ok(removed > 0)
+    }
+
+    /// Removes the group, returning the number of roots it held
+    pub fn remove_all(db: &Database, name: &str) -> Result<usize, FsPulseError> {
+        let removed = db.conn.execute("DELETE FROM root_groups WHERE name = ?", [name])?;
+
+        Ok(removed)
+    }
+
+    /// The ids of the roots in the group. A group that doesn't exist has none
+    pub fn members(db: &Database, name: &str) -> Result<Vec<i64>, FsPulseError> {
+        let mut stmt = db.conn.prepare("SELECT root_id FROM root_groups WHERE name = ? ORDER BY root_id ASC")?;
+        let rows = stmt.query_map([name], |row| row.get(0))?;
+
+        let mut root_ids = Vec::new();
+        for row in rows {
+            root_ids.push(row?);
+        }
+
+        Ok(root_ids)
+    }
+
+    /// Every group, by name, with the ids of its roots
+    pub fn all(db: &Database) -> Result<BTreeMap<String, Vec<i64>>, FsPulseError> {
+        let mut stmt = db.conn.prepare("SELECT name, root_id FROM root_groups ORDER BY name ASC, root_id ASC")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))?;
+
+        let mut groups: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+        for row in rows {
+            let (name, root_id) = row?;
+            groups.entry(name).or_default().push(root_id);
+        }
+
+        Ok(groups)
+    }
+}
@@ -1,32 +1,211 @@
-use std::{fs::File, io::{BufReader, Read}, path::PathBuf};
+use std::{collections::BTreeSet, fmt, fs::File, io::{BufReader, Read, Seek, SeekFrom}, path::{Path, PathBuf}, str::FromStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc::{self, Receiver, Sender, TryRecvError}, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
 use hex::encode;
 use indicatif::ProgressBar;
+use log::info;
 use md5::{Digest, Md5};
+use sha2::Sha256;
+use xxhash_rust::xxh3::Xxh3;
 
 use crate::error::FsPulseError;
 
+/// How often a file being hashed is checked for changes
+const FLUX_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How much of a file is read at a time, unless hashing is tuned
+pub const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// The most files a tuned scan hashes at once
+pub const MAX_TUNED_CONCURRENCY: usize = 8;
+
+/// How long each setting is tried for while tuning
+const TUNING_TRIAL: Duration = Duration::from_secs(10);
+
+/// Read sizes tried while tuning, once the number of files hashed at once is chosen
+const TUNING_BUFFER_SIZES: &[usize] = &[64 * 1024, 256 * 1024, 1024 * 1024, 4 * 1024 * 1024];
+
+/// The read size used while choosing the number of files hashed at once
+const TUNING_INITIAL_BUFFER_SIZE: usize = 256 * 1024;
+
+/// A setting must beat the best so far by this much to be preferred, so that noise in
+/// the measurements doesn't decide
+const TUNING_MIN_GAIN: f64 = 1.1;
+
+/// How many of a file's shingle hashes its similarity digest keeps
+const SIMILARITY_SKETCH_SIZE: usize = 64;
+
+/// How many bytes each shingle of a file's content spans
+const SIMILARITY_SHINGLE_LEN: usize = 8;
+
+/// A file's hash and, if requested, its confirmation hash
+pub type Hashes = (String, Option<String>);
+
+/// The algorithm a full hash is taken with. Hashes taken with different algorithms can't
+/// be compared, so the algorithm is recorded with each hash. Sampled hashes are always md5
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum HashAlgo {
+    #[default]
+    Md5,
+    Sha256,
+    Blake3,
+    Xxh3,
+}
+
+impl HashAlgo {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha256 => "sha256",
+            Self::Blake3 => "blake3",
+            Self::Xxh3 => "xxh3",
+        }
+    }
+
+    /// The algorithm of the confirmation hash taken alongside a hash with this one. It's
+    /// unrelated to this one, so that content which gives the same hash as before but is
+    /// actually different shows up as a mismatch
+    fn confirm_algo(&self) -> HashAlgo {
+        match self {
+            Self::Sha256 => Self::Blake3,
+            _ => Self::Sha256,
+        }
+    }
+
+    fn hasher(&self) -> AlgoHasher {
+        match self {
+            Self::Md5 => AlgoHasher::Md5(Md5::new()),
+            Self::Sha256 => AlgoHasher::Sha256(Sha256::new()),
+            Self::Blake3 => AlgoHasher::Blake3(Box::new(blake3::Hasher::new())),
+            Self::Xxh3 => AlgoHasher::Xxh3(Box::new(Xxh3::new())),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for HashAlgo {
+    type Err = FsPulseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md5" => Ok(Self::Md5),
+            "sha256" => Ok(Self::Sha256),
+            "blake3" => Ok(Self::Blake3),
+            "xxh3" => Ok(Self::Xxh3),
+            _ => Err(FsPulseError::Error(format!("Invalid hash algorithm: '{}'", s))),
+        }
+    }
+}
+
+/// A hash being computed with one of the algorithms
+enum AlgoHasher {
+    Md5(Md5),
+    Sha256(Sha256),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<Xxh3>),
+}
+
+impl AlgoHasher {
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Md5(hasher) => hasher.update(data),
+            Self::Sha256(hasher) => hasher.update(data),
+            Self::Blake3(hasher) => { hasher.update(data); },
+            Self::Xxh3(hasher) => hasher.update(data),
+        }
+    }
+
+    /// The hash as lowercase hex
+    fn finish(self) -> String {
+        match self {
+            Self::Md5(hasher) => encode(hasher.finalize()),
+            Self::Sha256(hasher) => encode(hasher.finalize()),
+            Self::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Self::Xxh3(hasher) => format!("{:032x}", hasher.digest128()),
+        }
+    }
+}
+
+/// How much is read from the start, the end and each stride of a sampled file, unless set
+pub const DEFAULT_SAMPLE_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// How far apart the chunks read from a sampled file are, unless set
+pub const DEFAULT_SAMPLE_STRIDE: u64 = 256 * 1024 * 1024;
+
+/// Which parts of a large file are read to give it a sampled hash: a chunk at its start,
+/// one every `stride` bytes after that, and one at its end. Only corruption within the
+/// chunks is noticed, in exchange for reading a small part of each file
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Sampling {
+    pub chunk_size: u64,
+    pub stride: u64,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Sampling { chunk_size: DEFAULT_SAMPLE_CHUNK_SIZE, stride: DEFAULT_SAMPLE_STRIDE }
+    }
+}
+
+impl Sampling {
+    /// The offsets of the chunks read from a file of `len` bytes, in order
+    fn offsets(&self, len: u64) -> Vec<u64> {
+        let mut offsets = Vec::new();
+        let mut offset = 0;
+        while offset < len.saturating_sub(self.chunk_size) {
+            offsets.push(offset);
+            offset += self.stride.max(1);
+        }
+        offsets.push(len.saturating_sub(self.chunk_size));
+
+        offsets
+    }
+
+    /// Whether two sampled hashes were taken the same way, so that a difference between
+    /// them means the content changed
+    pub fn comparable(a: &str, b: &str) -> bool {
+        a.rsplit_once(':').map(|(prefix, _)| prefix) == b.rsplit_once(':').map(|(prefix, _)| prefix)
+    }
+}
 
 pub struct Hash {
     // no fields
 }
 
 impl Hash {
-    pub fn compute_md5_hash(path: &PathBuf, bar: &ProgressBar) -> Result<String, FsPulseError> {
+    /// Computes the hash of the file with `algo` and, if `confirm` is set, a confirmation
+    /// hash from the same read. The second hash comes from an unrelated algorithm, so content
+    /// which produces the same hash as before but is actually different shows up as a mismatch.
+    ///
+    /// The file's size and modification time are checked periodically while it's read. If
+    /// either changes, the file is being written and hashing it is abandoned, returning None
+    pub fn compute_hashes(path: &Path, bar: &ProgressBar, algo: HashAlgo, confirm: bool, buffer_size: usize) -> Result<Option<Hashes>, FsPulseError> {
         let file_name = path.file_name()
             .unwrap_or_else(|| path.as_os_str())
             .to_string_lossy();
 
         let f = File::open(path)?;
-        let len = f.metadata()?.len();
+        let metadata = f.metadata()?;
+        let len = metadata.len();
+        let modified = metadata.modified().ok();
+        let mut last_check = Instant::now();
 
         bar.reset();
         bar.set_length(len);
         bar.set_message(format!("Computing hash for: {}", file_name));
 
         let mut reader = BufReader::new(f);
-        let mut hasher = Md5::new();
-        let mut buffer = [0; 8192]; // Read in 8KB chunks
+        let mut hasher = algo.hasher();
+        let mut confirm_hasher = confirm.then(|| algo.confirm_algo().hasher());
+        let mut buffer = vec![0; buffer_size.max(1)];
 
         loop {
             let bytes_read = reader.read(&mut buffer)?;
@@ -34,12 +213,426 @@ impl Hash {
                 break;
             }
             hasher.update(&buffer[..bytes_read]);
+            if let Some(confirm_hasher) = confirm_hasher.as_mut() {
+                confirm_hasher.update(&buffer[..bytes_read]);
+            }
             bar.inc(bytes_read.try_into().unwrap());
+
+            if last_check.elapsed() >= FLUX_CHECK_INTERVAL {
+                // The open file is checked rather than the path, so a file that is replaced
+                // by a rename is still hashed consistently
+                let current = reader.get_ref().metadata()?;
+                if current.len() != len || current.modified().ok() != modified {
+                    bar.finish_and_clear();
+                    return Ok(None);
+                }
+                last_check = Instant::now();
+            }
         }
 
-        let hash = hasher.finalize();
+        let hash = hasher.finish();
+        let confirm_hash = confirm_hasher.map(AlgoHasher::finish);
+
+        bar.finish_and_clear();
+        Ok(Some((hash, confirm_hash)))
+    }
+
+    /// Computes the md5 of the parts of the file chosen by `sampling`, along with its
+    /// length. The hash is prefixed with the chunk size and stride, which must match for
+    /// two sampled hashes to be compared.
+    ///
+    /// As with a full hash, None is returned if the file's size or modification time
+    /// changed while it was read
+    pub fn compute_sample_hash(path: &Path, bar: &ProgressBar, sampling: Sampling) -> Result<Option<String>, FsPulseError> {
+        let mut f = File::open(path)?;
+        let metadata = f.metadata()?;
+        let len = metadata.len();
+        let modified = metadata.modified().ok();
+
+        let offsets = sampling.offsets(len);
+        bar.reset();
+        bar.set_length(offsets.len() as u64 * sampling.chunk_size.min(len));
+        bar.set_message(format!(
+            "Computing sampled hash for: {}",
+            path.file_name().unwrap_or(path.as_os_str()).to_string_lossy()));
+
+        let mut hasher = Md5::new();
+        hasher.update(len.to_le_bytes());
+
+        let mut buffer = Vec::new();
+        for offset in offsets {
+            f.seek(SeekFrom::Start(offset))?;
+            buffer.clear();
+            (&mut f).take(sampling.chunk_size).read_to_end(&mut buffer)?;
+            hasher.update(&buffer);
+            bar.inc(buffer.len() as u64);
+        }
 
         bar.finish_and_clear();
-        Ok(encode(hash))
+
+        let current = f.metadata()?;
+        if current.len() != len || current.modified().ok() != modified {
+            return Ok(None);
+        }
+
+        Ok(Some(format!("{}:{}:{}", sampling.chunk_size, sampling.stride, encode(hasher.finalize()))))
+    }
+
+    /// Computes a digest of the file's content from which the similarity of two versions of
+    /// the file can be estimated. Every run of 8 bytes in the file (a shingle) is hashed,
+    /// and the 64 smallest distinct hashes are kept: a bottom-k sketch, whose overlap with
+    /// another file's estimates how much of the content the two share. The digest is
+    /// prefixed with the sketch size and shingle length, which must match for two digests
+    /// to be compared.
+    ///
+    /// As with a full hash, None is returned if the file's size or modification time
+    /// changed while it was read
+    pub fn compute_similarity_digest(path: &Path) -> Result<Option<String>, FsPulseError> {
+        let f = File::open(path)?;
+        let metadata = f.metadata()?;
+        let modified = metadata.modified().ok();
+
+        let mut sketch: BTreeSet<u32> = BTreeSet::new();
+        let mut window: u64 = 0;
+        let mut len: u64 = 0;
+
+        for byte in BufReader::new(&f).bytes() {
+            window = (window << 8) | u64::from(byte?);
+            len += 1;
+            if len >= SIMILARITY_SHINGLE_LEN as u64 {
+                Self::add_to_sketch(&mut sketch, window);
+            }
+        }
+
+        // A file shorter than a shingle is a single shingle of its own length
+        if len > 0 && len < SIMILARITY_SHINGLE_LEN as u64 {
+            Self::add_to_sketch(&mut sketch, window ^ (len << 56));
+        }
+
+        let current = f.metadata()?;
+        if current.len() != metadata.len() || current.modified().ok() != modified {
+            return Ok(None);
+        }
+
+        let hashes: String = sketch.iter().map(|hash| format!("{:08x}", hash)).collect();
+        Ok(Some(format!("{}:{}:{}", SIMILARITY_SKETCH_SIZE, SIMILARITY_SHINGLE_LEN, hashes)))
+    }
+
+    /// Estimates how much of their content the files two similarity digests were taken from
+    /// share, as a percentage: 100 for a file that's unchanged or barely touched, near 0 for
+    /// one that was replaced outright. None if the digests can't be compared
+    pub fn similarity(a: &str, b: &str) -> Option<u8> {
+        let (a_prefix, a_hashes) = a.rsplit_once(':')?;
+        let (b_prefix, b_hashes) = b.rsplit_once(':')?;
+        if a_prefix != b_prefix {
+            return None;
+        }
+
+        let a = Self::parse_sketch(a_hashes)?;
+        let b = Self::parse_sketch(b_hashes)?;
+        if a.is_empty() && b.is_empty() {
+            return Some(100);
+        }
+
+        // The smallest hashes of the two files together are a sample of all their shingles.
+        // The share of the sample found in both files estimates the share of all shingles
+        // found in both
+        let sample: Vec<&u32> = a.union(&b).take(SIMILARITY_SKETCH_SIZE).collect();
+        let shared = sample.iter().filter(|hash| a.contains(hash) && b.contains(hash)).count();
+
+        Some((shared * 100 / sample.len()) as u8)
+    }
+
+    /// Adds a shingle to a sketch, keeping only the smallest hashes
+    fn add_to_sketch(sketch: &mut BTreeSet<u32>, shingle: u64) {
+        // splitmix64's finalizer spreads the shingle's bits over the hash
+        let mut hash = shingle.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        let hash = ((hash ^ (hash >> 31)) >> 32) as u32;
+
+        if sketch.len() < SIMILARITY_SKETCH_SIZE {
+            sketch.insert(hash);
+        } else if sketch.last().is_some_and(|largest| hash < *largest) && sketch.insert(hash) {
+            sketch.pop_last();
+        }
+    }
+
+    fn parse_sketch(hashes: &str) -> Option<BTreeSet<u32>> {
+        if !hashes.len().is_multiple_of(8) {
+            return None;
+        }
+
+        (0..hashes.len()).step_by(8)
+            .map(|start| u32::from_str_radix(hashes.get(start..start + 8)?, 16).ok())
+            .collect()
+    }
+}
+
+/// How a scan hashes files: how many are read at once and how much of a file is read at a
+/// time
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HashSettings {
+    pub concurrency: usize,
+    pub buffer_size: usize,
+}
+
+impl Default for HashSettings {
+    fn default() -> Self {
+        HashSettings { concurrency: 1, buffer_size: DEFAULT_BUFFER_SIZE }
+    }
+}
+
+/// Chooses hash settings for a scan from the throughput it sees while hashing. Each
+/// setting is tried for a while: first the number of files hashed at once, doubling
+/// while that keeps helping, then the read size with the best number. The best setting
+/// seen is then kept for the rest of the scan
+pub struct HashTuner {
+    max_concurrency: usize,
+    phase: TuningPhase,
+    current: HashSettings,
+    best: Option<(HashSettings, f64)>,
+    bytes: u64,
+    elapsed: Duration,
+}
+
+enum TuningPhase {
+    Concurrency,
+    /// The read sizes still to be tried
+    BufferSize(Vec<usize>),
+    Done,
+}
+
+impl HashTuner {
+    /// A tuner which tries hashing at most `max_concurrency` files at once
+    pub fn new(max_concurrency: usize) -> Self {
+        HashTuner {
+            max_concurrency: max_concurrency.max(1),
+            phase: TuningPhase::Concurrency,
+            current: HashSettings { concurrency: 1, buffer_size: TUNING_INITIAL_BUFFER_SIZE },
+            best: None,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+        }
     }
-}
\ No newline at end of file
+
+    /// The settings to hash with next
+    pub fn settings(&self) -> HashSettings {
+        self.current
+    }
+
+    /// The settings chosen or, if hashing finished before tuning did, the best seen so far
+    pub fn chosen(&self) -> HashSettings {
+        self.best.map_or(self.current, |(settings, _)| settings)
+    }
+
+    /// Whether tuning has finished
+    pub fn is_done(&self) -> bool {
+        matches!(self.phase, TuningPhase::Done)
+    }
+
+    /// Records that `bytes` were hashed in `elapsed` with the current settings, and moves
+    /// on to the next setting once the current one has been tried for long enough
+    pub fn record(&mut self, bytes: u64, elapsed: Duration) {
+        if self.is_done() {
+            return;
+        }
+
+        self.bytes += bytes;
+        self.elapsed += elapsed;
+        if self.elapsed < TUNING_TRIAL {
+            return;
+        }
+
+        let throughput = self.bytes as f64 / self.elapsed.as_secs_f64();
+        info!(
+            "Hash tuning: {} file(s) at once with {} byte reads hashed {:.0} bytes/s",
+            self.current.concurrency, self.current.buffer_size, throughput,
+        );
+        self.bytes = 0;
+        self.elapsed = Duration::ZERO;
+
+        let improved = self.best.is_none_or(|(_, best)| throughput >= best * TUNING_MIN_GAIN);
+        if improved {
+            self.best = Some((self.current, throughput));
+        }
+
+        if let TuningPhase::Concurrency = self.phase {
+            if improved && self.current.concurrency < self.max_concurrency {
+                self.current.concurrency = (self.current.concurrency * 2).min(self.max_concurrency);
+                return;
+            }
+
+            // Popped from the end, so the smallest is tried first
+            let sizes = TUNING_BUFFER_SIZES.iter().copied().filter(|size| *size != TUNING_INITIAL_BUFFER_SIZE).rev().collect();
+            self.phase = TuningPhase::BufferSize(sizes);
+        }
+
+        let best = self.chosen();
+        match &mut self.phase {
+            TuningPhase::BufferSize(sizes) => match sizes.pop() {
+                Some(buffer_size) => self.current = HashSettings { concurrency: best.concurrency, buffer_size },
+                None => {
+                    self.current = best;
+                    self.phase = TuningPhase::Done;
+                },
+            },
+            TuningPhase::Concurrency | TuningPhase::Done => {},
+        }
+    }
+}
+
+/// What a file's hashing produced: its hashes, or None if it changed while it was read
+pub type HashResult = Result<Option<Hashes>, FsPulseError>;
+
+struct HashJob {
+    path: PathBuf,
+    bar: ProgressBar,
+    algo: HashAlgo,
+    confirm: bool,
+    buffer_size: usize,
+    reply: Box<dyn FnOnce(HashResult) + Send>,
+}
+
+/// A fixed set of hashing threads shared by all scans in a run. Because the number of
+/// threads is fixed, the pool also bounds how many files are being read at once, no
+/// matter how many roots are being scanned concurrently
+pub struct HashPool {
+    sender: Option<Sender<HashJob>>,
+    workers: Vec<JoinHandle<()>>,
+    threads: usize,
+}
+
+impl HashPool {
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<HashJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let threads = threads.max(1);
+        let workers = (0..threads)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || Self::worker(receiver))
+            })
+            .collect();
+
+        HashPool { sender: Some(sender), workers, threads }
+    }
+
+    /// The number of hashing threads, which is the most files the pool reads at once
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    fn worker(receiver: Arc<Mutex<Receiver<HashJob>>>) {
+        loop {
+            // The lock is released as soon as a job has been taken
+            let job = match receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => return,
+            };
+
+            match job {
+                Ok(job) => {
+                    // A panic is handed back as the file's error, so the scan waiting on it
+                    // isn't left waiting for a reply that never comes
+                    let hashed = panic::catch_unwind(AssertUnwindSafe(|| {
+                        Hash::compute_hashes(&job.path, &job.bar, job.algo, job.confirm, job.buffer_size)
+                    }));
+                    let result = hashed.unwrap_or_else(|_| Err(FsPulseError::Error(
+                        format!("Hashing '{}' failed unexpectedly", job.path.display()))));
+                    (job.reply)(result);
+                }
+                Err(_) => return, // The pool is shutting down
+            }
+        }
+    }
+
+    fn submit(&self, job: HashJob) -> Result<(), FsPulseError> {
+        self.sender
+            .as_ref()
+            .ok_or_else(|| FsPulseError::Error("Hash pool has shut down".to_string()))?
+            .send(job)
+            .map_err(|_| FsPulseError::Error("Hash pool has shut down".to_string()))
+    }
+}
+
+/// One scan's files being hashed by the pool. Hashes are handed back in the order the files
+/// finish rather than the order they were queued, so that a large file doesn't hold up the
+/// smaller ones queued after it, and the scan can queue another file as soon as any finishes.
+/// Each file is queued with a `T` which is handed back with its hashes
+pub struct HashQueue<'a, T> {
+    pool: &'a HashPool,
+    sender: Sender<(T, HashResult)>,
+    receiver: Receiver<(T, HashResult)>,
+    queued: usize,
+}
+
+impl<'a, T: Send + 'static> HashQueue<'a, T> {
+    pub fn new(pool: &'a HashPool) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        HashQueue { pool, sender, receiver, queued: 0 }
+    }
+
+    /// The number of files queued whose hashes haven't been handed back
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// Queues the file to be hashed with `algo` on one of the pool's threads, reading
+    /// `buffer_size` bytes at a time
+    pub fn submit(&mut self, token: T, path: &Path, bar: &ProgressBar, algo: HashAlgo, confirm: bool, buffer_size: usize) -> Result<(), FsPulseError> {
+        let sender = self.sender.clone();
+
+        self.pool.submit(HashJob {
+            path: path.to_path_buf(),
+            bar: bar.clone(),
+            algo,
+            confirm,
+            buffer_size,
+            // The scan may have gone away, in which case there's no one to tell
+            reply: Box::new(move |result| { let _ = sender.send((token, result)); }),
+        })?;
+
+        self.queued += 1;
+        Ok(())
+    }
+
+    /// Blocks until the next of the queued files is hashed, and hands back its token and
+    /// hashes. Returns None once nothing is queued
+    pub fn next(&mut self) -> Result<Option<(T, HashResult)>, FsPulseError> {
+        if self.queued == 0 {
+            return Ok(None);
+        }
+
+        let hashed = self.receiver.recv()
+            .map_err(|_| FsPulseError::Error("Hash pool thread exited unexpectedly".to_string()))?;
+        self.queued -= 1;
+
+        Ok(Some(hashed))
+    }
+
+    /// Hands back the token and hashes of a queued file which has already been hashed,
+    /// without waiting. Returns None if none has
+    pub fn try_next(&mut self) -> Result<Option<(T, HashResult)>, FsPulseError> {
+        match self.receiver.try_recv() {
+            Ok(hashed) => {
+                self.queued -= 1;
+                Ok(Some(hashed))
+            },
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err(FsPulseError::Error("Hash pool thread exited unexpectedly".to_string())),
+        }
+    }
+}
+
+impl Drop for HashPool {
+    fn drop(&mut self) {
+        // Closing the channel tells the workers to exit
+        self.sender.take();
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::archive::{Archive, ArchiveMember};
+use crate::error::FsPulseError;
+use crate::items::ItemType;
+
+/// The media types of an OCI image index and a Docker manifest list, which hold a manifest
+/// for each platform rather than the layers themselves
+const INDEX_MEDIA_TYPES: &[&str] = &[
+    "application/vnd.oci.image.index.v1+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+];
+
+/// A layer's member named with this prefix deletes the item of the rest of its name in the
+/// layers below
+const WHITEOUT_PREFIX: &str = ".wh.";
+
+/// A layer's member of this name hides everything the layers below hold in its directory
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+#[derive(Deserialize)]
+struct Descriptor {
+    #[serde(rename = "mediaType", default)]
+    media_type: String,
+    digest: String,
+    platform: Option<Platform>,
+}
+
+#[derive(Deserialize)]
+struct Platform {
+    architecture: String,
+    os: String,
+}
+
+#[derive(Deserialize)]
+struct Index {
+    manifests: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    layers: Vec<Descriptor>,
+}
+
+pub struct Image {
+    // No fields
+}
+
+impl Image {
+    /// Reads the filesystem of a container image, described the way archive members are
+    /// so it can be compared with a scan of a running container's filesystem. `path` is
+    /// either an OCI image layout, as exported by `docker save` or `skopeo copy`, or on
+    /// Linux the mount point of an overlay, such as a container's root filesystem, whose
+    /// lower layers are the image. The layers are stacked as the container runtime stacks
+    /// them, with the files they delete removed. With `hash`, the md5 of each file's
+    /// content is computed, which means reading every layer in full
+    pub fn read_members(path: &Path, hash: bool) -> Result<Vec<ArchiveMember>, FsPulseError> {
+        let mut filesystem = BTreeMap::new();
+
+        if path.join("index.json").is_file() {
+            for layer in Self::oci_layers(path)? {
+                let members = Archive::read_tar(Archive::open(&layer)?, 0, hash)?;
+                Self::apply_layer(&mut filesystem, members);
+            }
+        } else if let Some(lower_dirs) = Self::overlay_lower_dirs(path)? {
+            // The overlay lists its lower layers from the top down
+            for lower_dir in lower_dirs.iter().rev() {
+                let members = Self::read_layer_dir(lower_dir, hash)?;
+                Self::apply_layer(&mut filesystem, members);
+            }
+        } else {
+            return Err(FsPulseError::Error(format!(
+                "'{}' is neither an OCI image layout nor an overlay mount point", path.display())));
+        }
+
+        Ok(filesystem.into_values().collect())
+    }
+
+    /// The image's layer blobs, from the bottom up. Of an index's manifests, the one for
+    /// this machine's platform is used, or otherwise the first
+    fn oci_layers(layout: &Path) -> Result<Vec<PathBuf>, FsPulseError> {
+        let mut index: Index = Self::read_json(&layout.join("index.json"))?;
+
+        let manifest = loop {
+            let (os, architecture) = (std::env::consts::OS, Self::oci_architecture());
+            let descriptor = index.manifests.iter()
+                .find(|d| d.platform.as_ref().is_some_and(|p| p.os == os && p.architecture == architecture))
+                .or_else(|| index.manifests.first())
+                .ok_or_else(|| FsPulseError::Error(format!("The image at '{}' has no manifests", layout.display())))?;
+
+            let blob = Self::blob_path(layout, &descriptor.digest)?;
+            if INDEX_MEDIA_TYPES.contains(&descriptor.media_type.as_str()) {
+                index = Self::read_json(&blob)?;
+            } else {
+                break Self::read_json::<Manifest>(&blob)?;
+            }
+        };
+
+        manifest.layers.iter()
+            .map(|layer| {
+                if layer.media_type.contains("zstd") {
+                    return Err(FsPulseError::Error(format!("Layer {} is zstd compressed, which isn't supported", layer.digest)));
+                }
+                Self::blob_path(layout, &layer.digest)
+            })
+            .collect()
+    }
+
+    /// The names OCI gives architectures, which mostly follow Go's
+    fn oci_architecture() -> &'static str {
+        match std::env::consts::ARCH {
+            "x86_64" => "amd64",
+            "aarch64" => "arm64",
+            "x86" => "386",
+            "powerpc64" => "ppc64le",
+            arch => arch,
+        }
+    }
+
+    /// Where the layout keeps the blob with the digest, such as `sha256:ab12...`
+    fn blob_path(layout: &Path, digest: &str) -> Result<PathBuf, FsPulseError> {
+        match digest.split_once(':') {
+            Some((algorithm, encoded))
+                if !algorithm.is_empty() && !encoded.is_empty()
+                    && algorithm.chars().chain(encoded.chars()).all(|c| c.is_ascii_alphanumeric()) =>
+            {
+                Ok(layout.join("blobs").join(algorithm).join(encoded))
+            },
+            _ => Err(FsPulseError::Error(format!("Invalid digest '{}' in the image at '{}'", digest, layout.display()))),
+        }
+    }
+
+    fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<T, FsPulseError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|error| FsPulseError::Error(format!("Can't read '{}': {}", path.display(), error)))?;
+        serde_json::from_str(&contents)
+            .map_err(|error| FsPulseError::Error(format!("Invalid image file '{}': {}", path.display(), error)))
+    }
+
+    /// Stacks a layer on the filesystem built from the layers below it. The layer's
+    /// whiteouts delete what those layers hold, then its members replace theirs
+    fn apply_layer(filesystem: &mut BTreeMap<String, ArchiveMember>, members: Vec<ArchiveMember>) {
+        let (whiteouts, members): (Vec<_>, Vec<_>) = members.into_iter().partition(|member| {
+            Path::new(&member.path).file_name().is_some_and(|name| name.to_string_lossy().starts_with(WHITEOUT_PREFIX))
+        });
+
+        for whiteout in whiteouts {
+            let path = Path::new(&whiteout.path);
+            let parent = path.parent().unwrap_or(Path::new(""));
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+            if name == OPAQUE_WHITEOUT {
+                filesystem.retain(|path, _| !Path::new(path).starts_with(parent) || Path::new(path) == parent);
+            } else if let Some(deleted) = name.strip_prefix(WHITEOUT_PREFIX) {
+                let deleted = parent.join(deleted);
+                filesystem.retain(|path, _| !Path::new(path).starts_with(&deleted));
+            }
+        }
+
+        for member in members {
+            // A directory replaced by something else takes what it held with it
+            let replaces_dir = filesystem.get(&member.path)
+                .is_some_and(|existing| existing.item_type == ItemType::Directory && member.item_type != ItemType::Directory);
+            if replaces_dir {
+                let replaced = PathBuf::from(&member.path);
+                filesystem.retain(|path, _| !Path::new(path).starts_with(&replaced));
+            }
+
+            filesystem.insert(member.path.clone(), member);
+        }
+    }
+
+    /// The lower layers of the overlay mounted at `path`, from the top down, or None if
+    /// no overlay is mounted there. Mounts are read from /proc/self/mountinfo
+    #[cfg(target_os = "linux")]
+    fn overlay_lower_dirs(path: &Path) -> Result<Option<Vec<PathBuf>>, FsPulseError> {
+        let mount_point = fs::canonicalize(path)?;
+        let mountinfo = fs::read_to_string("/proc/self/mountinfo")?;
+
+        // The last mount at a path is the one in view
+        for line in mountinfo.lines().rev() {
+            let Some((mount, superblock)) = line.split_once(" - ") else {
+                continue;
+            };
+            let mut superblock = superblock.split(' ');
+            if mount.split(' ').nth(4).map(Self::unescape_mountinfo) != Some(mount_point.to_string_lossy().into_owned())
+                || superblock.next() != Some("overlay")
+            {
+                continue;
+            }
+
+            let mut lower_dirs = Vec::new();
+            for option in superblock.nth(1).unwrap_or_default().split(',').map(Self::unescape_mountinfo) {
+                if let Some(dirs) = option.strip_prefix("lowerdir=") {
+                    lower_dirs.extend(Self::split_lower_dirs(dirs));
+                } else if let Some(dir) = option.strip_prefix("lowerdir+=") {
+                    lower_dirs.push(PathBuf::from(dir));
+                }
+            }
+
+            return Ok(Some(lower_dirs));
+        }
+
+        Ok(None)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn overlay_lower_dirs(_path: &Path) -> Result<Option<Vec<PathBuf>>, FsPulseError> {
+        Ok(None)
+    }
+
+    /// mountinfo escapes spaces, tabs, newlines, commas and backslashes in octal, as `\040`
+    #[cfg(target_os = "linux")]
+    fn unescape_mountinfo(field: &str) -> String {
+        let bytes = field.as_bytes();
+        let mut unescaped = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let octal = bytes.get(i + 1..i + 4)
+                .filter(|digits| bytes[i] == b'\\' && digits.iter().all(|digit| (b'0'..=b'7').contains(digit)))
+                .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+            match octal {
+                Some(byte) => {
+                    unescaped.push(byte);
+                    i += 4;
+                },
+                None => {
+                    unescaped.push(bytes[i]);
+                    i += 1;
+                },
+            }
+        }
+
+        String::from_utf8_lossy(&unescaped).into_owned()
+    }
+
+    /// Splits a `lowerdir` option at the colons which aren't escaped with a backslash. The
+    /// layers after a double colon hold only data for the others, and aren't visible
+    #[cfg(target_os = "linux")]
+    fn split_lower_dirs(dirs: &str) -> Vec<PathBuf> {
+        let mut lower_dirs = Vec::new();
+        let mut current = String::new();
+        let mut chars = dirs.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => current.extend(chars.next()),
+                ':' if current.is_empty() => return lower_dirs,
+                ':' => lower_dirs.push(PathBuf::from(std::mem::take(&mut current))),
+                c => current.push(c),
+            }
+        }
+        if !current.is_empty() {
+            lower_dirs.push(PathBuf::from(current));
+        }
+
+        lower_dirs
+    }
+
+    /// Reads an overlay layer's directory tree as members of a layer. The overlay marks a
+    /// deleted item with a character device numbered 0/0 in its place and a directory
+    /// hiding those below it with an xattr, which are read as the whiteouts an image
+    /// layer would hold. Reading the xattr needs root, without which no directory is
+    /// taken to be opaque
+    #[cfg(target_os = "linux")]
+    fn read_layer_dir(layer: &Path, hash: bool) -> Result<Vec<ArchiveMember>, FsPulseError> {
+        use std::fs::File;
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        use crate::utils::Utils;
+
+        let mut members = Vec::new();
+        let mut dirs = vec![PathBuf::new()];
+
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(layer.join(&dir))? {
+                let entry = entry?;
+                let path = dir.join(entry.file_name());
+                let absolute_path = layer.join(&path);
+                let metadata = entry.metadata()?;
+                let file_type = metadata.file_type();
+
+                if file_type.is_char_device() && metadata.rdev() == 0 {
+                    let whiteout = dir.join(format!("{}{}", WHITEOUT_PREFIX, entry.file_name().to_string_lossy()));
+                    members.push(Self::whiteout(&whiteout));
+                    continue;
+                }
+
+                let (item_type, size, file_hash) = if file_type.is_dir() {
+                    let opaque = ["trusted.overlay.opaque", "user.overlay.opaque"].iter()
+                        .any(|name| xattr::get(&absolute_path, name).ok().flatten().is_some_and(|value| value == b"y"));
+                    if opaque {
+                        members.push(Self::whiteout(&path.join(OPAQUE_WHITEOUT)));
+                    }
+                    dirs.push(path.clone());
+                    (ItemType::Directory, None, None)
+                } else if file_type.is_file() {
+                    let file_hash = if hash { Some(Archive::hash(&mut File::open(&absolute_path)?)?) } else { None };
+                    (ItemType::File, Some(metadata.len() as i64), file_hash)
+                } else if file_type.is_symlink() {
+                    (ItemType::Symlink, None, None)
+                } else {
+                    (ItemType::Other, None, None)
+                };
+
+                members.push(ArchiveMember {
+                    path: path.to_string_lossy().into_owned(),
+                    item_type,
+                    size,
+                    modified: metadata.modified().ok().map(Utils::system_time_to_db_time),
+                    hash: file_hash,
+                });
+            }
+        }
+
+        Ok(members)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn read_layer_dir(_layer: &Path, _hash: bool) -> Result<Vec<ArchiveMember>, FsPulseError> {
+        Ok(Vec::new())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn whiteout(path: &Path) -> ArchiveMember {
+        ArchiveMember {
+            path: path.to_string_lossy().into_owned(),
+            item_type: ItemType::Other,
+            size: None,
+            modified: None,
+            hash: None,
+        }
+    }
+}
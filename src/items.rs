@@ -1,6 +1,145 @@
-use rusqlite::{self, params, OptionalExtension};
+use rusqlite::{self, params, params_from_iter, types::Value, OptionalExtension, Row, Statement};
 
-use crate::{database::Database, error::FsPulseError};
+use std::collections::HashMap;
+use std::path::MAIN_SEPARATOR_STR;
+
+use crate::{changes::ChangeType, database::Database, error::FsPulseError, utils::Utils};
+
+const SQL_ITEMS_IN_LATEST_SCAN: &str =
+    "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+        FROM items
+        WHERE last_scan_id = ? AND is_ignored = 0{}
+        ORDER BY path ASC";
+
+// The items seen by the latest scan of each root in a group
+const SQL_ITEMS_IN_GROUP: &str =
+    "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+        FROM items
+        WHERE is_ignored = 0 AND last_scan_id IN (
+            SELECT MAX(s.id) FROM scans s JOIN root_groups g ON g.root_id = s.root_id WHERE g.name = ?1 GROUP BY s.root_id)
+        ORDER BY path ASC, root_id ASC";
+
+// Files in the latest scans of a group's roots which share a hash with another, largest
+// first, with the files of each hash together
+const SQL_DUPLICATES_IN_GROUP: &str =
+    "WITH group_files AS (
+        SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+            FROM items
+            WHERE is_ignored = 0 AND item_type = 'F' AND file_hash IS NOT NULL AND last_scan_id IN (
+                SELECT MAX(s.id) FROM scans s JOIN root_groups g ON g.root_id = s.root_id WHERE g.name = ?1 GROUP BY s.root_id))
+    SELECT * FROM group_files
+        WHERE (hash_algo, file_hash) IN (SELECT hash_algo, file_hash FROM group_files GROUP BY hash_algo, file_hash HAVING COUNT(*) > 1)
+        ORDER BY file_size DESC, hash_algo ASC, file_hash ASC, root_id ASC, path ASC";
+
+// Files in the latest scan of a root which share a hash and size with another, largest
+// first, with the files of each hash together
+const SQL_DUPLICATES_IN_ROOT: &str =
+    "WITH root_files AS (
+        SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+            FROM items
+            WHERE is_ignored = 0 AND item_type = 'F' AND file_hash IS NOT NULL
+                AND last_scan_id = (SELECT MAX(id) FROM scans WHERE root_id = ?1))
+    SELECT * FROM root_files
+        WHERE (hash_algo, file_hash, file_size) IN (SELECT hash_algo, file_hash, file_size FROM root_files GROUP BY hash_algo, file_hash, file_size HAVING COUNT(*) > 1)
+        ORDER BY file_size DESC, hash_algo ASC, file_hash ASC, path ASC";
+
+// The items of a root present as of a scan: those whose most recent change at or before
+// the scan wasn't a delete. An item with no change that early, once its earlier changes
+// have been pruned, was present unless its next change added it or, with none since, it's
+// a tombstone
+const SQL_ITEMS_PRESENT_AT_SCAN: &str =
+    "SELECT i.id, i.root_id, i.path, i.item_type, i.is_tombstone, i.last_modified, i.file_size, i.file_hash, i.file_is_valid, i.last_scan_id, i.last_hash_scan_id, i.last_is_valid_scan_id, i.last_verified, i.symlink_target, i.symlink_dangling, i.owner_uid, i.owner_gid, i.sample_hash, i.hash_algo
+        FROM items i
+        WHERE i.root_id = ?1 AND i.is_ignored = 0 AND COALESCE(
+            (SELECT c.change_type FROM changes c WHERE c.item_id = i.id AND c.scan_id <= ?2 ORDER BY c.id DESC LIMIT 1) IN ('A', 'M', 'T', 'R'),
+            (SELECT c.change_type FROM changes c WHERE c.item_id = i.id AND c.scan_id > ?2 ORDER BY c.id ASC LIMIT 1) != 'A',
+            i.is_tombstone = 0)
+        ORDER BY i.path ASC";
+
+// Changes to a root's items after a scan, newest first
+const SQL_CHANGES_AFTER_SCAN: &str =
+    "SELECT c.item_id, c.change_type, c.prev_last_modified, c.prev_file_size, c.prev_hash, c.prev_is_valid, c.prev_symlink_target, c.prev_owner_uid, c.prev_owner_gid, c.prev_sample_hash, c.old_path
+        FROM changes c
+        JOIN items i ON i.id = c.item_id
+        WHERE i.root_id = ?1 AND c.scan_id > ?2
+        ORDER BY c.id DESC";
+
+const SQL_ITEMS_WITH_PATH: &str =
+    "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+        FROM items
+        WHERE path = ?1 OR (SELECT rtrim(roots.path, ?2) FROM roots WHERE roots.id = items.root_id) || ?2 || path = ?1
+        ORDER BY id ASC";
+
+const SQL_ITEMS_UNVERIFIED_SINCE: &str =
+    "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+        FROM items
+        WHERE (?1 IS NULL OR root_id = ?1) AND is_tombstone = 0 AND is_ignored = 0 AND item_type = 'F'
+            AND (last_verified IS NULL OR last_verified <= ?2)
+        ORDER BY last_verified ASC, path ASC
+        LIMIT ?3";
+
+const SQL_ITEMS_WITH_SUSPECT_TIMESTAMPS: &str =
+    "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+        FROM items
+        WHERE last_scan_id = ? AND is_tombstone = 0 AND is_ignored = 0 AND (last_modified < 0 OR last_modified > ?)
+        ORDER BY path ASC";
+
+const SQL_ITEMS_WITH_DANGLING_SYMLINKS: &str =
+    "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+        FROM items
+        WHERE last_scan_id = ? AND is_tombstone = 0 AND is_ignored = 0 AND symlink_dangling = 1
+        ORDER BY path ASC";
+
+const SQL_ITEMS_WITH_UNEXPECTED_OWNERS: &str =
+    "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+        FROM items
+        WHERE last_scan_id = ? AND is_tombstone = 0 AND is_ignored = 0 AND owner_uid IS NOT NULL
+            AND owner_uid NOT IN (SELECT uid FROM allowed_owners WHERE allowed_owners.root_id = items.root_id)
+        ORDER BY path ASC";
+
+const SQL_ITEMS_CONTAINING: &str =
+    "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+        FROM items
+        WHERE id IN (SELECT rowid FROM items_fts WHERE items_fts MATCH ?1) AND is_ignored = 0
+        ORDER BY path ASC
+        LIMIT ?2";
+
+// The trigram index can't match fewer than three characters, so short searches scan the table
+const SQL_ITEMS_CONTAINING_SHORT: &str =
+    "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
+        FROM items
+        WHERE instr(lower(path), lower(?1)) > 0 AND is_ignored = 0
+        ORDER BY path ASC
+        LIMIT ?2";
+
+const SQL_ITEMS_RESEMBLING: &str =
+    "SELECT i.id, i.root_id, i.path, i.item_type, i.is_tombstone, i.last_modified, i.file_size, i.file_hash, i.file_is_valid, i.last_scan_id, i.last_hash_scan_id, i.last_is_valid_scan_id, i.last_verified, i.symlink_target, i.symlink_dangling, i.owner_uid, i.owner_gid, i.sample_hash, i.hash_algo
+        FROM items_fts
+        JOIN items i ON i.id = items_fts.rowid
+        WHERE items_fts MATCH ?1 AND i.is_ignored = 0
+        ORDER BY items_fts.rank, i.path ASC
+        LIMIT ?2";
+
+/// Totals across a set of items, shown beneath a table of items
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ItemTotals {
+    pub items: i64,
+    pub files: i64,
+    pub folders: i64,
+    pub size: i64,
+}
+
+impl ItemTotals {
+    pub fn add(&mut self, item: &Item) {
+        self.items += 1;
+        match item.item_type() {
+            "F" => self.files += 1,
+            "D" => self.folders += 1,
+            _ => {}
+        }
+        self.size += item.file_size().unwrap_or(0);
+    }
+}
 
 #[derive(Copy,Clone, Debug, PartialEq)]
 pub enum ItemType {
@@ -34,7 +173,14 @@ pub struct Item {           // TODO: Change sql schema to have this column order
     file_is_valid: Option<bool>,
     last_scan_id: i64,
     last_hash_scan_id: Option<i64>,
-    last_is_valid_scan_id: Option<i64>
+    last_is_valid_scan_id: Option<i64>,
+    last_verified: Option<i64>,
+    symlink_target: Option<String>,
+    symlink_dangling: Option<bool>,
+    owner_uid: Option<i64>,
+    owner_gid: Option<i64>,
+    sample_hash: Option<String>,
+    hash_algo: Option<String>,
 }
 
 impl Item {
@@ -42,24 +188,11 @@ impl Item {
         let conn = &db.conn;
 
         conn.query_row(
-            "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id
+            "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, owner_uid, owner_gid, sample_hash, hash_algo
              FROM items
              WHERE id = ?",
             params![id],
-            |row| Ok(Item {
-                id: row.get(0)?,
-                root_id: row.get(1)?,
-                path: row.get(2)?,
-                item_type: row.get(3)?,
-                is_tombstone: row.get(4)?,
-                last_modified: row.get(5)?,
-                file_size: row.get(6)?,
-                file_hash: row.get(7)?,
-                file_is_valid: row.get(8)?,
-                last_scan_id: row.get(9)?,
-                last_hash_scan_id: row.get(10)?,
-                last_is_valid_scan_id: row.get(11)?,
-            }),
+            Item::from_row,
         )
         .optional()
         .map_err(FsPulseError::Database)
@@ -77,81 +210,310 @@ impl Item {
     pub fn last_scan_id(&self) -> i64 { self.last_scan_id }
     pub fn last_hash_scan_id(&self) -> Option<i64> { self.last_hash_scan_id }
     pub fn last_is_valid_scan_id(&self) -> Option<i64> { self.last_is_valid_scan_id }
+    pub fn last_verified(&self) -> Option<i64> { self.last_verified }
+    pub fn symlink_target(&self) -> Option<&str> { self.symlink_target.as_deref() }
+    pub fn symlink_dangling(&self) -> Option<bool> { self.symlink_dangling }
+    pub fn owner_uid(&self) -> Option<i64> { self.owner_uid }
+    pub fn owner_gid(&self) -> Option<i64> { self.owner_gid }
+    pub fn sample_hash(&self) -> Option<&str> { self.sample_hash.as_deref() }
+    pub fn hash_algo(&self) -> Option<&str> { self.hash_algo.as_deref() }
+
+    pub fn items_in_latest_scan(db: &Database, scan_id: i64) -> Result<ItemRows<'_>, FsPulseError> {
+        Self::items_in_latest_scan_under(db, scan_id, None)
+    }
 
-    pub fn for_each_item_in_latest_scan<F>(db: &Database, scan_id: i64, mut func: F) -> Result<(), FsPulseError>
-    where
-        F: FnMut(&Item) -> Result<(), FsPulseError>,
-    {
-        let mut item_count = 0;
+    /// Items seen by the scan at or beneath `path_prefix`, relative to the root, or all of
+    /// them without one
+    pub fn items_in_latest_scan_under<'db>(db: &'db Database, scan_id: i64, path_prefix: Option<&str>) -> Result<ItemRows<'db>, FsPulseError> {
+        let (condition, mut params) = Self::under_condition(path_prefix);
+        params.insert(0, scan_id.into());
+        ItemRows::prepare(db, &SQL_ITEMS_IN_LATEST_SCAN.replace("{}", &condition), params)
+    }
 
-        let mut stmt = db.conn.prepare(
-            "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id
-             FROM items
-             WHERE last_scan_id = ?
-             ORDER BY path ASC"
-        )?;
-
-        let rows = stmt.query_map([scan_id], |row| {
-            Ok(Item {
-                id: row.get::<_, i64>(0)?,
-                root_id: row.get::<_, i64>(1)?,
-                path: row.get::<_, String>(2)?,
-                item_type: row.get::<_, String>(3)?,
-                is_tombstone: row.get::<_, bool>(4)?,
-                last_modified: row.get::<_, Option<i64>>(5)?,
-                file_size: row.get::<_, Option<i64>>(6)?,
-                file_hash: row.get::<_, Option<String>>(7)?,
-                file_is_valid: row.get::<_, Option<bool>>(8)?,
-                last_scan_id: row.get::<_, i64>(9)?,
-                last_hash_scan_id: row.get::<_, Option<i64>>(10)?,
-                last_is_valid_scan_id: row.get::<_, Option<i64>>(11)?,
-            })
-        })?;
-        
-        for row in rows {
-            let item = row?;
-            func(&item)?;
-            item_count = item_count + 1;
+    /// Items seen by the latest scan of each root in the group, by path. Items at the same
+    /// path in different roots are listed together
+    pub fn items_in_group<'db>(db: &'db Database, name: &str) -> Result<ItemRows<'db>, FsPulseError> {
+        ItemRows::prepare(db, SQL_ITEMS_IN_GROUP, vec![name.to_owned().into()])
+    }
+
+    /// Files in the latest scans of the group's roots whose hash matches another such file,
+    /// in the same root or another. Only files hashed by a scan are compared
+    pub fn duplicates_in_group<'db>(db: &'db Database, name: &str) -> Result<ItemRows<'db>, FsPulseError> {
+        ItemRows::prepare(db, SQL_DUPLICATES_IN_GROUP, vec![name.to_owned().into()])
+    }
+
+    /// Files in the root's latest scan whose hash and size match another file of the scan.
+    /// Only files hashed by a scan are compared
+    pub fn duplicates_in_root(db: &Database, root_id: i64) -> Result<ItemRows<'_>, FsPulseError> {
+        ItemRows::prepare(db, SQL_DUPLICATES_IN_ROOT, vec![root_id.into()])
+    }
+
+    /// Items with the path, which may be either the full path of the item or its path
+    /// relative to its root. Since roots can be nested, several items may match
+    /// The root's items as they were when the scan ran, rebuilt by undoing the changes
+    /// recorded since. Adds and type changes don't record what they replaced, so values
+    /// from before an item was added again or changed type are unknown and left empty,
+    /// with an item type of '?'. Whether a symlink dangled isn't recorded by changes, and
+    /// is left empty
+    pub fn items_as_of(db: &Database, root_id: i64, scan_id: i64) -> Result<Vec<Item>, FsPulseError> {
+        let mut items = Vec::new();
+        let mut rows = ItemRows::prepare(db, SQL_ITEMS_PRESENT_AT_SCAN, vec![root_id.into(), scan_id.into()])?;
+        for item in rows.iter()? {
+            items.push(item?);
         }
-        Ok(())
+
+        let positions: HashMap<i64, usize> = items.iter().enumerate().map(|(i, item)| (item.id, i)).collect();
+
+        // Undoing the changes newest first leaves each value as it was before the oldest
+        // change after the scan to replace it
+        let mut stmt = db.conn.prepare(SQL_CHANGES_AFTER_SCAN)?;
+        let mut rows = stmt.query((root_id, scan_id))?;
+        while let Some(row) = rows.next()? {
+            let Some(&position) = positions.get(&row.get::<_, i64>(0)?) else {
+                continue;
+            };
+            let item = &mut items[position];
+
+            match row.get::<_, String>(1)?.parse::<ChangeType>()? {
+                ChangeType::Add | ChangeType::TypeChange => {
+                    *item = Item {
+                        id: item.id,
+                        root_id: item.root_id,
+                        path: std::mem::take(&mut item.path),
+                        item_type: "?".to_string(),
+                        ..Default::default()
+                    };
+                },
+                ChangeType::Modify | ChangeType::Move => {
+                    if let Some(last_modified) = row.get(2)? { item.last_modified = Some(last_modified); }
+                    if let Some(file_size) = row.get(3)? { item.file_size = Some(file_size); }
+                    if let Some(file_hash) = row.get(4)? { item.file_hash = Some(file_hash); }
+                    if let Some(file_is_valid) = row.get(5)? { item.file_is_valid = Some(file_is_valid); }
+                    if let Some(symlink_target) = row.get(6)? { item.symlink_target = Some(symlink_target); }
+                    if let Some(owner_uid) = row.get(7)? { item.owner_uid = Some(owner_uid); }
+                    if let Some(owner_gid) = row.get(8)? { item.owner_gid = Some(owner_gid); }
+                    if let Some(sample_hash) = row.get(9)? { item.sample_hash = Some(sample_hash); }
+                    if let Some(old_path) = row.get(10)? { item.path = old_path; }
+                },
+                _ => {},
+            }
+        }
+
+        // Items that moved since are back at their old paths
+        items.sort_by(|a, b| a.path.cmp(&b.path));
+
+        for item in &mut items {
+            item.is_tombstone = false;
+            item.last_scan_id = scan_id;
+            item.symlink_dangling = None;
+
+            if item.last_hash_scan_id.is_some_and(|id| id > scan_id) {
+                item.last_hash_scan_id = None;
+                item.last_verified = None;
+            }
+            if item.last_is_valid_scan_id.is_some_and(|id| id > scan_id) {
+                item.last_is_valid_scan_id = None;
+            }
+        }
+
+        Ok(items)
     }
 
-    pub fn for_each_item_with_path<F>(db: &Database, path: &str, mut func: F) -> Result<(), FsPulseError>
-    where
-        F: FnMut(&Item) -> Result<(), FsPulseError>,
-    {
-        let mut item_count = 0;
+    pub fn items_with_path<'db>(db: &'db Database, path: &str) -> Result<ItemRows<'db>, FsPulseError> {
+        ItemRows::prepare(db, SQL_ITEMS_WITH_PATH, vec![path.to_owned().into(), MAIN_SEPARATOR_STR.to_owned().into()])
+    }
 
-        let mut stmt = db.conn.prepare(
-            "SELECT id, root_id, path, item_type, is_tombstone, last_modified, file_size, file_hash, file_is_valid, last_scan_id, last_hash_scan_id, last_is_valid_scan_id
-             FROM items
-             WHERE path = ?
-             ORDER BY id ASC"
-        )?;
-
-        let rows = stmt.query_map([path], |row| {
-            Ok(Item {
-                id: row.get::<_, i64>(0)?,
-                root_id: row.get::<_, i64>(1)?,
-                path: row.get::<_, String>(2)?,
-                item_type: row.get::<_, String>(3)?,
-                is_tombstone: row.get::<_, bool>(4)?,
-                last_modified: row.get::<_, Option<i64>>(5)?,
-                file_size: row.get::<_, Option<i64>>(6)?,
-                file_hash: row.get::<_, Option<String>>(7)?,
-                file_is_valid: row.get::<_, Option<bool>>(8)?,
-                last_scan_id: row.get::<_, i64>(9)?,
-                last_hash_scan_id: row.get::<_, Option<i64>>(10)?,
-                last_is_valid_scan_id: row.get::<_, Option<i64>>(11)?,
-
-            })
-        })?;
-        
-        for row in rows {
-            let item = row?;
-            func(&item)?;
-            item_count = item_count + 1;
+    /// Items seen in the scan whose modification time is before the Unix epoch or
+    /// later than `future_after`
+    pub fn items_with_suspect_timestamps(db: &Database, scan_id: i64, future_after: i64) -> Result<ItemRows<'_>, FsPulseError> {
+        ItemRows::prepare(db, SQL_ITEMS_WITH_SUSPECT_TIMESTAMPS, vec![scan_id.into(), future_after.into()])
+    }
+
+    /// Symlinks seen in the scan whose target didn't exist
+    pub fn items_with_dangling_symlinks(db: &Database, scan_id: i64) -> Result<ItemRows<'_>, FsPulseError> {
+        ItemRows::prepare(db, SQL_ITEMS_WITH_DANGLING_SYMLINKS, vec![scan_id.into()])
+    }
+
+    /// Items seen in the scan owned by a user who isn't among the root's allowed owners.
+    /// Only meaningful for roots with allowed owners
+    pub fn items_with_unexpected_owners(db: &Database, scan_id: i64) -> Result<ItemRows<'_>, FsPulseError> {
+        ItemRows::prepare(db, SQL_ITEMS_WITH_UNEXPECTED_OWNERS, vec![scan_id.into()])
+    }
+
+    /// Files, oldest first, whose content hasn't been verified by a hashing scan since
+    /// `verified_before` (or ever). Limited to `root_id` if specified
+    pub fn items_unverified_since(db: &Database, root_id: Option<i64>, verified_before: i64, limit: i64) -> Result<ItemRows<'_>, FsPulseError> {
+        ItemRows::prepare(db, SQL_ITEMS_UNVERIFIED_SINCE, vec![root_id.into(), verified_before.into(), limit.into()])
+    }
+
+    /// Items whose path (relative to its root) contains `text`, ignoring case. With
+    /// `fuzzy`, items are instead ranked by how many three-character fragments of `text`
+    /// their path shares, so near misses and reordered words are still found
+    pub fn items_matching<'db>(db: &'db Database, text: &str, fuzzy: bool, limit: i64) -> Result<ItemRows<'db>, FsPulseError> {
+        let trigrams = Self::trigrams(text);
+
+        if trigrams.is_empty() {
+            ItemRows::prepare(db, SQL_ITEMS_CONTAINING_SHORT, vec![text.to_owned().into(), limit.into()])
+        } else if fuzzy {
+            let query = trigrams.iter()
+                .map(|trigram| Self::fts_phrase(trigram))
+                .collect::<Vec<_>>()
+                .join(" OR ");
+            ItemRows::prepare(db, SQL_ITEMS_RESEMBLING, vec![query.into(), limit.into()])
+        } else {
+            ItemRows::prepare(db, SQL_ITEMS_CONTAINING, vec![Self::fts_phrase(text).into(), limit.into()])
         }
-        Ok(())
     }
-}
\ No newline at end of file
+
+    /// The distinct, lowercased three-character windows of `text`, in order of appearance
+    fn trigrams(text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.to_lowercase().chars().collect();
+        let mut trigrams: Vec<String> = Vec::new();
+
+        for window in chars.windows(3) {
+            let trigram: String = window.iter().collect();
+            if !trigrams.contains(&trigram) {
+                trigrams.push(trigram);
+            }
+        }
+
+        trigrams
+    }
+
+    /// Quotes text as an FTS5 phrase so that it's matched literally
+    fn fts_phrase(text: &str) -> String {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    }
+
+    pub fn totals_in_latest_scan(db: &Database, scan_id: i64, path_prefix: Option<&str>) -> Result<ItemTotals, FsPulseError> {
+        let (condition, mut params) = Self::under_condition(path_prefix);
+        params.insert(0, scan_id.into());
+        Self::totals(db, &format!("last_scan_id = ? AND is_ignored = 0{}", condition), params)
+    }
+
+    /// The condition to add to a WHERE clause for items at or beneath the path, if there's one
+    fn under_condition(path_prefix: Option<&str>) -> (String, Vec<Value>) {
+        match path_prefix {
+            Some(path_prefix) => {
+                let (condition, params) = Utils::path_prefix_condition("path", path_prefix);
+                (format!(" AND {}", condition), params)
+            },
+            None => (String::new(), Vec::new()),
+        }
+    }
+
+    pub fn totals_with_path(db: &Database, path: &str) -> Result<ItemTotals, FsPulseError> {
+        Self::totals(
+            db,
+            "path = ?1 OR (SELECT rtrim(roots.path, ?2) FROM roots WHERE roots.id = items.root_id) || ?2 || path = ?1",
+            vec![path.to_owned().into(), MAIN_SEPARATOR_STR.to_owned().into()],
+        )
+    }
+
+    pub fn totals_unverified_since(db: &Database, root_id: Option<i64>, verified_before: i64) -> Result<ItemTotals, FsPulseError> {
+        Self::totals(
+            db,
+            "(?1 IS NULL OR root_id = ?1) AND is_tombstone = 0 AND is_ignored = 0 AND item_type = 'F' AND (last_verified IS NULL OR last_verified <= ?2)",
+            vec![root_id.into(), verified_before.into()],
+        )
+    }
+
+    /// Marks the root's items matching `pattern` as ignored (or, with `ignored` false, no
+    /// longer ignored), along with everything beneath matching directories. Patterns are
+    /// globs matched against the path relative to the root; a pattern without a separator
+    /// matches an item's name at any depth. Returns the number of items changed, which with
+    /// `dry_run` are only counted
+    pub fn set_ignored(db: &Database, root_id: i64, pattern: &str, ignored: bool, dry_run: bool) -> Result<usize, FsPulseError> {
+        let pattern = pattern.trim_matches('/').replace('/', MAIN_SEPARATOR_STR);
+
+        let matches = |column: &str| if pattern.contains(MAIN_SEPARATOR_STR) {
+            format!("{column} GLOB ?2")
+        } else {
+            format!("({column} GLOB ?2 OR {column} GLOB '*' || ?3 || ?2)")
+        };
+
+        let condition = format!(
+            "root_id = ?1 AND is_ignored != ?4 AND ({} OR EXISTS (
+                SELECT 1 FROM items d
+                WHERE d.root_id = ?1 AND d.item_type = 'D' AND {}
+                    AND substr(items.path, 1, length(d.path) + 1) = d.path || ?3))",
+            matches("items.path"),
+            matches("d.path"),
+        );
+        let params = (root_id, &pattern, MAIN_SEPARATOR_STR, ignored);
+
+        let count = if dry_run {
+            db.conn.query_row(&format!("SELECT COUNT(*) FROM items WHERE {}", condition), params, |row| row.get(0))?
+        } else {
+            db.conn.execute(&format!("UPDATE items SET is_ignored = ?4 WHERE {}", condition), params)?
+        };
+
+        Ok(count)
+    }
+
+    fn totals(db: &Database, condition: &str, params: Vec<Value>) -> Result<ItemTotals, FsPulseError> {
+        db.conn.query_row(
+            &format!(
+                "SELECT
+                    COUNT(*),
+                    COALESCE(SUM(CASE WHEN item_type = 'F' THEN 1 ELSE 0 END), 0),
+                    COALESCE(SUM(CASE WHEN item_type = 'D' THEN 1 ELSE 0 END), 0),
+                    COALESCE(SUM(file_size), 0)
+                FROM items
+                WHERE {}",
+                condition),
+            params_from_iter(params),
+            |row| Ok(ItemTotals {
+                items: row.get(0)?,
+                files: row.get(1)?,
+                folders: row.get(2)?,
+                size: row.get(3)?,
+            }),
+        )
+        .map_err(FsPulseError::Database)
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Item {
+            id: row.get::<_, i64>(0)?,
+            root_id: row.get::<_, i64>(1)?,
+            path: row.get::<_, String>(2)?,
+            item_type: row.get::<_, String>(3)?,
+            is_tombstone: row.get::<_, bool>(4)?,
+            last_modified: row.get::<_, Option<i64>>(5)?,
+            file_size: row.get::<_, Option<i64>>(6)?,
+            file_hash: row.get::<_, Option<String>>(7)?,
+            file_is_valid: row.get::<_, Option<bool>>(8)?,
+            last_scan_id: row.get::<_, i64>(9)?,
+            last_hash_scan_id: row.get::<_, Option<i64>>(10)?,
+            last_is_valid_scan_id: row.get::<_, Option<i64>>(11)?,
+            last_verified: row.get::<_, Option<i64>>(12)?,
+            symlink_target: row.get::<_, Option<String>>(13)?,
+            symlink_dangling: row.get::<_, Option<bool>>(14)?,
+            owner_uid: row.get::<_, Option<i64>>(15)?,
+            owner_gid: row.get::<_, Option<i64>>(16)?,
+            sample_hash: row.get::<_, Option<String>>(17)?,
+            hash_algo: row.get::<_, Option<String>>(18)?,
+        })
+    }
+}
+
+/// A prepared query over item rows. The statement is owned here so that `iter` can
+/// hand out a real `Iterator`, letting callers use combinators, stop early, and
+/// propagate errors with `?` from inside the loop.
+pub struct ItemRows<'db> {
+    stmt: Statement<'db>,
+    params: Vec<Value>,
+}
+
+impl<'db> ItemRows<'db> {
+    fn prepare(db: &'db Database, sql: &str, params: Vec<Value>) -> Result<Self, FsPulseError> {
+        let stmt = db.conn.prepare(sql)?;
+        Ok(ItemRows { stmt, params })
+    }
+
+    pub fn iter(&mut self) -> Result<impl Iterator<Item = Result<Item, FsPulseError>> + '_, FsPulseError> {
+        let rows = self.stmt.query_map(params_from_iter(&self.params), Item::from_row)?;
+        Ok(rows.map(|row| row.map_err(FsPulseError::Database)))
+    }
+}
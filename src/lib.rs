@@ -0,0 +1,83 @@
+//! FsPulse records the state of directory trees and the changes found between scans of
+//! them. Besides the `fspulse` command, it can be embedded in other programs: a
+//! [`Scanner`] scans a root into a [`Database`], and the scans, items and changes recorded
+//! there are read back as data. [`Reports`] returns the data behind the command's reports,
+//! such as [`Reports::summary`], [`Reports::scans`] and [`Reports::changes`], and its
+//! `print_*` functions print them as the command does.
+//!
+//! ```no_run
+//! use fspulse::{Change, Database, Scanner};
+//!
+//! let mut db = Database::new(None)?;
+//! let scan = Scanner::new("/data/photos").deep(true).run(&mut db)?;
+//!
+//! let mut changes = Change::changes_in_scan(&db, scan.id())?;
+//! for change in changes.iter()? {
+//!     let change = change?;
+//!     println!("{} {}", change.change_type, change.item_path);
+//! }
+//! # Ok::<(), fspulse::FsPulseError>(())
+//! ```
+
+mod archive;
+mod audit;
+mod backfill;
+mod capabilities;
+mod checksums;
+mod database;
+mod changes;
+mod cli;
+mod config;
+mod compare;
+mod containers;
+mod error;
+mod estimate;
+mod excludes;
+mod gc;
+mod groups;
+mod hash;
+mod image;
+mod items;
+mod migrations;
+mod notify;
+mod owners;
+mod packages;
+mod progress;
+mod prompts;
+mod prune;
+mod repair;
+mod reports;
+mod retention;
+mod review;
+mod roots;
+mod scans;
+mod scan_machine;
+mod scanner;
+mod schedules;
+mod schema;
+mod search;
+mod selftest;
+mod timings;
+mod utils;
+mod verify;
+mod watch;
+
+pub use audit::AuditEntry;
+pub use changes::{Change, ChangeCounts, ChangeSelection, ChangeRows, ChangeType, Deletion, HistoryEntry, HistoryValues, ModifyKind};
+pub use database::Database;
+pub use error::FsPulseError;
+pub use hash::HashAlgo;
+pub use items::{Item, ItemRows, ItemTotals, ItemType};
+pub use reports::{
+    ChangeSource, ChangesReport, DuplicateFile, DuplicateSet, GroupRoot, ItemHistory, ItemSource, ItemsReport,
+    ReportFormat, Reports, RootsReport, ScanReport, ScansReport, Summary, TreeDirs,
+};
+pub use roots::{Root, RootSummary};
+pub use scan_machine::OfflinePolicy;
+pub use scanner::Scanner;
+pub use scans::{Exclusion, ExclusionReason, Scan, ScanState, ScanTotals};
+pub use schedules::Schedule;
+
+/// The command line, run by the `fspulse` binary
+#[doc(hidden)]
+pub use cli::Cli;
@@ -1,17 +1,4 @@
-mod database;
-mod changes;
-mod cli;
-mod error;
-mod hash;
-mod items;
-mod reports;
-mod roots;
-mod scans;
-mod scan_machine;
-mod schema;
-mod utils;
-
-use cli::Cli;
+use fspulse::Cli;
 use log::{debug, error};
 
 
@@ -25,6 +12,6 @@ fn main() {
     if let Err(err) = Cli::handle_command_line() {
         error!("{:?}", err);
         eprint!("{}", err);
-        std::process::exit(1);
+        std::process::exit(err.exit_code());
     }
 }
\ No newline at end of file
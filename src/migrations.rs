@@ -0,0 +1,256 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::items::ItemType;
+
+/// How a file's content moved around a root over a range of scans
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum MigrationKind {
+    /// The content left its old path in the same scan it appeared at a new one: a move
+    /// or rename
+    Moved,
+    /// The content was copied to a new path, and the old copy was deleted by a later scan
+    CopyThenDelete,
+    /// The content was copied to a new path and the old copy is still there
+    Copied,
+    /// Some copies of the content were deleted, and at least one is left
+    Consolidated,
+}
+
+impl MigrationKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Moved => "moved",
+            Self::CopyThenDelete => "copy-then-delete",
+            Self::Copied => "copied",
+            Self::Consolidated => "consolidated",
+        }
+    }
+}
+
+/// A path that held the content: from the scan it appeared in until the scan that found
+/// it gone, if one did. A path present before the root's changes were first recorded has
+/// no scan it appeared in
+#[derive(Clone, Debug)]
+pub struct ContentLocation {
+    pub item_id: i64,
+    pub path: String,
+    pub appeared: Option<i64>,
+    pub left: Option<i64>,
+}
+
+impl ContentLocation {
+    fn present_at(&self, scan_id: i64) -> bool {
+        self.appeared.is_none_or(|appeared| appeared <= scan_id) && self.left.is_none_or(|left| left > scan_id)
+    }
+
+    fn within(scan_id: Option<i64>, from_scan: i64, to_scan: i64) -> bool {
+        scan_id.is_some_and(|scan_id| scan_id >= from_scan && scan_id <= to_scan)
+    }
+}
+
+/// Content which appeared at new paths, left old ones, or both, over a range of scans
+#[derive(Clone, Debug)]
+pub struct Migration {
+    pub kind: MigrationKind,
+    pub hash_algo: String,
+    pub hash: String,
+    pub file_size: i64,
+    /// Copies present before the range
+    pub copies_before: usize,
+    /// Copies present at the end of the range
+    pub copies_after: usize,
+    /// The paths which held the content during the range, in the order they appeared
+    pub locations: Vec<ContentLocation>,
+}
+
+impl Migration {
+    /// The bytes taken by copies beyond the first, for content that was copied, or the bytes
+    /// freed, for content that was consolidated
+    pub fn extra_bytes(&self) -> i64 {
+        let copies = match self.kind {
+            MigrationKind::Copied => self.copies_after.saturating_sub(self.copies_before.max(1)),
+            MigrationKind::Consolidated => self.copies_before.saturating_sub(self.copies_after),
+            MigrationKind::Moved | MigrationKind::CopyThenDelete => 0,
+        };
+
+        copies as i64 * self.file_size
+    }
+
+    /// Where content went in the root's files over a range of scans, largest first. The
+    /// range defaults to every scan after the root's first, whose files are the baseline.
+    /// Content is followed by the hashes hashing scans recorded, through the files' changes,
+    /// so files that weren't hashed can't be followed. Content that's entirely gone by the
+    /// end of the range isn't a migration, and is left out
+    pub fn in_root(db: &Database, root_id: i64, from_scan: Option<i64>, to_scan: Option<i64>) -> Result<Vec<Migration>, FsPulseError> {
+        let bounds: (Option<i64>, Option<i64>) = db.conn.query_row(
+            "SELECT MIN(id), MAX(id) FROM scans WHERE root_id = ?",
+            [root_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        let (Some(first_scan), Some(last_scan)) = bounds else {
+            return Ok(Vec::new());
+        };
+        let from_scan = from_scan.unwrap_or(first_scan + 1);
+        let to_scan = to_scan.unwrap_or(last_scan);
+
+        let mut contents: BTreeMap<(String, String), (i64, Vec<ContentLocation>)> = BTreeMap::new();
+        for span in Self::spans(db, root_id)? {
+            contents.entry((span.hash_algo, span.hash))
+                .or_insert_with(|| (span.file_size, Vec::new()))
+                .1.push(span.location);
+        }
+
+        let mut migrations = Vec::new();
+        for ((hash_algo, hash), (file_size, locations)) in contents {
+            let copies_before = locations.iter().filter(|location| location.present_at(from_scan - 1)).count();
+            let copies_after = locations.iter().filter(|location| location.present_at(to_scan)).count();
+
+            let first_appeared = locations.iter()
+                .filter_map(|location| location.appeared)
+                .filter(|scan_id| *scan_id >= from_scan && *scan_id <= to_scan)
+                .min();
+            let first_left = locations.iter()
+                .filter_map(|location| location.left)
+                .filter(|scan_id| *scan_id >= from_scan && *scan_id <= to_scan)
+                .min();
+
+            let kind = match (first_appeared, first_left) {
+                _ if copies_after == 0 => continue,
+                (Some(appeared), Some(left)) if left > appeared => MigrationKind::CopyThenDelete,
+                (Some(_), Some(_)) => MigrationKind::Moved,
+                // New content with a single copy is just a new file
+                (Some(_), None) if copies_after > 1 => MigrationKind::Copied,
+                (None, Some(_)) => MigrationKind::Consolidated,
+                _ => continue,
+            };
+
+            let mut locations: Vec<ContentLocation> = locations.into_iter()
+                .filter(|location| location.present_at(from_scan - 1)
+                    || location.present_at(to_scan)
+                    || ContentLocation::within(location.appeared, from_scan, to_scan)
+                    || ContentLocation::within(location.left, from_scan, to_scan))
+                .collect();
+            locations.sort_by(|a, b| a.appeared.cmp(&b.appeared).then_with(|| a.path.cmp(&b.path)));
+
+            migrations.push(Migration { kind, hash_algo, hash, file_size, copies_before, copies_after, locations });
+        }
+
+        migrations.sort_by(|a, b| b.file_size.cmp(&a.file_size).then_with(|| a.hash.cmp(&b.hash)));
+        Ok(migrations)
+    }
+
+    /// Each span of time a file of the root held one content. Spans are rebuilt from an
+    /// item's current hash and the previous hashes its modifications recorded, back to when
+    /// the item was last added. What an item held before it was deleted and added again
+    /// isn't recorded
+    fn spans(db: &Database, root_id: i64) -> Result<Vec<ContentSpan>, FsPulseError> {
+        let mut changes: HashMap<i64, Vec<HistoryChange>> = HashMap::new();
+        let mut stmt = db.conn.prepare(
+            "SELECT c.item_id, c.scan_id, c.change_type, c.prev_hash, c.prev_file_size, c.old_path
+                FROM changes c
+                JOIN items i ON i.id = c.item_id
+                WHERE i.root_id = ? AND i.item_type = ? AND i.is_ignored = 0 AND i.hash_algo IS NOT NULL
+                ORDER BY c.id DESC"
+        )?;
+        let rows = stmt.query_map((root_id, ItemType::File.as_str()), |row| {
+            Ok((row.get::<_, i64>(0)?, HistoryChange {
+                scan_id: row.get(1)?,
+                change_type: row.get(2)?,
+                prev_hash: row.get(3)?,
+                prev_file_size: row.get(4)?,
+                old_path: row.get(5)?,
+            }))
+        })?;
+        for row in rows {
+            let (item_id, change) = row?;
+            changes.entry(item_id).or_default().push(change);
+        }
+
+        let mut stmt = db.conn.prepare(
+            "SELECT id, path, hash_algo, file_hash, file_size
+                FROM items
+                WHERE root_id = ? AND item_type = ? AND is_ignored = 0 AND hash_algo IS NOT NULL"
+        )?;
+        let mut rows = stmt.query((root_id, ItemType::File.as_str()))?;
+
+        let mut spans = Vec::new();
+        while let Some(row) = rows.next()? {
+            let item_id: i64 = row.get(0)?;
+            let mut path: String = row.get(1)?;
+            let hash_algo: String = row.get(2)?;
+            let file_size: Option<i64> = row.get(4)?;
+            let mut content: Option<(String, i64)> = row.get::<_, Option<String>>(3)?
+                .map(|hash| (hash, file_size.unwrap_or_default()));
+            let mut left = None;
+            let mut appeared = None;
+
+            // Walking back through the changes, newest first, a modification that recorded
+            // a previous hash is where the content after it appeared and the one before it left.
+            // A move is where the content appeared at its new path and left its old one
+            for change in changes.remove(&item_id).unwrap_or_default() {
+                match change.change_type.as_str() {
+                    "D" => left = Some(change.scan_id),
+                    "M" => if let Some(prev_hash) = change.prev_hash {
+                        let file_size = match content.take() {
+                            Some((hash, file_size)) => {
+                                spans.push(ContentSpan::new(&hash_algo, hash, file_size, item_id, &path, Some(change.scan_id), left));
+                                file_size
+                            },
+                            None => 0,
+                        };
+                        content = Some((prev_hash, change.prev_file_size.unwrap_or(file_size)));
+                        left = Some(change.scan_id);
+                    },
+                    "R" => if let Some(old_path) = change.old_path {
+                        if let Some((hash, file_size)) = &content {
+                            spans.push(ContentSpan::new(&hash_algo, hash.clone(), *file_size, item_id, &path, Some(change.scan_id), left));
+                        }
+                        path = old_path;
+                        left = Some(change.scan_id);
+                    },
+                    "A" | "T" => {
+                        appeared = Some(change.scan_id);
+                        break;
+                    },
+                    _ => {},
+                }
+            }
+
+            if let Some((hash, file_size)) = content {
+                spans.push(ContentSpan::new(&hash_algo, hash, file_size, item_id, &path, appeared, left));
+            }
+        }
+
+        Ok(spans)
+    }
+}
+
+/// A change to an item, as far as it tells what content the item held
+struct HistoryChange {
+    scan_id: i64,
+    change_type: String,
+    prev_hash: Option<String>,
+    prev_file_size: Option<i64>,
+    old_path: Option<String>,
+}
+
+/// A path's span of holding one content
+struct ContentSpan {
+    hash_algo: String,
+    hash: String,
+    file_size: i64,
+    location: ContentLocation,
+}
+
+impl ContentSpan {
+    fn new(hash_algo: &str, hash: String, file_size: i64, item_id: i64, path: &str, appeared: Option<i64>, left: Option<i64>) -> Self {
+        ContentSpan {
+            hash_algo: hash_algo.to_owned(),
+            hash,
+            file_size,
+            location: ContentLocation { item_id, path: path.to_owned(), appeared, left },
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus, Stdio};
+
+use serde::{Deserialize, Serialize};
+
+use crate::changes::ChangeCounts;
+use crate::config::{Config, NotifierConfig};
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::groups::RootGroups;
+use crate::roots::Root;
+use crate::scans::Scan;
+
+/// How serious the changes a scan found are. A notifier is only run for scans at least as
+/// serious as its threshold
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Items were added, modified or moved
+    #[default]
+    Info,
+    /// Items were deleted or changed type
+    Warning,
+    /// A root expected to be frozen changed
+    Critical,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+
+    /// How the severity is named in summary lines
+    fn label(severity: Option<Self>) -> &'static str {
+        match severity {
+            None => "OK",
+            Some(Self::Info) => "INFO",
+            Some(Self::Warning) => "WARNING",
+            Some(Self::Critical) => "CRITICAL",
+        }
+    }
+
+    /// The severity of a scan's changes, or None if it found none
+    fn of(counts: &ChangeCounts, frozen: bool) -> Option<Self> {
+        match counts.total() {
+            0 => None,
+            _ if frozen => Some(Self::Critical),
+            _ if counts.delete_count > 0 || counts.type_change_count > 0 => Some(Self::Warning),
+            _ => Some(Self::Info),
+        }
+    }
+}
+
+/// What a notifier's command is given on stdin, as JSON
+#[derive(Serialize)]
+struct ScanNotice<'a> {
+    root_id: i64,
+    root_path: &'a str,
+    scan_id: i64,
+    severity: Severity,
+    adds: i64,
+    modifies: i64,
+    deletes: i64,
+    type_changes: i64,
+    moves: i64,
+}
+
+pub struct Notifications {
+    // No fields
+}
+
+impl Notifications {
+    /// Runs the notifiers the config routes the root to, for those whose threshold the
+    /// scan's changes meet. A root's first scan only records what's there, so it isn't
+    /// notified. Returns a description of each notifier that failed: a failed notification
+    /// doesn't fail the scan
+    pub fn send_for_scan(db: &Database, config: &Config, root: &Root, scan: &Scan, frozen: bool) -> Result<Vec<String>, FsPulseError> {
+        if config.notifiers.is_empty() || !scan.has_completed_predecessor(db)? {
+            return Ok(Vec::new());
+        }
+
+        let counts = scan.change_counts();
+        let Some(severity) = Severity::of(counts, frozen) else {
+            return Ok(Vec::new());
+        };
+
+        let groups: Vec<String> = RootGroups::all(db)?.into_iter()
+            .filter(|(_, root_ids)| root_ids.contains(&root.id()))
+            .map(|(name, _)| name)
+            .collect();
+
+        let notice = serde_json::to_string(&ScanNotice {
+            root_id: root.id(),
+            root_path: root.path(),
+            scan_id: scan.id(),
+            severity,
+            adds: counts.add_count,
+            modifies: counts.modify_count,
+            deletes: counts.delete_count,
+            type_changes: counts.type_change_count,
+            moves: counts.move_count,
+        })?;
+
+        let failures = config.notifiers_for(root.path(), &groups).into_iter()
+            .filter(|notifier| severity >= notifier.min_severity)
+            .filter_map(|notifier| {
+                let failure = match Self::run(notifier, &notice, root, scan, severity) {
+                    Ok(status) if status.success() => return None,
+                    Ok(status) => format!("'{}' {}", notifier.command, status),
+                    Err(error) => error.to_string(),
+                };
+                Some(format!("Notifier '{}' failed for '{}': {}", notifier.name, root.path(), failure))
+            })
+            .collect();
+
+        Ok(failures)
+    }
+
+    /// A single line summing up a scan's changes for pagers and incident tools, in a form
+    /// that doesn't vary so alert parsers can rely on it:
+    ///
+    /// `LEVEL: N changes, N adds, N modifies, N deletes, N type changes, N moves, scan ID, root PATH`
+    ///
+    /// LEVEL is OK, INFO, WARNING or CRITICAL, graded as notifications are, and every count
+    /// is given, with the same words whatever the number. The root's path comes last,
+    /// since it may hold commas. A root's first scan records what's there, so it's OK
+    pub fn summary_line(db: &Database, root: &Root, scan: &Scan, frozen: bool) -> Result<String, FsPulseError> {
+        let counts = scan.change_counts();
+        let severity = match scan.has_completed_predecessor(db)? {
+            true => Severity::of(counts, frozen),
+            false => None,
+        };
+
+        Ok(format!(
+            "{}: {} changes, {} adds, {} modifies, {} deletes, {} type changes, {} moves, scan {}, root {}",
+            Severity::label(severity), counts.total(), counts.add_count, counts.modify_count, counts.delete_count,
+            counts.type_change_count, counts.move_count, scan.id(), root.path(),
+        ))
+    }
+
+    /// Runs the notifier's command through the shell, with the notice on stdin and the
+    /// root, scan and severity in the environment
+    fn run(notifier: &NotifierConfig, notice: &str, root: &Root, scan: &Scan, severity: Severity) -> io::Result<ExitStatus> {
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+
+        let mut child = command.arg(&notifier.command)
+            .env("FSPULSE_ROOT", root.path())
+            .env("FSPULSE_SCAN_ID", scan.id().to_string())
+            .env("FSPULSE_SEVERITY", severity.as_str())
+            .stdin(Stdio::piped())
+            .spawn()?;
+
+        // A command which doesn't read the notice closes stdin early, which isn't a failure
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = writeln!(stdin, "{}", notice);
+        }
+
+        child.wait()
+    }
+}
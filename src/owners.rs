@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use rusqlite::Row;
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::items::Item;
+
+/// A change to the owner or group of an item, as recorded by a scan
+#[derive(Clone, Debug)]
+pub struct OwnershipChange {
+    pub change_id: i64,
+    pub scan_id: i64,
+    pub item_path: String,
+    pub prev_uid: Option<i64>,
+    pub prev_gid: Option<i64>,
+    pub uid: Option<i64>,
+    pub gid: Option<i64>,
+}
+
+impl OwnershipChange {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(OwnershipChange {
+            change_id: row.get(0)?,
+            scan_id: row.get(1)?,
+            item_path: row.get(2)?,
+            prev_uid: row.get(3)?,
+            prev_gid: row.get(4)?,
+            uid: row.get(5)?,
+            gid: row.get(6)?,
+        })
+    }
+}
+
+/// What a usage report adds items up by
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum UsageBy {
+    /// The user owning each item
+    User,
+    /// The group of each item
+    Group,
+}
+
+impl UsageBy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Group => "group",
+        }
+    }
+
+    /// The name of a user or group id, or the id if it has no name here
+    pub fn name(&self, id: Option<i64>) -> String {
+        match self {
+            Self::User => Owners::user_name(id),
+            Self::Group => Owners::group_name(id),
+        }
+    }
+}
+
+impl FromStr for UsageBy {
+    type Err = FsPulseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "user" => Ok(Self::User),
+            "group" => Ok(Self::Group),
+            _ => Err(FsPulseError::Error(format!("Invalid usage grouping '{}'", s))),
+        }
+    }
+}
+
+/// The items of a root held by one user or group as of a scan, and how they changed since
+/// the previous scan. The deltas are None when there's no previous scan to compare with
+#[derive(Clone, Debug, Default)]
+pub struct OwnerUsage {
+    pub id: Option<i64>,
+    pub items: i64,
+    pub files: i64,
+    pub bytes: i64,
+    pub item_delta: Option<i64>,
+    pub byte_delta: Option<i64>,
+}
+
+pub struct Owners {
+    // No fields
+}
+
+impl Owners {
+    /// Adds a user to those allowed to own the root's items. Returns false if the user
+    /// was already allowed
+    pub fn allow(db: &Database, root_id: i64, uid: i64) -> Result<bool, FsPulseError> {
+        let added = db.conn.execute(
+            "INSERT OR IGNORE INTO allowed_owners (root_id, uid) VALUES (?, ?)",
+            (root_id, uid),
+        )?;
+
+        Ok(added > 0)
+    }
+
+    /// Removes a user from those allowed to own the root's items. Returns false if the
+    /// user wasn't allowed
+    pub fn disallow(db: &Database, root_id: i64, uid: i64) -> Result<bool, FsPulseError> {
+        let removed = db.conn.execute(
+            "DELETE FROM allowed_owners WHERE root_id = ? AND uid = ?",
+            (root_id, uid),
+        )?;
+
+        Ok(removed > 0)
+    }
+
+    /// The users allowed to own the root's items. If there are none, any owner is allowed
+    pub fn allowed(db: &Database, root_id: i64) -> Result<Vec<i64>, FsPulseError> {
+        let mut stmt = db.conn.prepare("SELECT uid FROM allowed_owners WHERE root_id = ? ORDER BY uid ASC")?;
+        let rows = stmt.query_map([root_id], |row| row.get(0))?;
+
+        let mut uids = Vec::new();
+        for row in rows {
+            uids.push(row?);
+        }
+
+        Ok(uids)
+    }
+
+    /// Calls `func` with each change of owner or group recorded for the root's items by
+    /// scans from `from_scan` to `to_scan` (inclusive; either end may be open), oldest first
+    pub fn for_each_ownership_change<F>(db: &Database, root_id: i64, from_scan: Option<i64>, to_scan: Option<i64>, mut func: F) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&OwnershipChange) -> Result<(), FsPulseError>,
+    {
+        // The owner a change moved to is the previous owner of the item's next ownership
+        // change, or the item's current owner if there wasn't one
+        let mut stmt = db.conn.prepare(
+            "SELECT c.id, c.scan_id, i.path, c.prev_owner_uid, c.prev_owner_gid,
+                    COALESCE(n.prev_owner_uid, i.owner_uid), COALESCE(n.prev_owner_gid, i.owner_gid)
+                FROM changes c
+                JOIN items i ON i.id = c.item_id
+                LEFT JOIN changes n ON n.id = (
+                    SELECT MIN(id) FROM changes
+                    WHERE item_id = c.item_id AND id > c.id AND prev_owner_uid IS NOT NULL)
+                WHERE i.root_id = ?1 AND i.is_ignored = 0 AND c.prev_owner_uid IS NOT NULL
+                    AND (?2 IS NULL OR c.scan_id >= ?2) AND (?3 IS NULL OR c.scan_id <= ?3)
+                ORDER BY c.scan_id ASC, i.path ASC"
+        )?;
+
+        let rows = stmt.query_map((root_id, from_scan, to_scan), OwnershipChange::from_row)?;
+
+        for row in rows {
+            func(&row?)?;
+        }
+
+        Ok(())
+    }
+
+    /// The root's items as of the scan, added up by user or group, largest first. With
+    /// `prev_scan_id`, each total comes with its change since that scan, and users or groups
+    /// which no longer hold anything are listed with totals of zero. Items whose owner
+    /// isn't known, because they were recorded before owners were or by a platform without
+    /// them, are added up together
+    pub fn usage(db: &Database, root_id: i64, scan_id: i64, prev_scan_id: Option<i64>, by: UsageBy) -> Result<Vec<OwnerUsage>, FsPulseError> {
+        let mut usage = Self::add_up(&Item::items_as_of(db, root_id, scan_id)?, by);
+
+        if let Some(prev_scan_id) = prev_scan_id {
+            let prev_usage = Self::add_up(&Item::items_as_of(db, root_id, prev_scan_id)?, by);
+
+            for owner in usage.values_mut() {
+                owner.item_delta = Some(owner.items);
+                owner.byte_delta = Some(owner.bytes);
+            }
+
+            for (id, prev) in prev_usage {
+                let owner = usage.entry(id).or_insert_with(|| OwnerUsage {
+                    id,
+                    item_delta: Some(0),
+                    byte_delta: Some(0),
+                    ..Default::default()
+                });
+                owner.item_delta = owner.item_delta.map(|delta| delta - prev.items);
+                owner.byte_delta = owner.byte_delta.map(|delta| delta - prev.bytes);
+            }
+        }
+
+        let mut usage: Vec<OwnerUsage> = usage.into_values().collect();
+        usage.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.items.cmp(&a.items)).then(a.id.cmp(&b.id)));
+
+        Ok(usage)
+    }
+
+    fn add_up(items: &[Item], by: UsageBy) -> BTreeMap<Option<i64>, OwnerUsage> {
+        let mut usage: BTreeMap<Option<i64>, OwnerUsage> = BTreeMap::new();
+
+        for item in items {
+            let id = match by {
+                UsageBy::User => item.owner_uid(),
+                UsageBy::Group => item.owner_gid(),
+            };
+
+            let owner = usage.entry(id).or_insert_with(|| OwnerUsage { id, ..Default::default() });
+            owner.items += 1;
+            if let Some(file_size) = item.file_size() {
+                owner.files += 1;
+                owner.bytes += file_size;
+            }
+        }
+
+        usage
+    }
+
+    /// The user id of a user given by name or id
+    pub fn resolve_user(user: &str) -> Result<i64, FsPulseError> {
+        if let Ok(uid) = user.parse::<u32>() {
+            return Ok(uid.into());
+        }
+
+        Self::uid_by_name(user)
+            .ok_or_else(|| FsPulseError::Error(format!("Unknown user '{}'. Use a user id instead", user)))
+    }
+
+    /// A user's name, or their id if it has no name here
+    pub fn user_name(uid: Option<i64>) -> String {
+        match uid {
+            Some(uid) => Self::name_of_user(uid).unwrap_or_else(|| uid.to_string()),
+            None => "-".to_string(),
+        }
+    }
+
+    /// A group's name, or its id if it has no name here
+    pub fn group_name(gid: Option<i64>) -> String {
+        match gid {
+            Some(gid) => Self::name_of_group(gid).unwrap_or_else(|| gid.to_string()),
+            None => "-".to_string(),
+        }
+    }
+
+    #[cfg(unix)]
+    fn uid_by_name(name: &str) -> Option<i64> {
+        uzers::get_user_by_name(name).map(|user| user.uid().into())
+    }
+
+    #[cfg(not(unix))]
+    fn uid_by_name(_name: &str) -> Option<i64> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn name_of_user(uid: i64) -> Option<String> {
+        let user = uzers::get_user_by_uid(u32::try_from(uid).ok()?)?;
+        Some(user.name().to_string_lossy().into_owned())
+    }
+
+    #[cfg(not(unix))]
+    fn name_of_user(_uid: i64) -> Option<String> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn name_of_group(gid: i64) -> Option<String> {
+        let group = uzers::get_group_by_gid(u32::try_from(gid).ok()?)?;
+        Some(group.name().to_string_lossy().into_owned())
+    }
+
+    #[cfg(not(unix))]
+    fn name_of_group(_gid: i64) -> Option<String> {
+        None
+    }
+}
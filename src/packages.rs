@@ -0,0 +1,288 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::changes::ChangeType;
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::roots::Root;
+use crate::scans::Scan;
+use crate::utils::Utils;
+
+/// Where dpkg keeps each installed package's file list (`<package>.list`) and the md5 of
+/// each file it shipped (`<package>.md5sums`)
+const DPKG_INFO_DIR: &str = "/var/lib/dpkg/info";
+
+/// The directories merged into /usr on most current distributions. Packages may list
+/// their files under either path, while roots are recorded by their canonical path
+const USR_MERGED_DIRS: &[&str] = &["/usr/bin", "/usr/sbin", "/usr/lib", "/usr/lib32", "/usr/lib64", "/usr/libx32"];
+
+/// A package manager whose file lists explain changes to the files it installed
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PackageManager {
+    Dpkg,
+    Rpm,
+}
+
+impl PackageManager {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PackageManager::Dpkg => "dpkg",
+            PackageManager::Rpm => "rpm",
+        }
+    }
+
+    /// The package manager of the system fspulse runs on, if it has one of those known
+    pub fn detect() -> Option<Self> {
+        if Path::new(DPKG_INFO_DIR).is_dir() {
+            Some(PackageManager::Dpkg)
+        } else if Command::new("rpm").arg("--version").output().is_ok_and(|output| output.status.success()) {
+            Some(PackageManager::Rpm)
+        } else {
+            None
+        }
+    }
+
+    /// The packaged files among `paths`, by path
+    fn owners(&self, paths: &HashSet<PathBuf>) -> Result<HashMap<PathBuf, PackagedFile>, FsPulseError> {
+        match self {
+            PackageManager::Dpkg => Self::dpkg_owners(paths),
+            PackageManager::Rpm => Self::rpm_owners(paths),
+        }
+    }
+
+    /// A .list file is rewritten whenever its package is installed or upgraded, so its
+    /// modification time is when the package last changed
+    fn dpkg_owners(paths: &HashSet<PathBuf>) -> Result<HashMap<PathBuf, PackagedFile>, FsPulseError> {
+        let mut owners = HashMap::new();
+
+        for entry in fs::read_dir(DPKG_INFO_DIR)? {
+            let list_path = entry?.path();
+            if list_path.extension().is_none_or(|ext| ext != "list") {
+                continue;
+            }
+            let Some(package) = list_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()) else {
+                continue;
+            };
+
+            let installed = fs::metadata(&list_path)?.modified().ok().map(Utils::system_time_to_db_time);
+            let mut owns_any = false;
+            for line in fs::read_to_string(&list_path)?.lines() {
+                let path = PathBuf::from(line);
+                if paths.contains(&path) {
+                    owners.insert(path, PackagedFile { package: package.clone(), installed, digest: None });
+                    owns_any = true;
+                }
+            }
+            if !owns_any {
+                continue;
+            }
+
+            // Configuration files aren't listed, since they're expected to be edited
+            let Ok(md5sums) = fs::read_to_string(list_path.with_extension("md5sums")) else {
+                continue;
+            };
+            for line in md5sums.lines() {
+                let Some((md5, path)) = line.split_once("  ") else {
+                    continue;
+                };
+                if let Some(owner) = owners.get_mut(&Path::new("/").join(path)) {
+                    owner.digest = Some(md5.to_owned());
+                }
+            }
+        }
+
+        Ok(owners)
+    }
+
+    /// rpm records a digest for each file, an md5 for older packages and usually a sha256
+    /// for newer ones
+    fn rpm_owners(paths: &HashSet<PathBuf>) -> Result<HashMap<PathBuf, PackagedFile>, FsPulseError> {
+        let output = Command::new("rpm")
+            .args(["-qa", "--qf", "[%{FILENAMES}\\t%{FILEDIGESTS}\\t%{INSTALLTIME}\\t%{NAME}\\n]"])
+            .output()?;
+        if !output.status.success() {
+            return Err(FsPulseError::Error(format!(
+                "rpm failed to list packaged files: {}", String::from_utf8_lossy(&output.stderr).trim())));
+        }
+
+        let mut owners = HashMap::new();
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let mut fields = line.split('\t');
+            let (Some(path), Some(digest), Some(installed), Some(package)) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            let path = PathBuf::from(path);
+            if paths.contains(&path) {
+                owners.insert(path, PackagedFile {
+                    package: package.to_owned(),
+                    installed: installed.parse().ok(),
+                    digest: (!digest.is_empty()).then(|| digest.to_owned()),
+                });
+            }
+        }
+
+        Ok(owners)
+    }
+}
+
+/// A file installed by a package
+struct PackagedFile {
+    package: String,
+    /// When the package was last installed or upgraded
+    installed: Option<i64>,
+    /// The hash of the file as the package shipped it
+    digest: Option<String>,
+}
+
+impl PackagedFile {
+    /// Whether the file's hash is the one the package shipped. The algorithm is told by the
+    /// length of the digest
+    fn matches(&self, hash_algo: &str, hash: &str) -> bool {
+        self.digest.as_ref().is_some_and(|digest| {
+            let algo_matches = match digest.len() {
+                32 => hash_algo == "md5",
+                64 => hash_algo == "sha256",
+                _ => false,
+            };
+            algo_matches && digest.eq_ignore_ascii_case(hash)
+        })
+    }
+}
+
+/// A change to a packaged file which a package upgrade doesn't explain
+#[derive(Clone, Debug)]
+pub struct UnexplainedChange {
+    pub change_type: ChangeType,
+    pub path: PathBuf,
+    pub package: String,
+}
+
+/// What cross-referencing a scan's changes with the package manager found
+#[derive(Clone, Debug, Default)]
+pub struct PackageSummary {
+    /// Changes to packaged files which a package upgrade explains
+    pub explained: usize,
+    /// Changes to packaged files which nothing explains, most worth a reviewer's time
+    pub unexplained: Vec<UnexplainedChange>,
+    /// Changes to files no package installed
+    pub unpackaged: usize,
+}
+
+/// A scan's added and modified file, as recorded
+struct ChangedFile {
+    change_id: i64,
+    change_type: ChangeType,
+    path: PathBuf,
+    /// The file's hash, if the scan took it
+    hash: Option<(String, String)>,
+}
+
+pub struct Packages {
+    // No fields
+}
+
+impl Packages {
+    /// Cross-references the files a scan found added or modified with the package manager's
+    /// file lists. A change is explained if the file's package was installed or upgraded
+    /// since the root's previous scan, or if the scan hashed the file and its content is
+    /// what the package shipped. Explained changes are acknowledged, with the package named
+    /// in the review comment and the package manager as reviewer, which leaves the changes
+    /// nothing explains for people to review. Changes already reviewed are left as they are.
+    /// With `dry_run`, nothing is recorded but the summary is the same
+    pub fn explain_scan(db: &mut Database, scan: &Scan, manager: PackageManager, dry_run: bool) -> Result<PackageSummary, FsPulseError> {
+        let root = Root::get_by_id(db, scan.root_id())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", scan.root_id())))?;
+
+        // Upgrades since the previous scan started are those it may not have seen
+        let since = match scan.previous_completed_id(db)? {
+            Some(previous_id) => Scan::get_by_id(db, previous_id)?.map(|previous| previous.time_of_scan()),
+            None => None,
+        };
+
+        let files = {
+            let mut stmt = db.conn.prepare(
+                "SELECT changes.id, changes.change_type, items.path,
+                        CASE WHEN items.last_hash_scan_id = changes.scan_id THEN items.hash_algo END,
+                        CASE WHEN items.last_hash_scan_id = changes.scan_id THEN items.file_hash END
+                    FROM changes
+                    JOIN items ON items.id = changes.item_id
+                    WHERE changes.scan_id = ? AND changes.change_type IN (?, ?) AND changes.acknowledged = 0
+                    AND items.item_type = 'F' AND items.is_ignored = 0
+                    ORDER BY items.path ASC"
+            )?;
+            let rows = stmt.query_map((scan.id(), ChangeType::Add.as_str(), ChangeType::Modify.as_str()), |row| {
+                let change_type: String = row.get(1)?;
+                let path: String = row.get(2)?;
+                let hash_algo: Option<String> = row.get(3)?;
+                let hash: Option<String> = row.get(4)?;
+                Ok(ChangedFile {
+                    change_id: row.get(0)?,
+                    change_type: change_type.parse().unwrap_or(ChangeType::Modify),
+                    path: root.item_path(&path),
+                    hash: hash_algo.zip(hash),
+                })
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let paths: HashSet<PathBuf> = files.iter().flat_map(|file| Self::package_paths(&file.path)).collect();
+        let owners = manager.owners(&paths)?;
+
+        let mut summary = PackageSummary::default();
+        let tx = db.conn.transaction()?;
+
+        for file in files {
+            let Some(owner) = Self::package_paths(&file.path).iter().find_map(|path| owners.get(path)) else {
+                summary.unpackaged += 1;
+                continue;
+            };
+
+            let upgraded = match (since, owner.installed) {
+                (Some(since), Some(installed)) => installed >= since,
+                // A root's first scan records everything as added
+                (None, _) => true,
+                (Some(_), None) => false,
+            };
+            let comment = if file.hash.as_ref().is_some_and(|(hash_algo, hash)| owner.matches(hash_algo, hash)) {
+                format!("Content as shipped by package {}", owner.package)
+            } else if upgraded {
+                format!("Package {} was installed or upgraded", owner.package)
+            } else {
+                summary.unexplained.push(UnexplainedChange {
+                    change_type: file.change_type,
+                    path: file.path,
+                    package: owner.package.clone(),
+                });
+                continue;
+            };
+
+            tx.execute(
+                "UPDATE changes SET acknowledged = 1, review_comment = ?, reviewer = ? WHERE id = ?",
+                (&comment, manager.as_str(), file.change_id),
+            )?;
+            summary.explained += 1;
+        }
+
+        if dry_run {
+            tx.rollback()?;
+        } else {
+            tx.commit()?;
+        }
+
+        Ok(summary)
+    }
+
+    /// The paths a package may list the file under: its own, and for a file in a directory
+    /// merged into /usr, the path outside /usr
+    fn package_paths(path: &Path) -> Vec<PathBuf> {
+        let mut paths = vec![path.to_path_buf()];
+        if USR_MERGED_DIRS.iter().any(|dir| path.starts_with(dir)) {
+            if let Ok(unmerged) = path.strip_prefix("/usr") {
+                paths.push(Path::new("/").join(unmerged));
+            }
+        }
+        paths
+    }
+}
@@ -0,0 +1,213 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use indicatif::ProgressBar;
+
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What a scan is doing, as of the last update
+struct ProgressState {
+    root: String,
+    scan_id: i64,
+    phase: &'static str,
+    phase_started: Instant,
+    items: u64,
+    bytes_hashed: u64,
+    bytes_total: Option<u64>,
+    current_path: Option<String>,
+    // Tracks how far into the current file hashing has got
+    file_bar: Option<ProgressBar>,
+}
+
+/// Machine-readable progress for scripts which wrap fspulse, written to stderr as one JSON
+/// object per line. While a scan is running, a "progress" record is written every second,
+/// and "phase" and "complete" records are written as the scan moves along. When disabled,
+/// every method does nothing
+pub struct ProgressJson {
+    state: Arc<Mutex<Option<ProgressState>>>,
+    stop: Option<Sender<()>>,
+    ticker: Option<JoinHandle<()>>,
+}
+
+impl ProgressJson {
+    pub fn new(enabled: bool) -> Self {
+        let state = Arc::new(Mutex::new(None));
+
+        if !enabled {
+            return ProgressJson { state, stop: None, ticker: None };
+        }
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let ticker_state = Arc::clone(&state);
+        let ticker = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(PROGRESS_INTERVAL) {
+                if let Some(state) = ticker_state.lock().unwrap().as_ref() {
+                    Self::emit(&Self::progress_record(state));
+                }
+            }
+        });
+
+        ProgressJson { state, stop: Some(stop), ticker: Some(ticker) }
+    }
+
+    fn enabled(&self) -> bool {
+        self.stop.is_some()
+    }
+
+    /// Starts a phase of a scan ("scanning", "sweeping" or "hashing"). `bytes_total` is the
+    /// amount of data the phase will read, if known
+    pub fn phase(&self, root: &str, scan_id: i64, phase: &'static str, bytes_total: Option<u64>, file_bar: Option<&ProgressBar>) {
+        if !self.enabled() {
+            return;
+        }
+
+        let state = ProgressState {
+            root: root.to_owned(),
+            scan_id,
+            phase,
+            phase_started: Instant::now(),
+            items: 0,
+            bytes_hashed: 0,
+            bytes_total,
+            current_path: None,
+            file_bar: file_bar.cloned(),
+        };
+
+        let mut record = Self::record_start("phase", &state);
+        if let Some(bytes_total) = bytes_total {
+            let _ = write!(record, ",\"bytes_total\":{}", bytes_total);
+        }
+        record.push('}');
+        Self::emit(&record);
+
+        *self.state.lock().unwrap() = Some(state);
+    }
+
+    /// Notes that the phase has reached another item
+    pub fn item(&self, path: &Path) {
+        if !self.enabled() {
+            return;
+        }
+
+        if let Some(state) = self.state.lock().unwrap().as_mut() {
+            state.items += 1;
+            state.current_path = Some(path.to_string_lossy().into_owned());
+        }
+    }
+
+    /// Notes that a file of `bytes` has been hashed
+    pub fn hashed(&self, bytes: u64) {
+        if !self.enabled() {
+            return;
+        }
+
+        if let Some(state) = self.state.lock().unwrap().as_mut() {
+            state.bytes_hashed += bytes;
+        }
+    }
+
+    /// Ends the scan's progress records
+    pub fn complete(&self, file_count: i64, folder_count: i64) {
+        if !self.enabled() {
+            return;
+        }
+
+        if let Some(state) = self.state.lock().unwrap().take() {
+            let mut record = Self::record_start("complete", &state);
+            let _ = write!(record, ",\"files\":{},\"folders\":{}}}", file_count, folder_count);
+            Self::emit(&record);
+        }
+    }
+
+    /// Writes a warning or error as a "message" record, so that it's read like the other
+    /// records rather than as a line of text among them. Written during a scan, the record
+    /// names its root and scan
+    pub fn message(&self, message: &str) {
+        if !self.enabled() {
+            return;
+        }
+
+        let mut record = match self.state.lock().unwrap().as_ref() {
+            Some(state) => Self::record_start("message", state),
+            None => "{\"event\":\"message\"".to_string(),
+        };
+        let _ = write!(record, ",\"message\":{}}}", Self::json_string(message));
+        Self::emit(&record);
+    }
+
+    fn record_start(event: &str, state: &ProgressState) -> String {
+        format!(
+            "{{\"event\":\"{}\",\"root\":{},\"scan_id\":{},\"phase\":\"{}\"",
+            event, Self::json_string(&state.root), state.scan_id, state.phase,
+        )
+    }
+
+    fn progress_record(state: &ProgressState) -> String {
+        let in_file = state.file_bar.as_ref().map_or(0, |bar| bar.position());
+        let bytes_hashed = state.bytes_hashed + in_file;
+        let elapsed = state.phase_started.elapsed().as_secs_f64();
+
+        // Hashing dominates a deep scan, so the estimate is based on the bytes left to read
+        let eta = match state.bytes_total {
+            Some(total) if bytes_hashed > 0 && elapsed > 0.0 => {
+                let remaining = total.saturating_sub(bytes_hashed) as f64;
+                format!("{:.0}", elapsed * remaining / bytes_hashed as f64)
+            },
+            _ => "null".to_string(),
+        };
+
+        let mut record = Self::record_start("progress", state);
+        let _ = write!(
+            record,
+            ",\"items\":{},\"bytes_hashed\":{},\"bytes_total\":{},\"current_path\":{},\"elapsed_secs\":{:.0},\"eta_secs\":{}}}",
+            state.items,
+            bytes_hashed,
+            state.bytes_total.map_or("null".to_string(), |total| total.to_string()),
+            state.current_path.as_deref().map_or("null".to_string(), Self::json_string),
+            elapsed,
+            eta,
+        );
+
+        record
+    }
+
+    fn emit(record: &str) {
+        eprintln!("{}", record);
+    }
+
+    fn json_string(s: &str) -> String {
+        let mut quoted = String::with_capacity(s.len() + 2);
+        quoted.push('"');
+
+        for c in s.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                '\t' => quoted.push_str("\\t"),
+                c if (c as u32) < 0x20 => {
+                    let _ = write!(quoted, "\\u{:04x}", c as u32);
+                },
+                c => quoted.push(c),
+            }
+        }
+
+        quoted.push('"');
+        quoted
+    }
+}
+
+impl Drop for ProgressJson {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the ticker, which then exits
+        self.stop.take();
+        if let Some(ticker) = self.ticker.take() {
+            let _ = ticker.join();
+        }
+    }
+}
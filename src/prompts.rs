@@ -0,0 +1,87 @@
+use dialoguer::{Confirm, Select};
+use std::io::{self, IsTerminal};
+
+use crate::error::FsPulseError;
+
+/// How operations which are destructive or surprising get the go-ahead. By default the
+/// user is asked, `--yes` answers every question with the default go-ahead, and
+/// `--non-interactive` fails rather than asking
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum PromptPolicy {
+    #[default]
+    Interactive,
+    AssumeYes,
+    NonInteractive,
+}
+
+/// The outcome of asking for confirmation
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Answer {
+    Assumed,        // --yes was given
+    Confirmed,      // The user agreed
+    Declined,       // The user disagreed
+    Unavailable,    // Nobody could be asked
+}
+
+impl PromptPolicy {
+    pub fn from_flags(yes: bool, non_interactive: bool) -> Self {
+        match (yes, non_interactive) {
+            (true, _) => PromptPolicy::AssumeYes,
+            (_, true) => PromptPolicy::NonInteractive,
+            _ => PromptPolicy::Interactive,
+        }
+    }
+
+    /// The policy for work that runs without anyone watching, such as concurrent scans.
+    /// An explicit `--yes` still applies, but no questions are asked
+    pub fn unattended(self) -> Self {
+        match self {
+            PromptPolicy::AssumeYes => PromptPolicy::AssumeYes,
+            _ => PromptPolicy::NonInteractive,
+        }
+    }
+
+    fn can_ask(self) -> bool {
+        self == PromptPolicy::Interactive && io::stdin().is_terminal()
+    }
+
+    pub fn confirm(self, prompt: &str) -> Answer {
+        match self {
+            PromptPolicy::AssumeYes => Answer::Assumed,
+            _ if !self.can_ask() => Answer::Unavailable,
+            _ => match Confirm::new().with_prompt(prompt).default(false).interact() {
+                Ok(true) => Answer::Confirmed,
+                _ => Answer::Declined,
+            },
+        }
+    }
+
+    /// Like `confirm`, but it is an error if nobody can be asked. Returns whether to proceed
+    pub fn require(self, prompt: &str) -> Result<bool, FsPulseError> {
+        match self.confirm(prompt) {
+            Answer::Assumed | Answer::Confirmed => Ok(true),
+            Answer::Declined => Ok(false),
+            Answer::Unavailable => Err(Self::unavailable_error(prompt)),
+        }
+    }
+
+    /// Asks the user to choose one of `items`. With `--yes`, `assumed` is chosen
+    pub fn select(self, prompt: &str, items: &[&str], assumed: usize) -> Result<usize, FsPulseError> {
+        match self {
+            PromptPolicy::AssumeYes => Ok(assumed),
+            _ if !self.can_ask() => Err(Self::unavailable_error(prompt)),
+            _ => Select::new()
+                .with_prompt(prompt)
+                .items(items)
+                .default(assumed)
+                .interact()
+                .map_err(|err| FsPulseError::Error(err.to_string())),
+        }
+    }
+
+    fn unavailable_error(prompt: &str) -> FsPulseError {
+        FsPulseError::Error(format!(
+            "Confirmation required but not running interactively: {}\nRerun with --yes to proceed",
+            prompt))
+    }
+}
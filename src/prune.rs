@@ -0,0 +1,131 @@
+use std::time::SystemTime;
+
+use rusqlite::Transaction;
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::prompts::PromptPolicy;
+use crate::utils::Utils;
+
+// The rows recorded by each scan, as (description, table), removed along with the scans
+const SCAN_ROWS: &[(&str, &str)] = &[
+    ("Changes", "changes"),
+    ("Exclusions", "scan_exclusions"),
+    ("Exclusion counts", "scan_exclusion_counts"),
+    ("Exclude rules", "scan_exclude_rules"),
+    ("Unchecked directories", "unchecked_dirs"),
+];
+
+/// Which scans `prune` removes. A scan is removed only if every limit given allows it
+#[derive(Clone, Debug, Default)]
+pub struct PrunePolicy {
+    pub root_id: Option<i64>,
+    /// Keep this many of each root's most recent scans
+    pub keep_last: Option<u32>,
+    /// Keep scans run less than this many seconds ago
+    pub older_than: Option<i64>,
+}
+
+pub struct Prune {
+    // No fields
+}
+
+impl Prune {
+    /// Removes old scans and the changes and other rows they recorded, leaving the items as
+    /// the latest scans left them. Scans which haven't finished, and each root's latest
+    /// completed scan, are always kept. What would be removed is counted first, and nothing
+    /// is removed unless `prompts` allows it; with `dry_run`, only the counts are shown.
+    /// Everything is removed in one transaction. With `vacuum`, the database is compacted
+    /// afterwards to give the space back to the file system
+    pub fn do_prune(db: &mut Database, policy: &PrunePolicy, dry_run: bool, vacuum: bool, prompts: PromptPolicy) -> Result<(), FsPulseError> {
+        let now = Utils::system_time_to_db_time(SystemTime::now());
+        let (scans, rows) = {
+            let tx = db.conn.transaction()?;
+            Self::select_pruned(&tx, policy, now)?;
+            let mut counts = Vec::new();
+            for (description, table) in SCAN_ROWS {
+                let count: usize = tx.query_row(
+                    &format!("SELECT COUNT(*) FROM {} WHERE scan_id IN (SELECT id FROM pruned_scans)", table), [], |row| row.get(0))?;
+                counts.push((*description, count));
+            }
+            let scans: usize = tx.query_row("SELECT COUNT(*) FROM pruned_scans", [], |row| row.get(0))?;
+            tx.rollback()?;
+            (scans, counts)
+        };
+        let total = scans + rows.iter().map(|(_, count)| count).sum::<usize>();
+
+        for (description, count) in &rows {
+            println!("{} to remove: {}", description, count);
+        }
+        println!("Scans to remove: {}", scans);
+        if dry_run {
+            println!("Would remove {} scans, {} rows in all (dry run)", scans, total);
+            return Ok(());
+        }
+        if scans == 0 {
+            println!("Nothing to remove");
+            return Ok(());
+        }
+
+        if !prompts.require(&format!("Remove {} scans and {} rows in all? This can't be undone", scans, total))? {
+            println!("Nothing was removed");
+            return Ok(());
+        }
+
+        let tx = db.conn.transaction()?;
+        Self::select_pruned(&tx, policy, now)?;
+
+        let mut total = 0;
+        for (_, table) in SCAN_ROWS {
+            total += tx.execute(&format!("DELETE FROM {} WHERE scan_id IN (SELECT id FROM pruned_scans)", table), [])?;
+        }
+
+        // Items keep no reference to a removed scan. An item last seen by one, such as a file
+        // deleted long ago, counts as last seen by its root's oldest scan left
+        let remapped = tx.execute(
+            "UPDATE items SET last_scan_id = (
+                    SELECT MIN(s.id) FROM scans s WHERE s.root_id = items.root_id AND s.id NOT IN (SELECT id FROM pruned_scans))
+                WHERE last_scan_id IN (SELECT id FROM pruned_scans)",
+            [],
+        )?;
+        tx.execute("UPDATE items SET last_hash_scan_id = NULL WHERE last_hash_scan_id IN (SELECT id FROM pruned_scans)", [])?;
+        tx.execute("UPDATE items SET last_is_valid_scan_id = NULL WHERE last_is_valid_scan_id IN (SELECT id FROM pruned_scans)", [])?;
+
+        let scans = tx.execute("DELETE FROM scans WHERE id IN (SELECT id FROM pruned_scans)", [])?;
+        total += scans;
+
+        tx.execute("DROP TABLE pruned_scans", [])?;
+        tx.commit()?;
+        println!("Removed {} scans, {} rows in all", scans, total);
+        if remapped > 0 {
+            println!("{} items last seen by a removed scan are now counted as last seen by their root's oldest scan left", remapped);
+        }
+
+        // VACUUM can't run inside a transaction. Checkpointing afterwards empties the
+        // write-ahead log the vacuumed pages went to
+        if vacuum {
+            let size_before = db.size();
+            db.conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")?;
+            let freed = size_before.saturating_sub(db.size());
+            println!("Vacuumed the database, freeing {}", Utils::format_bytes(freed));
+        }
+
+        Ok(())
+    }
+
+    /// Fills the temporary table `pruned_scans` with the scans the policy removes
+    fn select_pruned(tx: &Transaction, policy: &PrunePolicy, now: i64) -> Result<(), FsPulseError> {
+        tx.execute("CREATE TEMP TABLE pruned_scans (id INTEGER PRIMARY KEY)", [])?;
+        tx.execute(
+            "INSERT INTO pruned_scans (id)
+                SELECT s.id FROM scans s
+                WHERE (?1 IS NULL OR s.root_id = ?1) AND s.state IN (4, 5)
+                AND (?2 IS NULL OR (SELECT COUNT(*) FROM scans n WHERE n.root_id = s.root_id AND n.id > s.id) >= ?2)
+                AND (?3 IS NULL OR s.time_of_scan < ?4 - ?3)
+                AND s.id IS NOT (SELECT MAX(id) FROM scans l WHERE l.root_id = s.root_id AND l.state = 4)",
+            (policy.root_id, policy.keep_last, policy.older_than, now),
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use indicatif::ProgressBar;
+
+use crate::audit::AuditEntry;
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::hash::{Hash, HashAlgo, DEFAULT_BUFFER_SIZE};
+use crate::items::{Item, ItemType};
+use crate::roots::Root;
+use crate::scans::{Scan, ScanState};
+
+/// What was wrong with a file `repair` restored
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Damage {
+    /// `verify` found its content no longer matches its hash
+    Corrupt,
+    /// It's gone since the baseline scan
+    Missing,
+}
+
+impl Damage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Damage::Corrupt => "corrupt",
+            Damage::Missing => "missing",
+        }
+    }
+}
+
+/// A damaged file, and whether it was restored
+#[derive(Clone, Debug)]
+pub struct RepairedFile {
+    pub path: PathBuf,
+    pub damage: Damage,
+    /// Why the file couldn't be restored, if it couldn't
+    pub failure: Option<String>,
+}
+
+pub struct Repairs {
+    // No fields
+}
+
+impl Repairs {
+    /// Restores the root's damaged files from a mirror holding a known-good copy of it, such
+    /// as a backup or replica. A file is damaged if it was in the root at the baseline scan
+    /// and is now missing, or if `verify` found its content no longer matches the hash
+    /// recorded at the baseline. Each is copied from the same relative path in the mirror,
+    /// but only if the mirror's copy has that hash. The copy is written beside the file,
+    /// given the recorded modification time and renamed into place, then hashed again.
+    /// Each file restored is recorded in the audit log. With `dry_run`, the mirror's copies
+    /// are checked but nothing is written
+    pub fn run(db: &Database, root: &Root, baseline: &Scan, mirror: &Path, dry_run: bool) -> Result<Vec<RepairedFile>, FsPulseError> {
+        if let Some(scan) = Scan::get_latest_for_root(db, root.id())?
+            .filter(|s| s.state() != ScanState::Completed && s.state() != ScanState::Aborted)
+        {
+            return Err(FsPulseError::Error(format!(
+                "Scan Id {} of '{}' is incomplete. Resume or abort it with `fspulse scan` before repairing",
+                scan.id(), root.path())));
+        }
+        if baseline.root_id() != root.id() || baseline.state() != ScanState::Completed {
+            return Err(FsPulseError::Error(format!(
+                "Scan Id {} isn't a completed scan of '{}'", baseline.id(), root.path())));
+        }
+
+        let mirror = mirror.canonicalize()
+            .map_err(|error| FsPulseError::Error(format!("Mirror '{}' can't be read: {}", mirror.display(), error)))?;
+        if !mirror.is_dir() || mirror == Path::new(root.path()) {
+            return Err(FsPulseError::Error(format!("Mirror '{}' must be a directory other than the root", mirror.display())));
+        }
+
+        // Hashes which verifying found a file no longer has
+        let mismatched: HashSet<(i64, String)> = {
+            let mut stmt = db.conn.prepare(
+                "SELECT DISTINCT m.item_id, m.expected_hash
+                    FROM verification_mismatches m
+                    JOIN verifications v ON v.id = m.verification_id
+                    WHERE v.root_id = ?"
+            )?;
+            let rows = stmt.query_map([root.id()], |row| Ok((row.get(0)?, row.get(1)?)))?;
+            rows.collect::<Result<_, _>>()?
+        };
+
+        let mut repaired = Vec::new();
+
+        for item in Item::items_as_of(db, root.id(), baseline.id())? {
+            if item.item_type() != ItemType::File.as_str() {
+                continue;
+            }
+            let (Some(hash), Some(hash_algo)) = (item.file_hash(), item.hash_algo()) else {
+                continue;
+            };
+
+            let path = root.item_path(item.path());
+            let damage = match fs::symlink_metadata(&path) {
+                Err(error) if error.kind() == io::ErrorKind::NotFound => Damage::Missing,
+                Ok(_) if mismatched.contains(&(item.id(), hash.to_owned())) => Damage::Corrupt,
+                _ => continue,
+            };
+
+            let hash_algo: HashAlgo = hash_algo.parse()?;
+            let source = mirror.join(item.path());
+
+            // A corrupt file put right since needs nothing
+            if damage == Damage::Corrupt && Self::hash_of(&path, hash_algo).is_ok_and(|found| found == hash) {
+                continue;
+            }
+
+            let restored = Self::check_source(&source, hash_algo, hash)
+                .and_then(|_| match dry_run {
+                    true => Ok(()),
+                    false => Self::restore(&source, &path, hash_algo, hash, item.last_modified()),
+                });
+
+            if restored.is_ok() && !dry_run {
+                AuditEntry::record_action(db, "repair", &format!(
+                    "Restored {} file '{}' from '{}' ({} {})",
+                    damage.as_str(), path.display(), source.display(), hash_algo.as_str(), hash))?;
+
+                // The file is as its last hashing scan recorded it, unless it was deleted since
+                db.conn.execute(
+                    "UPDATE items SET last_verified = strftime('%s', 'now', 'utc') WHERE id = ? AND is_tombstone = 0 AND file_hash = ?",
+                    (item.id(), hash),
+                )?;
+            }
+
+            repaired.push(RepairedFile { path, damage, failure: restored.err() });
+        }
+
+        Ok(repaired)
+    }
+
+    /// Checks that the mirror's copy of a file is the known-good one
+    fn check_source(source: &Path, hash_algo: HashAlgo, hash: &str) -> Result<(), String> {
+        if !source.is_file() {
+            return Err(format!("'{}' isn't in the mirror", source.display()));
+        }
+        match Self::hash_of(source, hash_algo) {
+            Ok(found) if found == hash => Ok(()),
+            Ok(_) => Err(format!("'{}' doesn't match the recorded hash either", source.display())),
+            Err(error) => Err(format!("'{}' can't be read: {}", source.display(), error)),
+        }
+    }
+
+    /// Copies the file into place through a temporary file beside it, so the damaged file is
+    /// only replaced by a complete copy, and hashes it again once it's there
+    fn restore(source: &Path, path: &Path, hash_algo: HashAlgo, hash: &str, last_modified: Option<i64>) -> Result<(), String> {
+        let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+            return Err(format!("'{}' has no parent directory", path.display()));
+        };
+        let temp_path = parent.join(format!(".{}.fspulse-repair", file_name.to_string_lossy()));
+
+        let copied = fs::create_dir_all(parent)
+            .and_then(|_| fs::copy(source, &temp_path))
+            .and_then(|_| {
+                let temp = File::options().write(true).open(&temp_path)?;
+                if let Some(last_modified) = last_modified {
+                    temp.set_modified(Self::db_time_to_system_time(last_modified))?;
+                }
+                temp.sync_all()
+            })
+            .and_then(|_| fs::rename(&temp_path, path));
+        if let Err(error) = copied {
+            let _ = fs::remove_file(&temp_path);
+            return Err(format!("copying from the mirror failed: {}", error));
+        }
+
+        match Self::hash_of(path, hash_algo) {
+            Ok(found) if found == hash => Ok(()),
+            Ok(_) => Err("the restored copy doesn't match the recorded hash".to_string()),
+            Err(error) => Err(format!("the restored copy can't be read: {}", error)),
+        }
+    }
+
+    fn hash_of(path: &Path, hash_algo: HashAlgo) -> Result<String, FsPulseError> {
+        match Hash::compute_hashes(path, &ProgressBar::hidden(), hash_algo, false, DEFAULT_BUFFER_SIZE)? {
+            Some((hash, _)) => Ok(hash),
+            None => Err(FsPulseError::Error(format!("'{}' is being written", path.display()))),
+        }
+    }
+
+    fn db_time_to_system_time(db_time: i64) -> SystemTime {
+        match db_time >= 0 {
+            true => UNIX_EPOCH + Duration::from_secs(db_time as u64),
+            false => UNIX_EPOCH - Duration::from_secs(db_time.unsigned_abs()),
+        }
+    }
+}
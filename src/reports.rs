@@ -1,16 +1,35 @@
-use crate::changes::{Change, ChangeType};
+use crate::archive::ArchiveMember;
+use crate::audit::AuditEntry;
+use crate::changes::{Change, ChangeCounts, ChangePeriod, ChangeSelection, ChangeType, Deletion, HistoryEntry, HistoryValues, PeriodChanges};
+use crate::compare::{Compare, Difference};
 use crate::error::FsPulseError;
+use crate::estimate::{Estimate, ScanKindEstimate};
+use crate::excludes::ExcludeRules;
+use crate::groups::RootGroups;
 use crate::database::Database;
-use crate::items::Item;
-use crate::roots::Root;
-use crate::scans::Scan;
+use crate::items::{Item, ItemRows, ItemTotals};
+use crate::migrations::{ContentLocation, Migration, MigrationKind};
+use crate::owners::{OwnerUsage, Owners, OwnershipChange, UsageBy};
+use crate::retention::{AgeBasis, RetainedFile};
+use crate::roots::{Root, RootSummary};
+use crate::scans::{Exclusion, ExclusionReason, Scan, ScanState, ScanTotals};
+use crate::schedules::Schedule;
+use crate::search::{ChangeFilter, Search, SearchHit};
 use crate::utils::Utils;
+use crate::verify::{Mismatch, Verification};
 
+use std::borrow::Cow;
 use std::cmp::max;
-use std::io::{self, Stdout};
-use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Stdout, Write};
+use std::path::{Component, Path, PathBuf, MAIN_SEPARATOR_STR};
 use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use chrono::Utc;
+use console::Term;
 use rusqlite::Result;
+use serde::{Serialize, Serializer};
 use tablestream::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -18,6 +37,10 @@ pub enum ReportFormat {
     Tree,
     Table,
     Csv,
+    Json,
+    Git,
+    /// Sets of identical files as fdupes and jdupes list them
+    Fdupes,
 }
 
 impl FromStr for ReportFormat {
@@ -27,195 +50,2068 @@ impl FromStr for ReportFormat {
             "tree" => Ok(ReportFormat::Tree),
             "table" => Ok(ReportFormat::Table),
             "csv" => Ok(ReportFormat::Csv),
+            "json" => Ok(ReportFormat::Json),
+            "git" => Ok(ReportFormat::Git),
+            "fdupes" | "jdupes" => Ok(ReportFormat::Fdupes),
             _ => Err(FsPulseError::Error("Invalid format specified.".to_string())),
         }
     }
 }
 
+/// Which rows the tree of a scan's changes shows for directories
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TreeDirs {
+    /// Changed directories, and the directories leading to changed files
+    #[default]
+    All,
+    /// Only changed files and other non-directories, under the directories leading to them
+    Hide,
+    /// Only directories holding changes, each with a count of the changes directly in it
+    Only,
+    /// Only the directories this many levels below the root holding changes, each with a
+    /// count of the changes anywhere beneath it. Shallower changes count in their own directory
+    RollUp(usize),
+}
+
+/// The changes in one directory, for the diffstat printed by `--format git`
+#[derive(Copy, Clone, Debug, Default)]
+struct DirStat {
+    added: i64,
+    modified: i64,
+    deleted: i64,
+    renamed: i64,
+}
+
+impl DirStat {
+    /// The widest a directory's histogram bar is drawn
+    const BAR_WIDTH: i64 = 40;
+
+    fn total(&self) -> i64 {
+        self.added + self.modified + self.deleted + self.renamed
+    }
+
+    /// A bar of one mark per change, scaled down to fit when the busiest directory has
+    /// more changes than fit. Each kind of change present gets at least one mark
+    fn bar(&self, max_total: i64) -> String {
+        let scale = |count: i64| match count {
+            0 => 0,
+            count if max_total <= Self::BAR_WIDTH => count,
+            count => max(1, count * Self::BAR_WIDTH / max_total),
+        };
+
+        [(self.added, "+"), (self.modified, "~"), (self.deleted, "-"), (self.renamed, ">")]
+            .iter()
+            .map(|(count, mark)| mark.repeat(scale(*count) as usize))
+            .collect()
+    }
+}
+
 pub struct Reports {
     // No fields
 }
 
+/// A root's activity as written by the JSON form of the activity report
+#[derive(Serialize)]
+struct RootActivity<'a> {
+    root_id: i64,
+    #[serde(serialize_with = "Reports::serialize_path")]
+    root_path: &'a str,
+    period: &'static str,
+    periods: Vec<PeriodChanges>,
+}
+
+/// An item as written by the CSV form of the items report. Times are local, and values
+/// that weren't recorded are left empty
+#[derive(Serialize)]
+struct ItemRecord {
+    item_id: i64,
+    root_id: i64,
+    path: String,
+    item_type: String,
+    is_tombstone: bool,
+    last_modified: Option<String>,
+    file_size: Option<i64>,
+    file_hash: Option<String>,
+    hash_algo: Option<String>,
+    sample_hash: Option<String>,
+    file_is_valid: Option<bool>,
+    last_scan_id: i64,
+    last_hash_scan_id: Option<i64>,
+    last_is_valid_scan_id: Option<i64>,
+    last_verified: Option<String>,
+    symlink_target: Option<String>,
+    owner_uid: Option<i64>,
+    owner_gid: Option<i64>,
+}
+
+impl From<Item> for ItemRecord {
+    fn from(item: Item) -> Self {
+        ItemRecord {
+            item_id: item.id(),
+            root_id: item.root_id(),
+            path: Reports::show_path(&Reports::item_path(item.path())).into_owned(),
+            item_type: item.item_type().to_owned(),
+            is_tombstone: item.is_tombstone(),
+            last_modified: Utils::format_db_time_or_none(item.last_modified()),
+            file_size: item.file_size(),
+            file_hash: item.file_hash().map(str::to_owned),
+            hash_algo: item.hash_algo().map(str::to_owned),
+            sample_hash: item.sample_hash().map(str::to_owned),
+            file_is_valid: item.file_is_valid(),
+            last_scan_id: item.last_scan_id(),
+            last_hash_scan_id: item.last_hash_scan_id(),
+            last_is_valid_scan_id: item.last_is_valid_scan_id(),
+            last_verified: Utils::format_db_time_or_none(item.last_verified()),
+            symlink_target: item.symlink_target().map(|target| Reports::show_path(target).into_owned()),
+            owner_uid: item.owner_uid(),
+            owner_gid: item.owner_gid(),
+        }
+    }
+}
+
+/// A change as written by the CSV form of the changes report. Times are local, and values
+/// that weren't recorded are left empty
+#[derive(Serialize)]
+struct ChangeRecord {
+    change_id: i64,
+    scan_id: i64,
+    item_id: i64,
+    item_type: String,
+    item_path: String,
+    change_type: String,
+    old_path: Option<String>,
+    prev_last_modified: Option<String>,
+    prev_file_size: Option<i64>,
+    prev_hash: Option<String>,
+    prev_sample_hash: Option<String>,
+    prev_is_valid: Option<bool>,
+    prev_symlink_target: Option<String>,
+    similarity: Option<i64>,
+    acknowledged: bool,
+    review_comment: Option<String>,
+}
+
+impl From<Change> for ChangeRecord {
+    fn from(change: Change) -> Self {
+        ChangeRecord {
+            change_id: change.id,
+            scan_id: change.scan_id,
+            item_id: change.item_id,
+            item_type: change.item_type,
+            item_path: Reports::show_path(&Reports::item_path(&change.item_path)).into_owned(),
+            change_type: change.change_type,
+            old_path: change.old_path.map(|path| Reports::show_path(&Reports::item_path(&path)).into_owned()),
+            prev_last_modified: Utils::format_db_time_or_none(change.prev_last_modified),
+            prev_file_size: change.prev_file_size,
+            prev_hash: change.prev_hash,
+            prev_sample_hash: change.prev_sample_hash,
+            prev_is_valid: change.prev_is_valid,
+            prev_symlink_target: change.prev_symlink_target.map(|target| Reports::show_path(&target).into_owned()),
+            similarity: change.similarity,
+            acknowledged: change.acknowledged,
+            review_comment: change.review_comment,
+        }
+    }
+}
+
+/// A set of identical files: files hashed by the same algorithm whose hashes and sizes
+/// match. This is also how the JSON form of the duplicates report writes it
+#[derive(Clone, Debug, Serialize)]
+pub struct DuplicateSet {
+    pub hash_algo: String,
+    pub hash: String,
+    pub file_size: i64,
+    /// The bytes taken by the files beyond the first
+    pub wasted_bytes: i64,
+    pub files: Vec<DuplicateFile>,
+}
+
+impl DuplicateSet {
+    /// Whether the set's files are in more than one root
+    pub fn spans_roots(&self) -> bool {
+        self.files.iter().any(|file| file.root_id != self.files[0].root_id)
+    }
+}
+
+/// A file of a duplicate set, as it was seen by its root's latest scan
+#[derive(Clone, Debug, Serialize)]
+pub struct DuplicateFile {
+    pub item_id: i64,
+    #[serde(skip)]
+    pub root_id: i64,
+    /// The path relative to the root
+    #[serde(serialize_with = "Reports::serialize_path")]
+    pub path: String,
+}
+
+/// A file of a duplicate set, as a table shows it
+struct DuplicateRow {
+    hash_algo: String,
+    hash: String,
+    file_size: i64,
+    file: DuplicateFile,
+}
+
+/// A root of a group, with its latest scan
+#[derive(Clone, Debug)]
+pub struct GroupRoot {
+    pub root: Root,
+    pub latest_scan: Option<Scan>,
+}
+
+/// The health of many roots at once: when each was last scanned, how much it holds and how
+/// much it changed recently
+#[derive(Clone, Debug)]
+pub struct Summary {
+    pub roots: Vec<RootSummary>,
+    /// A root is stale if it hasn't completed a scan in this many days
+    pub stale_days: u32,
+    /// Changes are counted from scans started in this many days
+    pub change_days: u32,
+    stale_before: i64,
+}
+
+impl Summary {
+    /// Whether the root hasn't completed a scan in `stale_days`
+    pub fn is_stale(&self, root: &RootSummary) -> bool {
+        root.last_scan_time.is_none_or(|time| time < self.stale_before)
+    }
+}
+
+/// A scan, with what the scans report shows beneath it
+#[derive(Clone, Debug)]
+pub struct ScanReport {
+    pub scan: Scan,
+    pub root: Root,
+    /// How much the numbers of files and folders changed since the root's previous
+    /// completed scan, if the two can be compared
+    pub count_deltas: Option<(i64, i64)>,
+    /// How many paths were excluded for each reason
+    pub exclusion_counts: Vec<(ExclusionReason, i64)>,
+    /// The exclude rules the scan was started with, as they'd be given on the command line
+    pub exclude_rules: Vec<String>,
+    /// The paths the scan excluded, if it was asked to record them
+    pub exclusions: Vec<Exclusion>,
+    /// The directories a budgeted scan didn't get to
+    pub unchecked_dirs: Vec<String>,
+}
+
+/// The latest scans, newest first, with their totals
+#[derive(Clone, Debug)]
+pub struct ScansReport {
+    pub scans: Vec<Scan>,
+    pub totals: ScanTotals,
+}
+
+/// Roots, with the schedules for scanning them
+#[derive(Clone, Debug)]
+pub struct RootsReport {
+    pub roots: Vec<Root>,
+    /// Whether one root was asked for, rather than every root
+    pub single: bool,
+    pub schedules: Vec<Schedule>,
+}
+
+/// What the items of an items report were chosen by
+#[derive(Clone, Debug)]
+pub enum ItemSource {
+    Id(i64),
+    /// Items with the path, absolute or within their root, in any root
+    Path(String),
+    /// The items seen by the root's latest scan or, with `as_of`, the root's items as they
+    /// were when that scan ran
+    Root { as_of: Option<Box<Scan>> },
+    /// Items whose paths match a search
+    Search(String),
+}
+
+/// Items chosen for a report, with their totals
+#[derive(Clone, Debug)]
+pub struct ItemsReport {
+    pub source: ItemSource,
+    pub items: Vec<Item>,
+    pub totals: ItemTotals,
+    /// The root the items were chosen from, or the root of the item asked for by id
+    pub root: Option<Root>,
+    /// When the item was deleted, if a deleted item was asked for by id
+    pub deletion: Option<Deletion>,
+}
+
+/// What the changes of a changes report were chosen by
+#[derive(Clone, Debug)]
+pub enum ChangeSource {
+    Change(i64),
+    Item(Box<Item>),
+    Scan(i64),
+}
+
+/// Changes chosen for a report
+#[derive(Clone, Debug)]
+pub struct ChangesReport {
+    pub source: ChangeSource,
+    pub changes: Vec<Change>,
+    /// The root the changes were found in
+    pub root: Option<Root>,
+    /// For a scan's changes, the deletions and additions found by hash to be files renamed,
+    /// as the ids of the deletion and the addition
+    pub renames: Vec<(i64, i64)>,
+}
+
+/// Every change recorded to an item, oldest first. This is also how the JSON form of the
+/// history report writes it
+#[derive(Clone, Debug, Serialize)]
+pub struct ItemHistory {
+    pub item_id: i64,
+    pub root_id: i64,
+    #[serde(serialize_with = "Reports::serialize_path")]
+    pub path: String,
+    #[serde(skip)]
+    pub root: Option<Root>,
+    pub changes: Vec<HistoryEntry>,
+}
+
+// Widest path shown in a table column, or 0 to show paths in full
+static MAX_PATH_WIDTH: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_PATH_WIDTH);
+
+// Width of the lines reports are laid out to fit, or 0 to use the terminal's width
+static REPORT_WIDTH: AtomicUsize = AtomicUsize::new(0);
+
+// Whether paths are shown with unusual characters escaped
+static ESCAPE_PATHS: AtomicBool = AtomicBool::new(false);
+
+// Whether item paths are shown relative to the current directory
+static RELATIVE_PATHS: AtomicBool = AtomicBool::new(false);
+
+// The current directory relative to the root being reported on, if it's inside it
+static CWD_IN_ROOT: Mutex<Option<PathBuf>> = Mutex::new(None);
+const DEFAULT_MAX_PATH_WIDTH: usize = 80;
+
+/// The width reports are laid out to fit when it isn't given and output isn't to a terminal
+const DEFAULT_REPORT_WIDTH: usize = 100;
+
+/// The number of results a fuzzy item search shows unless a limit is given
+const DEFAULT_FUZZY_LIMIT: i64 = 50;
+
+/// An item whose modification time is implausible. Skew is the distance from the time
+/// of the scan, and is only meaningful for future timestamps
+struct TimestampFinding {
+    item: Item,
+    kind: &'static str,
+    skew: Option<i64>,
+}
+
 impl Reports {
-    pub fn report_scans(
-        db: &Database, 
-        scan_id: Option<u32>, 
-        last: u32, 
-        format: ReportFormat,
-    ) -> Result<(), FsPulseError> 
-    {
-        match scan_id {
-            Some(scan_id) => {
-                let scan = Scan::get_by_id(db, scan_id.into())?;
-                Self::print_scan(db, &scan, format)?;
-            },
-            None => Reports::print_scans(db, last)?,
+    /// Sets the widest path shown in table columns. Longer paths are shortened in the
+    /// middle. With `None`, paths are shown in full
+    pub fn set_max_path_width(max_width: Option<usize>) {
+        MAX_PATH_WIDTH.store(max_width.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// Sets the width reports are laid out to fit. With `None`, the terminal's width is used
+    pub fn set_width(width: Option<usize>) {
+        REPORT_WIDTH.store(width.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    /// The width reports are laid out to fit, if it was given or output is to a terminal
+    fn known_width() -> Option<usize> {
+        match REPORT_WIDTH.load(Ordering::Relaxed) {
+            0 => Term::stdout().size_checked().map(|(_, columns)| columns as usize),
+            width => Some(width),
+        }
+    }
+
+    /// The width reports are laid out to fit
+    fn width() -> usize {
+        Self::known_width().unwrap_or(DEFAULT_REPORT_WIDTH)
+    }
+
+    /// The widest path shown in table columns unless one is given: half the width of the
+    /// report, so that the other columns fit beside it, up to DEFAULT_MAX_PATH_WIDTH
+    pub fn default_max_path_width() -> usize {
+        match Self::known_width() {
+            Some(width) => (width / 2).clamp(20, DEFAULT_MAX_PATH_WIDTH),
+            None => DEFAULT_MAX_PATH_WIDTH,
+        }
+    }
+
+    /// Sets whether paths are shown with unusual characters escaped
+    pub fn set_escape_paths(escape: bool) {
+        ESCAPE_PATHS.store(escape, Ordering::Relaxed);
+    }
+
+    /// Sets whether item paths are shown relative to the current directory when it's
+    /// inside the root being reported on
+    pub fn set_relative_paths(relative: bool) {
+        RELATIVE_PATHS.store(relative, Ordering::Relaxed);
+    }
+
+    pub fn escaping_paths() -> bool {
+        ESCAPE_PATHS.load(Ordering::Relaxed)
+    }
+
+    /// A path as it should be shown, escaped if asked for
+    pub fn show_path(path: &str) -> Cow<'_, str> {
+        if ESCAPE_PATHS.load(Ordering::Relaxed) {
+            Utils::escape_path(path)
+        } else {
+            Cow::Borrowed(path)
+        }
+    }
+
+    /// Writes a path to JSON: as a string, or with escaping asked for, as an array of the
+    /// bytes of its UTF-8 encoding so that nothing about it is open to interpretation
+    pub fn serialize_path<S: Serializer>(path: &str, serializer: S) -> Result<S::Ok, S::Error> {
+        if ESCAPE_PATHS.load(Ordering::Relaxed) {
+            serializer.collect_seq(path.as_bytes())
+        } else {
+            serializer.serialize_str(path)
+        }
+    }
+
+    /// As `serialize_path`, for a path that may be absent
+    pub fn serialize_opt_path<S: Serializer>(path: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+        match path {
+            Some(path) => Self::serialize_path(path, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    fn fit_path(path: &str) -> String {
+        let path = Self::show_path(path);
+        match MAX_PATH_WIDTH.load(Ordering::Relaxed) {
+            0 => path.into_owned(),
+            max_width => Utils::truncate_middle(&path, max_width),
+        }
+    }
+
+    /// Notes the root whose items are about to be shown, so that with relative paths
+    /// asked for, item paths can be shown relative to the current directory
+    fn use_root(root: &Root) {
+        let cwd_in_root = if RELATIVE_PATHS.load(Ordering::Relaxed) {
+            std::env::current_dir()
+                .and_then(|cwd| cwd.canonicalize())
+                .ok()
+                .and_then(|cwd| cwd.strip_prefix(root.path()).ok().map(Path::to_path_buf))
+        } else {
+            None
+        };
+
+        if let Ok(mut guard) = CWD_IN_ROOT.lock() {
+            *guard = cwd_in_root;
+        }
+    }
+
+    fn use_root_by_id(db: &Database, root_id: i64) -> Result<(), FsPulseError> {
+        if let Some(root) = Root::get_by_id(db, root_id)? {
+            Self::use_root(&root);
+        }
+        Ok(())
+    }
+
+    /// An item's path, which is relative to its root, as it should be shown: relative to
+    /// the current directory if that's inside the root and relative paths were asked for
+    fn item_path(path: &str) -> Cow<'_, str> {
+        let guard = CWD_IN_ROOT.lock().ok();
+        let Some(cwd) = guard.as_ref().and_then(|guard| guard.as_ref()) else {
+            return Cow::Borrowed(path);
+        };
+
+        let cwd: Vec<Component> = cwd.components().collect();
+        let item: Vec<Component> = Path::new(path).components().collect();
+        let common = cwd.iter().zip(&item).take_while(|(a, b)| a == b).count();
+
+        let mut relative = PathBuf::new();
+        for _ in common..cwd.len() {
+            relative.push("..");
+        }
+        for component in &item[common..] {
+            relative.push(component);
+        }
+
+        if relative.as_os_str().is_empty() {
+            Cow::Borrowed(".")
+        } else {
+            Cow::Owned(relative.to_string_lossy().into_owned())
+        }
+    }
+
+    fn fit_item_path(path: &str) -> String {
+        Self::fit_path(&Self::item_path(path))
+    }
+
+    /// The scan, with what the scans report shows beneath it, or None if there's no such scan
+    pub fn scan(db: &Database, scan_id: i64) -> Result<Option<ScanReport>, FsPulseError> {
+        let Some(scan) = Scan::get_by_id(db, scan_id)? else {
+            return Ok(None);
+        };
+        let root = Root::get_by_id(db, scan.root_id())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", scan.root_id())))?;
+
+        let count_deltas = match scan.file_count() {
+            Some(_) => scan.count_deltas(db)?,
+            None => None,
+        };
+        let exclusion_counts = match scan.excluded_count() {
+            0 => Vec::new(),
+            _ => scan.exclusion_counts(db)?,
+        };
+
+        let mut exclusions = Vec::new();
+        scan.for_each_exclusion(db, |exclusion| {
+            exclusions.push(exclusion.clone());
+            Ok(())
+        })?;
+
+        let mut unchecked_dirs = Vec::new();
+        scan.for_each_unchecked_dir(db, |path| {
+            unchecked_dirs.push(path.to_owned());
+            Ok(())
+        })?;
+
+        Ok(Some(ScanReport {
+            scan,
+            root,
+            count_deltas,
+            exclusion_counts,
+            exclude_rules: ExcludeRules::for_scan(db, scan.id())?.describe(),
+            exclusions,
+            unchecked_dirs,
+        }))
+    }
+
+    /// The last `last` scans, newest first, with their totals
+    pub fn scans(db: &Database, last: u32) -> Result<ScansReport, FsPulseError> {
+        let mut scans = Vec::new();
+        Scan::for_each_scan(db, last, |_db, scan| {
+            scans.push(*scan);
+            Ok(())
+        })?;
+
+        Ok(ScansReport { scans, totals: Scan::totals_for_last(db, last)? })
+    }
+
+    /// Prints the scans as a table, with their totals
+    pub fn print_scans(report: &ScansReport) -> Result<(), FsPulseError> {
+        let mut stream = Reports::begin_scans_table("Scans", "No Scans");
+
+        for scan in &report.scans {
+            stream.row(*scan)?;
         }
 
+        stream.finish()?;
+        Self::print_scan_totals(&report.totals);
+
         Ok(())
     }
 
-    pub fn report_roots(db: &Database, root_id: Option<u32>, root_path: Option<String>, _format: ReportFormat) -> Result<(), FsPulseError> {
-        if root_id.is_none() && root_path.is_none(){
-            let mut stream = Reports::begin_roots_table();
-            
-            Root::for_each_root(
-                db,
-                |root| {
-                    stream.row(root.clone())?;
+    /// Every root, or the one given by id or path, with the schedules for scanning them
+    pub fn roots(db: &Database, root_id: Option<u32>, root_path: Option<String>) -> Result<RootsReport, FsPulseError> {
+        let root = match (root_id, root_path) {
+            (Some(root_id), _) => Some(Root::get_by_id(db, root_id.into())?
+                .ok_or_else(|| FsPulseError::Error("Root Not Found".to_string()))?),
+            (_, Some(root_path)) => Some(Root::find_by_path_arg(db, &root_path)?
+                .ok_or_else(|| FsPulseError::Error(format!("Root Path '{}' not found", &root_path)))?),
+            (None, None) => None,
+        };
+
+        let mut schedules = Vec::new();
+        Schedule::for_each_schedule(db, root.as_ref().map(Root::id), |schedule| {
+            schedules.push(schedule.clone());
+            Ok(())
+        })?;
+
+        let roots = match root {
+            Some(root) => return Ok(RootsReport { roots: vec![root], single: true, schedules }),
+            None => {
+                let mut roots = Vec::new();
+                Root::for_each_root(db, |root| {
+                    roots.push(root.clone());
                     Ok(())
+                })?;
+                roots
+            },
+        };
+
+        Ok(RootsReport { roots, single: false, schedules })
+    }
+
+    /// Prints the roots as a table, followed by their schedules if they have any
+    pub fn print_roots(report: &RootsReport) -> Result<(), FsPulseError> {
+        let mut stream = Self::begin_roots_table();
+        if report.single {
+            stream = stream.title("Root");
+        }
+
+        for root in &report.roots {
+            stream.row(root.clone())?;
+        }
+
+        stream.finish()?;
+        Self::print_schedules(&report.schedules)?;
+
+        Ok(())
+    }
+
+    /// Lists scan schedules, with when each scan is next due, if there are any
+    fn print_schedules(schedules: &[Schedule]) -> Result<(), FsPulseError> {
+        if schedules.is_empty() {
+            return Ok(());
+        }
+
+        let now = Utc::now().timestamp();
+        let out = io::stdout();
+        let mut stream = Stream::new(out, vec![
+            Column::new(|f, s: &Schedule| write!(f, "{}", s.root_id())).header("Root ID").right().min_width(6),
+            Column::new(|f, s: &Schedule| write!(f, "{}", s.kind())).header("Scan").left(),
+            Column::new(|f, s: &Schedule| write!(f, "{}", Utils::format_interval(s.interval()))).header("Every").right(),
+            Column::new(|f, s: &Schedule| write!(f, "{}", Utils::format_db_time_short_or_none(s.last_run()))).header("Last Run").left(),
+            Column::new(move |f, s: &Schedule| write!(f, "{}{}",
+                Utils::format_db_time_short(s.next_due()),
+                if s.is_due(now) { " (due)" } else { "" })).header("Next Due").left(),
+        ]).title("Schedules");
+
+        for schedule in schedules {
+            stream.row(schedule.clone())?;
+        }
+
+        stream.finish()?;
+        Ok(())
+    }
+
+    /// The items given by id, by path, by root (as of its latest scan or an earlier one,
+    /// optionally at or beneath a path) or by a search of their paths
+    #[allow(clippy::too_many_arguments)]
+    pub fn items(
+        db: &Database,
+        item_id: Option<u32>,
+        item_path: Option<String>,
+        root_id: Option<u32>,
+        search: Option<String>,
+        fuzzy: bool,
+        limit: Option<u32>,
+        as_of: Option<i64>,
+        path_prefix: Option<String>,
+    ) -> Result<ItemsReport, FsPulseError> {
+        match (item_id, item_path, root_id, search) {
+            (Some(item_id), _, _, _) => {
+                let item = Item::get_by_id(db, item_id.into())?;
+                let root = match &item {
+                    Some(item) => Root::get_by_id(db, item.root_id())?,
+                    None => None,
+                };
+                let deletion = match &item {
+                    Some(item) if item.is_tombstone() => Deletion::get_for_item(db, item.id())?,
+                    _ => None,
+                };
+
+                let mut totals = ItemTotals::default();
+                item.iter().for_each(|item| totals.add(item));
+
+                Ok(ItemsReport { source: ItemSource::Id(item_id.into()), items: item.into_iter().collect(), totals, root, deletion })
+            },
+            (_, Some(item_path), _, _) => {
+                let items = Item::items_with_path(db, &item_path)?.iter()?.collect::<Result<Vec<_>, _>>()?;
+                let totals = Item::totals_with_path(db, &item_path)?;
+
+                Ok(ItemsReport { source: ItemSource::Path(item_path), items, totals, root: None, deletion: None })
+            },
+            (_, _, Some(root_id), _) => {
+                let root = Root::get_by_id(db, root_id.into())?
+                    .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+                if let Some(scan_id) = as_of {
+                    let scan = Scan::get_by_id(db, scan_id)?
+                        .ok_or_else(|| FsPulseError::Error(format!("Scan Id {} not found", scan_id)))?;
+                    if scan.root_id() != root.id() {
+                        return Err(FsPulseError::Error(format!("Scan Id {} is a scan of Root Id {}, not Root Id {}", scan.id(), scan.root_id(), root.id())));
+                    }
+
+                    let items = Self::items_as_of_under(db, &root, &scan, path_prefix.as_deref())?;
+                    let mut totals = ItemTotals::default();
+                    items.iter().for_each(|item| totals.add(item));
+
+                    return Ok(ItemsReport { source: ItemSource::Root { as_of: Some(Box::new(scan)) }, items, totals, root: Some(root), deletion: None });
                 }
-            )?;
 
-            stream.finish()?;
-        } else {
-            let root_id: i64 = match (root_id, root_path) {
-                (Some(root_id), _) => {
-                    root_id.into()
+                let scan = Scan::get_latest_for_root(db, root.id())?
+                    .ok_or_else(|| FsPulseError::Error(format!("No latest scan found for Root Id {}", root_id)))?;
+                let items = Item::items_in_latest_scan_under(db, scan.id(), path_prefix.as_deref())?
+                    .iter()?
+                    .collect::<Result<Vec<_>, _>>()?;
+                let totals = Item::totals_in_latest_scan(db, scan.id(), path_prefix.as_deref())?;
+
+                Ok(ItemsReport { source: ItemSource::Root { as_of: None }, items, totals, root: Some(root), deletion: None })
+            },
+            (_, _, _, Some(search)) => {
+                // An unlimited fuzzy search would return nearly every item sharing a single fragment
+                let limit = match (limit, fuzzy) {
+                    (Some(limit), _) => i64::from(limit),
+                    (None, true) => DEFAULT_FUZZY_LIMIT,
+                    (None, false) => -1,
+                };
+
+                let items = Item::items_matching(db, &search, fuzzy, limit)?.iter()?.collect::<Result<Vec<_>, _>>()?;
+                let mut totals = ItemTotals::default();
+                items.iter().for_each(|item| totals.add(item));
+
+                Ok(ItemsReport { source: ItemSource::Search(search), items, totals, root: None, deletion: None })
+            },
+            _ => Err(FsPulseError::Error("No item, path, root or search given".to_string())),
+        }
+    }
+
+    /// Prints the items as a table with their totals, as a tree (for a root's items) or as
+    /// CSV, with a header row, for loading into a spreadsheet. Fields holding commas, quotes
+    /// or line breaks are quoted
+    pub fn print_items(report: &ItemsReport, format: ReportFormat) -> Result<(), FsPulseError> {
+        if let Some(root) = &report.root {
+            Self::use_root(root);
+        }
+
+        if format == ReportFormat::Csv {
+            return Self::write_csv(report.items.iter().map(|item| Ok(ItemRecord::from(item.clone()))));
+        }
+
+        let (title, empty_row) = match &report.source {
+            // TODO: In the single item case, "tree" is not a valid report format
+            ItemSource::Id(item_id) => ("Item".to_string(), format!("Item {} Not Found", item_id)),
+            ItemSource::Path(item_path) => ("Items".to_string(), format!("Item Path '{}' Not Found", item_path)),
+            ItemSource::Search(search) => ("Items".to_string(), format!("No Items Matching '{}'", search)),
+            ItemSource::Root { as_of } => {
+                let root = report.root.as_ref()
+                    .ok_or_else(|| FsPulseError::Error("The items' root is missing".to_string()))?;
+                let title = match (as_of, format) {
+                    (Some(scan), _) => format!(
+                        "Items as of Scan Id {} at {} (Root Path: '{}')",
+                        scan.id(), Utils::format_db_time_short(scan.time_of_scan()), Self::show_path(root.path()),
+                    ),
+                    (None, ReportFormat::Tree) => format!("Items (Root Id: {}, Root Path: '{}'", root.id(), Self::show_path(root.path())),
+                    (None, _) => format!("Items (Root Path: '{}'", Self::show_path(root.path())),
+                };
+
+                match format {
+                    ReportFormat::Tree => return Self::print_items_as_tree(&title, &report.items),
+                    ReportFormat::Table => {},
+                    _ => return Err(FsPulseError::Error("Unsupported format.".to_string())),
                 }
-                (_, Some(root_path)) => {
-                    Root::get_by_path(db, &root_path)?
-                    .ok_or_else(|| FsPulseError::Error(format!("Root Path '{}' not found", &root_path)))?
-                    .id()
+                (title, "No Items".to_string())
+            },
+        };
+
+        let mut stream = Self::begin_items_table(&title, &empty_row);
+        for item in &report.items {
+            stream.row(item.clone())?;
+        }
+        stream.finish()?;
+
+        match (&report.source, &report.deletion) {
+            (ItemSource::Id(_), Some(deletion)) => println!(
+                "Deleted between {} (Scan Id {}) and {} (Scan Id {})",
+                Utils::format_db_time_short(deletion.last_seen_time),
+                deletion.last_seen_scan_id,
+                Utils::format_db_time_short(deletion.deleted_time),
+                deletion.deleted_scan_id,
+            ),
+            (ItemSource::Id(_), None) => {},
+            _ => Self::print_item_totals(&report.totals),
+        }
+
+        Ok(())
+    }
+
+    /// The changes given by id, to an item, or found by a scan, as far as the selection allows
+    pub fn changes(
+        db: &Database,
+        change_id: Option<u32>,
+        item_id: Option<u32>,
+        scan_id: Option<i64>,
+        selection: &ChangeSelection,
+    ) -> Result<ChangesReport, FsPulseError> {
+        match (change_id, item_id, scan_id) {
+            (Some(change_id), None, None) => {
+                let change = Change::get_by_id(db, change_id.into())?;
+                let root = match &change {
+                    Some(change) => Root::get_by_id(db, Self::root_id_of_item(db, change.item_id)?)?,
+                    None => None,
+                };
+
+                Ok(ChangesReport { source: ChangeSource::Change(change_id.into()), changes: change.into_iter().collect(), root, renames: Vec::new() })
+            },
+            (None, Some(item_id), None) => {
+                let item = Item::get_by_id(db, item_id.into())?
+                    .ok_or_else(|| FsPulseError::Error(format!("Item Id {} not found", item_id)))?;
+                let root = Root::get_by_id(db, item.root_id())?;
+                let changes = Change::changes_in_item_selected(db, item.id(), selection)?.iter()?.collect::<Result<Vec<_>, _>>()?;
+
+                Ok(ChangesReport { source: ChangeSource::Item(Box::new(item)), changes, root, renames: Vec::new() })
+            },
+            (None, None, Some(scan_id)) => {
+                let root = match Scan::get_by_id(db, scan_id)? {
+                    Some(scan) => Root::get_by_id(db, scan.root_id())?,
+                    None => None,
+                };
+                let changes = Change::changes_in_scan_selected(db, scan_id, selection)?.iter()?.collect::<Result<Vec<_>, _>>()?;
+
+                Ok(ChangesReport { source: ChangeSource::Scan(scan_id), changes, root, renames: Change::renames_in_scan(db, scan_id)? })
+            },
+            _ => Err(FsPulseError::Error("One of a change, an item or a scan must be given".to_string())),
+        }
+    }
+
+    /// Prints the changes as a table, a tree (for a scan's changes, showing `tree_dirs`), the
+    /// way git shows a working tree (for a scan's changes) or as CSV, with a header row, for
+    /// loading into a spreadsheet. Fields holding commas, quotes or line breaks are quoted
+    pub fn print_changes(report: &ChangesReport, format: ReportFormat, tree_dirs: TreeDirs) -> Result<(), FsPulseError> {
+        if let Some(root) = &report.root {
+            Self::use_root(root);
+        }
+
+        if format == ReportFormat::Csv {
+            return Self::write_csv(report.changes.iter().map(|change| Ok(ChangeRecord::from(change.clone()))));
+        }
+
+        match &report.source {
+            ChangeSource::Change(_) => {
+                let mut stream = Self::begin_changes_table("Change", "No Change Found");
+                for change in &report.changes {
+                    stream.row(change.clone())?;
+                }
+                stream.finish()?;
+            },
+            ChangeSource::Item(item) => Self::print_item_changes_as_table(item, &report.changes)?,
+            ChangeSource::Scan(scan_id) => match format {
+                ReportFormat::Table => Self::print_scan_changes_as_table(*scan_id, &report.changes)?,
+                ReportFormat::Tree => {
+                    let root = report.root.as_ref()
+                        .ok_or_else(|| FsPulseError::Error(format!("Scan Id {} not found", scan_id)))?;
+                    match tree_dirs {
+                        TreeDirs::Only => Self::print_scan_changes_by_dir(root, &report.changes, None)?,
+                        TreeDirs::RollUp(depth) => Self::print_scan_changes_by_dir(root, &report.changes, Some(depth))?,
+                        _ => Self::print_scan_changes_as_tree(root, &report.changes, tree_dirs)?,
+                    }
+                },
+                ReportFormat::Git => Self::print_scan_changes_as_git(&report.changes, &report.renames)?,
+                _ => return Err(FsPulseError::Error("Unsupported format.".to_string())),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Writes the records to stdout as CSV, each after the one before, with a header row
+    /// naming their fields ahead of the first
+    fn write_csv<T: Serialize>(records: impl IntoIterator<Item = Result<T, FsPulseError>>) -> Result<(), FsPulseError> {
+        let mut writer = csv::Writer::from_writer(io::stdout().lock());
+        for record in records {
+            writer.serialize(record?)?;
+        }
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    fn root_id_of_item(db: &Database, item_id: i64) -> Result<i64, FsPulseError> {
+        let item = Item::get_by_id(db, item_id)?
+            .ok_or_else(|| FsPulseError::Error(format!("Item Id {} not found", item_id)))?;
+
+        Ok(item.root_id())
+    }
+
+    /// Writes the absolute path of each item changed by a change, an item or a scan, each
+    /// followed by `terminator`. Paths are written as they were recorded, without quoting
+    /// or escaping, so they can be read back by `xargs` and the like. The paths of deleted
+    /// items are included, though they no longer exist
+    pub fn report_changed_paths(
+        db: &Database,
+        change_id: Option<u32>,
+        item_id: Option<u32>,
+        scan_id: Option<i64>,
+        selection: &ChangeSelection,
+        terminator: u8,
+    ) -> Result<(), FsPulseError> {
+        let mut paths: Vec<PathBuf> = Vec::new();
+
+        match (change_id, item_id, scan_id) {
+            (Some(change_id), None, None) => {
+                if let Some(change) = Change::get_by_id(db, change_id.into())? {
+                    if let Some(item) = Item::get_by_id(db, change.item_id)? {
+                        paths.push(Self::absolute_item_path(db, item.root_id(), item.path())?);
+                    }
                 }
-                (None, None) => {
-                    // should be unreachable
-                    return Err(FsPulseError::Error("No path specified".to_string()))
+            },
+            (None, Some(item_id), None) => {
+                // An item's changes all share its path
+                let mut changes = Change::changes_in_item_selected(db, item_id.into(), selection)?;
+                let changed = changes.iter()?.next().transpose()?.is_some();
+                if let (true, Some(item)) = (changed, Item::get_by_id(db, item_id.into())?) {
+                    paths.push(Self::absolute_item_path(db, item.root_id(), item.path())?);
                 }
-            };
+            },
+            (None, None, Some(scan_id)) => {
+                let scan = Scan::get_by_id(db, scan_id)?
+                    .ok_or_else(|| FsPulseError::Error(format!("Scan Id {} not found", scan_id)))?;
+                let root = Root::get_by_id(db, scan.root_id())?
+                    .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", scan.root_id())))?;
+
+                let mut changes = Change::changes_in_scan_selected(db, scan_id, selection)?;
+                for change in changes.iter()? {
+                    paths.push(root.item_path(&change?.item_path));
+                }
+            },
+            _ => {},
+        }
+
+        let mut out = io::BufWriter::new(io::stdout().lock());
+        for path in paths {
+            out.write_all(path.as_os_str().as_encoded_bytes())?;
+            out.write_all(&[terminator])?;
+        }
+        out.flush()?;
+
+        Ok(())
+    }
+
+    fn absolute_item_path(db: &Database, root_id: i64, item_path: &str) -> Result<PathBuf, FsPulseError> {
+        let root = Root::get_by_id(db, root_id)?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        Ok(root.item_path(item_path))
+    }
+
+    /// Reports items whose modification times can't be right: later than the scan that
+    /// recorded them (beyond `tolerance` seconds) or earlier than the Unix epoch. Each root's
+    /// most recent scan is examined, or just the specified root's
+    pub fn report_timestamps(db: &Database, root_id: Option<u32>, tolerance: u64) -> Result<(), FsPulseError> {
+        let mut roots = Vec::new();
+
+        match root_id {
+            Some(root_id) => {
+                let root = Root::get_by_id(db, root_id.into())?
+                    .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+                roots.push(root);
+            }
+            None => Root::for_each_root(db, |root| {
+                roots.push(root.clone());
+                Ok(())
+            })?,
+        }
+
+        for root in &roots {
+            if let Some(scan) = Scan::get_latest_for_root(db, root.id())? {
+                Self::use_root(root);
+                Self::print_timestamp_findings(db, root, &scan, tolerance as i64)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn print_timestamp_findings(db: &Database, root: &Root, scan: &Scan, tolerance: i64) -> Result<(), FsPulseError> {
+        let scan_time = scan.time_of_scan();
+
+        let mut stream = Self::begin_timestamp_findings_table(
+            &format!("Timestamp Findings (Root Path: '{}', Scan Id: {})", Self::show_path(root.path()), scan.id()),
+            "No Findings",
+        );
+
+        let mut future_skews = Vec::new();
+        let mut pre_epoch_count = 0;
+
+        let mut items = Item::items_with_suspect_timestamps(db, scan.id(), scan_time + tolerance)?;
+
+        for item in items.iter()? {
+            let item = item?;
+            let last_modified = item.last_modified().unwrap_or_default();
+
+            let finding = if last_modified < 0 {
+                pre_epoch_count += 1;
+                TimestampFinding { item, kind: "Pre-epoch", skew: None }
+            } else {
+                let skew = last_modified - scan_time;
+                future_skews.push(skew);
+                TimestampFinding { item, kind: "Future", skew: Some(skew) }
+            };
+
+            stream.row(finding)?;
+        }
+
+        stream.finish()?;
+
+        if future_skews.is_empty() && pre_epoch_count == 0 {
+            return Ok(());
+        }
+
+        println!(
+            "Future: {}, Pre-epoch: {}, Largest skew: {}",
+            future_skews.len(),
+            pre_epoch_count,
+            future_skews.iter().max().map_or("-".to_string(), |skew| Utils::format_skew(*skew)),
+        );
+
+        if let Some((hours, count)) = Self::dominant_hour_skew(&future_skews) {
+            println!(
+                "{} of {} future timestamps are about {} hour(s) ahead of the scan, which suggests a clock or time zone set wrong where they were written",
+                count, future_skews.len(), hours,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Looks for a clock skew pattern: most future timestamps falling in the same whole-hour
+    /// offset from the scan. Returns the offset in hours and the number of timestamps in it
+    fn dominant_hour_skew(skews: &[i64]) -> Option<(i64, usize)> {
+        let mut buckets: BTreeMap<i64, usize> = BTreeMap::new();
+
+        for skew in skews {
+            *buckets.entry((skew + 1_800) / 3_600).or_default() += 1;
+        }
+
+        buckets.into_iter()
+            .filter(|(hours, _)| *hours > 0)
+            .max_by_key(|(_, count)| *count)
+            .filter(|(_, count)| *count >= 3 && count * 2 > skews.len())
+    }
+
+    /// Reports symlinks whose target didn't exist when they were scanned. Each root's most
+    /// recent scan is examined, or just the specified root's
+    pub fn report_symlinks(db: &Database, root_id: Option<u32>) -> Result<(), FsPulseError> {
+        let mut roots = Vec::new();
+
+        match root_id {
+            Some(root_id) => {
+                let root = Root::get_by_id(db, root_id.into())?
+                    .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+                roots.push(root);
+            }
+            None => Root::for_each_root(db, |root| {
+                roots.push(root.clone());
+                Ok(())
+            })?,
+        }
+
+        for root in &roots {
+            if let Some(scan) = Scan::get_latest_for_root(db, root.id())? {
+                Self::use_root(root);
+                let mut stream = Self::begin_symlink_findings_table(
+                    &format!("Symlink Findings (Root Path: '{}', Scan Id: {})", Self::show_path(root.path()), scan.id()),
+                    "No Findings",
+                );
+
+                let mut items = Item::items_with_dangling_symlinks(db, scan.id())?;
+                for item in items.iter()? {
+                    stream.row(item?)?;
+                }
+
+                stream.finish()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The roots of a group, each with its latest scan
+    pub fn group_roots(db: &Database, name: &str) -> Result<Vec<GroupRoot>, FsPulseError> {
+        let root_ids = RootGroups::members(db, name)?;
+        if root_ids.is_empty() {
+            return Err(FsPulseError::Error(format!("Group '{}' not found", name)));
+        }
+
+        let mut roots = Vec::new();
+        for root_id in root_ids {
+            if let Some(root) = Root::get_by_id(db, root_id)? {
+                let latest_scan = Scan::get_latest_for_root(db, root.id())?;
+                roots.push(GroupRoot { root, latest_scan });
+            }
+        }
+
+        Ok(roots)
+    }
+
+    /// The sets of identical files among the latest scans of a group's roots, in the same
+    /// root or across roots
+    pub fn group_duplicates(db: &Database, name: &str) -> Result<Vec<DuplicateSet>, FsPulseError> {
+        Self::duplicate_sets(Item::duplicates_in_group(db, name)?)
+    }
+
+    /// The sets of identical files in the root's latest scan, largest first
+    pub fn duplicates(db: &Database, root: &Root) -> Result<Vec<DuplicateSet>, FsPulseError> {
+        Self::duplicate_sets(Item::duplicates_in_root(db, root.id())?)
+    }
+
+    /// Gathers files, ordered so that those sharing a hash and size are adjacent, into sets
+    fn duplicate_sets(mut items: ItemRows) -> Result<Vec<DuplicateSet>, FsPulseError> {
+        let mut sets: Vec<DuplicateSet> = Vec::new();
+
+        for item in items.iter()? {
+            let item = item?;
+            let hash_algo = item.hash_algo().unwrap_or_default();
+            let hash = item.file_hash().unwrap_or_default();
+            let file_size = item.file_size().unwrap_or(0);
+
+            match sets.last_mut() {
+                Some(set) if set.hash_algo == hash_algo && set.hash == hash && set.file_size == file_size => {
+                    set.wasted_bytes += file_size;
+                },
+                _ => sets.push(DuplicateSet {
+                    hash_algo: hash_algo.to_owned(),
+                    hash: hash.to_owned(),
+                    file_size,
+                    wasted_bytes: 0,
+                    files: Vec::new(),
+                }),
+            }
+
+            if let Some(set) = sets.last_mut() {
+                set.files.push(DuplicateFile { item_id: item.id(), root_id: item.root_id(), path: item.path().to_owned() });
+            }
+        }
+
+        Ok(sets)
+    }
+
+    /// Prints a group of roots as one dataset: the latest scan of each root
+    pub fn print_group_roots(name: &str, roots: &[GroupRoot]) -> Result<(), FsPulseError> {
+        let mut stream = Self::begin_group_roots_table(&format!("Roots (Group: '{}')", name), "No Roots");
+        for root in roots {
+            stream.row(root.clone())?;
+        }
+        stream.finish()?;
+
+        Ok(())
+    }
+
+    /// Prints the items of the latest scans of a group's roots together, by path
+    pub fn print_group_items(db: &Database, name: &str) -> Result<(), FsPulseError> {
+        let mut stream = Self::begin_items_table(&format!("Items (Group: '{}')", name), "No Items");
+        let mut items = Item::items_in_group(db, name)?;
+        let mut totals = ItemTotals::default();
+
+        // Items are ordered by path, so those sharing a path across roots are adjacent
+        let mut prev_path: Option<String> = None;
+        let mut shared_paths = 0;
+
+        for item in items.iter()? {
+            let item = item?;
+            totals.add(&item);
+
+            if prev_path.as_deref() == Some(item.path()) {
+                shared_paths += 1;
+            } else {
+                prev_path = Some(item.path().to_owned());
+            }
+
+            stream.row(item)?;
+        }
+
+        stream.finish()?;
+        Self::print_item_totals(&totals);
+
+        if shared_paths > 0 {
+            println!("{} paths are present in more than one root", shared_paths);
+        }
+
+        Ok(())
+    }
+
+    /// Prints the sets of identical files among a group's roots
+    pub fn print_group_duplicates(name: &str, sets: &[DuplicateSet]) -> Result<(), FsPulseError> {
+        let mut stream = Self::begin_duplicates_table(&format!("Duplicate Files (Group: '{}')", name), "No Duplicate Files");
+        Self::duplicate_rows(&mut stream, sets)?;
+        stream.finish()?;
+
+        let files: usize = sets.iter().map(|set| set.files.len()).sum();
+        let spanning_sets = sets.iter().filter(|set| set.spans_roots()).count();
+        let extra_bytes: i64 = sets.iter().map(|set| set.wasted_bytes).sum();
+        println!(
+            "{} duplicate files in {} sets ({} spanning more than one root), {} beyond one copy of each",
+            files, sets.len(), spanning_sets, Utils::format_bytes(extra_bytes as u64),
+        );
+
+        Ok(())
+    }
+
+    /// Prints the sets of identical files in a root, with the bytes taken by copies beyond
+    /// the first of each set
+    pub fn print_duplicates(root: &Root, sets: &[DuplicateSet], format: ReportFormat) -> Result<(), FsPulseError> {
+        match format {
+            ReportFormat::Table => {},
+            ReportFormat::Json => {
+                println!("{}", serde_json::to_string_pretty(sets)?);
+                return Ok(());
+            },
+            ReportFormat::Fdupes => return Self::print_duplicates_as_fdupes(root, sets),
+            _ => return Err(FsPulseError::Error("Unsupported format.".to_string())),
+        }
+
+        Self::use_root(root);
+
+        let mut stream = Self::begin_duplicates_table(
+            &format!("Duplicate Files (Root Path: '{}')", Self::show_path(root.path())),
+            "No Duplicate Files",
+        );
+        Self::duplicate_rows(&mut stream, sets)?;
+        stream.finish()?;
+
+        let files: usize = sets.iter().map(|set| set.files.len()).sum();
+        let wasted_bytes: i64 = sets.iter().map(|set| set.wasted_bytes).sum();
+        println!(
+            "{} duplicate files in {} sets, {} wasted beyond one copy of each",
+            files, sets.len(), Utils::format_bytes(wasted_bytes as u64),
+        );
+
+        Ok(())
+    }
+
+    fn duplicate_rows(stream: &mut Stream<DuplicateRow, Stdout>, sets: &[DuplicateSet]) -> Result<(), FsPulseError> {
+        for set in sets {
+            for file in &set.files {
+                stream.row(DuplicateRow {
+                    hash_algo: set.hash_algo.clone(),
+                    hash: set.hash.clone(),
+                    file_size: set.file_size,
+                    file: file.clone(),
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the sets of identical files the way fdupes and jdupes list them, so that
+    /// tools which act on their output, such as for hardlinking or removing the extra
+    /// copies, can use what hashing scans found without reading the files again: the
+    /// absolute path of each file on a line of its own, with a blank line after each set.
+    /// Paths are written as they were recorded, without quoting or escaping
+    fn print_duplicates_as_fdupes(root: &Root, sets: &[DuplicateSet]) -> Result<(), FsPulseError> {
+        let mut out = io::BufWriter::new(io::stdout().lock());
+
+        for set in sets {
+            for file in &set.files {
+                out.write_all(root.item_path(&file.path).as_os_str().as_encoded_bytes())?;
+                out.write_all(b"\n")?;
+            }
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+
+        Ok(())
+    }
+
+    /// Reports every change to the item given by id or path, oldest first, with its size,
+    /// modification time and hash before and after. A path may be absolute or within its
+    /// root, and is looked up in every root, so it can match several items
+    pub fn history(db: &Database, item: &str) -> Result<Vec<ItemHistory>, FsPulseError> {
+        let mut items = Vec::new();
+        match item.parse::<i64>().ok().map(|item_id| Item::get_by_id(db, item_id)).transpose()?.flatten() {
+            Some(item) => items.push(item),
+            None => {
+                // Paths of existing files are canonicalized as roots are, deleted ones can't be
+                let mut paths = vec![item.to_owned()];
+                if let Ok(path) = std::fs::canonicalize(item) {
+                    paths.push(path.to_string_lossy().into_owned());
+                }
+                for path in paths {
+                    for found in Item::items_with_path(db, &path)?.iter()? {
+                        let found = found?;
+                        if !items.iter().any(|item: &Item| item.id() == found.id()) {
+                            items.push(found);
+                        }
+                    }
+                }
+            },
+        }
+
+        if items.is_empty() {
+            return Err(FsPulseError::Error(format!("Item '{}' not found", item)));
+        }
+
+        items.into_iter()
+            .map(|item| Ok(ItemHistory {
+                item_id: item.id(),
+                root_id: item.root_id(),
+                path: item.path().to_owned(),
+                root: Root::get_by_id(db, item.root_id())?,
+                changes: HistoryEntry::for_item(db, item.id())?,
+            }))
+            .collect()
+    }
+
+    /// Prints each item's history as a table, or all of them as JSON
+    pub fn print_history(histories: &[ItemHistory], format: ReportFormat) -> Result<(), FsPulseError> {
+        match format {
+            ReportFormat::Table => {
+                for history in histories {
+                    if let Some(root) = &history.root {
+                        Self::use_root(root);
+                    }
+                    let mut stream = Self::begin_history_table(
+                        &format!("History (Item Id: {}, Item Path: '{}')", history.item_id, Self::show_path(&history.path)),
+                        "No Changes",
+                    );
+                    for change in &history.changes {
+                        stream.row(change.clone())?;
+                    }
+                    stream.finish()?;
+                }
+            },
+            ReportFormat::Json => println!("{}", serde_json::to_string_pretty(histories)?),
+            _ => return Err(FsPulseError::Error("Unsupported format.".to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Reports the root's deleted items whose deletion was recorded in a range of scans,
+    /// with the last scan that found each item and the scan that found it missing
+    pub fn report_deletions(db: &Database, root_id: u32, from_scan: Option<i64>, to_scan: Option<i64>) -> Result<(), FsPulseError> {
+        let root = Root::get_by_id(db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        Self::use_root(&root);
+
+        let mut stream = Self::begin_deletions_table(
+            &format!("Deletions (Root Path: '{}')", Self::show_path(root.path())),
+            "No Deletions",
+        );
+
+        Deletion::for_each_in_root(db, root.id(), from_scan, to_scan, |deletion| {
+            stream.row(deletion.clone())?;
+            Ok(())
+        })?;
+        stream.finish()?;
+
+        Ok(())
+    }
+
+    /// Reports where the root's content went across a range of scans, with each path that
+    /// held the content and when it appeared there and left
+    pub fn report_migrations(db: &Database, root_id: u32, from_scan: Option<i64>, to_scan: Option<i64>) -> Result<(), FsPulseError> {
+        let root = Root::get_by_id(db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        Self::use_root(&root);
+
+        let mut stream = Self::begin_migrations_table(
+            &format!("Content Migrations (Root Path: '{}')", Self::show_path(root.path())),
+            "No Content Migrations",
+        );
+
+        let migrations = Migration::in_root(db, root.id(), from_scan, to_scan)?;
+        for migration in &migrations {
+            for location in &migration.locations {
+                stream.row((migration.clone(), location.clone()))?;
+            }
+        }
+        stream.finish()?;
+
+        let count_of = |kind| migrations.iter().filter(|migration| migration.kind == kind).count();
+        let bytes_of = |kind| migrations.iter().filter(|migration| migration.kind == kind).map(Migration::extra_bytes).sum::<i64>();
+        println!(
+            "{} moved, {} copied then deleted, {} copied ({} in copies left behind), {} consolidated ({} freed)",
+            count_of(MigrationKind::Moved),
+            count_of(MigrationKind::CopyThenDelete),
+            count_of(MigrationKind::Copied),
+            Utils::format_bytes(bytes_of(MigrationKind::Copied) as u64),
+            count_of(MigrationKind::Consolidated),
+            Utils::format_bytes(bytes_of(MigrationKind::Consolidated) as u64),
+        );
+
+        Ok(())
+    }
+
+    /// Reports changes of owner or group to the root's items across a range of scans, and
+    /// the items in its most recent scan owned by users other than its allowed owners
+    pub fn report_ownership(db: &Database, root_id: u32, from_scan: Option<i64>, to_scan: Option<i64>) -> Result<(), FsPulseError> {
+        let root = Root::get_by_id(db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        Self::use_root(&root);
+
+        let mut stream = Self::begin_ownership_changes_table(
+            &format!("Ownership Changes (Root Path: '{}')", Self::show_path(root.path())),
+            "No Ownership Changes",
+        );
+
+        Owners::for_each_ownership_change(db, root.id(), from_scan, to_scan, |change| {
+            stream.row(change.clone())?;
+            Ok(())
+        })?;
+        stream.finish()?;
+
+        let allowed = Owners::allowed(db, root.id())?;
+        if allowed.is_empty() {
+            println!("Root Id {} has no allowed owners. Use 'fspulse owners allow' to list the users expected to own its items", root.id());
+            return Ok(());
+        }
+
+        if let Some(scan) = Scan::get_latest_for_root(db, root.id())? {
+            let allowed_names: Vec<String> = allowed.iter().map(|uid| Owners::user_name(Some(*uid))).collect();
+            let mut stream = Self::begin_unexpected_owners_table(
+                &format!("Unexpected Owners (Scan Id: {}, Allowed: {})", scan.id(), allowed_names.join(", ")),
+                "No Unexpected Owners",
+            );
+
+            let mut items = Item::items_with_unexpected_owners(db, scan.id())?;
+            for item in items.iter()? {
+                stream.row(item?)?;
+            }
+
+            stream.finish()?;
+        }
+
+        Ok(())
+    }
+
+    /// Reports how many items and bytes of the root each user or group held as of a scan
+    /// (by default the root's latest completed scan), and how that changed since the
+    /// root's previous completed scan
+    pub fn report_usage(db: &Database, root_id: u32, scan_id: Option<i64>, by: UsageBy) -> Result<(), FsPulseError> {
+        let root = Root::get_by_id(db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+
+        let scan = match scan_id {
+            Some(scan_id) => Scan::get_by_id(db, scan_id)?
+                .filter(|scan| scan.root_id() == root.id())
+                .ok_or_else(|| FsPulseError::Error(format!("Scan Id {} isn't a scan of Root Id {}", scan_id, root.id())))?,
+            None => Scan::get_latest_completed_at(db, root.id(), i64::MAX)?
+                .ok_or_else(|| FsPulseError::Error(format!("Root Id {} has no completed scan", root.id())))?,
+        };
+        let prev_scan_id = scan.previous_completed_id(db)?;
+
+        let title = match prev_scan_id {
+            Some(prev_scan_id) => format!(
+                "Usage by {} (Root Path: '{}', Scan Id: {}, Changes Since Scan Id: {})",
+                by.as_str(), Self::show_path(root.path()), scan.id(), prev_scan_id),
+            None => format!("Usage by {} (Root Path: '{}', Scan Id: {})", by.as_str(), Self::show_path(root.path()), scan.id()),
+        };
+
+        let mut stream = Self::begin_usage_table(&title, by);
+        for usage in Owners::usage(db, root.id(), scan.id(), prev_scan_id, by)? {
+            stream.row(usage)?;
+        }
+        stream.finish()?;
+
+        Ok(())
+    }
+
+    /// Reports files whose content hasn't been verified by a hashing scan in the last
+    /// `older_than_days` days, oldest first. With `limit`, the report is the next batch
+    /// of files to verify when verifying a large tree a portion at a time
+    pub fn report_unverified(db: &Database, root_id: Option<u32>, older_than_days: u32, limit: Option<u32>) -> Result<(), FsPulseError> {
+        let root_id = root_id.map(i64::from);
+        let verified_before = Utc::now().timestamp() - i64::from(older_than_days) * 86_400;
+
+        if let Some(root_id) = root_id {
+            Self::use_root_by_id(db, root_id)?;
+        }
+
+        let mut stream = Self::begin_items_table(
+            &format!("Items Not Verified In {} Days", older_than_days),
+            "No Items",
+        );
+
+        let mut items = Item::items_unverified_since(db, root_id, verified_before, limit.map_or(-1, i64::from))?;
+
+        for item in items.iter()? {
+            stream.row(item?)?;
+        }
+
+        stream.finish()?;
+        Self::print_item_totals(&Item::totals_unverified_since(db, root_id, verified_before)?);
+
+        Ok(())
+    }
+
+    /// Reports each verification, optionally of one root, and the mismatches they found
+    pub fn report_bitrot(db: &Database, root_id: Option<u32>) -> Result<(), FsPulseError> {
+        let root_id = root_id.map(i64::from);
+        if let Some(root_id) = root_id {
+            Self::use_root_by_id(db, root_id)?;
+        }
+
+        let mut stream = Self::begin_verifications_table("Verifications", "No Verifications");
+        Verification::for_each_verification(db, root_id, |verification| {
+            stream.row(verification.clone())?;
+            Ok(())
+        })?;
+        stream.finish()?;
+
+        let mut stream = Self::begin_mismatches_table("Potential Corruption", "No Mismatches");
+        Verification::for_each_mismatch(db, root_id, |mismatch| {
+            stream.row(mismatch.clone())?;
+            Ok(())
+        })?;
+        stream.finish()?;
+
+        Ok(())
+    }
+
+    /// Reports what a run of `verify` found
+    pub fn print_verification(root: &Root, verification: &Verification, mismatches: Vec<Mismatch>) -> Result<(), FsPulseError> {
+        Self::use_root(root);
+
+        println!(
+            "Verified '{}' (Verification Id {}): {} matched, {} mismatched, {} skipped",
+            Self::show_path(root.path()), verification.id(),
+            verification.files_matched(), verification.files_mismatched(), verification.files_skipped(),
+        );
+
+        if !mismatches.is_empty() {
+            let mut stream = Self::begin_mismatches_table("Potential Corruption", "No Mismatches");
+            for mismatch in mismatches {
+                stream.row(mismatch)?;
+            }
+            stream.finish()?;
+        }
+
+        Ok(())
+    }
+
+    /// The operations recorded in the audit log, oldest first, optionally only those of
+    /// one operation
+    pub fn audit(db: &Database, operation: Option<&str>, limit: Option<u32>) -> Result<Vec<AuditEntry>, FsPulseError> {
+        let mut entries = Vec::new();
+        AuditEntry::for_each_entry(db, operation, limit, |entry| {
+            entries.push(entry.clone());
+            Ok(())
+        })?;
+
+        Ok(entries)
+    }
+
+    /// Prints the audit log's entries as a table
+    pub fn print_audit(entries: &[AuditEntry]) -> Result<(), FsPulseError> {
+        let mut stream = Self::begin_audit_table("Audit Log", "No Operations");
+
+        for entry in entries {
+            stream.row(entry.clone())?;
+        }
+
+        stream.finish()?;
+        Ok(())
+    }
+
+    /// Reports the root's files older than the retention period, measured by `basis`,
+    /// optionally limited to files beneath certain paths
+    pub fn report_retention(db: &Database, root_id: u32, older_than_days: u32, under: &[String], basis: AgeBasis) -> Result<(), FsPulseError> {
+        let root = Root::get_by_id(db, root_id.into())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+        let before = Utc::now().timestamp() - i64::from(older_than_days) * 86_400;
+
+        Self::use_root(&root);
+
+        let mut stream = Self::begin_retention_table(
+            &format!("Files Older Than {} Days By {} (Root Path: '{}')", older_than_days, basis.as_str(), Self::show_path(root.path())),
+            "No Files",
+        );
+
+        let (mut files, mut size) = (0, 0);
+        RetainedFile::for_each_older_than(db, &root, under, basis, before, |file| {
+            files += 1;
+            size += file.file_size.unwrap_or(0);
+            stream.row(file.clone())?;
+            Ok(())
+        })?;
+        stream.finish()?;
+
+        println!("Total: {} files, {} bytes", files, size);
+
+        Ok(())
+    }
+
+    /// Summarizes the health of the roots given, or of every root if none are: when each was
+    /// last scanned, whether that was more than `stale_days` ago, how much it holds and how
+    /// much it changed in the last `change_days`
+    pub fn summary(db: &Database, root_ids: &[u32], stale_days: u32, change_days: u32) -> Result<Summary, FsPulseError> {
+        let now = Utc::now().timestamp();
+        let stale_before = now - i64::from(stale_days) * 86_400;
+        let changes_since = now - i64::from(change_days) * 86_400;
+
+        let mut roots = Vec::new();
+        Root::for_each_summary(db, changes_since, |summary| {
+            if root_ids.is_empty() || root_ids.iter().any(|id| i64::from(*id) == summary.root.id()) {
+                roots.push(summary.clone());
+            }
+            Ok(())
+        })?;
+
+        Ok(Summary { roots, stale_days, change_days, stale_before })
+    }
+
+    /// Prints a summary as a table, with its totals and a warning if any root is stale
+    pub fn print_summary(summary: &Summary) -> Result<(), FsPulseError> {
+        let mut stream = Self::begin_summary_table(summary.stale_before, summary.change_days);
+        let (mut stale_count, mut file_count, mut total_size, mut change_count) = (0, 0, 0, 0);
+
+        for root in &summary.roots {
+            if summary.is_stale(root) {
+                stale_count += 1;
+            }
+            file_count += root.file_count;
+            total_size += root.total_size;
+            change_count += root.recent_change_count;
+
+            stream.row(root.clone())?;
+        }
+
+        stream.finish()?;
+
+        let root_count = summary.roots.len();
+        println!(
+            "Total: {} roots, {} files, {} bytes, {} changes in the last {} days",
+            root_count, file_count, total_size, change_count, summary.change_days,
+        );
+
+        if stale_count > 0 {
+            println!("Warning: {} of {} roots have not completed a scan in the last {} days", stale_count, root_count, summary.stale_days);
+        }
+
+        Ok(())
+    }
+
+    /// Prints what scanning a tree would involve for each kind of scan: how many files each
+    /// would hash, and how long hashing would take at the rate measured on a sample
+    pub fn report_estimate(root_path: &Path, root_id: Option<i64>, estimate: &Estimate) -> Result<(), FsPulseError> {
+        match root_id {
+            Some(root_id) => println!("Estimate for '{}' (Root Id {})", Self::show_path(&root_path.to_string_lossy()), root_id),
+            None => println!("Estimate for '{}' (not yet scanned)", Self::show_path(&root_path.to_string_lossy())),
+        }
+
+        let walked = format!(
+            "{} files, {} folders and {} other items ({})",
+            Utils::format_count(estimate.files),
+            Utils::format_count(estimate.folders),
+            Utils::format_count(estimate.other_items),
+            Utils::format_bytes(estimate.bytes.max(0) as u64),
+        );
+
+        if estimate.complete {
+            println!("Walked {} in {:.1}s", walked, estimate.walk_time.as_secs_f64());
+        } else if estimate.extrapolated {
+            println!("Stopped walking after {:.1}s. Extrapolated from the part walked and the last scan: {}", estimate.walk_time.as_secs_f64(), walked);
+        } else {
+            println!("Stopped walking after {:.1}s. The tree holds at least {}", estimate.walk_time.as_secs_f64(), walked);
+        }
+
+        if let Some(history) = estimate.history {
+            println!(
+                "Last completed scan: Scan Id {} found {} files and {} folders ({})",
+                history.scan_id,
+                Utils::format_count(history.files),
+                Utils::format_count(history.folders),
+                Utils::format_bytes(history.bytes.max(0) as u64),
+            );
+        }
+
+        match estimate.hash_rate() {
+            Some(rate) => println!(
+                "Hashing rate: {}/s (read {} from {} files)",
+                Utils::format_bytes(rate as u64),
+                Utils::format_bytes(estimate.sampled_bytes),
+                estimate.sampled_files,
+            ),
+            None => println!("Hashing rate: unknown (no files could be sampled)"),
+        }
+
+        let mut stream = Self::begin_scan_estimates_table();
+        for scan_kind in estimate.by_scan_kind() {
+            stream.row(scan_kind)?;
+        }
+        stream.finish()?;
+
+        println!("Every scan also walks the tree and records each item, which takes longer than the walk above");
+
+        Ok(())
+    }
+
+    /// Reports the scans of each root (or the specified roots) and the changes they found in
+    /// each of the last `periods` days or weeks, as tables or as a JSON document
+    pub fn report_activity(db: &Database, root_ids: &[u32], period: ChangePeriod, periods: u32, format: ReportFormat) -> Result<(), FsPulseError> {
+        let mut roots = Vec::new();
+        Root::for_each_root(db, |root| {
+            if root_ids.is_empty() || root_ids.iter().any(|id| i64::from(*id) == root.id()) {
+                roots.push(root.clone());
+            }
+            Ok(())
+        })?;
+
+        match format {
+            ReportFormat::Table => {
+                for root in &roots {
+                    let mut stream = Self::begin_activity_table(
+                        &format!("Change Activity by {} (Root Path: '{}')", period.as_str(), Self::show_path(root.path())),
+                        period,
+                    );
+
+                    for changes in PeriodChanges::for_root(db, root.id(), period, periods)? {
+                        stream.row(changes)?;
+                    }
+
+                    stream.finish()?;
+                }
+            },
+            ReportFormat::Json => {
+                let mut activity = Vec::new();
+                for root in &roots {
+                    activity.push(RootActivity {
+                        root_id: root.id(),
+                        root_path: root.path(),
+                        period: period.as_str(),
+                        periods: PeriodChanges::for_root(db, root.id(), period, periods)?,
+                    });
+                }
+                println!("{}", serde_json::to_string_pretty(&activity)?);
+            },
+            _ => return Err(FsPulseError::Error("Unsupported format.".to_string())),
+        }
+
+        Ok(())
+    }
+
+    /// Lists the paths whose items differ between two roots holding what should be the
+    /// same content, such as the blue and green copies of a deployment
+    pub fn report_compare_roots(db: &Database, root_a: &Root, root_b: &Root) -> Result<(), FsPulseError> {
+        println!("A: [{}] {}", root_a.id(), Self::show_path(root_a.path()));
+        println!("B: [{}] {}", root_b.id(), Self::show_path(root_b.path()));
+
+        for root in [root_a, root_b] {
+            match Scan::get_latest_for_root(db, root.id())? {
+                None => println!("Warning: Root Id {} has never been scanned", root.id()),
+                Some(scan) if scan.state() != ScanState::Completed => println!(
+                    "Warning: The latest scan of Root Id {} (Scan Id {}) did not complete", root.id(), scan.id()),
+                Some(_) => {},
+            }
+        }
+
+        let mut stream = Self::begin_differences_table();
+        let totals = Compare::for_each_difference(db, root_a.id(), root_b.id(), |difference| {
+            stream.row(difference.clone())?;
+            Ok(())
+        })?;
+        stream.finish()?;
+
+        println!(
+            "Total: {} identical, {} only in A, {} only in B, {} differing",
+            totals.identical, totals.only_in_a, totals.only_in_b, totals.differing,
+        );
+
+        if totals.unhashed > 0 {
+            println!(
+                "Note: {} files of the same size couldn't be compared by content. Scan both roots with --hash to compare them",
+                totals.unhashed,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Compares a scan (A) with the members of an archive (B), such as a backup made from
+    /// the scanned root
+    pub fn report_compare_archive(db: &Database, scan: &Scan, archive_path: &Path, members: Vec<ArchiveMember>, hashed: bool) -> Result<(), FsPulseError> {
+        let root = Root::get_by_id(db, scan.root_id())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", scan.root_id())))?;
+
+        println!("A: Scan Id {} of '{}'", scan.id(), Self::show_path(root.path()));
+        println!("B: {} ({} members)", Self::show_path(&archive_path.to_string_lossy()), members.len());
+
+        if scan.state() != ScanState::Completed {
+            println!("Warning: Scan Id {} did not complete", scan.id());
+        }
+        if Scan::get_latest_for_root(db, root.id())?.is_some_and(|latest| latest.id() != scan.id()) {
+            println!("Warning: Scan Id {} isn't the latest scan of its root. Items are compared as they were last recorded", scan.id());
+        }
+
+        let mut stream = Self::begin_differences_table();
+        let totals = Compare::for_each_archive_difference(db, scan.id(), members, |difference| {
+            stream.row(difference.clone())?;
+            Ok(())
+        })?;
+        stream.finish()?;
+
+        println!(
+            "Total: {} identical, {} only in A, {} only in B, {} differing",
+            totals.identical, totals.only_in_a, totals.only_in_b, totals.differing,
+        );
+
+        if totals.unhashed > 0 {
+            println!(
+                "Note: {} files of the same size and modification time weren't compared by content. {}",
+                totals.unhashed,
+                if hashed { "Scan the root with --hash to compare them" } else { "Use --hash to compare them" },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Compares the items present at two scans of the same root, as they were when each ran
+    pub fn report_compare_scans(db: &Database, scan_a: &Scan, scan_b: &Scan) -> Result<(), FsPulseError> {
+        if scan_a.root_id() != scan_b.root_id() {
+            return Err(FsPulseError::Error(format!(
+                "Scan Id {} and Scan Id {} are of different roots. Use compare-roots to compare roots",
+                scan_a.id(), scan_b.id())));
+        }
+
+        let root = Root::get_by_id(db, scan_a.root_id())?
+            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", scan_a.root_id())))?;
+
+        println!("A: Scan Id {} of '{}'", scan_a.id(), Self::show_path(root.path()));
+        println!("B: Scan Id {} of '{}'", scan_b.id(), Self::show_path(root.path()));
+
+        for scan in [scan_a, scan_b] {
+            if scan.state() != ScanState::Completed {
+                println!("Warning: Scan Id {} did not complete", scan.id());
+            }
+        }
+
+        let mut stream = Self::begin_differences_table();
+        let totals = Compare::for_each_scan_difference(db, root.id(), scan_a.id(), scan_b.id(), |difference| {
+            stream.row(difference.clone())?;
+            Ok(())
+        })?;
+        stream.finish()?;
+
+        println!(
+            "Total: {} identical, {} only in A, {} only in B, {} differing",
+            totals.identical, totals.only_in_a, totals.only_in_b, totals.differing,
+        );
+
+        if totals.unhashed > 0 {
+            println!(
+                "Note: {} files of the same size and modification time weren't compared by content. Scan with --hash to compare them",
+                totals.unhashed,
+            );
+        }
+        if totals.unknown > 0 {
+            println!(
+                "Note: {} items were replaced after both scans, so they couldn't be compared",
+                totals.unknown,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn begin_differences_table() -> Stream<Difference, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, d: &Difference| write!(f, "{}", d.kind)).header("Difference").left(),
+            Column::new(|f, d: &Difference| write!(f, "{}", Utils::opt_i64_or_none_as_str(d.size_a))).header("Size (A)").right(),
+            Column::new(|f, d: &Difference| write!(f, "{}", Utils::opt_i64_or_none_as_str(d.size_b))).header("Size (B)").right(),
+            Column::new(|f, d: &Difference| write!(f, "{}", Self::fit_path(&d.path))).header("Path").left(),
+        ]).title("Differences").empty_row("No Differences");
+
+        stream
+    }
+
+    /// Lists the roots and items matching every word of the query
+    pub fn report_search(db: &Database, query: &str, filter: &ChangeFilter, limit: Option<u32>) -> Result<(), FsPulseError> {
+        let empty_row = match query.is_empty() {
+            true => "Nothing Matching".to_string(),
+            false => format!("Nothing Matching '{}'", query),
+        };
+        let mut stream = Self::begin_search_table(&empty_row);
+        let mut hit_count = 0;
+
+        Search::for_each_hit(db, query, filter, limit.map_or(-1, i64::from), |hit| {
+            hit_count += 1;
+            stream.row(hit.clone())?;
+            Ok(())
+        })?;
+
+        stream.finish()?;
+        println!("Total: {} results", hit_count);
+
+        Ok(())
+    }
+
+    fn begin_search_table(empty_row: &str) -> Stream<SearchHit, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, h: &SearchHit| write!(f, "{}", h.kind)).header("Kind").left(),
+            Column::new(|f, h: &SearchHit| write!(f, "{}", h.id)).header("ID").right().min_width(6),
+            Column::new(|f, h: &SearchHit| write!(f, "{}", h.root_id)).header("Root ID").right().min_width(6),
+            Column::new(|f, h: &SearchHit| write!(f, "{}", Utils::opt_string_or_none(&h.item_type))).header("Type").center(),
+            Column::new(|f, h: &SearchHit| write!(f, "{}", h.is_tombstone)).header("Tombstone").center(),
+            Column::new(|f, h: &SearchHit| write!(f, "{}", Self::fit_path(&h.path))).header("Path").left(),
+        ]).title("Search").empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_summary_table(stale_before: i64, change_days: u32) -> Stream<RootSummary, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, s: &RootSummary| write!(f, "{}", s.root.id())).header("Root ID").right().min_width(6),
+            Column::new(|f, s: &RootSummary| write!(f, "{}", Self::fit_path(s.root.path()))).header("Path").left(),
+            Column::new(|f, s: &RootSummary| write!(f, "{}", Utils::format_db_time_short_or_none(s.last_scan_time))).header("Last Scan").left(),
+            Column::new(move |f, s: &RootSummary| write!(f, "{}", match s.last_scan_time {
+                None => "NEVER SCANNED",
+                Some(time) if time < stale_before => "STALE",
+                Some(_) => "OK",
+            })).header("Status").center(),
+            Column::new(|f, s: &RootSummary| write!(f, "{}", s.file_count)).header("Files").right(),
+            Column::new(|f, s: &RootSummary| write!(f, "{}", s.total_size)).header("Size").right(),
+            Column::new(|f, s: &RootSummary| write!(f, "{}", s.recent_change_count)).header(&format!("Changes ({}d)", change_days)).right(),
+        ]).title("Summary").empty_row("No Roots");
+
+        stream
+    }
+
+    /// Prints the scan as a table, followed by its counts, what it excluded and how it was
+    /// run. Without a scan, the table says there's none
+    pub fn print_scan(report: Option<&ScanReport>) -> Result<(), FsPulseError> {
+        let table_title = match report {
+            Some(report) => format!("Scan (Root Path: '{}')", Self::show_path(report.root.path())),
+            None => "Scan".into()
+        };
+
+        let mut stream = Reports::begin_scans_table(&table_title, "No Scan");
+
+        if let Some(report) = report {
+            stream.row(report.scan)?;
+        }
+
+        stream.finish()?;
+
+        let Some(report) = report else {
+            return Ok(());
+        };
+        let scan = &report.scan;
+
+        if let Some(files) = scan.file_count() {
+            let (files, folders) = (files, scan.folder_count().unwrap_or_default());
+            match report.count_deltas {
+                Some((file_delta, folder_delta)) => println!(
+                    "Files: {} ({}), Folders: {} ({})",
+                    Utils::format_count(files), Utils::format_count_delta(file_delta),
+                    Utils::format_count(folders), Utils::format_count_delta(folder_delta),
+                ),
+                None => println!("Files: {}, Folders: {}", Utils::format_count(files), Utils::format_count(folders)),
+            }
+        }
 
-            let root = Root::get_by_id(db, root_id)?
-                .ok_or_else(|| FsPulseError::Error("Root Not Found".to_string()))?;
-            let mut stream = Self::begin_roots_table()
-                .title("Root");
+        if scan.excluded_count() > 0 {
+            if report.exclusion_counts.is_empty() {
+                println!("Excluded: {} path(s)", Utils::format_count(scan.excluded_count()));
+            } else {
+                let breakdown: Vec<String> = report.exclusion_counts.iter()
+                    .map(|(reason, count)| format!("{}: {}", reason, Utils::format_count(*count)))
+                    .collect();
+                println!("Excluded: {} path(s) ({})", Utils::format_count(scan.excluded_count()), breakdown.join(", "));
+            }
+        }
 
-            stream.row(root.clone())?;
-            stream.finish()?;
+        if !report.exclude_rules.is_empty() {
+            println!("Exclude rules: {}", report.exclude_rules.join(", "));
         }
 
-        Ok(())
-    }
+        if let Some(budget) = scan.budget_secs() {
+            println!(
+                "Budget: {}, Directories checked: {}, not checked: {}",
+                Utils::format_duration(budget.max(0) as u64),
+                Utils::format_count(scan.dirs_checked().unwrap_or_default()),
+                Utils::format_count(scan.dirs_unchecked()),
+            );
+        }
 
-    pub fn report_items(db: &Database, item_id: Option<u32>, item_path: Option<String>, root_id: Option<u32>, format: ReportFormat) -> Result<(), FsPulseError> {
+        if let Some(settings) = scan.hash_settings() {
+            println!(
+                "Hash tuning: {} file(s) at once, {} reads",
+                settings.concurrency, Utils::format_bytes(settings.buffer_size as u64),
+            );
+        }
 
-        match (item_id, item_path, root_id) {
-            (Some(item_id), _, _) => {
-                // TODO: In the single item case, "tree" is not a valid report format
-                let item = Item::get_by_id(db, item_id.into())?;
+        if let Some(growth) = scan.db_growth() {
+            if growth < 0 {
+                println!("Database growth: none (shrank by {})", Utils::format_bytes(growth.unsigned_abs()));
+            } else {
+                println!("Database growth: {}", Utils::format_bytes(growth as u64));
+            }
+        }
 
-                let mut stream = Self::begin_items_table("Item", &format!("Item {} Not Found", item_id));
-    
-                if let Some(item) = item {
-                    stream.row(item)?;
-                }
-    
-                stream.finish()?;
-            },
-            (_, Some(item_path), _) => {
-                let mut stream = Self::begin_items_table("Items", &format!("Item Path '{}' Not Found", item_path));
-                Item::for_each_item_with_path(
-                    db, 
-                    &item_path, 
-                    |item| {
-                        stream.row(item.clone())?;
-                        Ok(())
-                    }
-                )?;
+        if let Some(decision) = scan.empty_guardrail() {
+            println!("Empty scan guardrail: {}", decision);
+        }
 
-                stream.finish()?;
-            },
-            (_, _, Some(root_id)) => {
-                let root = Root::get_by_id(db, root_id.into())?
-                    .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", root_id)))?;
+        if let Some(threshold) = scan.confirm_threshold() {
+            println!(
+                "Hash confirmations (files of {} bytes or more): {} confirmed, {} collisions",
+                threshold, scan.hash_confirmations(), scan.hash_collisions(),
+            );
+        }
 
-                let scan = Scan::get_latest_for_root(db, root.id())?
-                    .ok_or_else(|| FsPulseError::Error(format!("No latest scan found for Root Id {}", root_id)))?;
+        Ok(())
+    }
 
-                match format {
-                    ReportFormat::Tree => Self::print_last_seen_scan_items_as_tree(db, &scan, &root)?,
-                    ReportFormat::Table => Self::print_last_seen_scan_items_as_table(db, &scan, &root)?,
-                    _ => return Err(FsPulseError::Error("Unsupported format.".to_string())),
+    /// Lists the paths the scan excluded and the directories it didn't get to, for a scan
+    /// which has any
+    pub fn print_scan_paths(report: &ScanReport) -> Result<(), FsPulseError> {
+        Self::use_root(&report.root);
 
-                }
-            },
-            _ => {
-                // Should never get here
-            }
+        if report.scan.excluded_count() > 0 {
+            Self::print_exclusions(report)?;
         }
-        
+        if report.scan.dirs_unchecked() > 0 {
+            Self::print_unchecked_dirs(report)?;
+        }
+
         Ok(())
     }
 
-    pub fn report_changes(
-        db: &Database, 
-        change_id: Option<u32>, 
-        item_id: Option<u32>,
-        scan_id: Option<u32>, 
-        format: ReportFormat
-    ) -> Result<(), FsPulseError> {
-
-        match (change_id, item_id, scan_id) {
-            (Some(change_id), None, None) => {
-                let change = Change::get_by_id(db, change_id.into())?;
-                let mut stream = Self::begin_changes_table("Change", "No Change Found");
-                if let Some(change) = change {
-                    stream.row(change)?;
-                }
-                stream.finish()?;
-            },
-            (None, Some(item_id), None) => {
-                Self::print_item_changes_as_table(db, item_id.into())?;
-            },
-            (None, None, Some(scan_id)) => {
-                match format {
-                    ReportFormat::Table => Self::print_scan_changes_as_table(db, scan_id.into())?,
-                    ReportFormat::Tree => Self::print_scan_changes_as_tree(db, scan_id.into())?,
-                    _ => return Err(FsPulseError::Error("Unsupported format.".to_string())),
-                }
-            },
-            _ => {
+    /// Lists the paths the scan excluded, if the scan was asked to record them
+    fn print_exclusions(report: &ScanReport) -> Result<(), FsPulseError> {
+        let mut stream = Stream::new(io::stdout(), vec![
+            Column::new(|f, e: &Exclusion| write!(f, "{}", Self::fit_item_path(&e.path))).header("Path").left(),
+            Column::new(|f, e: &Exclusion| write!(f, "{}", e.reason)).header("Reason").center(),
+        ])
+        .title(&format!("Excluded Paths (Scan Id: {})", report.scan.id()))
+        .empty_row("Paths not recorded (scan with --record-exclusions to record them)");
+
+        for exclusion in &report.exclusions {
+            stream.row(exclusion.clone())?;
+        }
 
-            },
-       }
+        stream.finish()?;
         Ok(())
     }
 
-    pub fn print_scan(db: &Database, scan: &Option<Scan>, _format: ReportFormat) -> Result<(), FsPulseError> {
-        let table_title= match scan {
-            Some(scan) => {
-                let root = Root::get_by_id(db, scan.root_id())?
-                    .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", scan.root_id())))?;
-                format!("Scan (Root Path: '{}')", root.path())
-            }
-            None => "Scan".into()
-        };
-
-        let mut stream = Reports::begin_scans_table(&table_title, "No Scan");
+    /// Lists the directories a budgeted scan didn't get to
+    fn print_unchecked_dirs(report: &ScanReport) -> Result<(), FsPulseError> {
+        let mut stream = Stream::new(io::stdout(), vec![
+            Column::new(|f, path: &String| write!(f, "{}", Self::fit_item_path(path))).header("Path").left(),
+        ])
+        .title(&format!("Directories Not Checked (Scan Id: {})", report.scan.id()))
+        .empty_row("None");
 
-        if let Some(scan) = scan {
-            stream.row(scan.clone())?;
+        for path in &report.unchecked_dirs {
+            stream.row(path.clone())?;
         }
 
         stream.finish()?;
-
         Ok(())
     }
 
-    fn print_scans(db: &Database, last: u32) -> Result<(), FsPulseError> {
-        let mut stream = Reports::begin_scans_table("Scans", "No Scans");
-        
-        Scan::for_each_scan(
-            db, 
-            last, 
-            |_db, scan| {
-                stream.row(scan.clone())?;
-                Ok(())
-            }
-        )?;
-
-        stream.finish()?;
+    fn print_scan_totals(totals: &ScanTotals) {
+        println!(
+            "Total: {} scans, {} files, {} folders, {} adds, {} modifies, {} deletes, {} type changes, {} moves",
+            totals.scans,
+            totals.files,
+            totals.folders,
+            totals.change_counts.count_of(ChangeType::Add),
+            totals.change_counts.count_of(ChangeType::Modify),
+            totals.change_counts.count_of(ChangeType::Delete),
+            totals.change_counts.count_of(ChangeType::TypeChange),
+            totals.change_counts.count_of(ChangeType::Move),
+        );
+    }
 
-        Ok(())
+    fn print_item_totals(totals: &ItemTotals) {
+        println!(
+            "Total: {} items, {} files, {} folders, {} bytes",
+            totals.items, totals.files, totals.folders, totals.size,
+        );
     }
 
     fn begin_scans_table(title: &str, empty_row: &str) -> Stream<Scan, Stdout> {
@@ -223,9 +2119,10 @@ impl Reports {
         let stream = Stream::new(out, vec![
             Column::new(|f, s: &Scan| write!(f, "{}", s.id())).header("ID").right().min_width(6),
             Column::new(|f, s: &Scan| write!(f, "{}", s.root_id())).header("Root ID").right().min_width(6),
+            Column::new(|f, s: &Scan| write!(f, "#{}", s.root_scan_number())).header("Root Scan").right(),
             Column::new(|f, s: &Scan| write!(f, "{}", s.state())).header("State").center().min_width(10),
 
-            Column::new(|f, s: &Scan| write!(f, "{}", s.hashing())).header("Hashing").center(),
+            Column::new(|f, s: &Scan| write!(f, "{}", Self::hashing_description(s))).header("Hashing").center(),
             Column::new(|f, s: &Scan| write!(f, "{}", s.validating())).header("Validating").center(),
             Column::new(|f, s: &Scan| write!(f, "{}", Utils::format_db_time_short(s.time_of_scan()))).header("Time"),
             Column::new(|f, s: &Scan| write!(f, "{}", Utils::opt_i64_or_none_as_str(s.file_count()))).header("Files").right().min_width(7),
@@ -235,16 +2132,276 @@ impl Reports {
             Column::new(|f, s: &Scan| write!(f, "{}", s.change_counts().count_of(ChangeType::Modify))).header("Modifies").right().min_width(7),
             Column::new(|f, s: &Scan| write!(f, "{}", s.change_counts().count_of(ChangeType::Delete))).header("Deletes").right().min_width(7),
             Column::new(|f, s: &Scan| write!(f, "{}", s.change_counts().count_of(ChangeType::TypeChange))).header("T Changes").right().min_width(7),
+            Column::new(|f, s: &Scan| write!(f, "{}", s.change_counts().count_of(ChangeType::Move))).header("Moves").right().min_width(7),
+            Column::new(|f, s: &Scan| write!(f, "{}", s.digest().as_deref().unwrap_or("-"))).header("Digest").center(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn hashing_description(scan: &Scan) -> String {
+        match (scan.hashing(), scan.hash_delta()) {
+            (true, true) => format!("true ({}, delta)", scan.hash_algo()),
+            (true, false) => format!("true ({})", scan.hash_algo()),
+            (false, _) => "false".to_string(),
+        }
+    }
+
+    fn symlink_target_description(item: &Item) -> String {
+        match (item.symlink_target(), item.symlink_dangling()) {
+            (Some(target), Some(true)) => format!("{} (dangling)", Self::fit_path(target)),
+            (Some(target), _) => Self::fit_path(target),
+            (None, _) => "-".to_string(),
+        }
+    }
+
+    fn begin_timestamp_findings_table(title: &str, empty_row: &str) -> Stream<TimestampFinding, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, t: &TimestampFinding| write!(f, "{}", t.item.id())).header("Item ID").right().min_width(6),
+            Column::new(|f, t: &TimestampFinding| write!(f, "{}", t.item.item_type())).header("Type").center(),
+            Column::new(|f, t: &TimestampFinding| write!(f, "{}", Self::fit_item_path(t.item.path()))).header("Path").left(),
+            Column::new(|f, t: &TimestampFinding| write!(f, "{}", Utils::format_db_time_short_or_none(t.item.last_modified()))).header("Modified").left(),
+            Column::new(|f, t: &TimestampFinding| write!(f, "{}", t.kind)).header("Finding").center(),
+            Column::new(|f, t: &TimestampFinding| write!(f, "{}", t.skew.map_or("-".to_string(), Utils::format_skew))).header("Skew").right(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_symlink_findings_table(title: &str, empty_row: &str) -> Stream<Item, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, i: &Item| write!(f, "{}", i.id())).header("Item ID").right().min_width(6),
+            Column::new(|f, i: &Item| write!(f, "{}", Self::fit_item_path(i.path()))).header("Path").left(),
+            Column::new(|f, i: &Item| write!(f, "{}", Self::fit_path(i.symlink_target().unwrap_or("-")))).header("Target").left(),
+            Column::new(|f, _: &Item| write!(f, "Dangling")).header("Finding").center(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_group_roots_table(title: &str, empty_row: &str) -> Stream<GroupRoot, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, g: &GroupRoot| write!(f, "{}", g.root.id())).header("Root ID").right().min_width(6),
+            Column::new(|f, g: &GroupRoot| write!(f, "{}", Utils::opt_i64_or_none_as_str(g.latest_scan.as_ref().map(|s| s.id())))).header("Latest Scan").right(),
+            Column::new(|f, g: &GroupRoot| write!(f, "{}", Utils::format_db_time_short_or_none(g.latest_scan.as_ref().map(|s| s.time_of_scan())))).header("Time"),
+            Column::new(|f, g: &GroupRoot| write!(f, "{}", Utils::opt_i64_or_none_as_str(g.latest_scan.as_ref().and_then(|s| s.file_count())))).header("Files").right().min_width(7),
+            Column::new(|f, g: &GroupRoot| write!(f, "{}", Utils::opt_i64_or_none_as_str(g.latest_scan.as_ref().and_then(|s| s.folder_count())))).header("Folders").right().min_width(7),
+            Column::new(|f, g: &GroupRoot| write!(f, "{}", Self::show_path(g.root.path()))).header("Path").left(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_duplicates_table(title: &str, empty_row: &str) -> Stream<DuplicateRow, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, d: &DuplicateRow| write!(f, "{}", d.hash)).header("Hash").center(),
+            Column::new(|f, d: &DuplicateRow| write!(f, "{}", d.hash_algo)).header("Algo").center(),
+            Column::new(|f, d: &DuplicateRow| write!(f, "{}", d.file_size)).header("Size").right(),
+            Column::new(|f, d: &DuplicateRow| write!(f, "{}", d.file.item_id)).header("Item ID").right().min_width(6),
+            Column::new(|f, d: &DuplicateRow| write!(f, "{}", d.file.root_id)).header("Root ID").right(),
+            Column::new(|f, d: &DuplicateRow| write!(f, "{}", Self::fit_item_path(&d.file.path))).header("Path").left(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_retention_table(title: &str, empty_row: &str) -> Stream<RetainedFile, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, r: &RetainedFile| write!(f, "{}", r.item_id)).header("Item ID").right().min_width(6),
+            Column::new(|f, r: &RetainedFile| write!(f, "{}", Utils::format_db_time_short_or_none(r.last_modified))).header("Modified").left(),
+            Column::new(|f, r: &RetainedFile| write!(f, "{}", Utils::format_db_time_short_or_none(r.first_seen))).header("First Seen").left(),
+            Column::new(|f, r: &RetainedFile| write!(f, "{}", Utils::opt_i64_or_none_as_str(r.file_size))).header("Size").right(),
+            Column::new(|f, r: &RetainedFile| write!(f, "{}", Self::fit_item_path(&r.path))).header("Path").left(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_deletions_table(title: &str, empty_row: &str) -> Stream<Deletion, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, d: &Deletion| write!(f, "{}", d.item_id)).header("Item ID").right().min_width(6),
+            Column::new(|f, d: &Deletion| write!(f, "{}", d.item_type)).header("Type").center(),
+            Column::new(|f, d: &Deletion| write!(f, "{}", d.last_seen_scan_id)).header("Last Seen Scan").right(),
+            Column::new(|f, d: &Deletion| write!(f, "{}", Utils::format_db_time_short(d.last_seen_time))).header("Last Seen").center(),
+            Column::new(|f, d: &Deletion| write!(f, "{}", d.deleted_scan_id)).header("Deleted Scan").right(),
+            Column::new(|f, d: &Deletion| write!(f, "{}", Utils::format_db_time_short(d.deleted_time))).header("Found Missing").center(),
+            Column::new(|f, d: &Deletion| write!(f, "{}", Self::fit_item_path(&d.item_path))).header("Path").left(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_history_table(title: &str, empty_row: &str) -> Stream<HistoryEntry, Stdout> {
+        // A side of the change whose values weren't recorded shows as empty
+        fn value(values: &Option<HistoryValues>, get: impl Fn(&HistoryValues) -> String) -> String {
+            values.as_ref().map_or("-".to_string(), get)
+        }
+
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", h.scan_id)).header("Scan Id").right().min_width(6),
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", Utils::format_db_time_short(h.time_of_scan))).header("Time").center(),
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", h.change_type)).header("Change").center(),
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", Self::fit_item_path(&h.path))).header("Path").left(),
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", h.old_path.as_deref().map_or("-".to_string(), Self::fit_item_path))).header("Moved From").left(),
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", value(&h.before, |v| Utils::opt_i64_or_none_as_str(v.file_size)))).header("Old Size").right(),
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", value(&h.after, |v| Utils::opt_i64_or_none_as_str(v.file_size)))).header("New Size").right(),
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", value(&h.before, |v| Utils::format_db_time_short_or_none(v.last_modified)))).header("Old Modified").center(),
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", value(&h.after, |v| Utils::format_db_time_short_or_none(v.last_modified)))).header("New Modified").center(),
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", value(&h.before, |v| Utils::opt_string_or_none(&v.hash).to_owned()))).header("Old Hash").center(),
+            Column::new(|f, h: &HistoryEntry| write!(f, "{}", value(&h.after, |v| Utils::opt_string_or_none(&v.hash).to_owned()))).header("New Hash").center(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_verifications_table(title: &str, empty_row: &str) -> Stream<Verification, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, v: &Verification| write!(f, "{}", v.id())).header("ID").right().min_width(6),
+            Column::new(|f, v: &Verification| write!(f, "{}", v.root_id())).header("Root ID").right(),
+            Column::new(|f, v: &Verification| write!(f, "{}", Utils::format_db_time_short(v.time_of_verification()))).header("Time"),
+            Column::new(|f, v: &Verification| write!(f, "{}", v.files_matched())).header("Matched").right(),
+            Column::new(|f, v: &Verification| write!(f, "{}", v.files_mismatched())).header("Mismatched").right(),
+            Column::new(|f, v: &Verification| write!(f, "{}", v.files_skipped())).header("Skipped").right(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_mismatches_table(title: &str, empty_row: &str) -> Stream<Mismatch, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, m: &Mismatch| write!(f, "{}", m.verification_id)).header("Verification").right(),
+            Column::new(|f, m: &Mismatch| write!(f, "{}", Utils::format_db_time_short(m.time_of_verification))).header("Time"),
+            Column::new(|f, m: &Mismatch| write!(f, "{}", m.item_id)).header("Item ID").right().min_width(6),
+            Column::new(|f, m: &Mismatch| write!(f, "{}", Utils::opt_i64_or_none_as_str(m.file_size))).header("Size").right(),
+            Column::new(|f, m: &Mismatch| write!(f, "{}", m.hash_algo)).header("Algo").center(),
+            Column::new(|f, m: &Mismatch| write!(f, "{}", m.expected_hash)).header("Recorded Hash").center(),
+            Column::new(|f, m: &Mismatch| write!(f, "{}", m.found_hash)).header("Found Hash").center(),
+            Column::new(|f, m: &Mismatch| write!(f, "{}", Self::fit_item_path(&m.path))).header("Path").left(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_audit_table(title: &str, empty_row: &str) -> Stream<AuditEntry, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, e: &AuditEntry| write!(f, "{}", e.id())).header("ID").right().min_width(6),
+            Column::new(|f, e: &AuditEntry| write!(f, "{}", Utils::format_db_time_short(e.time()))).header("Time"),
+            Column::new(|f, e: &AuditEntry| write!(f, "{}", e.user())).header("User"),
+            Column::new(|f, e: &AuditEntry| write!(f, "{}", e.operation())).header("Operation"),
+            Column::new(|f, e: &AuditEntry| write!(f, "{}", e.parameters())).header("Parameters").left(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_migrations_table(title: &str, empty_row: &str) -> Stream<(Migration, ContentLocation), Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, (m, _): &(Migration, ContentLocation)| write!(f, "{}:{}", m.hash_algo, m.hash)).header("Hash").center(),
+            Column::new(|f, (m, _): &(Migration, ContentLocation)| write!(f, "{}", m.kind.as_str())).header("Migration").center(),
+            Column::new(|f, (m, _): &(Migration, ContentLocation)| write!(f, "{}", m.file_size)).header("Size").right(),
+            Column::new(|f, (m, _): &(Migration, ContentLocation)| write!(f, "{} -> {}", m.copies_before, m.copies_after)).header("Copies").center(),
+            Column::new(|f, (_, l): &(Migration, ContentLocation)| write!(f, "{}", l.item_id)).header("Item ID").right().min_width(6),
+            Column::new(|f, (_, l): &(Migration, ContentLocation)| write!(f, "{}", Utils::opt_i64_or_none_as_str(l.appeared))).header("Appeared Scan").right(),
+            Column::new(|f, (_, l): &(Migration, ContentLocation)| write!(f, "{}", Utils::opt_i64_or_none_as_str(l.left))).header("Left Scan").right(),
+            Column::new(|f, (_, l): &(Migration, ContentLocation)| write!(f, "{}", Self::fit_item_path(&l.path))).header("Path").left(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_ownership_changes_table(title: &str, empty_row: &str) -> Stream<OwnershipChange, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, c: &OwnershipChange| write!(f, "{}", c.scan_id)).header("Scan ID").right().min_width(6),
+            Column::new(|f, c: &OwnershipChange| write!(f, "{}", c.change_id)).header("Change ID").right().min_width(6),
+            Column::new(|f, c: &OwnershipChange| write!(f, "{}:{}", Owners::user_name(c.prev_uid), Owners::group_name(c.prev_gid))).header("Prev Owner").left(),
+            Column::new(|f, c: &OwnershipChange| write!(f, "{}:{}", Owners::user_name(c.uid), Owners::group_name(c.gid))).header("Owner").left(),
+            Column::new(|f, c: &OwnershipChange| write!(f, "{}", Self::fit_item_path(&c.item_path))).header("Path").left(),
+        ]).title(title).empty_row(empty_row);
+
+        stream
+    }
+
+    fn begin_usage_table(title: &str, by: UsageBy) -> Stream<OwnerUsage, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(move |f, u: &OwnerUsage| write!(f, "{}", by.name(u.id))).header(if by == UsageBy::User { "User" } else { "Group" }).left(),
+            Column::new(|f, u: &OwnerUsage| write!(f, "{}", Utils::opt_i64_or_none_as_str(u.id))).header("Id").right(),
+            Column::new(|f, u: &OwnerUsage| write!(f, "{}", Utils::format_count(u.items))).header("Items").right(),
+            Column::new(|f, u: &OwnerUsage| write!(f, "{}", Utils::format_count(u.files))).header("Files").right(),
+            Column::new(|f, u: &OwnerUsage| write!(f, "{}", Utils::format_bytes(u.bytes.max(0) as u64))).header("Size").right(),
+            Column::new(|f, u: &OwnerUsage| write!(f, "{}", u.item_delta.map_or("-".to_string(), Utils::format_count_delta))).header("Items Change").right(),
+            Column::new(|f, u: &OwnerUsage| write!(f, "{}", u.byte_delta.map_or("-".to_string(), Utils::format_bytes_delta))).header("Size Change").right(),
+        ]).title(title).empty_row("No Items");
+
+        stream
+    }
+
+    fn begin_unexpected_owners_table(title: &str, empty_row: &str) -> Stream<Item, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, i: &Item| write!(f, "{}", i.id())).header("Item ID").right().min_width(6),
+            Column::new(|f, i: &Item| write!(f, "{}", i.item_type())).header("Type").center(),
+            Column::new(|f, i: &Item| write!(f, "{}", Owners::user_name(i.owner_uid()))).header("Owner").left(),
+            Column::new(|f, i: &Item| write!(f, "{}", Owners::group_name(i.owner_gid()))).header("Group").left(),
+            Column::new(|f, i: &Item| write!(f, "{}", Self::fit_item_path(i.path()))).header("Path").left(),
         ]).title(title).empty_row(empty_row);
 
         stream
     }
 
+    fn begin_scan_estimates_table() -> Stream<ScanKindEstimate, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(|f, e: &ScanKindEstimate| write!(f, "{}", e.kind)).header("Scan").left(),
+            Column::new(|f, e: &ScanKindEstimate| write!(f, "{}", e.option)).header("Option").left(),
+            Column::new(|f, e: &ScanKindEstimate| write!(f, "{}", Utils::format_count(e.files))).header("Files to Hash").right(),
+            Column::new(|f, e: &ScanKindEstimate| write!(f, "{}", Utils::format_bytes(e.bytes.max(0) as u64))).header("Bytes to Hash").right(),
+            Column::new(|f, e: &ScanKindEstimate| match (e.bytes, e.hash_time) {
+                (0, _) => write!(f, "-"),
+                (_, Some(time)) => write!(f, "~{}", Utils::format_duration(time.as_secs())),
+                (_, None) => write!(f, "?"),
+            }).header("Hashing Time").right(),
+        ]).title("Scan Estimates");
+
+        stream
+    }
+
+    fn begin_activity_table(title: &str, period: ChangePeriod) -> Stream<PeriodChanges, Stdout> {
+        let out = io::stdout();
+        let stream = Stream::new(out, vec![
+            Column::new(move |f, p: &PeriodChanges| match period {
+                ChangePeriod::Day => write!(f, "{}", p.start),
+                ChangePeriod::Week => write!(f, "Week of {}", p.start),
+            }).header("Period").left(),
+            Column::new(|f, p: &PeriodChanges| write!(f, "{}", p.scans)).header("Scans").right(),
+            Column::new(|f, p: &PeriodChanges| write!(f, "{}", Utils::format_count(p.adds))).header("Adds").right(),
+            Column::new(|f, p: &PeriodChanges| write!(f, "{}", Utils::format_count(p.modifies))).header("Modifies").right(),
+            Column::new(|f, p: &PeriodChanges| write!(f, "{}", Utils::format_count(p.deletes))).header("Deletes").right(),
+            Column::new(|f, p: &PeriodChanges| write!(f, "{}", Utils::format_count(p.type_changes))).header("Type Changes").right(),
+            Column::new(|f, p: &PeriodChanges| write!(f, "{}", Utils::format_count(p.moves))).header("Moves").right(),
+            Column::new(|f, p: &PeriodChanges| write!(f, "{}", Utils::format_count(p.total()))).header("Total").right(),
+        ]).title(title).empty_row("No Periods");
+
+        stream
+    }
+
     fn begin_roots_table() -> Stream<Root, Stdout> {
         let out = io::stdout();
         let stream = Stream::new(out, vec![
             Column::new(|f, root: &Root| write!(f, "{}", root.id())).header("ID").right().min_width(6),
-            Column::new(|f, root: &Root| write!(f, "{}", root.path())).header("Path").left().min_width(109),
+            Column::new(|f, root: &Root| write!(f, "{}", Self::fit_path(root.path()))).header("Path").left(),
         ]).title("Roots").empty_row("No Rootss");
 
         stream
@@ -255,16 +2412,20 @@ impl Reports {
         let stream = Stream::new(out, vec![
             Column::new(|f, i: &Item| write!(f, "{}", i.id())).header("ID").right().min_width(6),
             Column::new(|f, i: &Item| write!(f, "{}", i.root_id())).header("Root ID").right(),
-            Column::new(|f, i: &Item| write!(f, "{}", i.path())).header("Path").left(),
+            Column::new(|f, i: &Item| write!(f, "{}", Self::fit_item_path(i.path()))).header("Path").left(),
             Column::new(|f, i: &Item| write!(f, "{}", i.is_tombstone())).header("Tombstone").center(),
             Column::new(|f, i: &Item| write!(f, "{}", i.item_type())).header("Type").center(),
             Column::new(|f, i: &Item| write!(f, "{}", Utils::format_db_time_short_or_none(i.last_modified()))).header("Modified").left(),
             Column::new(|f, i: &Item| write!(f, "{}", Utils::opt_i64_or_none_as_str(i.file_size()))).header("Size").right(),
             Column::new(|f, i: &Item| write!(f, "{}", i.file_hash().unwrap_or("-"))).header("Hash").center(),
+            Column::new(|f, i: &Item| write!(f, "{}", i.hash_algo().unwrap_or("-"))).header("Hash Algo").center(),
+            Column::new(|f, i: &Item| write!(f, "{}", i.sample_hash().unwrap_or("-"))).header("Sampled Hash").center(),
             Column::new(|f, i: &Item| write!(f, "{}", Utils::opt_bool_or_none_as_str(i.file_is_valid()))).header("Is Valid").center(),
             Column::new(|f, i: &Item| write!(f, "{}", i.last_scan_id())).header("Last Scan").right(),
             Column::new(|f, i: &Item| write!(f, "{}", Utils::opt_i64_or_none_as_str(i.last_hash_scan_id()))).header("Last Hash Scan").right(),
             Column::new(|f, i: &Item| write!(f, "{}", Utils::opt_i64_or_none_as_str(i.last_is_valid_scan_id()))).header("Last Is Valid Scan").right(),
+            Column::new(|f, i: &Item| write!(f, "{}", Utils::format_db_time_short_or_none(i.last_verified()))).header("Last Verified").left(),
+            Column::new(|f, i: &Item| write!(f, "{}", Self::symlink_target_description(i))).header("Target").left(),
         ]).title(title).empty_row(empty_row);
         
         stream
@@ -277,20 +2438,26 @@ impl Reports {
             Column::new(|f, c: &Change| write!(f, "{}", c.scan_id)).header("Scan Id").right(),
             Column::new(|f, c: &Change| write!(f, "{}", c.item_id)).header("Item Id").right(),
             Column::new(|f, c: &Change| write!(f, "{}", c.item_type)).header("Item Type").center(),
-            Column::new(|f, c: &Change| write!(f, "{}", c.item_path)).header("Item Path").left(),
+            Column::new(|f, c: &Change| write!(f, "{}", Self::fit_item_path(&c.item_path))).header("Item Path").left(),
             Column::new(|f, c: &Change| write!(f, "{}", c.change_type)).header("Change Type").center(),
+            Column::new(|f, c: &Change| write!(f, "{}", c.old_path.as_deref().map_or("-".to_string(), Self::fit_item_path))).header("Moved From").left(),
             Column::new(|f, c: &Change| write!(f, "{}", Utils::format_db_time_short_or_none(c.prev_last_modified))).header("Prev Modified").center(),
             Column::new(|f, c: &Change| write!(f, "{}", Utils::opt_i64_or_none_as_str(c.prev_file_size))).header("Prev Size").right(),
             Column::new(|f, c: &Change| write!(f, "{}", Utils::opt_string_or_none(&c.prev_hash))).header("Prev Hash").center(),
+            Column::new(|f, c: &Change| write!(f, "{}", Utils::opt_string_or_none(&c.prev_sample_hash))).header("Prev Sampled Hash").center(),
             Column::new(|f, c: &Change| write!(f, "{}", Utils::opt_bool_or_none_as_str(c.prev_is_valid))).header("Prev Is Valid").center(),
+            Column::new(|f, c: &Change| write!(f, "{}", Utils::opt_string_or_none(&c.prev_symlink_target))).header("Prev Target").left(),
+            Column::new(|f, c: &Change| write!(f, "{}", c.similarity.map_or("-".to_string(), |similarity| format!("{}%", similarity)))).header("Similarity").right(),
+            Column::new(|f, c: &Change| write!(f, "{}", if c.acknowledged { "yes" } else { "-" })).header("Ack").center(),
+            Column::new(|f, c: &Change| write!(f, "{}", Utils::opt_string_or_none(&c.review_comment))).header("Comment").left(),
         ]).title(title).empty_row(empty_row);
 
         stream
     }
 
-    fn get_tree_path(path_stack: &mut Vec<PathBuf>, root_path: &Path, path: &str, is_dir: bool) -> (usize, PathBuf) {
-        // Reduce path to the portion that is relative to the root
-        let path = Path::new(path).strip_prefix(root_path).unwrap();
+    fn get_tree_path(path_stack: &mut Vec<PathBuf>, path: &str, is_dir: bool) -> (usize, PathBuf) {
+        // Item paths are relative to the root
+        let path = Path::new(path);
         let parent = path.parent();
 
         let mut new_path = path;
@@ -330,67 +2497,73 @@ impl Reports {
         (indent_level, new_path.to_path_buf())
     }
 
-    fn print_scan_changes_as_table(db: &Database, scan_id: i64) -> Result<(), FsPulseError> {
+    /// Reports the changes a scan found in a root expected never to change, set apart from
+    /// an ordinary scan report so they aren't mistaken for routine changes
+    pub fn print_frozen_root_changes(db: &Database, root: &Root, scan: &Scan, changes: i64) -> Result<(), FsPulseError> {
+        let width = Self::width();
+
+        Self::hr(width);
+        Self::print_center(width, "*** FROZEN ROOT CHANGED ***");
+        Self::print_center(width, &format!("'{}' was expected never to change", Self::show_path(root.path())));
+        Self::print_center(width, &format!("Scan Id {} found {} changes", scan.id(), changes));
+        Self::hr(width);
+
+        let report = Self::changes(db, None, None, Some(scan.id()), &ChangeSelection::default())?;
+        Self::print_changes(&report, ReportFormat::Table, TreeDirs::All)
+    }
+
+    fn print_scan_changes_as_table(scan_id: i64, changes: &[Change]) -> Result<(), FsPulseError> {
         let mut stream = Reports::begin_changes_table(&format!("Changes - Scan ID: {}", scan_id), "No Changes");
 
-        Change::for_each_change_in_scan(
-            db, 
-            scan_id, 
-            |change| {
-                stream.row(change.clone())?;
-                Ok(())
-            }
-        )?;
+        for change in changes {
+            stream.row(change.clone())?;
+        }
 
         stream.finish()?;
 
         Ok(())
     }
       
-    fn print_scan_changes_as_tree(db: &Database, scan_id: i64) -> Result<(), FsPulseError> {
-        let width = 100;
-
-        let scan = Scan::get_by_id(db, scan_id)?
-            .ok_or_else(|| FsPulseError::Error(format!("Scan Id {} not found", scan_id)))?;
-        let root = Root::get_by_id(db, scan.root_id())?
-            .ok_or_else(|| FsPulseError::Error(format!("Root Id {} not found", scan.root_id())))?;
+    fn print_scan_changes_as_tree(root: &Root, changes: &[Change], tree_dirs: TreeDirs) -> Result<(), FsPulseError> {
+        let width = Self::width();
 
         Self::print_center(width, "Changes");
-        Self::print_center(width, &format!("Root Path: '{}'", root.path()));
+        Self::print_center(width, &format!("Root Path: '{}'", Self::show_path(root.path())));
 
         Self::hr(width);
     
-        let root_path = Path::new(root.path());
         let mut path_stack: Vec<PathBuf> = Vec::new(); // Stack storing directory paths
         let mut change_count = 0;
 
-         // TODO: identify changes as metadata and/or hash
-        Change::for_each_change_in_scan(
-            db, 
-            scan.id(), 
-            |change| {
-                let is_dir = change.item_type == "D";
-
-                let (indent_level, new_path) = Self::get_tree_path(
-                    &mut path_stack, 
-                    root_path, 
-                    &change.item_path,
-                    is_dir,
-                );
-
-                // Print the item
-                println!("{}[{}] {}{} ({})", 
-                    " ".repeat(indent_level * 4), 
-                    change.change_type, 
-                    new_path.to_string_lossy(),
-                    Utils::dir_sep_or_empty(is_dir),
-                    change.id,
-                );
+        for change in changes {
+            let is_dir = change.item_type == "D";
 
-                change_count += 1;
-                Ok(())
+            // The directories leading to the files are still shown, as structure
+            if is_dir && tree_dirs == TreeDirs::Hide {
+                continue;
             }
-        )?;
+
+            let (indent_level, new_path) = Self::get_tree_path(
+                &mut path_stack, 
+                &change.item_path,
+                is_dir,
+            );
+
+            // Print the item, and where it moved from
+            let moved_from = change.old_path.as_deref()
+                .map(|old_path| format!(" <- {}", Self::show_path(&Self::item_path(old_path))))
+                .unwrap_or_default();
+            println!("{}[{}] {}{}{} ({})", 
+                " ".repeat(indent_level * 4), 
+                change.annotated_type(), 
+                Self::show_path(&new_path.to_string_lossy()),
+                Utils::dir_sep_or_empty(is_dir),
+                moved_from,
+                change.id,
+            );
+
+            change_count += 1;
+        }
 
         if change_count == 0 {
             Self::print_center(width, "No Changes");
@@ -400,78 +2573,204 @@ impl Reports {
         Ok(())
     }
 
-    fn print_item_changes_as_table(db: &Database, item_id: i64) -> Result<(), FsPulseError> {
-        let item = Item::get_by_id(db, item_id)?
-            .ok_or_else(|| FsPulseError::Error(format!("Item Id {} not found", item_id)))?;
+    /// Shows the directories holding a scan's changes as a tree, each with the number of
+    /// each type of change to the items directly in it. With a `depth`, changes are rolled up
+    /// to the directory that many levels below the root, and each line leads with its total
+    fn print_scan_changes_by_dir(root: &Root, changes: &[Change], depth: Option<usize>) -> Result<(), FsPulseError> {
+        let width = Self::width();
 
-        let mut stream = Self::begin_changes_table(
-            &format!("Changes (Item Id: {}, Item Path: '{}'", item.id(), item.path()), 
-            "No Changes");
+        Self::print_center(width, "Changes By Directory");
+        Self::print_center(width, &format!("Root Path: '{}'", Self::show_path(root.path())));
 
-        Change::for_each_change_in_item(
-            db, 
-            item_id,
-            |change| {
-                stream.row(change.clone())?;
-                Ok(())
+        Self::hr(width);
+
+        // Paths order component by component, so each directory follows its parent
+        let mut dirs: BTreeMap<PathBuf, ChangeCounts> = BTreeMap::new();
+
+        for change in changes {
+            let change_type: ChangeType = change.change_type.parse()?;
+            let dir = Path::new(&change.item_path).parent().map(Path::to_path_buf).unwrap_or_default();
+            let dir = match depth {
+                Some(depth) => dir.components().take(depth).collect(),
+                None => dir,
+            };
+
+            let counts = dirs.entry(dir).or_default();
+            counts.set_count_of(change_type, counts.count_of(change_type) + 1);
+        }
+
+        let mut path_stack: Vec<PathBuf> = Vec::new();
+        for (dir, counts) in &dirs {
+            let summary = [ChangeType::Add, ChangeType::Modify, ChangeType::Delete, ChangeType::TypeChange, ChangeType::Move]
+                .iter()
+                .filter(|change_type| counts.count_of(**change_type) > 0)
+                .map(|change_type| format!("{} {}", change_type, counts.count_of(*change_type)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let summary = match depth {
+                Some(_) => format!("{} {}: {}", counts.total(), if counts.total() == 1 { "change" } else { "changes" }, summary),
+                None => summary,
+            };
+
+            // The root itself isn't an item, so it can't be pushed onto the stack
+            if dir.as_os_str().is_empty() {
+                println!("[{}] .{}", summary, MAIN_SEPARATOR_STR);
+                continue;
             }
-        )?;
 
-        stream.finish()?;
+            let (indent_level, new_path) = Self::get_tree_path(&mut path_stack, &dir.to_string_lossy(), true);
+            println!("{}[{}] {}{}",
+                " ".repeat(indent_level * 4),
+                summary,
+                Self::show_path(&new_path.to_string_lossy()),
+                MAIN_SEPARATOR_STR,
+            );
+        }
+
+        if dirs.is_empty() {
+            Self::print_center(width, "No Changes");
+        }
 
+        Self::hr(width);
         Ok(())
     }
 
-    fn print_last_seen_scan_items_as_table(db: &Database, scan: &Scan, root: &Root) -> Result<(), FsPulseError> {
-        let mut stream = 
-            Self::begin_items_table(&format!("Items (Root Path: '{}'", root.path()), "No Items");
+    /// Prints a scan's changes the way git shows a working tree: a status letter and path for
+    /// each changed item, then a diffstat with a line and histogram bar for each directory
+    /// holding changes, and a summary. Moves, and renames the scan recorded as a deletion and
+    /// an addition but found by hash, are shown as `R old -> new`
+    fn print_scan_changes_as_git(changes: &[Change], renames: &[(i64, i64)]) -> Result<(), FsPulseError> {
+        // A renamed file is shown once, at its new path
+        let renamed: HashSet<i64> = renames.iter().map(|(delete_id, _)| *delete_id).collect();
+        let old_paths: HashMap<i64, String> = renames.iter()
+            .filter_map(|(delete_id, add_id)| {
+                changes.iter().find(|change| change.id == *delete_id).map(|change| (*add_id, change.item_path.clone()))
+            })
+            .collect();
+
+        let show = |path: &str, item_type: &str| {
+            let separator = if item_type == "D" { MAIN_SEPARATOR_STR } else { "" };
+            format!("{}{}", Self::show_path(&Self::item_path(path)), separator)
+        };
 
-        Item::for_each_item_in_latest_scan(
-            db, 
-            scan.id(),
-            |item|  {
-                stream.row(item.clone())?;
-                Ok(())
+        let mut dirs: BTreeMap<PathBuf, DirStat> = BTreeMap::new();
+        for change in changes.iter().filter(|change| !renamed.contains(&change.id)) {
+            let change_type: ChangeType = change.change_type.parse()?;
+            let stat = dirs.entry(Path::new(&change.item_path).parent().map(Path::to_path_buf).unwrap_or_default())
+                .or_default();
+
+            match change.old_path.as_ref().or_else(|| old_paths.get(&change.id)) {
+                Some(old_path) => {
+                    println!("R  {} -> {}", show(old_path, &change.item_type), show(&change.item_path, &change.item_type));
+                    stat.renamed += 1;
+                },
+                None => {
+                    println!("{}  {}", change_type, show(&change.item_path, &change.item_type));
+                    match change_type {
+                        ChangeType::Add => stat.added += 1,
+                        ChangeType::Delete => stat.deleted += 1,
+                        _ => stat.modified += 1,
+                    }
+                },
             }
-        )?;
+        }
+
+        if dirs.is_empty() {
+            println!("No changes");
+            return Ok(());
+        }
+
+        let labels: Vec<String> = dirs.keys()
+            .map(|dir| match dir.as_os_str().is_empty() {
+                true => format!(".{}", MAIN_SEPARATOR_STR),
+                false => show(&dir.to_string_lossy(), "D"),
+            })
+            .collect();
+        let label_width = labels.iter().map(|label| label.chars().count()).max().unwrap_or_default();
+        let max_total = dirs.values().map(DirStat::total).max().unwrap_or_default();
+        let count_width = max_total.to_string().len();
+
+        println!();
+        let mut totals = DirStat::default();
+        for (label, stat) in labels.iter().zip(dirs.values()) {
+            println!(" {:<label_width$} | {:>count_width$} {}", label, stat.total(), stat.bar(max_total));
+            totals.added += stat.added;
+            totals.modified += stat.modified;
+            totals.deleted += stat.deleted;
+            totals.renamed += stat.renamed;
+        }
+
+        let summary: Vec<String> = [
+            (totals.added, "added(+)"),
+            (totals.modified, "modified(~)"),
+            (totals.deleted, "deleted(-)"),
+            (totals.renamed, "renamed(>)"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, label)| format!("{} {}", Utils::format_count(*count), label))
+        .collect();
+
+        println!(
+            " {} {} changed, {}",
+            Utils::format_count(dirs.len() as i64),
+            if dirs.len() == 1 { "directory" } else { "directories" },
+            summary.join(", "),
+        );
+
+        Ok(())
+    }
+
+    fn print_item_changes_as_table(item: &Item, changes: &[Change]) -> Result<(), FsPulseError> {
+        let mut stream = Self::begin_changes_table(
+            &format!("Changes (Item Id: {}, Item Path: '{}'", item.id(), Self::show_path(item.path())), 
+            "No Changes");
+
+        for change in changes {
+            stream.row(change.clone())?;
+        }
 
         stream.finish()?;
 
         Ok(())
     }
 
-    fn print_last_seen_scan_items_as_tree(db: &Database, scan: &Scan, root: &Root) -> Result<(), FsPulseError> {
+    /// The root's items as they were when the scan ran, at or beneath `path_prefix`. Items
+    /// which moved since are only back at their old paths once rebuilt, so they're chosen
+    /// by path afterwards rather than by the query
+    fn items_as_of_under(db: &Database, root: &Root, scan: &Scan, path_prefix: Option<&str>) -> Result<Vec<Item>, FsPulseError> {
+        let mut items = Item::items_as_of(db, root.id(), scan.id())?;
+        if let Some(path_prefix) = path_prefix {
+            items.retain(|item| Path::new(item.path()).starts_with(path_prefix));
+        }
+
+        Ok(items)
+    }
 
-        let title = format!("Items (Root Id: {}, Root Path: '{}'", root.id(), root.path());
-        let width = max(100, title.len() + 20);
+    fn print_items_as_tree(title: &str, items: &[Item]) -> Result<(), FsPulseError> {
+        let width = Self::width();
 
         Self::hr(width);
-        Self::print_center(width, &title);
+        Self::print_center(width, title);
         Self::hr(width);
 
-        let root_path = Path::new(root.path());
         let mut path_stack: Vec<PathBuf> = Vec::new();
         let mut item_count = 0;
 
-        Item::for_each_item_in_latest_scan(
-            db, 
-            scan.id(), 
-            |item| {
-                let is_dir = item.item_type() == "D";
+        for item in items {
+            let is_dir = item.item_type() == "D";
 
-                let (indent_level, new_path) = Self::get_tree_path(&mut path_stack, root_path, item.path(), is_dir);
+            let (indent_level, new_path) = Self::get_tree_path(&mut path_stack, item.path(), is_dir);
 
-                // Print the item
-                println!("{}[{}] {}{}",
-                    " ".repeat(indent_level * 4), 
-                    item.id(),
-                    new_path.to_string_lossy(),
-                    Utils::dir_sep_or_empty(is_dir),
-                );
-                item_count += 1;
-                Ok(())
-            }
-        )?;
+            // Print the item
+            println!("{}[{}] {}{}",
+                " ".repeat(indent_level * 4), 
+                item.id(),
+                Self::show_path(&new_path.to_string_lossy()),
+                Utils::dir_sep_or_empty(is_dir),
+            );
+            item_count += 1;
+        }
 
         if item_count == 0 {
             Self::print_center(width, "No Items");
@@ -487,15 +2786,16 @@ impl Reports {
     }
 
     fn __print_left(width: usize, value: &str) {
-        println!("{0:1$}{3}{0:2$}", "", 0, width - value.len(), value);
+        println!("{0:1$}{3}{0:2$}", "", 0, width.saturating_sub(value.chars().count()), value);
     }
 
     fn print_center(width: usize, value: &str) {
-        // determine left padding
-        let padding = width - value.len();
+        // determine left padding. A value wider than the line is printed as it is
+        let padding = width.saturating_sub(value.chars().count());
         let lpad = padding / 2;
         let rpad = lpad + (padding % 2);
         println!("{0:1$}{3}{0:2$}", "", lpad, rpad, value);
 
     }
-}
\ No newline at end of file
+}
+
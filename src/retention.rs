@@ -0,0 +1,139 @@
+use std::path::{Path, MAIN_SEPARATOR_STR};
+use std::str::FromStr;
+
+use rusqlite::{params_from_iter, types::Value, Row};
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::roots::Root;
+
+/// What a file's age is measured from when checking it against a retention period
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum AgeBasis {
+    /// The file's modification time
+    Modified,
+    /// The time of the scan that found the file, or found it again after it was deleted
+    FirstSeen,
+}
+
+impl AgeBasis {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Modified => "mtime",
+            Self::FirstSeen => "first-seen",
+        }
+    }
+}
+
+impl FromStr for AgeBasis {
+    type Err = FsPulseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mtime" => Ok(Self::Modified),
+            "first-seen" => Ok(Self::FirstSeen),
+            _ => Err(FsPulseError::Error(format!("Invalid age basis '{}'", s))),
+        }
+    }
+}
+
+/// A file older than a retention period allows
+#[derive(Clone, Debug)]
+pub struct RetainedFile {
+    pub item_id: i64,
+    pub path: String,
+    pub file_size: Option<i64>,
+    pub last_modified: Option<i64>,
+    pub first_seen: Option<i64>,
+}
+
+impl RetainedFile {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(RetainedFile {
+            item_id: row.get(0)?,
+            path: row.get(1)?,
+            file_size: row.get(2)?,
+            last_modified: row.get(3)?,
+            first_seen: row.get(4)?,
+        })
+    }
+
+    /// Calls `func` with each of the root's files, oldest first, whose age by `basis` began
+    /// at or before `before`. With `under`, only files at or beneath those paths are
+    /// included; the paths may be relative to the root or absolute paths within it. Files
+    /// whose age isn't known, such as those without a modification time, are left out
+    pub fn for_each_older_than<F>(
+        db: &Database,
+        root: &Root,
+        under: &[String],
+        basis: AgeBasis,
+        before: i64,
+        mut func: F,
+    ) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&RetainedFile) -> Result<(), FsPulseError>,
+    {
+        let age = match basis {
+            AgeBasis::Modified => "last_modified",
+            AgeBasis::FirstSeen => "first_seen",
+        };
+
+        let under = under.iter()
+            .map(|path| Self::relative_path(root, path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut params: Vec<Value> = vec![root.id().into(), before.into()];
+        let mut under_condition = String::new();
+
+        // The root itself holds everything
+        if !under.is_empty() && !under.iter().any(|path| path.is_empty()) {
+            let mut conditions = Vec::new();
+            for path in under {
+                let dir_prefix = format!("{}{}", path, MAIN_SEPARATOR_STR);
+                params.push(path.into());
+                params.push(dir_prefix.into());
+                let (n, prefix_n) = (params.len() - 1, params.len());
+                conditions.push(format!("path = ?{n} OR substr(path, 1, length(?{prefix_n})) = ?{prefix_n}"));
+            }
+            under_condition = format!("AND ({})", conditions.join(" OR "));
+        }
+
+        // An item's current presence began with its most recent add
+        let sql = format!(
+            "SELECT * FROM (
+                SELECT i.id, i.path, i.file_size, i.last_modified, (
+                    SELECT s.time_of_scan FROM changes c JOIN scans s ON s.id = c.scan_id
+                        WHERE c.item_id = i.id AND c.change_type = 'A'
+                        ORDER BY c.id DESC LIMIT 1) AS first_seen
+                FROM items i
+                WHERE i.root_id = ?1 AND i.is_tombstone = 0 AND i.is_ignored = 0 AND i.item_type = 'F' {})
+            WHERE {age} <= ?2
+            ORDER BY {age} ASC, path ASC",
+            under_condition
+        );
+
+        let mut stmt = db.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(params), Self::from_row)?;
+
+        for row in rows {
+            func(&row?)?;
+        }
+
+        Ok(())
+    }
+
+    /// The path relative to the root, for a path given either way
+    fn relative_path(root: &Root, path: &str) -> Result<String, FsPulseError> {
+        let path = Path::new(path);
+        let relative = match path.strip_prefix(root.path()) {
+            Ok(relative) => relative,
+            Err(_) if path.is_absolute() => {
+                return Err(FsPulseError::Error(format!("'{}' isn't within '{}'", path.display(), root.path())));
+            },
+            Err(_) => path,
+        };
+
+        // Components drop any trailing separator
+        Ok(relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join(MAIN_SEPARATOR_STR))
+    }
+}
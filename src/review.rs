@@ -0,0 +1,263 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::reports::Reports;
+use crate::utils::Utils;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ReviewFormat {
+    Csv,
+    Json,
+}
+
+impl ReviewFormat {
+    /// The format named by `format`, or else the one suggested by the file's extension
+    pub fn for_file(format: Option<&str>, path: &Path) -> Result<Self, FsPulseError> {
+        match format {
+            Some(format) => format.parse(),
+            None => match path.extension().and_then(|ext| ext.to_str()) {
+                Some(ext) if ext.eq_ignore_ascii_case("json") => Ok(ReviewFormat::Json),
+                Some(ext) if ext.eq_ignore_ascii_case("csv") => Ok(ReviewFormat::Csv),
+                _ => Err(FsPulseError::Error(format!(
+                    "Can't tell the format of '{}' from its extension. Use --format csv or --format json",
+                    path.display()))),
+            },
+        }
+    }
+}
+
+impl FromStr for ReviewFormat {
+    type Err = FsPulseError;
+    fn from_str(s: &str) -> Result<Self, FsPulseError> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ReviewFormat::Csv),
+            "json" => Ok(ReviewFormat::Json),
+            _ => Err(FsPulseError::Error(format!("Invalid review format '{}'", s))),
+        }
+    }
+}
+
+impl fmt::Display for ReviewFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReviewFormat::Csv => write!(f, "csv"),
+            ReviewFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// An item's path in a review file. With --escape-paths, CSV files hold the escaped path
+/// and JSON files the bytes of the path; either form is accepted on import
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ReviewPath {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl ReviewPath {
+    fn for_export(path: String, format: ReviewFormat) -> Self {
+        match (Reports::escaping_paths(), format) {
+            (true, ReviewFormat::Json) => ReviewPath::Bytes(path.into_bytes()),
+            (true, ReviewFormat::Csv) => ReviewPath::Text(Utils::escape_path(&path).into_owned()),
+            (false, _) => ReviewPath::Text(path),
+        }
+    }
+
+    /// Whether this is the path of the item at `path`, in any of the forms it's exported in
+    fn matches(&self, path: &str) -> bool {
+        match self {
+            ReviewPath::Text(text) => text == path || *text == Utils::escape_path(path),
+            ReviewPath::Bytes(bytes) => bytes == path.as_bytes(),
+        }
+    }
+}
+
+/// One change as it appears in a review file. Reviewers fill in `acknowledged`, `comment`
+/// and `reviewer`; the other fields identify the change and are ignored on import, apart
+/// from checking that the change still refers to the same item
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReviewRecord {
+    pub change_id: i64,
+    pub scan_id: i64,
+    pub change_type: String,
+    pub item_type: String,
+    pub item_path: ReviewPath,
+    #[serde(deserialize_with = "deserialize_flag", default)]
+    pub acknowledged: bool,
+    #[serde(deserialize_with = "deserialize_text", default)]
+    pub comment: Option<String>,
+    #[serde(deserialize_with = "deserialize_text", default)]
+    pub reviewer: Option<String>,
+}
+
+/// What importing a review file did
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ImportSummary {
+    pub updated: usize,
+    pub unchanged: usize,
+    /// Records whose change doesn't exist or refers to a different item, which usually
+    /// means the file was exported from another database
+    pub mismatched: usize,
+}
+
+pub struct Review {
+    // No fields
+}
+
+impl Review {
+    /// Writes the changes found by a scan to a review file, along with any review
+    /// already recorded for them. Returns the number of changes written
+    pub fn export(db: &Database, scan_id: i64, path: &Path, format: ReviewFormat) -> Result<usize, FsPulseError> {
+        let records = Self::records_for_scan(db, scan_id, format)?;
+        let writer = BufWriter::new(File::create(path)?);
+
+        match format {
+            ReviewFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(writer);
+                for record in &records {
+                    writer.serialize(record)?;
+                }
+                writer.flush()?;
+            },
+            ReviewFormat::Json => {
+                let mut writer = writer;
+                serde_json::to_writer_pretty(&mut writer, &records)?;
+                writeln!(writer)?;
+                writer.flush()?;
+            },
+        }
+
+        Ok(records.len())
+    }
+
+    /// Reads a review file and records each change's acknowledgement, comment and reviewer.
+    /// With `dry_run`, nothing is recorded but the summary is the same
+    pub fn import(db: &mut Database, path: &Path, format: ReviewFormat, dry_run: bool) -> Result<ImportSummary, FsPulseError> {
+        let reader = BufReader::new(File::open(path)?);
+
+        let records: Vec<ReviewRecord> = match format {
+            ReviewFormat::Csv => csv::Reader::from_reader(reader)
+                .deserialize()
+                .collect::<Result<_, _>>()?,
+            ReviewFormat::Json => serde_json::from_reader(reader)?,
+        };
+
+        let mut summary = ImportSummary::default();
+        let tx = db.conn.transaction()?;
+
+        for record in &records {
+            let existing = tx.query_row(
+                "SELECT items.path, changes.acknowledged, changes.review_comment, changes.reviewer
+                    FROM changes
+                    JOIN items ON items.id = changes.item_id
+                    WHERE changes.id = ?",
+                [record.change_id],
+                |row| Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, bool>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                )),
+            );
+
+            let (item_path, acknowledged, comment, reviewer) = match existing {
+                Ok(existing) => existing,
+                Err(rusqlite::Error::QueryReturnedNoRows) => {
+                    summary.mismatched += 1;
+                    continue;
+                },
+                Err(error) => return Err(error.into()),
+            };
+
+            if !record.item_path.matches(&item_path) {
+                summary.mismatched += 1;
+            } else if acknowledged == record.acknowledged && comment == record.comment && reviewer == record.reviewer {
+                summary.unchanged += 1;
+            } else {
+                tx.execute(
+                    "UPDATE changes SET acknowledged = ?, review_comment = ?, reviewer = ? WHERE id = ?",
+                    (record.acknowledged, &record.comment, &record.reviewer, record.change_id),
+                )?;
+                summary.updated += 1;
+            }
+        }
+
+        if dry_run {
+            tx.rollback()?;
+        } else {
+            tx.commit()?;
+        }
+
+        Ok(summary)
+    }
+
+    fn records_for_scan(db: &Database, scan_id: i64, format: ReviewFormat) -> Result<Vec<ReviewRecord>, FsPulseError> {
+        let mut stmt = db.conn.prepare(
+            "SELECT changes.id, changes.scan_id, changes.change_type, items.item_type, items.path,
+                    changes.acknowledged, changes.review_comment, changes.reviewer
+                FROM changes
+                JOIN items ON items.id = changes.item_id
+                WHERE changes.scan_id = ? AND items.is_ignored = 0
+                ORDER BY items.path ASC"
+        )?;
+
+        let rows = stmt.query_map([scan_id], |row| {
+            Ok(ReviewRecord {
+                change_id: row.get(0)?,
+                scan_id: row.get(1)?,
+                change_type: row.get(2)?,
+                item_type: row.get(3)?,
+                item_path: ReviewPath::for_export(row.get(4)?, format),
+                acknowledged: row.get(5)?,
+                comment: row.get(6)?,
+                reviewer: row.get(7)?,
+            })
+        })?;
+
+        let mut records = Vec::new();
+        for row in rows {
+            records.push(row?);
+        }
+
+        Ok(records)
+    }
+}
+
+/// Reviewers editing a spreadsheet mark acknowledgements in whatever way comes naturally,
+/// so anything other than an empty cell, "false", "no", "n" or "0" counts as acknowledged
+fn deserialize_flag<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        Bool(bool),
+        Int(i64),
+        Text(String),
+    }
+
+    Ok(match Option::<Flag>::deserialize(deserializer)? {
+        None => false,
+        Some(Flag::Bool(flag)) => flag,
+        Some(Flag::Int(n)) => n != 0,
+        Some(Flag::Text(text)) => !matches!(text.trim().to_lowercase().as_str(), "" | "false" | "no" | "n" | "0"),
+    })
+}
+
+/// Blank cells are read as no value rather than an empty string
+fn deserialize_text<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = Option::<String>::deserialize(deserializer)?;
+    Ok(text.map(|text| text.trim().to_owned()).filter(|text| !text.is_empty()))
+}
@@ -1,9 +1,11 @@
+use std::collections::BTreeMap;
 use std::{env, fs, i64};
-use std::path::{Path, PathBuf};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR, MAIN_SEPARATOR_STR};
 
 use rusqlite::OptionalExtension;
 use crate::database::Database;
 use crate::error::FsPulseError;
+use crate::prompts::PromptPolicy;
 
 
 #[derive(Clone, Debug, Default)]
@@ -12,6 +14,17 @@ pub struct Root {
     path: String
 }
 
+/// The state of a root as of its most recent completed scan, used to summarize many
+/// roots at once
+#[derive(Clone, Debug, Default)]
+pub struct RootSummary {
+    pub root: Root,
+    pub last_scan_time: Option<i64>,
+    pub file_count: i64,
+    pub total_size: i64,
+    pub recent_change_count: i64,
+}
+
 impl Root {
     pub fn get_by_id(db: &Database, id: i64) -> Result<Option<Self>, FsPulseError> {
         let conn = &db.conn;
@@ -43,25 +56,183 @@ impl Root {
         .map_err(FsPulseError::Database)
     }
 
-    pub fn create(db: &Database, path: &str) -> Result<Self, FsPulseError> {
-        let conn = &db.conn;
+    /// Finds the root for a path given on the command line. The root may have been
+    /// registered under another form of the path, and a path which no longer exists can
+    /// still match the path it was registered under
+    pub fn find_by_path_arg(db: &Database, path_arg: &str) -> Result<Option<Self>, FsPulseError> {
+        match Self::validate_and_canonicalize_path(path_arg) {
+            Ok(canonical_path) => Self::get_by_directory(db, &canonical_path),
+            Err(_) => Self::get_by_path(db, path_arg),
+        }
+    }
 
-        let id: i64 = conn.query_row(
-            "INSERT INTO roots (path) VALUES (?) RETURNING id",
-            [path],
-            |row| row.get(0),
-        )?;
+    /// Finds a root given on the command line as either a root id or a path
+    pub fn find_by_id_or_path(db: &Database, arg: &str) -> Result<Self, FsPulseError> {
+        let by_id = match arg.parse::<i64>() {
+            Ok(id) => Self::get_by_id(db, id)?,
+            Err(_) => None,
+        };
 
-        Ok(Root { id, path: path.to_owned() })
+        match by_id {
+            Some(root) => Ok(root),
+            None => Self::find_by_path_arg(db, arg)?
+                .ok_or_else(|| FsPulseError::Error(format!("Root '{}' not found", arg))),
+        }
     }
 
-    pub fn get_or_insert(db: &Database, path: &str) -> Result<Self, FsPulseError> {
-        let conn = &db.conn;
+    /// Finds the known root for the directory at `path`, which must be canonical. A root
+    /// matches if it was registered under the same path or under another path that leads
+    /// to the same directory: a symlinked alias, a trailing separator, or different case
+    /// on a case-insensitive file system
+    pub fn get_by_directory(db: &Database, path: &Path) -> Result<Option<Self>, FsPulseError> {
+        if let Some(root) = Self::get_by_path(db, &path.to_string_lossy())? {
+            return Ok(Some(root));
+        }
+
+        let key = Self::directory_key(path);
+        let mut found = None;
+
+        Self::for_each_root(db, |root| {
+            if found.is_none() && Self::directory_key(Path::new(root.path())) == key {
+                found = Some(root.clone());
+            }
+            Ok(())
+        })?;
+
+        Ok(found)
+    }
+
+    /// Finds the innermost known root containing the directory at `path`, which must be
+    /// canonical. The directory itself may be a root
+    pub fn find_containing(db: &Database, path: &Path) -> Result<Option<Self>, FsPulseError> {
+        for dir in path.ancestors() {
+            if let Some(root) = Self::get_by_directory(db, dir)? {
+                return Ok(Some(root));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Identifies the directory a path leads to. Directories which exist are identified by
+    /// the file system. Otherwise, the path is compared after normalizing its separators
+    fn directory_key(path: &Path) -> String {
+        match Self::file_system_key(path) {
+            Some(key) => key,
+            None => path.components().collect::<PathBuf>().to_string_lossy().into_owned(),
+        }
+    }
+
+    #[cfg(unix)]
+    fn file_system_key(path: &Path) -> Option<String> {
+        use std::os::unix::fs::MetadataExt;
+
+        let metadata = fs::metadata(path).ok()?;
+        Some(format!("{}:{}", metadata.dev(), metadata.ino()))
+    }
+
+    #[cfg(not(unix))]
+    fn file_system_key(path: &Path) -> Option<String> {
+        // Windows and macOS file systems are usually case-insensitive
+        path.canonicalize().ok().map(|path| path.to_string_lossy().to_lowercase())
+    }
+
+    /// Finds roots which are registered more than once under different paths and merges
+    /// each set into one root. The root scanned most recently is kept, and moved to the
+    /// canonical path if needed. The history of the others is removed
+    pub fn dedup_roots(db: &mut Database, dry_run: bool, prompts: PromptPolicy) -> Result<(), FsPulseError> {
+        let mut groups: BTreeMap<String, Vec<Root>> = BTreeMap::new();
+
+        Self::for_each_root(db, |root| {
+            groups.entry(Self::directory_key(Path::new(root.path()))).or_default().push(root.clone());
+            Ok(())
+        })?;
+
+        let mut duplicate_count = 0;
+
+        for roots in groups.into_values().filter(|roots| roots.len() > 1) {
+            let keep = Self::most_recently_scanned(db, &roots)?;
+            let canonical_path = Path::new(keep.path()).canonicalize()
+                .map(|path| path.to_string_lossy().into_owned())
+                .unwrap_or_else(|_| keep.path().to_owned());
+
+            println!("Root Id {} ('{}') is registered {} times:", keep.id(), canonical_path, roots.len());
+            for root in &roots {
+                let disposition = if root.id() == keep.id() { "keep" } else { "remove" };
+                println!("    {} Root Id {} ('{}')", disposition, root.id(), root.path());
+            }
+
+            duplicate_count += roots.len() - 1;
+
+            if dry_run || !prompts.require(&format!("Merge the roots for '{}'? The history of the removed roots will be deleted", canonical_path))? {
+                continue;
+            }
 
-        conn.execute("INSERT OR IGNORE INTO roots (path) VALUES (?)", [path])?;
+            let tx = db.conn.transaction()?;
+
+            for root in roots.iter().filter(|root| root.id() != keep.id()) {
+                tx.execute("DELETE FROM changes WHERE item_id IN (SELECT id FROM items WHERE root_id = ?)", [root.id()])?;
+                tx.execute("DELETE FROM changes WHERE scan_id IN (SELECT id FROM scans WHERE root_id = ?)", [root.id()])?;
+                tx.execute("DELETE FROM scan_exclusions WHERE scan_id IN (SELECT id FROM scans WHERE root_id = ?)", [root.id()])?;
+                tx.execute("DELETE FROM scan_exclusion_counts WHERE scan_id IN (SELECT id FROM scans WHERE root_id = ?)", [root.id()])?;
+                tx.execute("DELETE FROM scan_exclude_rules WHERE scan_id IN (SELECT id FROM scans WHERE root_id = ?)", [root.id()])?;
+                tx.execute("DELETE FROM unchecked_dirs WHERE scan_id IN (SELECT id FROM scans WHERE root_id = ?)", [root.id()])?;
+                tx.execute("DELETE FROM verification_mismatches WHERE verification_id IN (SELECT id FROM verifications WHERE root_id = ?)", [root.id()])?;
+                tx.execute("DELETE FROM verifications WHERE root_id = ?", [root.id()])?;
+                tx.execute("DELETE FROM items WHERE root_id = ?", [root.id()])?;
+                tx.execute("DELETE FROM scans WHERE root_id = ?", [root.id()])?;
+                tx.execute("DELETE FROM schedules WHERE root_id = ?", [root.id()])?;
+                tx.execute("DELETE FROM allowed_owners WHERE root_id = ?", [root.id()])?;
+                tx.execute("UPDATE OR IGNORE root_groups SET root_id = ? WHERE root_id = ?", (keep.id(), root.id()))?;
+                tx.execute("DELETE FROM root_groups WHERE root_id = ?", [root.id()])?;
+                tx.execute("UPDATE OR IGNORE container_roots SET root_id = ? WHERE root_id = ?", (keep.id(), root.id()))?;
+                tx.execute("DELETE FROM container_roots WHERE root_id = ?", [root.id()])?;
+                tx.execute("DELETE FROM roots WHERE id = ?", [root.id()])?;
+            }
+
+            // Item paths are relative to the root, so only the root itself moves
+            if keep.path() != canonical_path {
+                tx.execute("UPDATE roots SET path = ? WHERE id = ?", (&canonical_path, keep.id()))?;
+            }
+
+            tx.commit()?;
+            println!("Merged into Root Id {}", keep.id());
+        }
+
+        if duplicate_count == 0 {
+            println!("No duplicate roots found");
+        } else if dry_run {
+            println!("{} duplicate root(s) would be removed (dry run)", duplicate_count);
+        }
+
+        Ok(())
+    }
+
+    fn most_recently_scanned(db: &Database, roots: &[Root]) -> Result<Root, FsPulseError> {
+        let mut keep = &roots[0];
+        let mut keep_scan_id = None;
+
+        for root in roots {
+            let scan_id: Option<i64> = db.conn.query_row(
+                "SELECT MAX(id) FROM scans WHERE root_id = ?",
+                [root.id()],
+                |row| row.get(0),
+            )?;
+
+            if scan_id > keep_scan_id {
+                keep = root;
+                keep_scan_id = scan_id;
+            }
+        }
+
+        Ok(keep.clone())
+    }
+
+    pub fn create(db: &Database, path: &str) -> Result<Self, FsPulseError> {
+        let conn = &db.conn;
 
         let id: i64 = conn.query_row(
-            "SELECT id FROM roots WHERE path = ?",
+            "INSERT INTO roots (path) VALUES (?) RETURNING id",
             [path],
             |row| row.get(0),
         )?;
@@ -77,6 +248,28 @@ impl Root {
         &self.path
     }
 
+    /// The absolute path of an item, given its path relative to the root
+    pub fn item_path(&self, relative_path: &str) -> PathBuf {
+        Path::new(&self.path).join(relative_path)
+    }
+
+    /// A path given on the command line to narrow a report to part of the root, relative
+    /// to the root as item paths are. It may be given either relative to the root or in
+    /// full, with either separator. Returns None for the root itself
+    pub fn relative_path_prefix(&self, prefix: &str) -> Result<Option<String>, FsPulseError> {
+        let relative = match Path::new(prefix).strip_prefix(&self.path) {
+            Ok(relative) => relative.to_string_lossy().into_owned(),
+            Err(_) if Path::new(prefix).is_absolute() => {
+                return Err(FsPulseError::Error(format!("'{}' isn't within the root '{}'", prefix, self.path)));
+            },
+            Err(_) => prefix.to_owned(),
+        };
+
+        let relative = relative.replace('/', MAIN_SEPARATOR_STR);
+        let relative = relative.trim_matches(MAIN_SEPARATOR);
+        Ok((!relative.is_empty()).then(|| relative.to_owned()))
+    }
+
     pub fn for_each_root<F>(db: &Database, mut func: F) -> Result<(), FsPulseError> 
     where
         F: FnMut(&Root) -> Result<(), FsPulseError>,
@@ -104,6 +297,44 @@ impl Root {
         Ok(())
     }
 
+    /// Calls `func` with a summary of each root, counting changes recorded by scans which
+    /// started at or after `changes_since`
+    pub fn for_each_summary<F>(db: &Database, changes_since: i64, mut func: F) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&RootSummary) -> Result<(), FsPulseError>,
+    {
+        let mut stmt = db.conn.prepare(
+            "SELECT
+                r.id,
+                r.path,
+                (SELECT MAX(time_of_scan) FROM scans WHERE root_id = r.id AND state = 4),
+                (SELECT COUNT(*) FROM items WHERE root_id = r.id AND is_tombstone = 0 AND is_ignored = 0 AND item_type = 'F'),
+                (SELECT COALESCE(SUM(file_size), 0) FROM items WHERE root_id = r.id AND is_tombstone = 0 AND is_ignored = 0),
+                (SELECT COUNT(*) FROM changes c JOIN scans s ON s.id = c.scan_id JOIN items i ON i.id = c.item_id WHERE s.root_id = r.id AND s.time_of_scan >= ? AND i.is_ignored = 0)
+            FROM roots r
+            ORDER BY r.id ASC"
+        )?;
+
+        let rows = stmt.query_map([changes_since], |row| {
+            Ok(RootSummary {
+                root: Root {
+                    id: row.get(0)?,
+                    path: row.get(1)?,
+                },
+                last_scan_time: row.get(2)?,
+                file_count: row.get(3)?,
+                total_size: row.get(4)?,
+                recent_change_count: row.get(5)?,
+            })
+        })?;
+
+        for row in rows {
+            func(&row?)?;
+        }
+
+        Ok(())
+    }
+
     pub fn validate_and_canonicalize_path(path_arg: &str) -> Result<PathBuf, FsPulseError> {
         let path_arg = path_arg.trim();
         if path_arg.is_empty() {
@@ -122,11 +353,8 @@ impl Root {
             return Err(FsPulseError::Error(format!("Path '{}' does not exist", absolute_path.display())));
         }
     
-        let metadata = fs::symlink_metadata(&absolute_path)?;
-
-        if metadata.file_type().is_symlink() {
-            return Err(FsPulseError::Error(format!("Path '{}' is a symlink and not allowed", absolute_path.display())));
-        }
+        // A symlink is followed so that the root is registered under the directory it leads to
+        let metadata = fs::metadata(&absolute_path)?;
         
         if !metadata.is_dir() {
             return Err(FsPulseError::Error(format!("Path '{}' is not a directory", absolute_path.display())));
@@ -5,6 +5,7 @@
 //          - If tombstone: Update item type, metadata, is_tombstone, last_scan; null hash, valid; create change (Add)
 //          - If folder <-> file change: update Item metadata, last_scan; null hash, valid; create change (Type Changed)
 //          - If metadata change: update Item metadata, last_scan; create change (Modify)
+//      - A hashing scan (unless tuning) also hashes each file it would hash in state 3 as it's found
 //  (Set State to 2)
 // 2. Tombstone
 //      - For each previously seen, non-tombstone item:
@@ -14,27 +15,305 @@
 //      - For each non-tombstone, file item with last_scan < current scan:
 //          - Hash and/or Validate per scan configuration
 //          - If Hash and/or Valid are non-null and have changed, create change record with old value(s) of the changed value(s)
+//      - For each deleted file whose hash and size match a new file: move the deleted item to the
+//        new path in place of the new item; the deletion becomes a change (Move)
 // 4. Completed
 // 5. Aborted
 
+use crate::capabilities::{FsCapabilities, PROBE_DIR_PREFIX};
+use crate::checksums::{ChecksumFiles, CHECKSUM_FILE_NAME};
+use crate::config::Config;
+use crate::changes::{Change, ChangeCounts, ChangeType};
+use crate::containers::Container;
+use crate::database::{DEFAULT_WRITE_BATCH_SIZE, PROJECT_DIR_NAME};
+use crate::excludes::ExcludeRules;
+use crate::hash::{Hash, HashAlgo, HashPool, HashQueue, HashResult, HashSettings, HashTuner, Sampling, MAX_TUNED_CONCURRENCY};
 use crate::items::ItemType;
+use crate::notify::Notifications;
+use crate::progress::ProgressJson;
+use crate::prompts::{Answer, PromptPolicy};
+use crate::reports::Reports;
 use crate::{database::Database, error::FsPulseError, scans::Scan};
 use crate::roots::Root;
-use crate::scans::ScanState;
+use crate::scans::{EmptyGuardrail, Exclusion, ExclusionReason, ScanState};
+use crate::schedules::{ScanKind, Schedule};
+use crate::timings::Timings;
+use crate::utils::Utils;
 
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 
-use dialoguer::Select;
-use log::Metadata;
-use std::collections::VecDeque;
-use std::fs;
+use rusqlite::{Connection, OptionalExtension};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fs::{self, Metadata};
+use std::io::{self, IsTerminal};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-#[derive(Clone, Debug)]
-struct QueueEntry {
-    path: PathBuf,
-    metadata: fs::Metadata,
+// Number of items read per query while hashing. Items are read in batches so that
+// no read transaction is held open while hashes are written, which would otherwise
+// block (or be invalidated by) concurrent scans of other roots
+const HASH_BATCH_SIZE: i64 = 100;
+
+// Number of recent scans whose changes decide which directories a budgeted scan reads first
+const CHURN_SCANS: i64 = 10;
+
+// Space left free on the database's device. A scan stops before using it, so that there's
+// still room to record the scan's completion or abort it
+const MIN_DB_FREE_SPACE: u64 = 64 * 1024 * 1024;
+
+// With no earlier scan to go by, a scan warns when less than this is free on the database's device
+const LOW_DB_FREE_SPACE: u64 = 1024 * 1024 * 1024;
+
+// A file waiting to be hashed, along with what was known about its content before this scan
+#[derive(Clone)]
+struct HashCandidate {
+    item_id: i64,
+    path: String,
+    file_size: Option<i64>,
+    prev_hash: Option<String>,
+    prev_confirm_hash: Option<String>,
+    prev_sample_hash: Option<String>,
+    prev_hash_algo: Option<String>,
+    prev_similarity_digest: Option<String>,
+}
+
+/// Directories waiting to be read. Without a budget, the tree is walked depth first and
+/// only the path of each waiting directory is kept. The stack holds the unread siblings at
+/// each level of the path being walked, so memory grows with the depth of the tree times
+/// its fan-out: a directory with a million subdirectories still pushes a million paths,
+/// but the whole frontier of a breadth-first walk is never held. A budgeted scan reads the
+/// directories whose items changed most in recent scans first (shallower ones first among
+/// equals), so the busiest parts of the tree are checked before its time runs out
+enum PendingDirs {
+    Stack(Vec<PathBuf>),
+    ByChurn {
+        root_path: PathBuf,
+        churn: HashMap<PathBuf, u64>,
+        heap: BinaryHeap<(u64, Reverse<usize>, PathBuf)>,
+    },
+}
+
+impl PendingDirs {
+    fn for_scan(db: &Database, root: &Root, scan: &Scan) -> Result<Self, FsPulseError> {
+        if scan.budget_secs().is_none() {
+            return Ok(PendingDirs::Stack(Vec::new()));
+        }
+
+        let mut stmt = db.conn.prepare(
+            "SELECT i.path
+                FROM changes c
+                JOIN items i ON i.id = c.item_id
+                WHERE i.root_id = ?1 AND c.scan_id IN (
+                    SELECT id FROM scans WHERE root_id = ?1 AND state = ?2 ORDER BY id DESC LIMIT ?3)"
+        )?;
+        let rows = stmt.query_map((root.id(), ScanState::Completed.as_i64(), CHURN_SCANS), |row| row.get::<_, String>(0))?;
+
+        // A change counts toward the item itself and every directory above it
+        let mut churn: HashMap<PathBuf, u64> = HashMap::new();
+        for row in rows {
+            for dir in Path::new(&row?).ancestors() {
+                *churn.entry(dir.to_path_buf()).or_default() += 1;
+            }
+        }
+
+        Ok(PendingDirs::ByChurn { root_path: PathBuf::from(root.path()), churn, heap: BinaryHeap::new() })
+    }
+
+    fn push(&mut self, path: PathBuf) {
+        match self {
+            PendingDirs::Stack(stack) => stack.push(path),
+            PendingDirs::ByChurn { root_path, churn, heap } => {
+                let relative_path = path.strip_prefix(&*root_path).unwrap_or(&path);
+                let score = churn.get(relative_path).copied().unwrap_or_default();
+                let depth = relative_path.components().count();
+                heap.push((score, Reverse(depth), path));
+            },
+        }
+    }
+
+    fn pop(&mut self) -> Option<PathBuf> {
+        match self {
+            PendingDirs::Stack(stack) => stack.pop(),
+            PendingDirs::ByChurn { heap, .. } => heap.pop().map(|(_, _, path)| path),
+        }
+    }
+
+    /// The directories still waiting, relative to the root and in path order
+    fn into_relative_paths(self, root_path: &Path) -> Vec<String> {
+        let paths: Vec<PathBuf> = match self {
+            PendingDirs::Stack(stack) => stack,
+            PendingDirs::ByChurn { heap, .. } => heap.into_iter().map(|(_, _, path)| path).collect(),
+        };
+
+        let mut relative_paths: Vec<String> = paths.iter()
+            .map(|path| path.strip_prefix(root_path).unwrap_or(path).to_string_lossy().into_owned())
+            .collect();
+        relative_paths.sort();
+        relative_paths
+    }
+}
+
+/// The paths a scan passed over. They're always counted by reason, but only listed when
+/// asked for since there may be many
+struct Excluded {
+    record: bool,
+    counts: BTreeMap<ExclusionReason, i64>,
+    exclusions: Vec<Exclusion>,
+    unreadable_dirs: Vec<String>,
+}
+
+impl Excluded {
+    fn new(record: bool) -> Self {
+        Excluded { record, counts: BTreeMap::new(), exclusions: Vec::new(), unreadable_dirs: Vec::new() }
+    }
+
+    fn add(&mut self, path: &Path, reason: ExclusionReason) {
+        *self.counts.entry(reason).or_default() += 1;
+        if self.record {
+            self.exclusions.push(Exclusion { path: path.to_string_lossy().into_owned(), reason });
+        }
+    }
+
+    /// Passes over part of a directory that couldn't be read, with a warning. The directory
+    /// is recorded as not checked, so nothing in it that went unseen is taken to be deleted.
+    /// An unreadable root fails the scan
+    fn unreadable(&mut self, ctx: &ScanContext, dir: &Path, path: &Path, error: io::Error) -> Result<(), FsPulseError> {
+        if dir.as_os_str().is_empty() {
+            return Err(error.into());
+        }
+
+        ctx.println(format!("Warning: Couldn't read '{}': {}", path.to_string_lossy(), error))?;
+        self.add(path, ExclusionReason::Unreadable);
+
+        let dir = dir.to_string_lossy().into_owned();
+        if self.unreadable_dirs.last() != Some(&dir) {
+            self.unreadable_dirs.push(dir);
+        }
+
+        Ok(())
+    }
+}
+
+/// How a scan treats files whose content is held elsewhere, such as cloud sync placeholders
+/// and files an HSM has migrated to other storage. Reading such a file fetches its content
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OfflinePolicy {
+    /// Record the file's metadata, but don't hash it until its content is available
+    #[default]
+    Record,
+    /// Leave the file out of the scan. One recorded earlier is kept as it was
+    Skip,
+    /// Hash the file anyway, fetching its content
+    Hydrate,
+}
+
+/// Options chosen on the command line which apply to every scan in a run
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ScanOptions {
+    pub hash: bool,
+    pub hash_delta: bool,
+    /// Without one, each root is hashed with the algorithm its last hashing scan used
+    pub hash_algo: Option<HashAlgo>,
+    pub validate: bool,
+    pub allow_empty: bool,
+    pub confirm_threshold: Option<u64>,
+    pub prompts: PromptPolicy,
+    pub progress_json: bool,
+    pub ignore_dir_mtime: bool,
+    pub record_exclusions: bool,
+    pub budget_secs: Option<i64>,
+    pub write_checksums: bool,
+    pub expect_frozen: bool,
+    pub tune_hashing: bool,
+    /// Without a number, a run scanning one root at a time hashes one file at a time, or
+    /// up to the most a tuned scan tries
+    pub hash_threads: Option<usize>,
+    pub sample_threshold: Option<u64>,
+    pub sampling: Sampling,
+    /// Files of at most this many bytes get a similarity digest when hashed
+    pub similarity_threshold: Option<u64>,
+    pub max_db_growth: Option<u64>,
+    pub offline_files: OfflinePolicy,
+    /// Draw no progress display
+    pub quiet: bool,
+    /// Print no report when a scan completes, for scans run by other programs
+    pub no_report: bool,
+    /// Print a line summing up each scan's changes and their severity once it completes
+    pub summary_line: bool,
+    /// Number of items recorded in each transaction while a tree is read. Without one,
+    /// `DEFAULT_WRITE_BATCH_SIZE`
+    pub batch_size: Option<usize>,
+}
+
+impl ScanOptions {
+    /// How a file of the size is sampled, if it's large enough to get a sampled hash
+    /// rather than a full one
+    fn sampling_for(&self, file_size: Option<i64>) -> Option<Sampling> {
+        self.sample_threshold
+            .filter(|threshold| file_size.unwrap_or_default() >= *threshold as i64)
+            .map(|_| self.sampling)
+    }
+
+    /// Whether a file of the size gets a similarity digest when it's hashed
+    fn takes_similarity(&self, file_size: Option<i64>) -> bool {
+        self.similarity_threshold
+            .is_some_and(|threshold| file_size.unwrap_or_default() <= threshold as i64)
+    }
+}
+
+/// Resources used by the scan machine for the duration of a run. When several roots
+/// are scanned concurrently, they share the progress display and the hash pool
+pub struct ScanContext<'a> {
+    multi: &'a MultiProgress,
+    hash_pool: &'a HashPool,
+    options: ScanOptions,
+    progress: ProgressJson,
+    /// For a watch checkpoint, the directories seen to change, relative to the root
+    watched: Option<&'a BTreeSet<PathBuf>>,
+    /// The rules new scans exclude paths by, unless the config gives a root its own. A
+    /// resumed scan follows the rules it was started with
+    rules: &'a ExcludeRules,
+    config: &'a Config,
+}
+
+impl ScanContext<'_> {
+    /// Prints a warning or error above the progress bars. A hidden MultiProgress drops
+    /// what's printed through it, so when the bars are hidden the message goes to stderr,
+    /// as a JSON record if that's how progress is being written
+    fn println(&self, message: String) -> Result<(), FsPulseError> {
+        if self.options.progress_json {
+            self.progress.message(&message);
+        } else if self.multi.is_hidden() {
+            eprintln!("{}", message);
+        } else {
+            self.multi.println(message)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Scans run one at a time only hash more than one file at once when asked to, or when
+/// hashing is tuned
+fn single_scan_hash_threads(options: &ScanOptions) -> usize {
+    match options.hash_threads {
+        Some(threads) => threads,
+        None if options.tune_hashing => MAX_TUNED_CONCURRENCY,
+        None => 1,
+    }
+}
+
+/// Progress bars are drawn to stderr, so they're hidden when progress goes there as JSON,
+/// when the scan is run quietly, and when output isn't going to a terminal
+fn new_multi_progress(options: &ScanOptions) -> MultiProgress {
+    if options.progress_json || options.quiet || !io::stdout().is_terminal() {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    }
 }
 
 
@@ -43,8 +322,9 @@ pub fn do_scan_machine(
     root_id: Option<u32>, 
     root_path: Option<String>,
     last: bool, 
-    hash: bool,
-    validate: bool) -> Result<(), FsPulseError> {
+    options: ScanOptions,
+    rules: &ExcludeRules,
+    config: &Config) -> Result<(), FsPulseError> {
         // If an incomplete scan exists, find it.
         // TODO: Allow incomplete scans on different roots to exist. We won't, however,
         // allow the user to initiate a new scan on a root that has an outstanding scan until they
@@ -63,7 +343,7 @@ pub fn do_scan_machine(
                 let root_path_buf = Root::validate_and_canonicalize_path(&root_path)?;
                 let root_path_str = root_path_buf.to_string_lossy().to_string();
 
-                let root = Root::get_by_path(db, &root_path_str)?;
+                let root = Root::get_by_directory(db, &root_path_buf)?;
                 match root {
                     Some(root) => {
                         // Found the root. Look for an outstanding scan
@@ -80,42 +360,223 @@ pub fn do_scan_machine(
             },
             (_, _, true) => {
                 let scan = Scan::get_latest(db)?
-                    .ok_or_else(|| FsPulseError::Error(format!("No latest scan found")))?;
-                let root = Root::get_by_id(db, scan.id())?
+                    .ok_or_else(|| FsPulseError::Error("No latest scan found".to_string()))?;
+                let root = Root::get_by_id(db, scan.root_id())?
                     .ok_or_else(|| FsPulseError::Error(format!("No root found for latest Scan Id {}", scan.id())))?;
+                let scan = Some(scan)
+                    .filter(|s| s.state() != ScanState::Completed && s.state() != ScanState::Aborted);
 
-                (root, Some(scan))
+                (root, scan)
             },
             _ => {
                 return Err(FsPulseError::Error("Invalid arguments".into()));
             }
         };
 
+        let multi = new_multi_progress(&options);
+        let hash_pool = HashPool::new(single_scan_hash_threads(&options));
+        let ctx = ScanContext {
+            multi: &multi,
+            hash_pool: &hash_pool,
+            options,
+            progress: ProgressJson::new(options.progress_json),
+            watched: None,
+            rules,
+            config,
+        };
+
         // If scan is present, it is incomplete. Ask the user to decide if it should be resumed or aborted.
         // Also allows the user to exit without making the choice now
         match scan.as_mut() {
-            Some(scan) => abort_or_resume_scan(db, &root, scan),
-            None => initiate_scan(db, &root, hash, validate),
+            Some(scan) => abort_or_resume_scan(db, &root, scan, &ctx),
+            None => initiate_scan(db, &root, &ctx),
+        }
+}
+
+/// Scans every known root, `jobs` roots at a time. Each concurrent scan uses its own
+/// database connection while all of them share one pool of hashing threads, as many as
+/// `jobs` unless the options give a number.
+/// Roots with an incomplete scan are skipped since resuming requires a decision from the user
+pub fn do_scan_all(
+    db: &mut Database,
+    jobs: usize,
+    options: ScanOptions,
+    rules: &ExcludeRules,
+    config: &Config,
+) -> Result<(), FsPulseError> {
+    // Containers are synced first, so new subdirectories are scanned and gone ones aren't
+    Container::sync_all(db)?;
+    let archived = Container::archived_root_ids(db)?;
+
+    let mut roots = Vec::new();
+    Root::for_each_root(db, |root| {
+        if !archived.contains(&root.id()) {
+            roots.push(root.clone());
         }
+        Ok(())
+    })?;
+
+    if roots.is_empty() {
+        return Err(FsPulseError::Error("No roots to scan".to_string()));
+    }
+
+    if jobs > 1 {
+        db.enable_concurrency()?;
+    }
+
+    let multi = new_multi_progress(&options);
+    let hash_pool = HashPool::new(options.hash_threads.unwrap_or(jobs));
+    let root_count = roots.len();
+    let queue = Mutex::new(roots.into_iter().collect::<VecDeque<Root>>());
+    let failures = Mutex::new(0);
+    let frozen_changed = Mutex::new(0);
+
+    // Connections can't be shared between threads so each worker gets its own
+    let worker_dbs = (0..jobs.clamp(1, root_count))
+        .map(|_| db.reopen())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    thread::scope(|s| {
+        for mut worker_db in worker_dbs {
+            let (multi, hash_pool, queue, failures, frozen_changed) = (&multi, &hash_pool, &queue, &failures, &frozen_changed);
+
+            s.spawn(move || {
+                // Concurrent scans can't stop to ask questions
+                let options = ScanOptions { prompts: options.prompts.unattended(), ..options };
+                let ctx = ScanContext { multi, hash_pool, options, progress: ProgressJson::new(options.progress_json), watched: None, rules, config };
+
+                loop {
+                    // The queue is locked only while a root is taken from it, not while it's scanned
+                    let next = queue.lock().unwrap().pop_front();
+                    let Some(root) = next else {
+                        break;
+                    };
+
+                    match scan_root_unattended(&mut worker_db, &root, &ctx) {
+                        Err(FsPulseError::FrozenRootChanged(_)) => *frozen_changed.lock().unwrap() += 1,
+                        Err(err) => {
+                            let _ = ctx.println(format!("Scan of '{}' failed: {}", root.path(), err));
+                            *failures.lock().unwrap() += 1;
+                        },
+                        Ok(()) => {},
+                    }
+                }
+            });
+        }
+    });
+
+    // A failure to scan is the more serious problem, since it leaves a root unchecked
+    match (failures.into_inner().unwrap(), frozen_changed.into_inner().unwrap()) {
+        (0, 0) => Ok(()),
+        (0, frozen_changed) => Err(FsPulseError::FrozenRootChanged(format!("{} of {} roots changed", frozen_changed, root_count))),
+        (failures, _) => Err(FsPulseError::Error(format!("{} of {} root scans failed", failures, root_count))),
+    }
+}
+
+/// Scans each root with a scheduled scan due, running the most thorough scan due for the
+/// root. Meant to be run periodically, such as hourly from cron, so nothing is asked
+pub fn do_scan_due(db: &mut Database, options: ScanOptions, config: &Config) -> Result<(), FsPulseError> {
+    let now = Utils::system_time_to_db_time(SystemTime::now());
+
+    // Schedules are listed by root, so each root's due schedules are adjacent
+    let mut due: Vec<(i64, ScanKind)> = Vec::new();
+    Schedule::for_each_schedule(db, None, |schedule| {
+        if schedule.is_due(now) {
+            match due.last_mut() {
+                Some((root_id, kind)) if *root_id == schedule.root_id() => *kind = (*kind).max(schedule.kind()),
+                _ => due.push((schedule.root_id(), schedule.kind())),
+            }
+        }
+        Ok(())
+    })?;
+
+    if due.is_empty() {
+        println!("No scheduled scans are due");
+        return Ok(());
+    }
+
+    let multi = new_multi_progress(&options);
+    let hash_pool = HashPool::new(single_scan_hash_threads(&options));
+    let rules = ExcludeRules::default();
+    let mut failures = 0;
+    let mut frozen_changed = 0;
+
+    for (root_id, kind) in &due {
+        let Some(root) = Root::get_by_id(db, *root_id)? else {
+            continue;
+        };
+
+        let options = ScanOptions {
+            hash: *kind >= ScanKind::Deep,
+            hash_delta: *kind == ScanKind::Deep,
+            prompts: options.prompts.unattended(),
+            ..options
+        };
+        let ctx = ScanContext { multi: &multi, hash_pool: &hash_pool, options, progress: ProgressJson::new(options.progress_json), watched: None, rules: &rules, config };
+
+        let previous_scan_id = Scan::get_latest_for_root(db, root.id())?.map(|scan| scan.id());
+        multi.println(format!("Running scheduled {} scan of '{}'", kind, root.path()))?;
+
+        match scan_root_unattended(db, &root, &ctx) {
+            Err(FsPulseError::FrozenRootChanged(_)) => frozen_changed += 1,
+            Err(err) => {
+                ctx.println(format!("Scan of '{}' failed: {}", root.path(), err))?;
+                failures += 1;
+                continue;
+            },
+            Ok(()) => {},
+        }
+
+        // A root with an incomplete scan is skipped rather than scanned, and stays due
+        let completed = Scan::get_latest_for_root(db, root.id())?
+            .is_some_and(|scan| Some(scan.id()) != previous_scan_id && scan.state() == ScanState::Completed);
+        if completed {
+            Schedule::record_run(db, root.id(), *kind, now)?;
+        }
+    }
+
+    match (failures, frozen_changed) {
+        (0, 0) => Ok(()),
+        (0, frozen_changed) => Err(FsPulseError::FrozenRootChanged(format!("{} of {} roots changed", frozen_changed, due.len()))),
+        (failures, _) => Err(FsPulseError::Error(format!("{} of {} scheduled scans failed", failures, due.len()))),
+    }
 }
 
-fn abort_or_resume_scan(db: &mut Database, root: &Root, scan: &mut Scan) -> Result<(), FsPulseError> {
+fn scan_root_unattended(db: &mut Database, root: &Root, ctx: &ScanContext) -> Result<(), FsPulseError> {
+    let incomplete = Scan::get_latest_for_root(db, root.id())?
+        .filter(|s| s.state() != ScanState::Completed && s.state() != ScanState::Aborted);
+
+    match incomplete {
+        Some(scan) => {
+            ctx.println(format!(
+                "Skipping '{}': Scan Id {} did not complete. Scan this root individually to resume or abort it",
+                root.path(), scan.id()))?;
+            Ok(())
+        },
+        None => initiate_scan(db, root, ctx),
+    }
+}
+
+fn abort_or_resume_scan(db: &mut Database, root: &Root, scan: &mut Scan, ctx: &ScanContext) -> Result<(), FsPulseError> {
     let options = vec!["resume scan", "abort scan", "exit"];
 
-    let selection = Select::new()
-        .with_prompt(format!("Scan Id {} did not complete.\nYou can choose to resume, abort, or exit", scan.id()))
-        .items(&options)
-        .interact()
-        .unwrap();
+    // With --yes the scan is resumed, since aborting discards the work already done
+    let selection = ctx.options.prompts.select(
+        &format!("Scan Id {} did not complete.\nYou can choose to resume, abort, or exit", scan.id()),
+        &options,
+        0,
+    )?;
 
-    match (selection) {
+    match selection {
         0 => {
-            match scan.state() {
-                ScanState::Scanning => do_state_scanning(db, root, scan),
-                ScanState::Sweeping => do_state_sweeping(db, root, scan),
-                ScanState::Analyzing => do_state_analyzing(db, root, scan),
+            scan.start_budget();
+            let result = match scan.state() {
+                ScanState::Scanning => do_state_scanning(db, root, scan, ctx),
+                ScanState::Sweeping => do_state_sweeping(db, root, scan, ctx),
+                ScanState::Analyzing => do_state_analyzing(db, root, scan, ctx),
                 _ => Err(FsPulseError::Error(format!("Unexpected incomplete scan state: {}", scan.state()))),
-            }
+            };
+            result.map_err(|error| explain_disk_full(scan, error))
         }, 
         1 => {
             scan.abort(db)   // abort and exit
@@ -124,74 +585,1357 @@ fn abort_or_resume_scan(db: &mut Database, root: &Root, scan: &mut Scan) -> Resu
     }
 }
 
-fn initiate_scan(db: &Database, root: &Root, hashing: bool, validating: bool) -> Result<(), FsPulseError> {
-    let scan = Scan::create(db, root, hashing, validating)?;
-    do_state_scanning(db, root, &scan)
+/// Records what a watch saw change in the root as a scan of it, reading only the directories
+/// that changed (and any directories added to them) rather than the whole tree. Items
+/// elsewhere are taken to be as they were, so the scan is complete like any other
+pub fn do_watch_checkpoint(
+    db: &mut Database,
+    root: &Root,
+    watched: &BTreeSet<PathBuf>,
+    options: ScanOptions,
+    rules: &ExcludeRules,
+    config: &Config,
+) -> Result<(), FsPulseError> {
+    if let Some(scan) = Scan::get_latest_for_root(db, root.id())?
+        .filter(|s| s.state() != ScanState::Completed && s.state() != ScanState::Aborted)
+    {
+        return Err(FsPulseError::Error(format!(
+            "Scan Id {} of '{}' is incomplete. Resume or abort it with `fspulse scan` before watching",
+            scan.id(), root.path())));
+    }
+
+    let multi = new_multi_progress(&options);
+    let hash_pool = HashPool::new(single_scan_hash_threads(&options));
+    let ctx = ScanContext {
+        multi: &multi,
+        hash_pool: &hash_pool,
+        options,
+        progress: ProgressJson::new(options.progress_json),
+        watched: Some(watched),
+        rules,
+        config,
+    };
+
+    initiate_scan(db, root, &ctx)
+}
+
+fn initiate_scan(db: &mut Database, root: &Root, ctx: &ScanContext) -> Result<(), FsPulseError> {
+    let options = ctx.options;
+    let confirm_threshold = options.confirm_threshold.map(|threshold| threshold as i64);
+    warn_if_low_on_space(db, root, ctx)?;
+
+    // Without an algorithm named on the command line or in the config, sticking with the
+    // root's algorithm keeps every file from being hashed again
+    let hash_algo = match options.hash_algo.or_else(|| ctx.config.hash_algo_for(root.path())) {
+        Some(hash_algo) => hash_algo,
+        None => Scan::last_hash_algo(db, root.id())?.unwrap_or_default(),
+    };
+    if options.hash && options.write_checksums && hash_algo != HashAlgo::Md5 {
+        return Err(FsPulseError::Error(format!(
+            "Checksum files list md5 hashes, but '{}' is hashed with {}. Use --hash-algo md5 to write them",
+            root.path(), hash_algo)));
+    }
+
+    let mut scan = Scan::create(db, root, options.hash, options.hash_delta, options.validate, confirm_threshold, options.budget_secs, hash_algo)?;
+    ctx.config.rules_for(root.path(), ctx.rules).store(db, scan.id())?;
+    scan.start_budget();
+    do_state_scanning(db, root, &mut scan, ctx).map_err(|error| explain_disk_full(&scan, error))
+}
+
+/// Warns before a scan starts if the database's device may not have room for it, going by
+/// how much the root's last scan of the same kind grew the database
+fn warn_if_low_on_space(db: &Database, root: &Root, ctx: &ScanContext) -> Result<(), FsPulseError> {
+    let estimate = Scan::last_db_growth(db, root.id(), ctx.options.hash)?.map(|growth| growth.max(0) as u64);
+
+    if let Some(free) = db.free_space() {
+        match estimate {
+            Some(estimate) if free < estimate.saturating_add(MIN_DB_FREE_SPACE) => ctx.println(format!(
+                "Warning: The database's device has {} free, and the last scan like this of '{}' grew the database by {}. \
+                The scan will stop if less than {} is left",
+                Utils::format_bytes(free), root.path(), Utils::format_bytes(estimate), Utils::format_bytes(MIN_DB_FREE_SPACE)))?,
+            None if free < LOW_DB_FREE_SPACE => ctx.println(format!(
+                "Warning: The database's device has {} free. The scan of '{}' will stop if less than {} is left",
+                Utils::format_bytes(free), root.path(), Utils::format_bytes(MIN_DB_FREE_SPACE)))?,
+            _ => {},
+        }
+    }
+
+    if let Some((estimate, max)) = estimate.zip(ctx.options.max_db_growth).filter(|(estimate, max)| estimate > max) {
+        ctx.println(format!(
+            "Warning: The last scan like this of '{}' grew the database by {}, more than --max-db-growth allows ({})",
+            root.path(), Utils::format_bytes(estimate), Utils::format_bytes(max)))?;
+    }
+
+    Ok(())
 }
 
-fn do_state_scanning(db: &Database, root: &Root, scan: &Scan) -> Result<(), FsPulseError> {
+/// Stops a scan which has grown the database by more than --max-db-growth, or which has
+/// nearly filled the database's device, before a write fails for want of room. Everything
+/// recorded so far is kept and the scan is left incomplete, as if it had been interrupted
+fn check_db_space(db: &Database, scan: &Scan, ctx: &ScanContext) -> Result<(), FsPulseError> {
+    if let (Some(max), Some(start)) = (ctx.options.max_db_growth, scan.db_size_start()) {
+        let growth = (db.size() as i64 - start).max(0) as u64;
+        if growth > max {
+            return Err(FsPulseError::Error(format!(
+                "Scan Id {} stopped after growing the database by {}, more than --max-db-growth allows. \
+                Scan the root again with a larger limit to resume it, or abort it",
+                scan.id(), Utils::format_bytes(growth))));
+        }
+    }
+
+    if let Some(free) = db.free_space().filter(|free| *free < MIN_DB_FREE_SPACE) {
+        return Err(FsPulseError::Error(format!(
+            "Scan Id {} stopped with {} left on the database's device. Free some space, then scan the root again to resume or abort it",
+            scan.id(), Utils::format_bytes(free))));
+    }
+
+    Ok(())
+}
+
+/// Explains a scan stopped by the database's device filling up. SQLite rolls back the write
+/// that failed, so the scan is left as it was and can be resumed once there's room
+fn explain_disk_full(scan: &Scan, error: FsPulseError) -> FsPulseError {
+    if !error.is_disk_full() {
+        return error;
+    }
+
+    FsPulseError::Error(format!(
+        "The database's device filled up during Scan Id {}. What the scan recorded so far is intact. \
+        Free some space, then scan the root again to resume or abort it",
+        scan.id()))
+}
+
+/// Probes the root's file system and records what it supports. Warnings are shown the first
+/// time a root is probed and whenever the capabilities change, which suggests a different
+/// file system is mounted there. Returns the modification time tolerance for the scan
+fn probe_capabilities(db: &Database, root: &Root, ctx: &ScanContext) -> Result<i64, FsPulseError> {
+    let probed = FsCapabilities::probe(Path::new(root.path()));
+    let recorded = FsCapabilities::get_for_root(db, root.id())?;
+
+    // A root which can't be probed (e.g. a read-only mount) keeps what was recorded before
+    if probed == FsCapabilities::default() {
+        return Ok(recorded.unwrap_or_default().mtime_tolerance());
+    }
+
+    match recorded {
+        None => {
+            for warning in probed.warnings() {
+                ctx.println(format!("Note: In '{}', {}", root.path(), warning))?;
+            }
+        },
+        Some(recorded) if recorded != probed => {
+            ctx.println(format!(
+                "Warning: The file system at '{}' has changed since it was last scanned: {}",
+                root.path(), probed.differences(&recorded).join(", ")))?;
+            for warning in probed.warnings() {
+                ctx.println(format!("Note: In '{}', {}", root.path(), warning))?;
+            }
+        },
+        Some(_) => {},
+    }
+
+    probed.store(db, root.id())?;
+    Ok(probed.mtime_tolerance())
+}
+
+/// Counts the items a scan has processed. A full scan of a root scanned before is expected
+/// to find about as many items as the last completed scan did, which gives an estimate of
+/// when traversal will finish. Otherwise there's nothing to estimate from, so only the
+/// count and elapsed time are shown
+/// A bar following the file being hashed
+fn new_file_hash_bar(root: &Root, ctx: &ScanContext) -> ProgressBar {
+    let bar = ctx.multi.add(ProgressBar::new(0)); // Initialize with 0 length
+    bar.set_style(ProgressStyle::default_bar()
+        .template("{prefix}{msg}\n[{bar:40}] {bytes}/{total_bytes} ({eta})")
+        .expect("valid progress template")
+        .progress_chars("#>-"));
+    bar.set_prefix(format!("[{}] ", root.path()));
+
+    bar
+}
+
+fn new_stats_bar(db: &Database, root: &Root, scan: &Scan, ctx: &ScanContext) -> Result<ProgressBar, FsPulseError> {
+    let expected = match (ctx.watched, scan.previous_completed_id(db)?) {
+        (None, Some(previous_id)) => Scan::get_by_id(db, previous_id)?
+            .map(|previous| previous.file_count().unwrap_or_default() + previous.folder_count().unwrap_or_default())
+            .filter(|expected| *expected > 0),
+        _ => None,
+    };
+
+    let bar = match expected {
+        Some(expected) => ProgressBar::new(expected as u64).with_style(ProgressStyle::default_bar()
+            .template("{prefix}[{bar:40}] {pos}/{len} items, {msg} ({elapsed}, ETA {eta})")
+            .expect("valid progress template")
+            .progress_chars("#>-")),
+        None => ProgressBar::new_spinner().with_style(ProgressStyle::default_spinner()
+            .template("{prefix}{spinner} {pos} items, {msg} ({elapsed})")
+            .expect("valid progress template")),
+    };
+    let bar = ctx.multi.add(bar);
+    bar.set_prefix(format!("[{}] ", root.path()));
+    bar.set_message("0 directories read");
+
+    Ok(bar)
+}
+
+fn do_state_scanning(db: &mut Database, root: &Root, scan: &mut Scan, ctx: &ScanContext) -> Result<(), FsPulseError> {
+    let timer = Timings::start("scan: traversal");
     let root_path_buf = PathBuf::from(root.path());
-    let metadata = fs::symlink_metadata(&root_path_buf)?;
+    let mtime_tolerance = probe_capabilities(db, root, ctx)?;
+    let dir_mtime_tolerance = if ctx.options.ignore_dir_mtime { None } else { Some(mtime_tolerance) };
+    ctx.progress.phase(root.path(), scan.id(), "scanning", None, None);
+
+    // Items are recorded by their path relative to the root
+    let relative_path = |path: &Path| path.strip_prefix(&root_path_buf).unwrap_or(path).to_path_buf();
+
+    // Paths are checked against the rules the scan was started with
+    let mut matcher = ExcludeRules::for_scan(db, scan.id())?.matcher(&root_path_buf);
+
+    // Each directory is recorded when it's found, then waits to be read. A watch checkpoint
+    // reads only the directories seen to change, apart from those the rules exclude. One
+    // that's gone was seen to go from its parent
+    let watched: Option<BTreeSet<PathBuf>> = ctx.watched.map(|watched| watched.iter()
+        .filter(|dir| !dir.ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .any(|ancestor| matcher.check(ancestor, true).is_some()))
+        .cloned()
+        .collect());
+    let mut dirs = PendingDirs::for_scan(db, root, scan)?;
+    match &watched {
+        Some(watched) => watched.iter()
+            .map(|dir| if dir.as_os_str().is_empty() { root_path_buf.clone() } else { root_path_buf.join(dir) })
+            .filter(|dir| dir.is_dir())
+            .for_each(|dir| dirs.push(dir)),
+        None => dirs.push(root_path_buf.clone()),
+    }
+    let mut dirs_checked = 0;
 
-    let mut q = VecDeque::new();
+    let mut excluded = Excluded::new(ctx.options.record_exclusions);
 
-    let multi = MultiProgress::new();
-    multi.println(format!("Scanning: {}", root.path()))?;
-    let dir_bar = multi.add(ProgressBar::new_spinner());
+    ctx.multi.println(format!("Scanning: {}", root.path()))?;
+    let stats_bar = new_stats_bar(db, root, scan, ctx)?;
+    stats_bar.enable_steady_tick(Duration::from_millis(100));
+    let dir_bar = ctx.multi.add(ProgressBar::new_spinner());
     dir_bar.enable_steady_tick(Duration::from_millis(100));
-    let item_bar = multi.add(ProgressBar::new_spinner());
+    let item_bar = ctx.multi.add(ProgressBar::new_spinner());
     item_bar.enable_steady_tick(Duration::from_millis(100));
 
-    let mut progress_bar = if scan.hashing() || scan.validating() {
-        let bar = ProgressBar::new(0); // Initialize with 0 length
+    // A hashing scan queues each file it records on the hash pool as the tree is walked,
+    // so that reading the tree and reading files overlap. The walk records the hashes as
+    // the files finish, and waits for one when as many are queued as the pool has threads.
+    // Tuning measures hashing on its own, so a tuning scan leaves it until after the walk
+    let hydrate = ctx.options.offline_files == OfflinePolicy::Hydrate;
+    let mut walk_hashing = (scan.hashing() && !ctx.options.tune_hashing).then(|| {
+        let file_bar = new_file_hash_bar(root, ctx);
+        let total_bar = ctx.multi.add(ProgressBar::no_length());
+        total_bar.set_style(ProgressStyle::default_bar()
+            .template("{prefix}Hashed {bytes} ({binary_bytes_per_sec})")
+            .expect("valid progress template"));
+        total_bar.set_prefix(format!("[{}] ", root.path()));
+        (Hashing::new(ctx, total_bar), file_bar)
+    });
+
+    // Budgets are checked between directories. The root is always read
+    // Items are committed a batch at a time rather than one at a time
+    db.batched(ctx.options.batch_size.unwrap_or(DEFAULT_WRITE_BATCH_SIZE), |db, batch| {
+        while !(dirs_checked > 0 && scan.budget_exhausted()) {
+            if let Err(error) = check_db_space(db, scan, ctx) {
+                stats_bar.finish_and_clear();
+                dir_bar.finish_and_clear();
+                item_bar.finish_and_clear();
+                if let Some((hashing, file_bar)) = &walk_hashing {
+                    file_bar.finish_and_clear();
+                    hashing.total_bar.finish_and_clear();
+                }
+                return Err(error);
+            }
+
+            let Some(dir_path) = dirs.pop() else {
+                break;
+            };
+            dirs_checked += 1;
+            stats_bar.set_message(format!("{} directories read", dirs_checked));
+            dir_bar.set_message(format!("Directory: '{}'", dir_path.to_string_lossy()));
+            let relative_dir_path = relative_path(&dir_path);
+
+            let items = match fs::read_dir(&dir_path) {
+                Ok(items) => items,
+                Err(error) => {
+                    excluded.unreadable(ctx, &relative_dir_path, &relative_dir_path, error)?;
+                    continue;
+                },
+            };
+
+            for item in items {
+                let item = match item {
+                    Ok(item) => item,
+                    Err(error) => {
+                        excluded.unreadable(ctx, &relative_dir_path, &relative_dir_path, error)?;
+                        break;
+                    },
+                };
+                let file_name = item.file_name();
+                item_bar.set_message(format!("Item: '{}'", file_name.to_string_lossy()));
+
+                // The type usually comes from the directory listing itself, so entries which
+                // are skipped are never stat'ed
+                let file_type = match item.file_type() {
+                    Ok(file_type) => file_type,
+                    Err(error) => {
+                        excluded.unreadable(ctx, &relative_dir_path, &relative_path(&item.path()), error)?;
+                        continue;
+                    },
+                };
+
+                // A project database folder lives inside the tree it tracks. It changes
+                // on every scan so it must not be tracked as part of the tree
+                if file_type.is_dir() && file_name == PROJECT_DIR_NAME {
+                    excluded.add(&relative_path(&item.path()), ExclusionReason::Database);
+                    continue;
+                }
+
+                // Another scan may be probing a root nested inside this one
+                if file_type.is_dir() && file_name.to_string_lossy().starts_with(PROBE_DIR_PREFIX) {
+                    continue;
+                }
+
+                // Checksum files are written by hashing scans, so they'd otherwise be reported
+                // as changed whenever a file in their directory is
+                if file_type.is_file() && file_name == CHECKSUM_FILE_NAME {
+                    excluded.add(&relative_path(&item.path()), ExclusionReason::Checksum);
+                    continue;
+                }
+
+                // Nothing beneath an excluded directory is scanned
+                let item_path = item.path();
+                let relative_item_path = relative_path(&item_path);
+                if let Some(reason) = matcher.check(&relative_item_path, file_type.is_dir()) {
+                    excluded.add(&relative_item_path, reason);
+                    continue;
+                }
+
+                // On Unix, this stats the entry relative to the open directory rather than
+                // resolving its full path again. Like symlink_metadata, symlinks aren't followed
+                let metadata = match item.metadata() {
+                    Ok(metadata) => metadata,
+                    Err(error) => {
+                        excluded.unreadable(ctx, &relative_dir_path, &relative_item_path, error)?;
+                        continue;
+                    },
+                };
+                ctx.progress.item(&item_path);
+                stats_bar.inc(1);
+
+                if metadata.is_dir() {
+                    // Nothing beneath an ignored directory is scanned
+                    let change_type = handle_scan_item(db, scan, ItemType::Directory, &relative_item_path, &metadata, None, dir_mtime_tolerance)?;
+                    batch.wrote(db)?;
+                    match change_type {
+                        // A watch checkpoint reads directories new to it, but not others it didn't see change
+                        Some(change_type) if ctx.watched.is_none() || matches!(change_type, ChangeType::Add | ChangeType::TypeChange) => {
+                            dirs.push(item_path);
+                        },
+                        Some(_) => {},
+                        None => excluded.add(&relative_item_path, ExclusionReason::Ignored),
+                    }
+                } else {
+                    // Reading an offline file's metadata doesn't fetch its content. A skipped
+                    // one is kept as it was recorded, so it isn't taken to be deleted
+                    if ctx.options.offline_files == OfflinePolicy::Skip && metadata.is_file() && is_offline(&metadata) {
+                        keep_item(db, scan, &relative_item_path)?;
+                        batch.wrote(db)?;
+                        excluded.add(&relative_item_path, ExclusionReason::Offline);
+                        continue;
+                    }
+
+                    let (item_type, symlink) = if metadata.is_file() {
+                        (ItemType::File, None)
+                    } else if metadata.is_symlink() {
+                        (ItemType::Symlink, SymlinkTarget::read(&item_path))
+                    } else {
+                        (ItemType::Other, None)
+                    };
+
+                    let change_type = handle_scan_item(db, scan, item_type, &relative_item_path, &metadata, symlink.as_ref(), Some(mtime_tolerance))?;
+                    batch.wrote(db)?;
+                    if change_type.is_none() {
+                        excluded.add(&relative_item_path, ExclusionReason::Ignored);
+                    } else if let Some((hashing, file_bar)) = walk_hashing.as_mut().filter(|_| item_type == ItemType::File) {
+                        if let Some(candidate) = hash_candidate(&db.conn, scan, &relative_item_path, hydrate)? {
+                            hashing.hash(&mut db.conn, root, scan, ctx, file_bar, candidate)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        // The files still queued are recorded with the rest of the walk
+        if let Some((hashing, _)) = walk_hashing.as_mut() {
+            hashing.finish(&mut db.conn, root, scan, ctx)?;
+        }
+
+        Ok(())
+    })?;
+
+    stats_bar.finish_and_clear();
+    dir_bar.finish_and_clear();
+    item_bar.finish_and_clear();
+
+    // Files which changed while the walk hashed them, and any it didn't reach, are left
+    // to the hashing phase
+    if let Some((hashing, file_bar)) = walk_hashing {
+        file_bar.finish_and_clear();
+        hashing.total_bar.finish_and_clear();
+    }
+
+    if let Some(watched) = &watched {
+        settle_unwatched(db, root, scan, watched)?;
+    }
+
+    scan.set_exclusions(db, &excluded.counts, &excluded.exclusions)?;
+
+    if scan.budget_secs().is_some() || !excluded.unreadable_dirs.is_empty() {
+        let mut unchecked = dirs.into_relative_paths(&root_path_buf);
+        if !unchecked.is_empty() {
+            ctx.println(format!(
+                "Scan budget used up: {} directories in '{}' were not checked", unchecked.len(), root.path()))?;
+        }
+        unchecked.extend(excluded.unreadable_dirs);
+        unchecked.sort();
+        scan.set_coverage(db, dirs_checked, &unchecked)?;
+    }
+
+    scan.set_state(db, ScanState::Sweeping)?;
+    drop(timer);
+    do_state_sweeping(db, root, scan, ctx)
+}
+
+/// Takes the items a watch checkpoint didn't read to be as they were, by marking them seen.
+/// Only items gone from the directories it read, and everything beneath those, are left
+/// unseen, so that sweeping takes them to be deleted
+fn settle_unwatched(db: &mut Database, root: &Root, scan: &Scan, watched: &BTreeSet<PathBuf>) -> Result<(), FsPulseError> {
+    // Paths sort each directory ahead of what's beneath it
+    let mut gone: HashSet<PathBuf> = HashSet::new();
+    let mut gone_ids = Vec::new();
+    {
+        let mut stmt = db.conn.prepare(
+            "SELECT id, path FROM items
+                WHERE root_id = ? AND last_scan_id < ? AND is_tombstone = 0 AND is_ignored = 0
+                ORDER BY path ASC"
+        )?;
+        let mut rows = stmt.query((root.id(), scan.id()))?;
+
+        while let Some(row) = rows.next()? {
+            let path = PathBuf::from(row.get::<_, String>(1)?);
+            let in_watched = path.parent().is_some_and(|parent| watched.contains(parent));
+
+            if in_watched || path.ancestors().skip(1).any(|ancestor| gone.contains(ancestor)) {
+                gone_ids.push(row.get::<_, i64>(0)?);
+                gone.insert(path);
+            }
+        }
+    }
+
+    let tx = db.conn.transaction()?;
+    tx.execute("CREATE TEMP TABLE IF NOT EXISTS watch_gone (item_id INTEGER PRIMARY KEY)", [])?;
+    tx.execute("DELETE FROM watch_gone", [])?;
+    {
+        let mut stmt = tx.prepare("INSERT INTO watch_gone (item_id) VALUES (?)")?;
+        for id in &gone_ids {
+            stmt.execute([id])?;
+        }
+    }
+    tx.execute(
+        "UPDATE items SET last_scan_id = ?1
+            WHERE root_id = ?2 AND last_scan_id < ?1 AND is_tombstone = 0 AND is_ignored = 0
+            AND id NOT IN (SELECT item_id FROM watch_gone)",
+        (scan.id(), root.id()),
+    )?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn do_state_sweeping(db: &mut Database, root: &Root, scan: &mut Scan, ctx: &ScanContext) -> Result<(), FsPulseError> { 
+    let timer = Timings::start("scan: sweep");
+    ctx.progress.phase(root.path(), scan.id(), "sweeping", None, None);
+    check_empty_guardrail(db, root, scan, ctx)?;
+
+    let tx = db.conn.transaction()?;
+
+    // Items beneath directories a budgeted scan didn't get to, or that couldn't be read,
+    // weren't checked, so they can't be taken to be deleted
+    let separator = std::path::MAIN_SEPARATOR.to_string();
+
+    // Insert deletion records into changes
+    tx.execute(
+        "INSERT INTO changes (scan_id, item_id, change_type)
+            SELECT ?1, id, ?2
+            FROM items
+            WHERE root_id = ?3 AND is_tombstone = 0 AND is_ignored = 0 AND last_scan_id < ?1
+            AND NOT EXISTS (
+                SELECT 1 FROM unchecked_dirs u
+                WHERE u.scan_id = ?1 AND substr(items.path, 1, length(u.path) + 1) = u.path || ?4)",
+        (scan.id(), ChangeType::Delete.as_str(), root.id(), &separator),
+    )?;
     
-        // TODO: this error will panic
-        bar.set_style(ProgressStyle::default_bar()
-            .template("{msg}\n[{bar:40}] {bytes}/{total_bytes} ({eta})")
-            .unwrap()
-            .progress_chars("#>-"));
-        Some(bar)
+    // Mark unseen items as tombstones. Ignored items are never seen, but aren't deleted
+    tx.execute(
+        "UPDATE items SET is_tombstone = 1
+            WHERE root_id = ?1 AND last_scan_id < ?2 AND is_tombstone = 0 AND is_ignored = 0
+            AND NOT EXISTS (
+                SELECT 1 FROM unchecked_dirs u
+                WHERE u.scan_id = ?2 AND substr(items.path, 1, length(u.path) + 1) = u.path || ?3)",
+        (root.id(), scan.id(), &separator),
+    )?;
+
+    tx.commit()?;
+    drop(timer);
+
+    if scan.hashing() || scan.validating() {
+        scan.set_state(db, ScanState::Analyzing)?;
+        do_state_analyzing(db, root, scan, ctx)
+    } else {
+        do_state_completed(db, root, scan, ctx)
+    }
+}
+
+/// A scan that finds nothing where items previously existed is far more likely to be the
+/// result of a typo, an unmounted volume, or a permissions problem than a real deletion of
+/// everything. Unless allowed by flag or confirmed by the user, the scan is aborted before
+/// anything is tombstoned. The decision is recorded on the scan
+fn check_empty_guardrail(db: &mut Database, root: &Root, scan: &mut Scan, ctx: &ScanContext) -> Result<(), FsPulseError> {
+    let (seen_count, unseen_count): (i64, i64) = db.conn.query_row(
+        "SELECT
+            COALESCE(SUM(CASE WHEN last_scan_id = ?1 THEN 1 ELSE 0 END), 0),
+            COALESCE(SUM(CASE WHEN last_scan_id < ?1 THEN 1 ELSE 0 END), 0)
+            FROM items
+            WHERE root_id = ?2 AND is_tombstone = 0 AND is_ignored = 0",
+        [scan.id(), root.id()],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    if seen_count > 0 || unseen_count == 0 {
+        return Ok(());
+    }
+
+    let decision = if ctx.options.allow_empty {
+        EmptyGuardrail::AllowedByFlag
     } else {
+        let prompt = format!(
+            "Scan found no items in '{}' but {} items were present previously. Mark them all as deleted?",
+            root.path(), unseen_count);
+
+        match ctx.multi.suspend(|| ctx.options.prompts.confirm(&prompt)) {
+            Answer::Assumed => EmptyGuardrail::AllowedByFlag,
+            Answer::Confirmed => EmptyGuardrail::Confirmed,
+            Answer::Declined | Answer::Unavailable => EmptyGuardrail::Refused,
+        }
+    };
+
+    scan.set_empty_guardrail(db, decision)?;
+
+    if decision == EmptyGuardrail::Refused {
+        scan.abort(db)?;
+        return Err(FsPulseError::Error(format!(
+            "Scan Id {} found no items in '{}' where {} items were present previously, and was aborted. \
+            Use --allow-empty or --yes to record them as deleted",
+            scan.id(), root.path(), unseen_count)));
+    }
+
+    Ok(())
+}
+
+fn do_state_analyzing(db: &mut Database, root: &Root, scan: &mut Scan, ctx: &ScanContext) -> Result<(), FsPulseError> {
+    let timer = Timings::start("scan: hashing");
+    // TODO: validation of file contents is not yet implemented
+    if scan.hashing() {
+        let bar = new_file_hash_bar(root, ctx);
+
+        // Offline files are only hashed when asked to, since reading them fetches their content
+        let hydrate = ctx.options.offline_files == OfflinePolicy::Hydrate;
+
+        // The size of everything left to hash, which is only needed to estimate when hashing will finish
+        let bytes_total = if ctx.options.progress_json || !ctx.multi.is_hidden() {
+            let bytes: i64 = db.conn.query_row(
+                "SELECT COALESCE(SUM(file_size), 0)
+                    FROM items
+                    WHERE last_scan_id = ?1 AND is_tombstone = 0 AND item_type = ?2
+                    AND (last_hash_scan_id IS NULL OR last_hash_scan_id < ?1)
+                    AND (?3 = 0 OR (file_hash IS NULL AND sample_hash IS NULL) OR (file_hash IS NOT NULL AND hash_algo IS NOT ?4) OR hash_in_flux = 1 OR id IN (SELECT item_id FROM changes WHERE scan_id = ?1))
+                    AND (?5 = 1 OR is_offline = 0)",
+                (scan.id(), ItemType::File.as_str(), scan.hash_delta(), scan.hash_algo().as_str(), hydrate),
+                |row| row.get(0),
+            )?;
+            Some(bytes as u64)
+        } else {
+            None
+        };
+        ctx.progress.phase(root.path(), scan.id(), "hashing", bytes_total, Some(&bar));
+        let mut last_path = String::new();
+        let mut out_of_time = false;
+
+        // Follows the bytes hashed across all the files, where the other bar follows one file
+        let total_bar = ctx.multi.add(ProgressBar::new(bytes_total.unwrap_or_default()));
+        total_bar.set_style(ProgressStyle::default_bar()
+            .template("{prefix}Hashed [{bar:40}] {bytes}/{total_bytes} ({binary_bytes_per_sec}, ETA {eta})")
+            .expect("valid progress template")
+            .progress_chars("#>-"));
+        total_bar.set_prefix(format!("[{}] ", root.path()));
+
+        let mut hashing = Hashing::new(ctx, total_bar.clone());
+
+        while !out_of_time {
+            // Files seen in this scan which haven't yet been hashed by this scan. If the
+            // scan is resumed, only the remaining files are hashed. A delta scan skips files
+            // which already have a hash (full or sampled) and weren't changed by this scan,
+            // unless they were being written when an earlier scan hashed them or their full
+            // hash was taken with another algorithm
+            let batch = {
+                let mut stmt = db.conn.prepare(
+                    "SELECT id, path, file_size, file_hash, confirm_hash, sample_hash, hash_algo, similarity_digest
+                        FROM items
+                        WHERE last_scan_id = ?1 AND is_tombstone = 0 AND item_type = ?2
+                        AND (last_hash_scan_id IS NULL OR last_hash_scan_id < ?1)
+                        AND (?3 = 0 OR (file_hash IS NULL AND sample_hash IS NULL) OR (file_hash IS NOT NULL AND hash_algo IS NOT ?4) OR hash_in_flux = 1 OR id IN (SELECT item_id FROM changes WHERE scan_id = ?1))
+                        AND (?7 = 1 OR is_offline = 0)
+                        AND path > ?5
+                        ORDER BY path ASC
+                        LIMIT ?6"
+                )?;
+
+                let rows = stmt.query_map(
+                    (scan.id(), ItemType::File.as_str(), scan.hash_delta(), scan.hash_algo().as_str(), &last_path, HASH_BATCH_SIZE, hydrate),
+                    |row| Ok(HashCandidate {
+                        item_id: row.get(0)?,
+                        path: row.get(1)?,
+                        file_size: row.get(2)?,
+                        prev_hash: row.get(3)?,
+                        prev_confirm_hash: row.get(4)?,
+                        prev_sample_hash: row.get(5)?,
+                        prev_hash_algo: row.get(6)?,
+                        prev_similarity_digest: row.get(7)?,
+                    }),
+                )?;
+
+                rows.collect::<Result<Vec<_>, _>>()?
+            };
+
+            if batch.is_empty() {
+                break;
+            }
+
+            for candidate in batch {
+                if scan.budget_exhausted() {
+                    out_of_time = true;
+                    break;
+                }
+
+                if let Err(error) = check_db_space(db, scan, ctx) {
+                    bar.finish_and_clear();
+                    total_bar.finish_and_clear();
+                    return Err(error);
+                }
+
+                last_path = candidate.path.clone();
+                ctx.progress.item(&root.item_path(&candidate.path));
+                hashing.hash(&mut db.conn, root, scan, ctx, &bar, candidate)?;
+            }
+        }
+
+        // Files already queued are hashed even when the budget is used up
+        hashing.finish(&mut db.conn, root, scan, ctx)?;
+
+        if let Some(tuner) = &hashing.tuner {
+            scan.set_hash_settings(db, tuner.chosen())?;
+        }
+
+        if out_of_time {
+            ctx.println(format!(
+                "Scan budget used up before hashing finished in '{}'. Files not hashed keep their previous hashes",
+                root.path()))?;
+            hashing.in_flux.clear();
+        }
+
+        // Files which were being written get one more try, since they may have settled by now
+        for candidate in std::mem::take(&mut hashing.in_flux) {
+            ctx.progress.item(&root.item_path(&candidate.path));
+            hashing.hash(&mut db.conn, root, scan, ctx, &bar, candidate)?;
+        }
+        hashing.finish(&mut db.conn, root, scan, ctx)?;
+        for candidate in &hashing.in_flux {
+            ctx.println(format!(
+                "'{}' changed while it was being hashed. It will be hashed again by the next hashing scan",
+                root.item_path(&candidate.path).display()))?;
+        }
+
+        bar.finish_and_clear();
+        total_bar.finish_and_clear();
+
+        if !hydrate && !out_of_time {
+            mark_offline_unhashed(&db.conn, root, scan, ctx)?;
+        }
+
+        // Added files are hashed now, so their content can be matched with deleted files
+        record_moves(db, scan)?;
+
+        if ctx.options.write_checksums {
+            let summary = ChecksumFiles::write_for_scan(db, root, scan)?;
+            ctx.multi.println(format!(
+                "Checksum files in '{}': {} written, {} unchanged, {} removed",
+                root.path(), summary.written, summary.unchanged, summary.removed))?;
+        }
+    }
+
+    drop(timer);
+    do_state_completed(db, root, scan, ctx)
+}
+
+/// Offline files the scan would have hashed are left to be hashed again by a later scan,
+/// by when their content may be available. Until then, they keep their previous hashes
+fn mark_offline_unhashed(conn: &Connection, root: &Root, scan: &Scan, ctx: &ScanContext) -> Result<(), FsPulseError> {
+    let marked = conn.execute(
+        "UPDATE items SET hash_in_flux = 1
+            WHERE last_scan_id = ?1 AND is_tombstone = 0 AND item_type = ?2 AND is_offline = 1
+            AND (last_hash_scan_id IS NULL OR last_hash_scan_id < ?1)
+            AND (?3 = 0 OR (file_hash IS NULL AND sample_hash IS NULL) OR (file_hash IS NOT NULL AND hash_algo IS NOT ?4) OR hash_in_flux = 1 OR id IN (SELECT item_id FROM changes WHERE scan_id = ?1))",
+        (scan.id(), ItemType::File.as_str(), scan.hash_delta(), scan.hash_algo().as_str()),
+    )?;
+
+    if marked > 0 {
+        ctx.println(format!(
+            "{} offline files in '{}' were not hashed, to avoid fetching their content. Use --offline-files hydrate to hash them",
+            marked, root.path()))?;
+    }
+
+    Ok(())
+}
+
+/// Records each file the scan found deleted whose content it found at a new path as a move.
+/// The deleted item takes the new path and the state the scan recorded there, so its history
+/// follows it, and its deletion becomes the move. Where the new path already had a history
+/// of its own, the two can't be merged, and the deletion and addition are left as they are
+fn record_moves(db: &mut Database, scan: &Scan) -> Result<(), FsPulseError> {
+    let renames = Change::renames_in_scan(db, scan.id())?;
+    if renames.is_empty() {
+        return Ok(());
+    }
+
+    let tx = db.conn.transaction()?;
+
+    for (delete_id, add_id) in renames {
+        let old_id: i64 = tx.query_row("SELECT item_id FROM changes WHERE id = ?", [delete_id], |row| row.get(0))?;
+        let (new_id, new_path): (i64, String) = tx.query_row(
+            "SELECT i.id, i.path FROM changes c JOIN items i ON i.id = c.item_id WHERE c.id = ?",
+            [add_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let history: i64 = tx.query_row("SELECT COUNT(*) FROM changes WHERE item_id = ?", [new_id], |row| row.get(0))?;
+        if history > 1 {
+            continue;
+        }
+
+        // The content is the same, so only the metadata that differs is recorded, as a
+        // modification would
+        tx.execute(
+            "UPDATE changes SET change_type = ?1, old_path = o.path,
+                prev_last_modified = CASE WHEN o.last_modified IS NOT n.last_modified THEN o.last_modified END,
+                prev_owner_uid = CASE WHEN o.owner_uid IS NOT n.owner_uid THEN o.owner_uid END,
+                prev_owner_gid = CASE WHEN o.owner_gid IS NOT n.owner_gid THEN o.owner_gid END
+                FROM items o, items n
+                WHERE changes.id = ?2 AND o.id = ?3 AND n.id = ?4",
+            (ChangeType::Move.as_str(), delete_id, old_id, new_id),
+        )?;
+
+        tx.execute(
+            "UPDATE items SET (item_type, last_modified, file_size, file_hash, confirm_hash, file_is_valid, last_scan_id,
+                    last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, hash_in_flux,
+                    owner_uid, owner_gid, sample_hash, hash_algo, similarity_digest, is_offline) = (
+                SELECT item_type, last_modified, file_size, file_hash, confirm_hash, file_is_valid, last_scan_id,
+                    last_hash_scan_id, last_is_valid_scan_id, last_verified, symlink_target, symlink_dangling, hash_in_flux,
+                    owner_uid, owner_gid, sample_hash, hash_algo, similarity_digest, is_offline
+                FROM items WHERE id = ?2), is_tombstone = 0
+                WHERE id = ?1",
+            (old_id, new_id),
+        )?;
+
+        // The path is only free once the added item is gone
+        tx.execute("DELETE FROM changes WHERE id = ?", [add_id])?;
+        tx.execute("DELETE FROM items WHERE id = ?", [new_id])?;
+        tx.execute("UPDATE items SET path = ? WHERE id = ?", (&new_path, old_id))?;
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// The file at `path`, as a file to hash if it's one the scan would hash. A delta scan
+/// passes over files it has hashed before, as the hashing phase does
+fn hash_candidate(conn: &Connection, scan: &Scan, path: &Path, hydrate: bool) -> Result<Option<HashCandidate>, FsPulseError> {
+    let candidate = conn.query_row(
+        "SELECT id, path, file_size, file_hash, confirm_hash, sample_hash, hash_algo, similarity_digest
+            FROM items
+            WHERE root_id = ?1 AND path = ?2 AND last_scan_id = ?3 AND is_tombstone = 0 AND item_type = ?4
+            AND (last_hash_scan_id IS NULL OR last_hash_scan_id < ?3)
+            AND (?5 = 0 OR (file_hash IS NULL AND sample_hash IS NULL) OR (file_hash IS NOT NULL AND hash_algo IS NOT ?6) OR hash_in_flux = 1 OR id IN (SELECT item_id FROM changes WHERE scan_id = ?3))
+            AND (?7 = 1 OR is_offline = 0)",
+        (scan.root_id(), path.to_string_lossy(), scan.id(), ItemType::File.as_str(), scan.hash_delta(), scan.hash_algo().as_str(), hydrate),
+        |row| Ok(HashCandidate {
+            item_id: row.get(0)?,
+            path: row.get(1)?,
+            file_size: row.get(2)?,
+            prev_hash: row.get(3)?,
+            prev_confirm_hash: row.get(4)?,
+            prev_sample_hash: row.get(5)?,
+            prev_hash_algo: row.get(6)?,
+            prev_similarity_digest: row.get(7)?,
+        }),
+    ).optional()?;
+
+    Ok(candidate)
+}
+
+/// A file queued on the hash pool
+struct QueuedFile {
+    candidate: HashCandidate,
+    /// The progress bar follows this file
+    has_bar: bool,
+}
+
+/// A scan's hashing: the files queued on the hash pool, and what's been learned from those
+/// which have been hashed. Hashes are recorded by the scan as each file finishes, so the
+/// database is only ever written from the scan's own thread, whether the files are queued
+/// while the tree is walked or afterwards
+struct Hashing<'a> {
+    queue: HashQueue<'a, QueuedFile>,
+    /// Files which changed while they were being hashed
+    in_flux: Vec<HashCandidate>,
+    tuner: Option<HashTuner>,
+    settings: HashSettings,
+    /// When the tuner was last told how much was hashed
+    measured: Instant,
+    /// None of the queued files is followed by the progress bar
+    bar_free: bool,
+    /// Counts the bytes hashed across all the files
+    total_bar: ProgressBar,
+}
+
+impl<'a> Hashing<'a> {
+    /// Without tuning, the scan keeps as many files queued as the pool has threads.
+    /// Tuning starts from one file at a time
+    fn new(ctx: &ScanContext<'a>, total_bar: ProgressBar) -> Self {
+        let tuner = ctx.options.tune_hashing.then(|| HashTuner::new(ctx.hash_pool.threads()));
+        let settings = tuner.as_ref().map_or_else(
+            || HashSettings { concurrency: ctx.hash_pool.threads(), ..HashSettings::default() },
+            HashTuner::settings,
+        );
+
+        Hashing {
+            queue: HashQueue::new(ctx.hash_pool),
+            in_flux: Vec::new(),
+            tuner,
+            settings,
+            measured: Instant::now(),
+            bar_free: true,
+            total_bar,
+        }
+    }
+
+    /// Hashes the file. Files large enough to be sampled are sampled right away while the
+    /// pool hashes the rest. Others are queued on the pool, once the files already hashed
+    /// are recorded, first waiting for a queued file to finish if as many are queued as the
+    /// settings allow
+    fn hash(
+        &mut self,
+        conn: &mut Connection,
+        root: &Root,
+        scan: &Scan,
+        ctx: &ScanContext,
+        bar: &ProgressBar,
+        candidate: HashCandidate,
+    ) -> Result<(), FsPulseError> {
+        let absolute_path = root.item_path(&candidate.path);
+
+        if let Some(sampling) = ctx.options.sampling_for(candidate.file_size) {
+            let file_bar = if self.bar_free { bar.clone() } else { ProgressBar::hidden() };
+            let sample_hash = Hash::compute_sample_hash(&absolute_path, &file_bar, sampling);
+            ctx.progress.hashed(candidate.file_size.unwrap_or_default() as u64);
+            self.total_bar.inc(candidate.file_size.unwrap_or_default().max(0) as u64);
+
+            if !record_sample_hash(conn, root, scan, ctx, &candidate, sample_hash)? {
+                self.in_flux.push(candidate);
+            }
+            return Ok(());
+        }
+
+        while let Some((queued, hashes)) = self.queue.try_next()? {
+            self.record(conn, root, scan, ctx, queued, hashes)?;
+        }
+        while self.queue.queued() >= self.settings.concurrency && self.record_next(conn, root, scan, ctx)? {}
+
+        // Large files also get a second, independent hash so that an unchanged hash
+        // isn't taken on trust
+        let confirm = scan.confirm_threshold()
+            .is_some_and(|threshold| candidate.file_size.unwrap_or_default() >= threshold);
+
+        // The progress bar follows one file at a time, taken up by the next file queued
+        // once the file it followed finishes
+        let has_bar = std::mem::take(&mut self.bar_free);
+        let file_bar = if has_bar { bar.clone() } else { ProgressBar::hidden() };
+
+        self.queue.submit(QueuedFile { candidate, has_bar }, &absolute_path, &file_bar, scan.hash_algo(), confirm, self.settings.buffer_size)
+    }
+
+    /// Records the hashes of the next queued file to finish. Returns false once nothing
+    /// is queued
+    fn record_next(&mut self, conn: &mut Connection, root: &Root, scan: &Scan, ctx: &ScanContext) -> Result<bool, FsPulseError> {
+        let Some((queued, hashes)) = self.queue.next()? else {
+            return Ok(false);
+        };
+
+        self.record(conn, root, scan, ctx, queued, hashes)?;
+        Ok(true)
+    }
+
+    /// Records the hashes of a queued file which has finished
+    fn record(&mut self, conn: &mut Connection, root: &Root, scan: &Scan, ctx: &ScanContext, queued: QueuedFile, hashes: HashResult) -> Result<(), FsPulseError> {
+        let QueuedFile { candidate, has_bar } = queued;
+        self.bar_free |= has_bar;
+
+        let file_size = candidate.file_size.unwrap_or_default().max(0) as u64;
+        ctx.progress.hashed(file_size);
+        self.total_bar.inc(file_size);
+
+        if !record_hashes(conn, root, scan, ctx, &candidate, hashes)? {
+            self.in_flux.push(candidate);
+        }
+
+        if let Some(tuner) = self.tuner.as_mut().filter(|tuner| !tuner.is_done()) {
+            tuner.record(file_size, self.measured.elapsed());
+            self.measured = Instant::now();
+            self.settings = tuner.settings();
+        }
+
+        Ok(())
+    }
+
+    /// Records the hashes of every queued file as they finish
+    fn finish(&mut self, conn: &mut Connection, root: &Root, scan: &Scan, ctx: &ScanContext) -> Result<(), FsPulseError> {
+        while self.record_next(conn, root, scan, ctx)? {}
+        Ok(())
+    }
+}
+
+/// Records a file's hashes, along with a modification if its content changed. Returns
+/// false if the file changed while it was being hashed, in which case nothing but that
+/// is recorded
+fn record_hashes(
+    conn: &mut Connection,
+    root: &Root,
+    scan: &Scan,
+    ctx: &ScanContext,
+    candidate: &HashCandidate,
+    hashes: HashResult,
+) -> Result<bool, FsPulseError> {
+    let HashCandidate { item_id, path, file_size, prev_hash, prev_confirm_hash, prev_hash_algo, prev_similarity_digest, .. } = candidate;
+    let absolute_path = root.item_path(path);
+
+    // Hashes taken with another algorithm can't be compared, so they're replaced without
+    // recording a change
+    let (prev_hash, prev_confirm_hash) = match prev_hash_algo.as_deref() {
+        Some(algo) if algo == scan.hash_algo().as_str() => (prev_hash, prev_confirm_hash),
+        _ => (&None, &None),
+    };
+
+    let (hash, confirm_hash) = match hashes {
+        Ok(Some(hashes)) => hashes,
+        Ok(None) => {
+            // The file is being written. It's flagged so that it's hashed again even if a
+            // later delta scan sees no change to its metadata
+            conn.execute("UPDATE items SET hash_in_flux = 1 WHERE id = ?", [item_id])?;
+            return Ok(false);
+        },
+        Err(error) => {
+            ctx.println(format!("Error computing hash for '{}': {}", absolute_path.display(), error))?;
+            return Ok(true);
+        }
+    };
+
+    let mut hash_changed = prev_hash.as_deref().is_some_and(|prev| prev != hash);
+
+    // Only an unchanged hash with a confirmation hash from an earlier scan can be confirmed
+    let confirmation = match (&prev_hash, &prev_confirm_hash, &confirm_hash) {
+        (Some(_), Some(prev_confirm), Some(confirm)) if !hash_changed => Some(prev_confirm == confirm),
+        _ => None,
+    };
+
+    if confirmation == Some(false) {
+        ctx.println(format!(
+            "Hash collision: '{}' has an unchanged {} hash but its content changed", absolute_path.display(), scan.hash_algo()))?;
+        hash_changed = true;
+    }
+
+    // A confirmation hash is only kept while it describes the same content as the hash
+    let confirm_hash = match confirm_hash {
+        Some(confirm_hash) => Some(confirm_hash),
+        None if hash_changed => None,
+        None => prev_confirm_hash.clone(),
+    };
+
+    // The digest only needs taking again when the content changed, or it hasn't been taken.
+    // Without a new one, a digest of content the file no longer has is dropped
+    let similarity_digest = if !ctx.options.takes_similarity(*file_size) {
         None
+    } else if !hash_changed && prev_hash.is_some() && prev_similarity_digest.is_some() {
+        prev_similarity_digest.clone()
+    } else {
+        match Hash::compute_similarity_digest(&absolute_path) {
+            Ok(digest) => digest,
+            Err(error) => {
+                ctx.println(format!("Error computing similarity digest for '{}': {}", absolute_path.display(), error))?;
+                None
+            }
+        }
+    };
+    let similarity = match (hash_changed, prev_similarity_digest, &similarity_digest) {
+        (true, Some(prev), Some(digest)) => Hash::similarity(prev, digest),
+        _ => None,
     };
 
-    q.push_back(QueueEntry {
-        path: root_path_buf.clone(),
-        metadata,
-    });
+    // A savepoint rather than a transaction, since a file hashed while the tree is walked
+    // is recorded within the walk's batch
+    let tx = conn.savepoint()?;
+
+    match confirmation {
+        Some(true) => tx.execute("UPDATE scans SET hash_confirmations = hash_confirmations + 1 WHERE id = ?", [scan.id()])?,
+        Some(false) => tx.execute("UPDATE scans SET hash_collisions = hash_collisions + 1 WHERE id = ?", [scan.id()])?,
+        None => 0,
+    };
 
-    while let Some(q_entry) = q.pop_front() {
-        dir_bar.set_message(format!("Directory: '{}'", q_entry.path.to_string_lossy()));
+    if hash_changed {
+        // If the scan already recorded a metadata modification, attach the previous hash
+        // to it. Otherwise, this is a content-only modification
+        let rows_updated = tx.execute(
+            "UPDATE changes SET prev_hash = ? WHERE scan_id = ? AND item_id = ? AND change_type = ?",
+            (prev_hash, scan.id(), item_id, ChangeType::Modify.as_str()),
+        )?;
 
-        // The root was previously pushed onto the queue to enable it to be scanned but
-        // we don't want to insert it into the database as an item, so we skip this
+        if rows_updated == 0 {
+            tx.execute(
+                "INSERT INTO changes (scan_id, item_id, change_type, prev_hash) VALUES (?, ?, ?, ?)",
+                (scan.id(), item_id, ChangeType::Modify.as_str(), prev_hash),
+            )?;
+        }
 
+        if similarity.is_some() {
+            tx.execute(
+                "UPDATE changes SET similarity = ? WHERE scan_id = ? AND item_id = ? AND change_type = ?",
+                (similarity, scan.id(), item_id, ChangeType::Modify.as_str()),
+            )?;
+        }
     }
 
+    tx.execute(
+        "UPDATE items SET file_hash = ?, confirm_hash = ?, hash_algo = ?, similarity_digest = ?, hash_in_flux = 0, last_hash_scan_id = ?, last_verified = strftime('%s', 'now', 'utc') WHERE id = ?",
+        (&hash, &confirm_hash, scan.hash_algo().as_str(), &similarity_digest, scan.id(), item_id),
+    )?;
 
+    tx.commit()?;
+    Ok(true)
+}
 
+/// Records a file's sampled hash, along with a modification if it changed. Returns false
+/// if the file changed while it was being sampled, in which case nothing but that is
+/// recorded
+fn record_sample_hash(
+    conn: &mut Connection,
+    root: &Root,
+    scan: &Scan,
+    ctx: &ScanContext,
+    candidate: &HashCandidate,
+    sample_hash: Result<Option<String>, FsPulseError>,
+) -> Result<bool, FsPulseError> {
+    let HashCandidate { item_id, path, prev_sample_hash, .. } = candidate;
 
-    do_state_sweeping(db, root, scan)
+    let sample_hash = match sample_hash {
+        Ok(Some(sample_hash)) => sample_hash,
+        Ok(None) => {
+            conn.execute("UPDATE items SET hash_in_flux = 1 WHERE id = ?", [item_id])?;
+            return Ok(false);
+        },
+        Err(error) => {
+            ctx.println(format!("Error computing sampled hash for '{}': {}", root.item_path(path).display(), error))?;
+            return Ok(true);
+        }
+    };
+
+    // Hashes sampled differently can't be compared
+    let changed = prev_sample_hash.as_deref()
+        .is_some_and(|prev| Sampling::comparable(prev, &sample_hash) && prev != sample_hash);
+
+    let tx = conn.savepoint()?;
+
+    if changed {
+        let rows_updated = tx.execute(
+            "UPDATE changes SET prev_sample_hash = ? WHERE scan_id = ? AND item_id = ? AND change_type = ?",
+            (prev_sample_hash, scan.id(), item_id, ChangeType::Modify.as_str()),
+        )?;
+
+        if rows_updated == 0 {
+            tx.execute(
+                "INSERT INTO changes (scan_id, item_id, change_type, prev_sample_hash) VALUES (?, ?, ?, ?)",
+                (scan.id(), item_id, ChangeType::Modify.as_str(), prev_sample_hash),
+            )?;
+        }
+
+        // A full hash taken earlier no longer describes the content
+        tx.execute("UPDATE items SET file_hash = NULL, confirm_hash = NULL, hash_algo = NULL, similarity_digest = NULL WHERE id = ?", [item_id])?;
+    }
+
+    // The file isn't fully read, so it isn't counted as verified
+    tx.execute(
+        "UPDATE items SET sample_hash = ?, hash_in_flux = 0, last_hash_scan_id = ? WHERE id = ?",
+        (&sample_hash, scan.id(), item_id),
+    )?;
+
+    tx.commit()?;
+    Ok(true)
 }
 
-fn do_state_sweeping(db: &Database, root: &Root, scan: &Scan) -> Result<(), FsPulseError> { 
-    
-    do_state_analyzing(db, root, scan)
+fn do_state_completed(db: &mut Database, root: &Root, scan: &mut Scan, ctx: &ScanContext) -> Result<(), FsPulseError> {
+    scan.complete(db, root)?;
+    ctx.progress.complete(scan.file_count().unwrap_or_default(), scan.folder_count().unwrap_or_default());
+
+    // Keep concurrent scans from interleaving their reports with each other or the progress display
+    if !ctx.options.no_report {
+        let report = Reports::scan(db, scan.id())?;
+        ctx.multi.suspend(|| Reports::print_scan(report.as_ref()))?;
+    }
+
+    for failure in Notifications::send_for_scan(db, ctx.config, root, scan, ctx.options.expect_frozen)? {
+        ctx.println(failure)?;
+    }
+
+    if ctx.options.summary_line {
+        let summary = Notifications::summary_line(db, root, scan, ctx.options.expect_frozen)?;
+        ctx.multi.suspend(|| println!("{}", summary));
+    }
+
+    if ctx.options.expect_frozen {
+        check_frozen(db, root, scan, ctx)?;
+    }
+
+    Ok(())
+}
+
+/// Fails if a scan of a root expected to be frozen found any change. The root's first
+/// scan records the baseline, so its adds aren't changes
+fn check_frozen(db: &Database, root: &Root, scan: &Scan, ctx: &ScanContext) -> Result<(), FsPulseError> {
+    if !scan.has_completed_predecessor(db)? {
+        ctx.multi.println(format!("Recorded the frozen baseline of '{}'", root.path()))?;
+        return Ok(());
+    }
+
+    let counts = ChangeCounts::get_by_scan_id(db, scan.id())?;
+    let changes = counts.add_count + counts.modify_count + counts.delete_count + counts.type_change_count;
+    if changes == 0 {
+        return Ok(());
+    }
+
+    ctx.multi.suspend(|| Reports::print_frozen_root_changes(db, root, scan, changes))?;
+
+    Err(FsPulseError::FrozenRootChanged(format!(
+        "'{}' has {} changes in Scan Id {}", root.path(), changes, scan.id()
+    )))
+}
+
+/// Where a symlink points, as stored in the link, and whether anything exists there
+struct SymlinkTarget {
+    target: String,
+    dangling: bool,
+}
+
+impl SymlinkTarget {
+    fn read(path: &Path) -> Option<Self> {
+        let target = fs::read_link(path).ok()?;
+
+        Some(SymlinkTarget {
+            target: target.to_string_lossy().into_owned(),
+            // Following the link fails if the target (or any link on the way to it) is missing
+            dangling: fs::metadata(path).is_err(),
+        })
+    }
+}
+
+/// Whether a file's content is held elsewhere, so reading it would fetch it first. Windows
+/// and macOS mark placeholders. Elsewhere, a file with no storage allocated to it is taken
+/// to be a stub, though a file that's nothing but a hole looks the same. Small files can be
+/// stored within the inode, so they're never taken for stubs
+#[cfg(windows)]
+fn is_offline(metadata: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+
+    metadata.file_attributes() & (FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_RECALL_ON_OPEN | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS) != 0
+}
+
+#[cfg(target_os = "macos")]
+fn is_offline(metadata: &Metadata) -> bool {
+    use std::os::macos::fs::MetadataExt;
+
+    const SF_DATALESS: u32 = 0x40000000;
+
+    metadata.st_flags() & SF_DATALESS != 0
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+fn is_offline(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.len() > 4096 && metadata.blocks() == 0
+}
+
+#[cfg(not(any(windows, unix)))]
+fn is_offline(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// The user and group ids of an item's owner, where the platform has them
+#[cfg(unix)]
+fn owner_of(metadata: &Metadata) -> (Option<i64>, Option<i64>) {
+    use std::os::unix::fs::MetadataExt;
+
+    (Some(metadata.uid().into()), Some(metadata.gid().into()))
+}
+
+#[cfg(not(unix))]
+fn owner_of(_metadata: &Metadata) -> (Option<i64>, Option<i64>) {
+    (None, None)
 }
 
-fn do_state_analyzing(db: &Database, root: &Root, scan: &Scan) -> Result<(), FsPulseError> {
+/// Keeps a file the scan left out as it was recorded, rather than taking it to be deleted
+fn keep_item(db: &Database, scan: &Scan, path: &Path) -> Result<(), FsPulseError> {
+    db.conn.execute(
+        "UPDATE items SET last_scan_id = ? WHERE root_id = ? AND path = ? AND is_tombstone = 0 AND is_ignored = 0",
+        (scan.id(), scan.root_id(), path.to_string_lossy()),
+    )?;
 
     Ok(())
 }
 
 fn handle_scan_item(
-    db: &Database, 
+    db: &mut Database, 
+    scan: &Scan,
     item_type: ItemType, 
     path: &Path, 
     metadata: &Metadata,
-    file_hash: Option<&str>,
-    file_is_valid: Option<bool>,
-) -> Result<(), FsPulseError> {
-    
-}
+    symlink: Option<&SymlinkTarget>,
+    mtime_tolerance: Option<i64>,
+) -> Result<Option<ChangeType>, FsPulseError> {
+    let path_str = path.to_string_lossy();
+    let scan_id = scan.id();
+    let root_id = scan.root_id();
+
+    let conn = &mut db.conn;
+
+    // Determine timestamps and file size
+    let last_modified = metadata.modified()
+        .ok()
+        .map(Utils::system_time_to_db_time);
+    let file_size = if metadata.is_file() { Some(metadata.len() as i64) } else { None };
+    let symlink_target = symlink.map(|s| s.target.as_str());
+    let symlink_dangling = symlink.map(|s| s.dangling);
+    let (owner_uid, owner_gid) = owner_of(metadata);
+    let is_offline = metadata.is_file() && is_offline(metadata);
+
+    // Check if the item already exists (fetching `id`, `is_tombstone` as well)
+    let existing_item: Option<(i64, String, Option<i64>, Option<i64>, bool, bool, Option<String>, Option<i64>, Option<i64>)> = conn.query_row(
+        "SELECT id, item_type, last_modified, file_size, is_tombstone, is_ignored, symlink_target, owner_uid, owner_gid FROM items WHERE root_id = ? AND path = ?",
+        (root_id, &path_str),
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?, row.get(8)?)),
+    ).optional()?;
+
+    // Ignored items are left exactly as they were
+    if existing_item.as_ref().is_some_and(|(.., is_ignored, _, _, _)| *is_ignored) {
+        return Ok(None);
+    }
+
+    let change_type = match existing_item {
+        Some((item_id, existing_type, existing_modified, existing_size, is_tombstone, _, existing_target, existing_uid, existing_gid)) => {
+            let item_type_str = item_type.as_str();
+            // Modification times closer together than the file system can store are the same time
+            // With no tolerance, modification times aren't compared at all
+            let modified_changed = match (mtime_tolerance, existing_modified, last_modified) {
+                (None, _, _) => false,
+                (Some(tolerance), Some(existing), Some(current)) => (existing - current).abs() > tolerance,
+                (Some(_), existing, current) => existing != current,
+            };
+            let metadata_changed = modified_changed || existing_size != file_size;
+            // A symlink which now points somewhere else is modified, whatever its metadata says
+            let target_changed = existing_target.as_deref() != symlink_target;
+            // Items recorded before ownership was tracked have no owner to compare with
+            let owner_changed = existing_uid.is_some() && (existing_uid, existing_gid) != (owner_uid, owner_gid);
 
+            if is_tombstone {
+                let tx = conn.savepoint()?;
+                tx.execute("UPDATE items SET item_type = ?, last_modified = ?, file_size = ?, file_hash = NULL, confirm_hash = NULL, hash_algo = NULL, similarity_digest = NULL, file_is_valid = NULL, last_verified = NULL, symlink_target = ?, symlink_dangling = ?, owner_uid = ?, owner_gid = ?, is_offline = ?, last_scan_id = ?, is_tombstone = 0 WHERE id = ?", 
+                    (item_type_str, last_modified, file_size, symlink_target, symlink_dangling, owner_uid, owner_gid, is_offline, scan_id, item_id))?;
+                tx.execute("INSERT INTO changes (scan_id, item_id, change_type) VALUES (?, ?, ?)", 
+                    (scan_id, item_id, ChangeType::Add.as_str()))?;
+                tx.commit()?;
+                ChangeType::Add
+            } else if existing_type != item_type_str {
+                // Item type changed (e.g., file -> directory)
+                let tx = conn.savepoint()?;
+                tx.execute("UPDATE items SET item_type = ?, last_modified = ?, file_size = ?, file_hash = NULL, confirm_hash = NULL, hash_algo = NULL, similarity_digest = NULL, file_is_valid = NULL, last_verified = NULL, symlink_target = ?, symlink_dangling = ?, owner_uid = ?, owner_gid = ?, is_offline = ?, last_scan_id = ? WHERE id = ?", 
+                    (item_type_str, last_modified, file_size, symlink_target, symlink_dangling, owner_uid, owner_gid, is_offline, scan_id, item_id))?;
+                tx.execute("INSERT INTO changes (scan_id, item_id, change_type) VALUES (?, ?, ?)", 
+                    (scan_id, item_id, ChangeType::TypeChange.as_str()))?;
+                tx.commit()?;
+                ChangeType::TypeChange
+            } else if metadata_changed || target_changed || owner_changed {
+                // Item metadata changed. Content changes are detected while analyzing
+                let prev_target = if target_changed { existing_target } else { None };
+                let (prev_uid, prev_gid) = if owner_changed { (existing_uid, existing_gid) } else { (None, None) };
+                let tx = conn.savepoint()?;
+                tx.execute("UPDATE items SET last_modified = ?, file_size = ?, symlink_target = ?, symlink_dangling = ?, owner_uid = ?, owner_gid = ?, is_offline = ?, last_scan_id = ? WHERE id = ?", 
+                    (last_modified, file_size, symlink_target, symlink_dangling, owner_uid, owner_gid, is_offline, scan_id, item_id))?;
+                tx.execute("INSERT INTO changes 
+                    (scan_id, item_id, change_type, prev_last_modified, prev_file_size, prev_symlink_target, prev_owner_uid, prev_owner_gid) 
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?)", 
+                    (scan_id, item_id, ChangeType::Modify.as_str(), existing_modified, existing_size, prev_target, prev_uid, prev_gid))?;
+                tx.commit()?;
+                ChangeType::Modify
+            } else {
+                // No change, just update last_scan_id. A symlink's target can appear or
+                // disappear without the symlink itself changing, an ignored modification
+                // time is still kept current, and items recorded before ownership was
+                // tracked get their owner. A file can go offline or come back without changing
+                let last_modified = if mtime_tolerance.is_none() { last_modified } else { existing_modified };
+                conn.execute("UPDATE items SET last_modified = ?, symlink_dangling = ?, owner_uid = ?, owner_gid = ?, is_offline = ?, last_scan_id = ? WHERE id = ?", 
+                    (last_modified, symlink_dangling, owner_uid, owner_gid, is_offline, scan_id, item_id))?;
+                ChangeType::NoChange
+            }
+        }
+        None => {
+            // Item is new, insert into items and changes tables
+            let tx = conn.savepoint()?;
+            let item_id: i64 = tx.query_row(
+                "INSERT INTO items (root_id, path, item_type, last_modified, file_size, symlink_target, symlink_dangling, owner_uid, owner_gid, is_offline, last_scan_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING id",
+                (root_id, &path_str, item_type.as_str(), last_modified, file_size, symlink_target, symlink_dangling, owner_uid, owner_gid, is_offline, scan_id),
+                |row| row.get(0),
+            )?;
+            tx.execute("INSERT INTO changes (scan_id, item_id, change_type) VALUES (?, ?, ?)",
+                (scan_id, item_id, ChangeType::Add.as_str()))?;
+            tx.commit()?;
+            ChangeType::Add
+        }
+    };
+
+    Ok(Some(change_type))
+}
@@ -0,0 +1,108 @@
+use crate::config::Config;
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::excludes::ExcludeRules;
+use crate::hash::HashAlgo;
+use crate::prompts::PromptPolicy;
+use crate::roots::Root;
+use crate::scan_machine::{do_scan_machine, OfflinePolicy, ScanOptions};
+use crate::scans::Scan;
+
+/// Scans a root from another program, as `fspulse scan --root-path` does from the command
+/// line. The root is registered if it isn't known yet. Nothing is drawn or printed, and
+/// nothing is asked: a scan which would need confirmation fails instead, as do attempts
+/// to scan a root whose last scan didn't complete.
+///
+/// ```no_run
+/// use fspulse::{Database, Scanner};
+///
+/// let mut db = Database::new(None)?;
+/// let scan = Scanner::new("/data/photos").deep(true).exclude("*.tmp").run(&mut db)?;
+/// println!("{} files added", scan.change_counts().add_count);
+/// # Ok::<(), fspulse::FsPulseError>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Scanner {
+    path: String,
+    options: ScanOptions,
+    exclude: Vec<String>,
+    include: Vec<String>,
+    respect_gitignore: bool,
+}
+
+impl Scanner {
+    pub fn new(path: impl Into<String>) -> Self {
+        Scanner {
+            path: path.into(),
+            options: ScanOptions { prompts: PromptPolicy::NonInteractive, quiet: true, no_report: true, ..ScanOptions::default() },
+            exclude: Vec::new(),
+            include: Vec::new(),
+            respect_gitignore: false,
+        }
+    }
+
+    /// Hash files, to find changes to their content as well as their metadata
+    pub fn deep(mut self, deep: bool) -> Self {
+        self.options.hash = deep;
+        self
+    }
+
+    /// With `deep`, hash only files which are new or whose metadata changed, as `--deep-delta` does
+    pub fn delta(mut self, delta: bool) -> Self {
+        self.options.hash_delta = delta;
+        self
+    }
+
+    /// The algorithm files are hashed with. Without one, the root's last algorithm is kept
+    pub fn hash_algo(mut self, hash_algo: HashAlgo) -> Self {
+        self.options.hash_algo = Some(hash_algo);
+        self
+    }
+
+    /// Skip paths matching the glob, as `--exclude` does. May be called more than once
+    pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+        self.exclude.push(glob.into());
+        self
+    }
+
+    /// Scan paths matching the glob even though they'd be excluded, as `--include` does
+    pub fn include(mut self, glob: impl Into<String>) -> Self {
+        self.include.push(glob.into());
+        self
+    }
+
+    /// Skip paths ignored by the `.gitignore` and `.ignore` files found in the tree
+    pub fn respect_gitignore(mut self, respect_gitignore: bool) -> Self {
+        self.respect_gitignore = respect_gitignore;
+        self
+    }
+
+    /// How files whose content is held elsewhere, such as cloud placeholders, are treated.
+    /// By default they're recorded without being hashed
+    pub fn offline_files(mut self, policy: OfflinePolicy) -> Self {
+        self.options.offline_files = policy;
+        self
+    }
+
+    /// Record a scan which finds no items as deleting everything, rather than failing
+    pub fn allow_empty(mut self, allow_empty: bool) -> Self {
+        self.options.allow_empty = allow_empty;
+        self
+    }
+
+    /// Runs the scan, returning it once it's completed. Its changes can be read with
+    /// `Change::changes_in_scan`, and the report `fspulse scan` prints with
+    /// `Reports::scan`
+    pub fn run(&self, db: &mut Database) -> Result<Scan, FsPulseError> {
+        let options = ScanOptions { hash_delta: self.options.hash && self.options.hash_delta, ..self.options };
+        let rules = ExcludeRules::new(self.exclude.clone(), self.include.clone(), self.respect_gitignore);
+        do_scan_machine(db, None, Some(self.path.clone()), false, options, &rules, &Config::default())?;
+
+        let root_path = Root::validate_and_canonicalize_path(&self.path)?;
+        let root = Root::get_by_directory(db, &root_path)?
+            .ok_or_else(|| FsPulseError::Error(format!("Root '{}' not found", self.path)))?;
+
+        Scan::get_latest_for_root(db, root.id())?
+            .ok_or_else(|| FsPulseError::Error(format!("No scan of '{}' was recorded", self.path)))
+    }
+}
@@ -1,33 +1,36 @@
-use crate::changes::{ ChangeCounts, ChangeType };
+use crate::changes::ChangeCounts;
 use crate::error::FsPulseError;
 use crate::database::Database;
-use crate::hash::Hash;
-use crate::items::ItemType;
-use crate::reports::{ReportFormat, Reports};
+use crate::hash::{HashAlgo, HashSettings};
 use crate::roots::Root;
 
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use rusqlite::{ OptionalExtension, Result, params };
+use crate::utils::Utils;
+
+use md5::{Digest, Md5};
+use rusqlite::{ OptionalExtension, Result, Row, TransactionBehavior, params };
 
-use std::collections::VecDeque;
 use std::{env, fmt};
-use std::fs;
-use std::fs::Metadata;
+use std::collections::BTreeMap;
 use std::path::Path;
-use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 const SQL_SCAN_ID_OR_LATEST: &str = 
-    "SELECT id, root_id, state, hashing, validating, time_of_scan, file_count, folder_count
+    "SELECT id, root_id, state, hashing, validating, time_of_scan, file_count, folder_count, empty_guardrail, digest, confirm_threshold, hash_confirmations, hash_collisions, hash_delta, excluded_count, root_scan_number, budget_secs, dirs_checked, dirs_unchecked, hash_concurrency, hash_buffer_size, db_size_start, db_growth, hash_algo
         FROM scans
         WHERE id = IFNULL(?1, (SELECT MAX(id) FROM scans))";
 
 const SQL_LATEST_FOR_ROOT: &str = 
-    "SELECT id, root_id, state, hashing, validating, time_of_scan, file_count, folder_count
+    "SELECT id, root_id, state, hashing, validating, time_of_scan, file_count, folder_count, empty_guardrail, digest, confirm_threshold, hash_confirmations, hash_collisions, hash_delta, excluded_count, root_scan_number, budget_secs, dirs_checked, dirs_unchecked, hash_concurrency, hash_buffer_size, db_size_start, db_growth, hash_algo
         FROM scans
         WHERE root_id = ?
         ORDER BY id DESC LIMIT 1";
 
+const SQL_BY_ROOT_SCAN_NUMBER: &str =
+    "SELECT id FROM scans WHERE root_id = ? AND root_scan_number = ?";
+
+const SQL_BY_ROOT_SCAN_OFFSET: &str =
+    "SELECT id FROM scans WHERE root_id = ? ORDER BY root_scan_number DESC LIMIT 1 OFFSET ?";
+
 #[derive(Copy, Clone, Debug, Default)]
 pub struct Scan {
     // Schema fields
@@ -35,13 +38,98 @@ pub struct Scan {
     root_id: i64,
     state: ScanState,
     hashing: bool,
+    hash_delta: bool,
     validating: bool,
     time_of_scan: i64,
     file_count: Option<i64>,
     folder_count: Option<i64>,
+    empty_guardrail: Option<EmptyGuardrail>,
+    digest: Option<[u8; 16]>,
+    confirm_threshold: Option<i64>,
+    hash_confirmations: i64,
+    hash_collisions: i64,
+    excluded_count: i64,
+    root_scan_number: i64,
+    budget_secs: Option<i64>,
+    dirs_checked: Option<i64>,
+    dirs_unchecked: i64,
+    hash_settings: Option<HashSettings>,
+    db_size_start: Option<i64>,
+    db_growth: Option<i64>,
+    hash_algo: HashAlgo,
     
     // Scan state
     change_counts: ChangeCounts,
+    deadline: Option<Instant>,
+}
+
+/// Why a scan passed over a path
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExclusionReason {
+    /// The item was hidden with `fspulse ignore`
+    Ignored,
+    /// The folder holds a per-project database
+    Database,
+    /// The file is a checksum file written by a hashing scan
+    Checksum,
+    /// The entry couldn't be read. Nothing beneath an unreadable directory is checked
+    Unreadable,
+    /// The path matches a glob given with --exclude
+    Excluded,
+    /// The path is ignored by a `.gitignore` or `.ignore` file, with --respect-gitignore
+    Gitignore,
+    /// The file's content is held elsewhere, with --offline-files skip
+    Offline,
+}
+
+impl ExclusionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExclusionReason::Ignored => "ignored",
+            ExclusionReason::Database => "database",
+            ExclusionReason::Checksum => "checksum",
+            ExclusionReason::Unreadable => "unreadable",
+            ExclusionReason::Excluded => "excluded",
+            ExclusionReason::Gitignore => "gitignore",
+            ExclusionReason::Offline => "offline",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "ignored" => Some(ExclusionReason::Ignored),
+            "database" => Some(ExclusionReason::Database),
+            "checksum" => Some(ExclusionReason::Checksum),
+            "unreadable" => Some(ExclusionReason::Unreadable),
+            "excluded" => Some(ExclusionReason::Excluded),
+            "gitignore" => Some(ExclusionReason::Gitignore),
+            "offline" => Some(ExclusionReason::Offline),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExclusionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A path, relative to the root, which a scan passed over. Nothing beneath an excluded
+/// directory is visited, so only the directory itself is listed
+#[derive(Clone, Debug)]
+pub struct Exclusion {
+    pub path: String,
+    pub reason: ExclusionReason,
+}
+
+/// Totals across a set of scans, shown beneath a table of scans
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ScanTotals {
+    pub scans: i64,
+    pub files: i64,
+    pub folders: i64,
+    pub change_counts: ChangeCounts,
 }
 
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -89,37 +177,82 @@ impl fmt::Display for ScanState {
     }
 }
 
-#[derive(Clone, Debug)]
-struct QueueEntry {
-    path: PathBuf,
-    metadata: fs::Metadata,
+/// How a scan proceeded after finding no items in a root where items previously existed
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(i64)]
+pub enum EmptyGuardrail {
+    AllowedByFlag = 1,
+    Confirmed = 2,
+    Refused = 3,
+}
+
+impl EmptyGuardrail {
+    pub fn from_i64(value: i64) -> Option<Self> {
+        match value {
+            1 => Some(EmptyGuardrail::AllowedByFlag),
+            2 => Some(EmptyGuardrail::Confirmed),
+            3 => Some(EmptyGuardrail::Refused),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> i64 {
+        *self as i64
+    }
+}
+
+impl fmt::Display for EmptyGuardrail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EmptyGuardrail::AllowedByFlag => "Allowed (--allow-empty or --yes)",
+            EmptyGuardrail::Confirmed => "Confirmed by user",
+            EmptyGuardrail::Refused => "Refused",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 impl Scan {
     // Create a Scan that will be used during a directory scan
     // In this case, the scan_id is not yet known
-    fn new_for_scan(id: i64, root_id: i64, state: ScanState, hashing: bool, validating: bool, time_of_scan: i64) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    fn new_for_scan(id: i64, root_id: i64, root_scan_number: i64, state: ScanState, hashing: bool, hash_delta: bool, validating: bool, confirm_threshold: Option<i64>, budget_secs: Option<i64>, hash_algo: HashAlgo, db_size_start: i64, time_of_scan: i64) -> Self {
         Scan {
             id,
             root_id,
+            root_scan_number,
+            budget_secs,
+            db_size_start: Some(db_size_start),
             state,
             hashing,
+            hash_delta,
             validating,
+            confirm_threshold,
+            hash_algo,
             time_of_scan,
             ..Default::default()
         }
     }
 
-    pub fn create(db: &Database, root: &Root, hashing: bool, validating: bool) -> Result<Self, FsPulseError> {
-        let (scan_id, time_of_scan): (i64, i64) = db.conn.query_row(
-            "INSERT INTO scans (root_id, state, hashing, validating, time_of_scan) 
-             VALUES (?, ?, ?, ?, strftime('%s', 'now', 'utc')) 
-             RETURNING id, time_of_scan",
-            [root.id(), ScanState::Scanning.as_i64(), hashing as i64, validating as i64],
-            |row| Ok((row.get(0)?, row.get(1)?)),
+    /// Creates a new scan of the root. With `hash_delta`, a hashing scan only hashes files
+    /// which are new or whose metadata changed, and trusts the hashes of all other files.
+    /// With `budget_secs`, the scan stops reading directories and hashing files once it
+    /// has run that long. A hashing scan hashes files with `hash_algo`
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(db: &Database, root: &Root, hashing: bool, hash_delta: bool, validating: bool, confirm_threshold: Option<i64>, budget_secs: Option<i64>, hash_algo: HashAlgo) -> Result<Self, FsPulseError> {
+        // The database's growth during the scan is measured from here
+        let db_size_start = db.size() as i64;
+
+        let (scan_id, time_of_scan, root_scan_number): (i64, i64, i64) = db.conn.query_row(
+            "INSERT INTO scans (root_id, state, hashing, hash_delta, validating, confirm_threshold, budget_secs, db_size_start, hash_algo, time_of_scan, root_scan_number) 
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, strftime('%s', 'now', 'utc'),
+                (SELECT COALESCE(MAX(root_scan_number), 0) + 1 FROM scans WHERE root_id = ?1)) 
+             RETURNING id, time_of_scan, root_scan_number",
+            params![root.id(), ScanState::Scanning.as_i64(), hashing, hash_delta, validating, confirm_threshold, budget_secs, db_size_start, hashing.then(|| hash_algo.as_str())],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
         )?;
     
-        let scan = Scan::new_for_scan(scan_id, root.id(), ScanState::Scanning, false, false, time_of_scan);
+        let scan = Scan::new_for_scan(scan_id, root.id(), root_scan_number, ScanState::Scanning, hashing, hash_delta, validating, confirm_threshold, budget_secs, hash_algo, db_size_start, time_of_scan);
         Ok(scan)
     }
 
@@ -135,6 +268,74 @@ impl Scan {
         Self::get_by_id_or_latest(db, None, Some(root_id))
     }
 
+    /// The most recent scan of the root to have completed, which was started at or before
+    /// the time
+    pub fn get_latest_completed_at(db: &Database, root_id: i64, time: i64) -> Result<Option<Self>, FsPulseError> {
+        let scan_id: Option<i64> = db.conn.query_row(
+            "SELECT id FROM scans WHERE root_id = ? AND state = ? AND time_of_scan <= ? ORDER BY id DESC LIMIT 1",
+            (root_id, ScanState::Completed.as_i64(), time),
+            |row| row.get(0),
+        ).optional()?;
+
+        scan_id.map(|scan_id| Self::get_by_id(db, scan_id)).transpose().map(Option::flatten)
+    }
+
+    /// Finds a scan given on the command line: either a scan id, or a root (by id or
+    /// path, optionally preceded by "root:") and a position within that root, separated
+    /// by '@'. The position is the scan's number within the root, or counts back from the
+    /// root's latest scan: `-1` or `HEAD` is the latest, `-2` or `HEAD~1` the one before.
+    /// `HEAD` and `HEAD~N` on their own refer to the root containing the current directory
+    pub fn find_by_ref(db: &Database, scan_ref: &str) -> Result<Self, FsPulseError> {
+        let (root, position) = match scan_ref.rsplit_once('@') {
+            Some((root_arg, position)) => (Root::find_by_id_or_path(db, root_arg.strip_prefix("root:").unwrap_or(root_arg))?, position),
+            None if scan_ref.starts_with("HEAD") => {
+                let cwd = env::current_dir()?.canonicalize()?;
+                let root = Root::find_containing(db, &cwd)?
+                    .ok_or_else(|| FsPulseError::Error(format!(
+                        "The current directory isn't inside a root. Use ROOT@{} to name the root", scan_ref)))?;
+                (root, scan_ref)
+            },
+            None => {
+                let scan_id = scan_ref.parse::<i64>()
+                    .map_err(|_| FsPulseError::Error(format!("Invalid scan '{}'. Use a scan id, ROOT@NUMBER or ROOT@HEAD~N", scan_ref)))?;
+                return Self::get_by_id(db, scan_id)?
+                    .ok_or_else(|| FsPulseError::Error(format!("Scan Id {} not found", scan_id)));
+            },
+        };
+
+        let (sql, param) = Self::parse_position(position).ok_or_else(|| FsPulseError::Error(format!(
+            "Invalid scan position '{}'. Use a number from 1, a number from -1 for the latest scan, or HEAD~N", position)))?;
+
+        let scan_id: Option<i64> = db.conn.query_row(sql, params![root.id(), param], |row| row.get(0)).optional()?;
+
+        scan_id.map(|scan_id| Self::get_by_id(db, scan_id)).transpose()?.flatten()
+            .ok_or_else(|| FsPulseError::Error(format!("Scan '{}' not found", scan_ref)))
+    }
+
+    // The query finding a scan at a position within its root, and its parameter
+    fn parse_position(position: &str) -> Option<(&'static str, i64)> {
+        if let Some(back) = position.strip_prefix("HEAD") {
+            let offset = match back.strip_prefix('~') {
+                Some("") => 1,
+                Some(count) => count.parse::<i64>().ok().filter(|count| *count >= 0)?,
+                None if back.is_empty() => 0,
+                None => return None,
+            };
+            return Some((SQL_BY_ROOT_SCAN_OFFSET, offset));
+        }
+
+        match position.parse::<i64>().ok()? {
+            0 => None,
+            number if number > 0 => Some((SQL_BY_ROOT_SCAN_NUMBER, number)),
+            number => Some((SQL_BY_ROOT_SCAN_OFFSET, number.checked_neg()? - 1)),
+        }
+    }
+
+    /// The id of a scan given on the command line, as for `find_by_ref`
+    pub fn id_by_ref(db: &Database, scan_ref: &str) -> Result<i64, FsPulseError> {
+        Ok(Self::find_by_ref(db, scan_ref)?.id)
+    }
+
     fn get_by_id_or_latest(db: &Database, scan_id: Option<i64>, root_id: Option<i64>) -> Result<Option<Self>, FsPulseError> {
         let conn = &db.conn;
 
@@ -146,30 +347,57 @@ impl Scan {
 
         // If the scan id wasn't explicitly specified, load the most recent otherwise,
         // load the specified scan
-        let scan_row: Option<(i64, i64, i64, bool, bool, i64, Option<i64>, Option<i64>)> = conn.query_row(
-            query,
-            params![query_param],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?)),
-        )
-        .optional()?;
+        let scan = conn.query_row(query, params![query_param], Scan::from_row).optional()?;
 
-        scan_row.map(|(id, root_id, state, hashing, validating, time_of_scan, file_count, folder_count)| {
-            let change_counts = ChangeCounts::get_by_scan_id(db, id)?;
-            Ok(Scan {
-                id,
-                root_id,
-                state: ScanState::from_i64(state),
-                hashing,
-                validating,
-                time_of_scan,
-                file_count,
-                folder_count,
-                change_counts,
-            })
+        scan.map(|mut scan| {
+            scan.change_counts = ChangeCounts::get_by_scan_id(db, scan.id)?;
+            Ok(scan)
         })
         .transpose()
     }
 
+    // Reads the scan's schema fields from the first columns of the row. Change
+    // counts are left at their defaults
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Scan {
+            id: row.get::<_, i64>(0)?,                              // scan id
+            root_id: row.get::<_, i64>(1)?,                         // root id
+            state: ScanState::from_i64(row.get::<_, i64>(2)?),      // state
+            hashing: row.get::<_, bool>(3)?,                        // hashing
+            validating: row.get::<_, bool>(4)?,                     // validating
+            time_of_scan: row.get::<_, i64>(5)?,                    // time of scan
+            file_count: row.get::<_, Option<i64>>(6)?,              // file count
+            folder_count: row.get::<_, Option<i64>>(7)?,            // folder count
+            empty_guardrail: row.get::<_, Option<i64>>(8)?.and_then(EmptyGuardrail::from_i64),
+            digest: row.get::<_, Option<String>>(9)?                // digest
+                .and_then(|digest| hex::decode(digest).ok())
+                .and_then(|digest| digest.try_into().ok()),
+            confirm_threshold: row.get::<_, Option<i64>>(10)?,      // confirm threshold
+            hash_confirmations: row.get::<_, i64>(11)?,             // hash confirmations
+            hash_collisions: row.get::<_, i64>(12)?,                // hash collisions
+            hash_delta: row.get::<_, bool>(13)?,                    // hash delta
+            excluded_count: row.get::<_, i64>(14)?,                 // excluded count
+            root_scan_number: row.get::<_, i64>(15)?,               // root scan number
+            budget_secs: row.get::<_, Option<i64>>(16)?,            // budget
+            dirs_checked: row.get::<_, Option<i64>>(17)?,           // directories checked
+            dirs_unchecked: row.get::<_, i64>(18)?,                 // directories unchecked
+            hash_settings: match (row.get::<_, Option<i64>>(19)?, row.get::<_, Option<i64>>(20)?) {
+                (Some(concurrency), Some(buffer_size)) => Some(HashSettings {
+                    concurrency: concurrency.max(1) as usize,
+                    buffer_size: buffer_size.max(1) as usize,
+                }),
+                _ => None,
+            },
+            db_size_start: row.get::<_, Option<i64>>(21)?,          // database size at start
+            db_growth: row.get::<_, Option<i64>>(22)?,              // database growth
+            hash_algo: row.get::<_, Option<String>>(23)?            // hash algorithm
+                .and_then(|algo| algo.parse().ok())
+                .unwrap_or_default(),
+            change_counts: ChangeCounts::default(),
+            deadline: None,
+        })
+    }
+
     pub fn id(&self) -> i64 {
         self.id
     }
@@ -178,6 +406,11 @@ impl Scan {
         self.root_id
     }
 
+    /// The scan's number among the scans of its root, counting from 1
+    pub fn root_scan_number(&self) -> i64 {
+        self.root_scan_number
+    }
+
     pub fn state(&self) -> ScanState {
         self.state
     }
@@ -186,6 +419,15 @@ impl Scan {
         self.hashing
     }
 
+    pub fn hash_delta(&self) -> bool {
+        self.hash_delta
+    }
+
+    /// The algorithm files are hashed with, if the scan hashes
+    pub fn hash_algo(&self) -> HashAlgo {
+        self.hash_algo
+    }
+
     pub fn validating(&self) -> bool {
         self.validating
     }
@@ -202,366 +444,441 @@ impl Scan {
         self.folder_count
     }
 
+    pub fn empty_guardrail(&self) -> Option<EmptyGuardrail> {
+        self.empty_guardrail
+    }
+
+    pub fn confirm_threshold(&self) -> Option<i64> {
+        self.confirm_threshold
+    }
+
+    pub fn hash_confirmations(&self) -> i64 {
+        self.hash_confirmations
+    }
+
+    pub fn hash_collisions(&self) -> i64 {
+        self.hash_collisions
+    }
+
+    pub fn digest(&self) -> Option<String> {
+        self.digest.map(hex::encode)
+    }
+
     pub fn change_counts(&self) -> &ChangeCounts {
         &self.change_counts
     }
 
+    /// How the file and folder counts of this scan differ from those of the previous
+    /// completed scan of the same root. None if there is no previous scan or this scan
+    /// hasn't completed. Scans which left directories unchecked didn't count everything,
+    /// so they're left out of the comparison
+    pub fn count_deltas(&self, db: &Database) -> Result<Option<(i64, i64)>, FsPulseError> {
+        let deltas = db.conn.query_row(
+            "SELECT s.file_count - p.file_count, s.folder_count - p.folder_count
+                FROM scans s
+                JOIN scans p ON p.id = (SELECT MAX(id) FROM scans WHERE root_id = s.root_id AND id < s.id AND state = ?2 AND dirs_unchecked = 0)
+                WHERE s.id = ?1 AND s.dirs_unchecked = 0 AND s.file_count IS NOT NULL AND p.file_count IS NOT NULL",
+            (self.id, ScanState::Completed.as_i64()),
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
 
-    pub fn abort(&mut self, db: &mut Database) -> Result<(), FsPulseError> {
-        match self.state {
-            ScanState::Scanning | ScanState::Sweeping | ScanState::Analyzing => {
-                self.set_state(db, ScanState::Aborted)
-            }
-            _ => Err(FsPulseError::Error(format!("Can't abort scan - invalid state {}", self.state.as_i64())))
-        }
+        Ok(deltas)
     }
 
-    fn set_state(&mut self, db: &mut Database, new_state: ScanState) -> Result<(), FsPulseError> {
-        let conn = &mut db.conn;
+    /// Whether an earlier scan of the same root completed, giving this scan something to
+    /// compare with. The first completed scan of a root finds every item added
+    pub fn has_completed_predecessor(&self, db: &Database) -> Result<bool, FsPulseError> {
+        let exists = db.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM scans WHERE root_id = ? AND id < ? AND state = ?)",
+            (self.root_id, self.id, ScanState::Completed.as_i64()),
+            |row| row.get(0),
+        )?;
 
-        let rows_updated = conn.execute(
-            "UPDATE scans SET state = ? WHERE id = ?", 
-            [new_state.as_i64(), self.id]
+        Ok(exists)
+    }
+
+    /// The id of the root's last scan to complete before this one, if any
+    pub fn previous_completed_id(&self, db: &Database) -> Result<Option<i64>, FsPulseError> {
+        let scan_id = db.conn.query_row(
+            "SELECT MAX(id) FROM scans WHERE root_id = ? AND id < ? AND state = ?",
+            (self.root_id, self.id, ScanState::Completed.as_i64()),
+            |row| row.get(0),
         )?;
 
-        if rows_updated == 0 {
-            return Err(FsPulseError::Error(
-                format!("Could not update the state of Scan Id {} to {}", self.id, new_state.as_i64())));
+        Ok(scan_id)
+    }
+
+    /// The number of paths the scan passed over because they were excluded
+    pub fn excluded_count(&self) -> i64 {
+        self.excluded_count
+    }
+
+    /// Records how many paths the scan excluded for each reason and, if they were collected,
+    /// which paths. Replaces what an interrupted attempt at the same scan recorded
+    pub fn set_exclusions(
+        &mut self,
+        db: &mut Database,
+        counts: &BTreeMap<ExclusionReason, i64>,
+        exclusions: &[Exclusion],
+    ) -> Result<(), FsPulseError> {
+        let excluded_count = counts.values().sum();
+        let tx = db.conn.transaction()?;
+
+        tx.execute("UPDATE scans SET excluded_count = ? WHERE id = ?", (excluded_count, self.id))?;
+        tx.execute("DELETE FROM scan_exclusions WHERE scan_id = ?", [self.id])?;
+        tx.execute("DELETE FROM scan_exclusion_counts WHERE scan_id = ?", [self.id])?;
+
+        {
+            let mut stmt = tx.prepare("INSERT INTO scan_exclusion_counts (scan_id, reason, count) VALUES (?, ?, ?)")?;
+            for (reason, count) in counts {
+                stmt.execute((self.id, reason.as_str(), count))?;
+            }
         }
 
-        self.state = new_state;
+        {
+            let mut stmt = tx.prepare("INSERT INTO scan_exclusions (scan_id, path, reason) VALUES (?, ?, ?)")?;
+            for exclusion in exclusions {
+                stmt.execute((self.id, &exclusion.path, exclusion.reason.as_str()))?;
+            }
+        }
+
+        tx.commit()?;
+        self.excluded_count = excluded_count;
 
         Ok(())
     }
 
-    pub fn do_scan(
-        db: &mut Database, 
-        root_id: Option<u32>, 
-        root_path: Option<String>, 
-        last: bool, 
-        hash: bool,
-        validate: bool
-    ) -> Result<Scan, FsPulseError> {
-        let path = match (last, root_id, root_path) {
-            (_, Some(root_id), _) => {
-                Root::get_by_id(db, root_id.into())?
-                    .ok_or_else(|| FsPulseError::Error(format!("Root {} not found", root_id)))?
-                    .path()
-                    .to_string()
-            }
-            (true, None, _) => {
-                let scan = Self::get_latest(db)?
-                    .ok_or_else(|| FsPulseError::Error("No latest scan found".into()))?;
-                Root::get_by_id(db, scan.root_id())?
-                    .ok_or_else(|| FsPulseError::Error("Root not found".into()))?
-                    .path()
-                    .to_string()
+    /// How many paths the scan excluded for each reason. Scans made before the counts were
+    /// recorded by reason have none, though they may have a total
+    pub fn exclusion_counts(&self, db: &Database) -> Result<Vec<(ExclusionReason, i64)>, FsPulseError> {
+        let mut stmt = db.conn.prepare(
+            "SELECT reason, count FROM scan_exclusion_counts WHERE scan_id = ? AND count > 0"
+        )?;
+
+        let rows = stmt.query_map([self.id], |row| {
+            let reason: String = row.get(0)?;
+            Ok((ExclusionReason::from_str(&reason), row.get::<_, i64>(1)?))
+        })?;
+
+        let mut counts = Vec::new();
+        for row in rows {
+            if let (Some(reason), count) = row? {
+                counts.push((reason, count));
             }
-            (false, None, Some(p)) => p,
-            (false, None, None) => return Err(FsPulseError::Error("No path specified".into())),
-        };
-        
-        let (scan, _root) = Scan::scan_directory(db, &path, hash, validate)?;
-        Reports::print_scan(db, &Some(scan), ReportFormat::Table)?;
+        }
+        counts.sort();
 
-        Ok(scan)
+        Ok(counts)
     }
 
-    fn path_arg_to_canonical_path_buf(path_arg: &str) -> Result<PathBuf, FsPulseError> {
-        if path_arg.is_empty() {
-            return Err(FsPulseError::Error("Provided path is empty".into()));
-        }
+    /// Calls `func` with each path the scan recorded as excluded
+    pub fn for_each_exclusion<F>(&self, db: &Database, mut func: F) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&Exclusion) -> Result<(), FsPulseError>,
+    {
+        let mut stmt = db.conn.prepare(
+            "SELECT path, reason FROM scan_exclusions WHERE scan_id = ? ORDER BY path ASC"
+        )?;
 
-        let path = Path::new(path_arg);
+        let rows = stmt.query_map([self.id], |row| {
+            let reason: String = row.get(1)?;
+            Ok(Exclusion {
+                path: row.get(0)?,
+                reason: ExclusionReason::from_str(&reason).unwrap_or(ExclusionReason::Ignored),
+            })
+        })?;
 
-        let absolute_path = if path.is_absolute() {
-            path.to_owned() 
-        } else {
-            env::current_dir()?.join(path)
-        };
-        
-        if !absolute_path.exists() {
-            return Err(FsPulseError::Error(format!("Path '{}' does not exist", absolute_path.display())));
+        for row in rows {
+            func(&row?)?;
         }
-    
-        let metadata = fs::symlink_metadata(&absolute_path)?;
 
-        if metadata.file_type().is_symlink() {
-            return Err(FsPulseError::Error(format!("Path '{}' is a symlink and not allowed", absolute_path.display())));
-        }
-        
-        if !metadata.is_dir() {
-            return Err(FsPulseError::Error(format!("Path '{}' is not a directory", absolute_path.display())));
-        }
+        Ok(())
+    }
 
-        let canonical_path = absolute_path.canonicalize()?;
-    
-        Ok(canonical_path)
+    /// The time the scan was allowed, in seconds, if it was budgeted
+    pub fn budget_secs(&self) -> Option<i64> {
+        self.budget_secs
     }
 
-    fn scan_directory(db: &mut Database, path: &str, hash: bool, validate: bool) -> Result<(Self, Root), FsPulseError> {
-        let (mut scan, root) = Self::begin_scan(db, path, hash, validate)?;
-        let root_path_buf = PathBuf::from(root.path());
-        let metadata = fs::symlink_metadata(&root_path_buf)?;
+    /// The number of directories whose entries the scan read, if it was budgeted
+    pub fn dirs_checked(&self) -> Option<i64> {
+        self.dirs_checked
+    }
 
-        let mut q = VecDeque::new();
+    /// The number of directories a budgeted scan didn't get to
+    pub fn dirs_unchecked(&self) -> i64 {
+        self.dirs_unchecked
+    }
 
-        
-        let multi = MultiProgress::new();
-        multi.println(format!("Scanning: {}", &path))?;
-        let dir_bar = multi.add(ProgressBar::new_spinner());
-        dir_bar.enable_steady_tick(Duration::from_millis(100));
-        let item_bar = multi.add(ProgressBar::new_spinner());
-        item_bar.enable_steady_tick(Duration::from_millis(100));
-
-        let mut progress_bar = if hash {
-            let bar = ProgressBar::new(0); // Initialize with 0 length
-        
-            // TODO: this error will panic
-            bar.set_style(ProgressStyle::default_bar()
-                .template("{msg}\n[{bar:40}] {bytes}/{total_bytes} ({eta})")
-                .unwrap()
-                .progress_chars("#>-"));
-            Some(bar)
-        } else {
-            None
-        };
-    
-        q.push_back(QueueEntry {
-            path: root_path_buf.clone(),
-            metadata,
-        });
-    
-        while let Some(q_entry) = q.pop_front() {
-            dir_bar.set_message(format!("Directory: '{}'", q_entry.path.to_string_lossy()));
-            // Update the database
-            if q_entry.path != root_path_buf {
-                let dir_change_type = scan.handle_item(db, ItemType::Directory, q_entry.path.as_path(), &q_entry.metadata, None)?;
-                scan.change_counts.increment_count_of(dir_change_type);
-            }
-    
-            let items = fs::read_dir(&q_entry.path)?;
-    
-            for item in items {
-                let item = item?;
-                let metadata = fs::symlink_metadata(item.path())?; // Use symlink_metadata to check for symlinks
-                item_bar.set_message(format!("Item: '{}'", item.file_name().to_string_lossy()));
-
-                if metadata.is_dir() {
-                    q.push_back(QueueEntry {
-                        path: item.path(),
-                        metadata,
-                    });
-                } else {
-                    let item_type = if metadata.is_file() {
-                        ItemType::File
-                    } else if metadata.is_symlink() {
-                        ItemType::Symlink
-                    } else {
-                        ItemType::Other
-                    };
-    
-                    // println!("{:?}: {}", item_type, item.path().display());
-                    let mut hash = None;
-                    match (item_type, progress_bar.as_mut()) {
-                        (ItemType::File, Some(ref progress_bar)) => {
-                            hash = match Hash::compute_md5_hash(&item.path(), progress_bar) {
-                                Ok(hash_s) => Some(hash_s),
-                                Err(error) => {
-                                    eprintln!("Error computing hash: {}", error);
-                                    None
-                                }
-                            }
-                        },
-                        _ => { // nothing to do
-                        },
-                    }
-
-                    let file_change_type = scan.handle_item(db, item_type, &item.path(), &metadata, hash.as_deref())?;
-                    scan.change_counts.increment_count_of(file_change_type);
-                }
+    /// Starts the clock on the scan's budget, if it has one. A resumed scan gets its
+    /// whole budget again
+    pub fn start_budget(&mut self) {
+        self.deadline = self.budget_secs.map(|secs| Instant::now() + Duration::from_secs(secs.max(0) as u64));
+    }
+
+    /// Whether the scan has used up its budget
+    pub fn budget_exhausted(&self) -> bool {
+        self.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    /// Records how many directories a budgeted scan read and which it didn't get to.
+    /// Replaces what an interrupted attempt at the same scan recorded
+    pub fn set_coverage(&mut self, db: &mut Database, dirs_checked: i64, unchecked: &[String]) -> Result<(), FsPulseError> {
+        let tx = db.conn.transaction()?;
+
+        tx.execute(
+            "UPDATE scans SET dirs_checked = ?, dirs_unchecked = ? WHERE id = ?",
+            (dirs_checked, unchecked.len() as i64, self.id),
+        )?;
+        tx.execute("DELETE FROM unchecked_dirs WHERE scan_id = ?", [self.id])?;
+
+        {
+            let mut stmt = tx.prepare("INSERT INTO unchecked_dirs (scan_id, path) VALUES (?, ?)")?;
+            for path in unchecked {
+                stmt.execute((self.id, path))?;
             }
         }
-        scan.end_scan(db)?;
-    
-        Ok((scan, root))
-    }
 
-    fn begin_scan(db: &mut Database, path_arg: &str, hashing: bool, validating: bool) -> Result<(Self, Root), FsPulseError> {
-        let path_canonical = Self::path_arg_to_canonical_path_buf(&path_arg)?;
-        let root_path_str = path_canonical.to_string_lossy().to_string();
+        tx.commit()?;
+        self.dirs_checked = Some(dirs_checked);
+        self.dirs_unchecked = unchecked.len() as i64;
 
-        let root = Root::get_or_insert(db, &root_path_str)?;
-        let root_id = root.id();
+        Ok(())
+    }
 
-        let (scan_id, time_of_scan): (i64, i64) = db.conn.query_row(
-            "INSERT INTO scans (root_id, state, hashing, validating, time_of_scan) 
-             VALUES (?, ?, ?, ?, strftime('%s', 'now', 'utc')) 
-             RETURNING id, time_of_scan",
-            [root_id, ScanState::Scanning.as_i64(), hashing as i64, validating as i64],
-            |row| Ok((row.get(0)?, row.get(1)?)),
+    /// The hash settings chosen by tuning, if the scan tuned hashing
+    pub fn hash_settings(&self) -> Option<HashSettings> {
+        self.hash_settings
+    }
+
+    /// Records the hash settings chosen by tuning
+    pub fn set_hash_settings(&mut self, db: &Database, settings: HashSettings) -> Result<(), FsPulseError> {
+        db.conn.execute(
+            "UPDATE scans SET hash_concurrency = ?, hash_buffer_size = ? WHERE id = ?",
+            (settings.concurrency as i64, settings.buffer_size as i64, self.id),
         )?;
+        self.hash_settings = Some(settings);
 
-        let scan = Scan::new_for_scan(scan_id, root_id, ScanState::Scanning, false, false, time_of_scan);
-        Ok((scan, root))
+        Ok(())
     }
 
-    fn handle_item(
-        &mut self, 
-        db: &mut Database,
-        item_type: ItemType, 
-        path: &Path, 
-        metadata: &Metadata, 
-        file_hash: Option<&str>
-    ) -> Result<ChangeType, FsPulseError> {
-        let path_str = path.to_string_lossy();
-        let scan_id = self.id;
-        let root_id = self.root_id;
+    /// The size of the database when the scan started, if it was measured
+    pub fn db_size_start(&self) -> Option<i64> {
+        self.db_size_start
+    }
 
-        let conn = &mut db.conn;
-    
-        // Determine timestamps and file size
-        let last_modified = metadata.modified()
-            .ok()
-            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
-            .map(|d| d.as_secs() as i64);
-        let file_size = if metadata.is_file() { Some(metadata.len() as i64) } else { None };
-    
-        // Check if the item already exists (fetching `id`, `is_tombstone` as well)
-        let existing_item: Option<(i64, String, Option<i64>, Option<i64>, Option<String>, bool)> = conn.query_row(
-            "SELECT id, item_type, last_modified, file_size, file_hash, is_tombstone FROM items WHERE root_id = ? AND path = ?",
-            (root_id, &path_str),
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2).ok(), row.get(3).ok(), row.get(4)?, row.get(5)?)),
+    /// How many bytes the database grew by during the scan, once it has completed. The
+    /// database can shrink if it was checkpointed during the scan
+    pub fn db_growth(&self) -> Option<i64> {
+        self.db_growth
+    }
+
+    /// How much the most recent completed scan of the root of the same kind (hashing or
+    /// not) grew the database, as an estimate of how much the next one will
+    pub fn last_db_growth(db: &Database, root_id: i64, hashing: bool) -> Result<Option<i64>, FsPulseError> {
+        let growth = db.conn.query_row(
+            "SELECT db_growth FROM scans
+                WHERE root_id = ? AND hashing = ? AND state = ? AND db_growth IS NOT NULL
+                ORDER BY id DESC LIMIT 1",
+            (root_id, hashing, ScanState::Completed.as_i64()),
+            |row| row.get(0),
         ).optional()?;
-    
-        let change_type = match existing_item {
-            Some((item_id, existing_type, existing_modified, existing_size, existing_hash, is_tombstone)) => {
-                let item_type_str = item_type.as_str();
-                let metadata_changed = existing_modified != last_modified || existing_size != file_size;
-
-                // println!("{}", Utils::string_value_or_none(&existing_hash));
-                // println!("{}", Utils::str_value_or_none(&file_hash));
-                // println!();
-
-                let hash_changed = file_hash.map_or(false, |h| Some(h) != existing_hash.as_deref());
-            
-                if is_tombstone {
-                    let tx = conn.transaction()?;
-                    tx.execute("UPDATE items SET item_type = ?, last_modified = ?, file_size = ?, file_hash = ?, last_scan_id = ?, is_tombstone = 0 WHERE id = ?", 
-                        (item_type_str, last_modified, file_size, file_hash, scan_id, item_id))?;
-                    tx.execute("INSERT INTO changes (scan_id, item_id, change_type) VALUES (?, ?, ?)", 
-                        (scan_id, item_id, ChangeType::Add.as_str()))?;
-                    tx.commit()?;
-                    ChangeType::Add
-                } else if existing_type != item_type_str {
-                    // Item type changed (e.g., file -> directory)
-                    let tx = conn.transaction()?;
-                    tx.execute("UPDATE items SET item_type = ?, last_modified = ?, file_size = ?, file_hash = ?, last_scan_id = ? WHERE id = ?", 
-                        (item_type_str, last_modified, file_size, file_hash, scan_id, item_id))?;
-                    tx.execute("INSERT INTO changes (scanhttps://docs.rs/crate/console/0.15.11/features_id, item_id, change_type) VALUES (?, ?, ?)", 
-                        (self.id, item_id, ChangeType::TypeChange.as_str()))?;
-                    tx.commit()?;
-                    ChangeType::TypeChange
-                } else if metadata_changed || hash_changed {
-                    // Item content changed
-                    let tx = conn.transaction()?;
-
-                     // TODO: this is not doing the right thing with last_hash_scan_id and last_is_valid_scan_id
-                    tx.execute("UPDATE items 
-                        SET last_modified = ?, 
-                        file_size = ?,             
-                        file_hash = ?,
-                        last_scan_id = ? 
-                        WHERE id = ?", 
-                        (last_modified, file_size, file_hash.or(existing_hash.as_deref()), scan_id, item_id))?;
-                    tx.execute("INSERT INTO changes 
-                        (scan_id, item_id, change_type, prev_last_modified, prev_file_size, prev_hash) 
-                        VALUES (?, ?, ?, ?, ?, ?)", 
-                        (
-                            scan_id, 
-                            item_id, 
-                            ChangeType::Modify.as_str(),
-                            metadata_changed.then_some(existing_modified),
-                            metadata_changed.then_some(existing_size),
-                            hash_changed.then_some(existing_hash),
-                        ))?;
-                    tx.commit()?;
-                    ChangeType::Modify
-                } else {
-                    // No change, just update last_scan_id
-                    conn.execute("UPDATE items SET last_scan_id = ? WHERE root_id = ? AND id = ?", 
-                        (scan_id, root_id, item_id))?;
-                    ChangeType::NoChange
-                }
-            }
-            None => {
-                // Item is new, insert into items and changes tables
-                let tx = conn.transaction()?;
-                tx.execute("INSERT INTO items (root_id, path, item_type, last_modified, file_size, file_hash, last_scan_id) VALUES (?, ?, ?, ?, ?, ?, ?)",
-                    (root_id, &path_str, item_type.as_str(), last_modified, file_size, file_hash, scan_id))?;
-                let item_id: i64 = tx.query_row("SELECT last_insert_rowid()", [], |row| row.get(0))?;
-                tx.execute("INSERT INTO changes (scan_id, item_id, change_type) VALUES (?, ?, ?)",
-                    (scan_id, item_id, ChangeType::Add.as_str()))?;
-                tx.commit()?;
-                ChangeType::Add
+
+        Ok(growth)
+    }
+
+    /// The algorithm the root's most recent hashing scan hashed files with
+    pub fn last_hash_algo(db: &Database, root_id: i64) -> Result<Option<HashAlgo>, FsPulseError> {
+        let algo: Option<String> = db.conn.query_row(
+            "SELECT hash_algo FROM scans
+                WHERE root_id = ? AND hash_algo IS NOT NULL
+                ORDER BY id DESC LIMIT 1",
+            [root_id],
+            |row| row.get(0),
+        ).optional()?;
+
+        algo.map(|algo| algo.parse()).transpose()
+    }
+
+    /// Calls `func` with the path of each directory the scan didn't get to, in path order
+    pub fn for_each_unchecked_dir<F>(&self, db: &Database, mut func: F) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&str) -> Result<(), FsPulseError>,
+    {
+        let mut stmt = db.conn.prepare("SELECT path FROM unchecked_dirs WHERE scan_id = ? ORDER BY path ASC")?;
+        let rows = stmt.query_map([self.id], |row| row.get::<_, String>(0))?;
+
+        for row in rows {
+            func(&row?)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn set_empty_guardrail(&mut self, db: &Database, decision: EmptyGuardrail) -> Result<(), FsPulseError> {
+        db.conn.execute(
+            "UPDATE scans SET empty_guardrail = ? WHERE id = ?",
+            [decision.as_i64(), self.id],
+        )?;
+
+        self.empty_guardrail = Some(decision);
+
+        Ok(())
+    }
+
+
+    pub fn abort(&mut self, db: &mut Database) -> Result<(), FsPulseError> {
+        match self.state {
+            ScanState::Scanning | ScanState::Sweeping | ScanState::Analyzing => {
+                self.set_state(db, ScanState::Aborted)
             }
-        };
-        
-        Ok(change_type)
+            _ => Err(FsPulseError::Error(format!("Can't abort scan - invalid state {}", self.state.as_i64())))
+        }
     }
 
-    fn end_scan(&mut self, db: &mut Database) -> Result<(), FsPulseError> {
-        let root_id = self.root_id;
+    pub fn set_state(&mut self, db: &mut Database, new_state: ScanState) -> Result<(), FsPulseError> {
+        let conn = &mut db.conn;
+
+        let rows_updated = conn.execute(
+            "UPDATE scans SET state = ? WHERE id = ?", 
+            [new_state.as_i64(), self.id]
+        )?;
+
+        if rows_updated == 0 {
+            return Err(FsPulseError::Error(
+                format!("Could not update the state of Scan Id {} to {}", self.id, new_state.as_i64())));
+        }
+
+        self.state = new_state;
+
+        Ok(())
+    }
+
+    pub fn complete(&mut self, db: &mut Database, root: &Root) -> Result<(), FsPulseError> {
         let scan_id = self.id;
+        // A scan which left directories unchecked didn't see the whole tree
+        let digest = if self.dirs_unchecked == 0 { Some(self.compute_digest(db, root)?) } else { None };
+        let db_growth = self.db_size_start.map(|start| db.size() as i64 - start);
 
         let conn = &mut db.conn;
     
-        let tx = conn.transaction()?;
-    
-        // Insert deletion records into changes
-        tx.execute(
-            "INSERT INTO changes (scan_id, item_id, change_type)
-             SELECT ?, id, ?
-             FROM items
-             WHERE root_id = ? AND is_tombstone = 0 AND last_scan_id < ?",
-            (scan_id, ChangeType::Delete.as_str(), root_id, scan_id),
-        )?;
-        
-        // Mark unseen items as tombstones
-        tx.execute(
-            "UPDATE items SET is_tombstone = 1 WHERE root_id = ? AND last_scan_id < ? AND is_tombstone = 0",
-            (root_id, scan_id),
-        )?;
+        // Reads and then writes, so take the write lock up front
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
 
-        // Step 3: Count total files and directories seen in this scan
+        // Count total files and directories seen in this scan
         let (file_count, folder_count): (i64, i64) = tx.query_row(
         "SELECT 
             SUM(CASE WHEN item_type = 'F' THEN 1 ELSE 0 END) AS file_count, 
             SUM(CASE WHEN item_type = 'D' THEN 1 ELSE 0 END) AS folder_count 
-            FROM items WHERE last_scan_id = ?",
+            FROM items WHERE last_scan_id = ? AND is_tombstone = 0",
             [scan_id],
-            |row| Ok((row.get(0)?, row.get(1)?)),
-        ).unwrap_or((0, 0)); // If no data, default to 0
+            |row| Ok((row.get::<_, Option<i64>>(0)?.unwrap_or(0), row.get::<_, Option<i64>>(1)?.unwrap_or(0))),
+        )?;
+
+        // Deletes aren't known until tombstoning is complete, so the changes are counted
+        // now and recorded with the scan, which can't change once it's completed
+        let change_counts = ChangeCounts::count_for_scan(&tx, scan_id)?;
 
         // Update the scan entity to indicate that it completed
         tx.execute(
-            "UPDATE scans SET file_count = ?, folder_count = ?, state = ? WHERE id = ?",
-            (file_count, folder_count, ScanState::Completed.as_i64(), scan_id)
+            "UPDATE scans SET file_count = ?, folder_count = ?, digest = ?, db_growth = ?,
+                add_count = ?, modify_count = ?, delete_count = ?, type_change_count = ?, move_count = ?, state = ?
+                WHERE id = ?",
+            params![
+                file_count, folder_count, digest.map(hex::encode), db_growth,
+                change_counts.add_count, change_counts.modify_count, change_counts.delete_count,
+                change_counts.type_change_count, change_counts.move_count,
+                ScanState::Completed.as_i64(), scan_id,
+            ],
+        )?;
+
+        // Confirmations are counted in the database as files are hashed
+        let (hash_confirmations, hash_collisions): (i64, i64) = tx.query_row(
+            "SELECT hash_confirmations, hash_collisions FROM scans WHERE id = ?",
+            [scan_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
         )?;
 
         tx.commit()?;
 
+        self.hash_confirmations = hash_confirmations;
+        self.hash_collisions = hash_collisions;
+
         self.file_count = Some(file_count);
         self.folder_count = Some(folder_count);
+        self.digest = digest;
+        self.db_growth = db_growth;
         self.state = ScanState::Completed;
-
-        // scan.change_counts acts as an accumulator during a scan but now we get the truth from the
-        // database. We need this to include deletes since they aren't known until tombstoning is complete
-        self.change_counts = ChangeCounts::get_by_scan_id(db, self.id)?;
+        self.change_counts = change_counts;
 
         Ok(())
     }
 
+    /// Computes a fingerprint of the root's state as of this scan from the sorted stream of
+    /// item paths (which are relative to the root), item types, sizes and, if the scan hashed files, file hashes.
+    /// Modification times are left out so that identical trees on different machines produce
+    /// the same digest. Two scans with equal digests saw the same tree
+    fn compute_digest(&self, db: &Database, root: &Root) -> Result<[u8; 16], FsPulseError> {
+        let mut stmt = db.conn.prepare(
+            "SELECT path, item_type, file_size, file_hash
+                FROM items
+                WHERE root_id = ? AND is_tombstone = 0 AND is_ignored = 0
+                ORDER BY path ASC"
+        )?;
+
+        let mut rows = stmt.query([root.id()])?;
+        let mut hasher = Md5::new();
+
+        while let Some(row) = rows.next()? {
+            let path: String = row.get(0)?;
+            let item_type: String = row.get(1)?;
+            let file_size: Option<i64> = row.get(2)?;
+            let file_hash: Option<String> = if self.hashing { row.get(3)? } else { None };
+
+            // Separators are normalized so the digest doesn't depend on the platform
+            let relative_path = Path::new(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            hasher.update(format!(
+                "{}\0{}\0{}\0{}\n",
+                relative_path,
+                item_type,
+                Utils::opt_i64_or_none_as_str(file_size),
+                file_hash.as_deref().unwrap_or("-"),
+            ));
+        }
+
+        Ok(hasher.finalize().into())
+    }
+
+    /// Totals across the most recent `last` scans: the scans listed by `for_each_scan`
+    pub fn totals_for_last(db: &Database, last: u32) -> Result<ScanTotals, FsPulseError> {
+        db.conn.query_row(
+            "SELECT
+                COUNT(*),
+                COALESCE(SUM(s.file_count), 0),
+                COALESCE(SUM(s.folder_count), 0),
+                COALESCE(SUM(COALESCE(s.add_count, (SELECT COUNT(*) FROM changes c WHERE c.scan_id = s.id AND c.change_type = 'A'))), 0),
+                COALESCE(SUM(COALESCE(s.modify_count, (SELECT COUNT(*) FROM changes c WHERE c.scan_id = s.id AND c.change_type = 'M'))), 0),
+                COALESCE(SUM(COALESCE(s.delete_count, (SELECT COUNT(*) FROM changes c WHERE c.scan_id = s.id AND c.change_type = 'D'))), 0),
+                COALESCE(SUM(COALESCE(s.type_change_count, (SELECT COUNT(*) FROM changes c WHERE c.scan_id = s.id AND c.change_type = 'T'))), 0),
+                COALESCE(SUM(COALESCE(s.move_count, (SELECT COUNT(*) FROM changes c WHERE c.scan_id = s.id AND c.change_type = 'R'))), 0)
+            FROM (SELECT id, file_count, folder_count, add_count, modify_count, delete_count, type_change_count, move_count FROM scans ORDER BY id DESC LIMIT ?1) s",
+            [last],
+            |row| Ok(ScanTotals {
+                scans: row.get(0)?,
+                files: row.get(1)?,
+                folders: row.get(2)?,
+                change_counts: ChangeCounts::new(row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?, row.get(7)?, 0),
+            }),
+        )
+        .map_err(FsPulseError::Database)
+    }
+
     pub fn for_each_scan<F>(db: &Database, last: u32, mut func: F) -> Result<i32, FsPulseError> 
     where
         F: FnMut(&Database, &Scan) -> Result<(), FsPulseError>,
@@ -570,6 +887,8 @@ impl Scan {
             return Ok(0);
         }
         
+        // Completed scans recorded their change counts. Changes are counted for the others,
+        // just for the scans returned, using the changes index
         let mut stmt = db.conn.prepare(
             "SELECT 
                 s.id,
@@ -580,34 +899,43 @@ impl Scan {
                 s.time_of_scan,
                 s.file_count,
                 s.folder_count, 
-                COALESCE(SUM(CASE WHEN c.change_type = 'A' THEN 1 ELSE 0 END), 0) AS add_count,
-                COALESCE(SUM(CASE WHEN c.change_type = 'M' THEN 1 ELSE 0 END), 0) AS modify_count,
-                COALESCE(SUM(CASE WHEN c.change_type = 'D' THEN 1 ELSE 0 END), 0) AS delete_count,
-                COALESCE(SUM(CASE WHEN c.change_type = 'T' THEN 1 ELSE 0 END), 0) AS type_change_count
+                s.empty_guardrail,
+                s.digest,
+                s.confirm_threshold,
+                s.hash_confirmations,
+                s.hash_collisions,
+                s.hash_delta,
+                s.excluded_count,
+                s.root_scan_number,
+                s.budget_secs,
+                s.dirs_checked,
+                s.dirs_unchecked,
+                s.hash_concurrency,
+                s.hash_buffer_size,
+                s.db_size_start,
+                s.db_growth,
+                s.hash_algo,
+                COALESCE(s.add_count, (SELECT COUNT(*) FROM changes c WHERE c.scan_id = s.id AND c.change_type = 'A')) AS add_count,
+                COALESCE(s.modify_count, (SELECT COUNT(*) FROM changes c WHERE c.scan_id = s.id AND c.change_type = 'M')) AS modify_count,
+                COALESCE(s.delete_count, (SELECT COUNT(*) FROM changes c WHERE c.scan_id = s.id AND c.change_type = 'D')) AS delete_count,
+                COALESCE(s.type_change_count, (SELECT COUNT(*) FROM changes c WHERE c.scan_id = s.id AND c.change_type = 'T')) AS type_change_count,
+                COALESCE(s.move_count, (SELECT COUNT(*) FROM changes c WHERE c.scan_id = s.id AND c.change_type = 'R')) AS move_count
             FROM scans s
-            LEFT JOIN changes c ON s.id = c.scan_id
-            GROUP BY s.id, s.root_id, s.state, s.hashing, s.validating, s.time_of_scan, s.file_count, s.folder_count
             ORDER BY s.id DESC
             LIMIT ?"
         )?;
 
         let rows = stmt.query_map([last], |row| {
             Ok(Scan {
-                id: row.get::<_, i64>(0)?,                              // scan id
-                root_id: row.get::<_, i64>(1)?,                         // root id
-                state: ScanState::from_i64(row.get::<_, i64>(2)?),                         // root id
-                hashing: row.get::<_, bool>(3)?,                        // hashing
-                validating: row.get::<_, bool>(4)?,                        // validating
-                time_of_scan: row.get::<_, i64>(5)?,                    // time of scan
-                file_count: row.get::<_, Option<i64>>(6)?,              // file count
-                folder_count: row.get::<_, Option<i64>>(7)?,            // folder count
                 change_counts: ChangeCounts::new(  
-                    row.get::<_, i64>(8)?,             // adds
-                    row.get::<_, i64>(9)?,          // modifies
-                    row.get::<_, i64>(10)?,          // deletes
-                    row.get::<_, i64>(11)?,    // type changes
+                    row.get::<_, i64>(24)?,             // adds
+                    row.get::<_, i64>(25)?,          // modifies
+                    row.get::<_, i64>(26)?,          // deletes
+                    row.get::<_, i64>(27)?,    // type changes
+                    row.get::<_, i64>(28)?,    // moves
                     0,
                 ),
+                ..Scan::from_row(row)?
             })
         })?;
 
@@ -621,4 +949,43 @@ impl Scan {
 
         Ok(scan_count)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn head_counts_back_from_the_latest_scan() {
+        assert_eq!(Scan::parse_position("HEAD"), Some((SQL_BY_ROOT_SCAN_OFFSET, 0)));
+        assert_eq!(Scan::parse_position("HEAD~"), Some((SQL_BY_ROOT_SCAN_OFFSET, 1)));
+        assert_eq!(Scan::parse_position("HEAD~0"), Some((SQL_BY_ROOT_SCAN_OFFSET, 0)));
+        assert_eq!(Scan::parse_position("HEAD~3"), Some((SQL_BY_ROOT_SCAN_OFFSET, 3)));
+    }
+
+    #[test]
+    fn malformed_head_is_rejected() {
+        assert_eq!(Scan::parse_position("HEAD~-1"), None);
+        assert_eq!(Scan::parse_position("HEAD~x"), None);
+        assert_eq!(Scan::parse_position("HEAD1"), None);
+        assert_eq!(Scan::parse_position("head"), None);
+        assert_eq!(Scan::parse_position("HEAD~99999999999999999999"), None);
+    }
+
+    #[test]
+    fn numbers_count_from_either_end() {
+        assert_eq!(Scan::parse_position("1"), Some((SQL_BY_ROOT_SCAN_NUMBER, 1)));
+        assert_eq!(Scan::parse_position("12"), Some((SQL_BY_ROOT_SCAN_NUMBER, 12)));
+        assert_eq!(Scan::parse_position("-1"), Some((SQL_BY_ROOT_SCAN_OFFSET, 0)));
+        assert_eq!(Scan::parse_position("-3"), Some((SQL_BY_ROOT_SCAN_OFFSET, 2)));
+    }
+
+    #[test]
+    fn zero_and_overflowing_numbers_are_rejected() {
+        assert_eq!(Scan::parse_position("0"), None);
+        assert_eq!(Scan::parse_position(""), None);
+        assert_eq!(Scan::parse_position("99999999999999999999"), None);
+        assert_eq!(Scan::parse_position(&i64::MIN.to_string()), None);
+        assert_eq!(Scan::parse_position(&(i64::MIN + 1).to_string()), Some((SQL_BY_ROOT_SCAN_OFFSET, i64::MAX - 1)));
+    }
+}
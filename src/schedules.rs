@@ -0,0 +1,154 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rusqlite::Row;
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+
+/// How thorough a scheduled scan is. A more thorough scan also satisfies the schedules
+/// for less thorough ones
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ScanKind {
+    /// Metadata only
+    Shallow = 0,
+    /// Hashes new files and files whose metadata changed
+    Deep = 1,
+    /// Hashes every file
+    Verify = 2,
+}
+
+impl ScanKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanKind::Shallow => "shallow",
+            ScanKind::Deep => "deep",
+            ScanKind::Verify => "verify",
+        }
+    }
+}
+
+impl fmt::Display for ScanKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ScanKind {
+    type Err = FsPulseError;
+    fn from_str(s: &str) -> Result<Self, FsPulseError> {
+        match s.to_lowercase().as_str() {
+            "shallow" => Ok(ScanKind::Shallow),
+            "deep" => Ok(ScanKind::Deep),
+            "verify" => Ok(ScanKind::Verify),
+            _ => Err(FsPulseError::Error(format!("Invalid scan kind '{}'", s))),
+        }
+    }
+}
+
+/// A policy to scan a root every `interval` seconds. Scans fall due at `anchor` and every
+/// interval after it, so an anchor on a Sunday at 02:00 with an interval of a week means
+/// every Sunday at 02:00
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    root_id: i64,
+    kind: ScanKind,
+    interval: i64,
+    anchor: i64,
+    last_run: Option<i64>,
+}
+
+impl Schedule {
+    pub fn root_id(&self) -> i64 { self.root_id }
+    pub fn kind(&self) -> ScanKind { self.kind }
+    pub fn interval(&self) -> i64 { self.interval }
+    pub fn last_run(&self) -> Option<i64> { self.last_run }
+
+    /// The first time a scan is due after the last run: the anchor if the schedule has
+    /// never run, otherwise the next anchor + n * interval following the last run
+    pub fn next_due(&self) -> i64 {
+        match self.last_run {
+            Some(last_run) if last_run >= self.anchor => {
+                self.anchor + ((last_run - self.anchor) / self.interval + 1) * self.interval
+            },
+            _ => self.anchor,
+        }
+    }
+
+    pub fn is_due(&self, now: i64) -> bool {
+        self.next_due() <= now
+    }
+
+    /// Creates the root's schedule for scans of this kind, or replaces it
+    pub fn set(db: &Database, root_id: i64, kind: ScanKind, interval: i64, anchor: i64) -> Result<(), FsPulseError> {
+        db.conn.execute(
+            "INSERT INTO schedules (root_id, kind, interval, anchor) VALUES (?1, ?2, ?3, ?4)
+                ON CONFLICT (root_id, kind) DO UPDATE SET interval = ?3, anchor = ?4",
+            (root_id, kind.as_str(), interval, anchor),
+        )?;
+
+        Ok(())
+    }
+
+    /// Removes the root's schedule for scans of this kind. Returns false if there was none
+    pub fn remove(db: &Database, root_id: i64, kind: ScanKind) -> Result<bool, FsPulseError> {
+        let removed = db.conn.execute(
+            "DELETE FROM schedules WHERE root_id = ? AND kind = ?",
+            (root_id, kind.as_str()),
+        )?;
+
+        Ok(removed > 0)
+    }
+
+    /// Records that a scan of this kind ran at `time`, which satisfies the root's schedules
+    /// for this kind and every less thorough kind
+    pub fn record_run(db: &Database, root_id: i64, kind: ScanKind, time: i64) -> Result<(), FsPulseError> {
+        let satisfied: Vec<&str> = [ScanKind::Shallow, ScanKind::Deep, ScanKind::Verify]
+            .iter()
+            .filter(|k| **k <= kind)
+            .map(|k| k.as_str())
+            .collect();
+
+        for satisfied_kind in satisfied {
+            db.conn.execute(
+                "UPDATE schedules SET last_run = ? WHERE root_id = ? AND kind = ?",
+                (time, root_id, satisfied_kind),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Calls `func` with each schedule, or only those of `root_id` if specified
+    pub fn for_each_schedule<F>(db: &Database, root_id: Option<i64>, mut func: F) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&Schedule) -> Result<(), FsPulseError>,
+    {
+        let mut stmt = db.conn.prepare(
+            "SELECT root_id, kind, interval, anchor, last_run
+                FROM schedules
+                WHERE ?1 IS NULL OR root_id = ?1
+                ORDER BY root_id ASC, interval ASC"
+        )?;
+
+        let rows = stmt.query_map([root_id], Schedule::from_row)?;
+
+        for row in rows {
+            func(&row?)?;
+        }
+
+        Ok(())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let kind: String = row.get(1)?;
+
+        Ok(Schedule {
+            root_id: row.get(0)?,
+            kind: kind.parse().unwrap_or(ScanKind::Shallow),
+            interval: row.get(2)?,
+            anchor: row.get(3)?,
+            last_run: row.get(4)?,
+        })
+    }
+}
@@ -6,12 +6,16 @@ CREATE TABLE IF NOT EXISTS meta (
     value TEXT NOT NULL
 );
 
-INSERT OR REPLACE INTO meta (key, value) VALUES ('schema_version', '2');
+INSERT OR REPLACE INTO meta (key, value) VALUES ('schema_version', '34');
 
 -- Roots table stores unique root directories that have been scanned
 CREATE TABLE IF NOT EXISTS roots (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
-    path TEXT NOT NULL UNIQUE
+    path TEXT NOT NULL UNIQUE,
+    timestamp_granularity INTEGER DEFAULT NULL, -- Finest difference in modification times the file system stores, in nanoseconds (NULL = unknown)
+    case_sensitive BOOLEAN DEFAULT NULL,        -- Whether file names are case sensitive (NULL = unknown)
+    supports_symlinks BOOLEAN DEFAULT NULL,     -- Whether the file system supports symlinks (NULL = unknown)
+    supports_xattrs BOOLEAN DEFAULT NULL        -- Whether the file system supports extended attributes (NULL = unknown)
 );
 
 -- Indexes to optimize queries
@@ -23,10 +27,31 @@ CREATE TABLE IF NOT EXISTS scans (
     root_id INTEGER NOT NULL,          -- Links scan to a root path
     state INTEGER NOT NULL,            -- The state of the scan (0 = Pending, 1 = Scanning, 2 = Sweeping, 3 = Analyzing, 4 = Completed, 5 = Aborted)
     hashing BOOLEAN NOT NULL,          -- Indicated the scan computes hashes for files
+    hash_delta BOOLEAN NOT NULL DEFAULT 0, -- Indicates a hashing scan only hashes new files and files whose metadata changed
     validating BOOLEAN NOT NULL,       -- Indicates the scan validates file contents
     time_of_scan INTEGER NOT NULL,     -- Timestamp of when scan was performed (UTC)
     file_count INTEGER DEFAULT NULL,   -- Count of files found in the scan
     folder_count INTEGER DEFAULT NULL, -- Count of directories found in the scan
+    empty_guardrail INTEGER DEFAULT NULL, -- Decision when a scan found nothing where items existed (NULL = not triggered, 1 = Allowed by flag, 2 = Confirmed, 3 = Refused)
+    digest TEXT DEFAULT NULL,          -- Fingerprint of the root's state as of the completed scan
+    confirm_threshold INTEGER DEFAULT NULL, -- Files of at least this size get a confirmation hash (NULL = no confirmation)
+    hash_confirmations INTEGER NOT NULL DEFAULT 0, -- Unchanged hashes confirmed by the confirmation hash
+    hash_collisions INTEGER NOT NULL DEFAULT 0,    -- Unchanged hashes contradicted by the confirmation hash
+    excluded_count INTEGER NOT NULL DEFAULT 0,     -- Paths the scan passed over because they were excluded
+    root_scan_number INTEGER NOT NULL DEFAULT 0,   -- The scan's number among the scans of its root, counting from 1
+    budget_secs INTEGER DEFAULT NULL,              -- Time the scan was allowed, in seconds (NULL = unlimited)
+    dirs_checked INTEGER DEFAULT NULL,             -- Directories whose entries the scan read (NULL = not recorded)
+    dirs_unchecked INTEGER NOT NULL DEFAULT 0,     -- Directories left unread when the budget ran out
+    hash_concurrency INTEGER DEFAULT NULL,         -- Files hashed at once, as chosen by tuning (NULL = not tuned)
+    hash_buffer_size INTEGER DEFAULT NULL,         -- Bytes read from a file at a time, as chosen by tuning (NULL = not tuned)
+    db_size_start INTEGER DEFAULT NULL,            -- Size of the database when the scan started, in bytes (NULL = not measured)
+    db_growth INTEGER DEFAULT NULL,                -- Bytes the database grew by during the scan (NULL = not measured)
+    hash_algo TEXT DEFAULT NULL,                   -- Algorithm files are hashed with ('md5', 'sha256', 'blake3' or 'xxh3'; NULL = not hashing)
+    add_count INTEGER DEFAULT NULL,                -- Adds the scan found, recorded when it completed (NULL = not completed)
+    modify_count INTEGER DEFAULT NULL,             -- Modifications the scan found, recorded when it completed
+    delete_count INTEGER DEFAULT NULL,             -- Deletes the scan found, recorded when it completed
+    type_change_count INTEGER DEFAULT NULL,        -- Type changes the scan found, recorded when it completed
+    move_count INTEGER DEFAULT NULL,               -- Moves the scan found, recorded when it completed
     FOREIGN KEY (root_id) REFERENCES roots(id)
 );
 
@@ -34,16 +59,28 @@ CREATE TABLE IF NOT EXISTS scans (
 CREATE TABLE IF NOT EXISTS items (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     root_id INTEGER NOT NULL,               -- Links each item to a root
-    path TEXT NOT NULL,               -- Relative path from the root path
+    path TEXT NOT NULL,               -- Path relative to the root path
     is_tombstone BOOLEAN NOT NULL DEFAULT 0,  -- Indicates if the item was deleted
     item_type CHAR(1) NOT NULL,       -- ('F' for file, 'D' for directory, 'S' for symlink, 'O' for other)
     last_modified INTEGER,            -- Last modified timestamp
     file_size INTEGER,                -- File size in bytes (NULL for directories)
     file_hash TEXT,                   -- Hash of file contents (NULL for directories and if not computed)
+    confirm_hash TEXT,                -- Second, independent hash used to confirm an unchanged file_hash (NULL if not computed)
     file_is_valid BOOL,               -- Validation state of file. If null, file was not scanned
     last_scan_id INTEGER NOT NULL,    -- Last scan where the item was present
     last_hash_scan_id INTEGER,        -- Id of last scan during which a hash was computed
     last_is_valid_scan_id INTEGER,    -- Id of last scan during which file was validated
+    last_verified INTEGER,            -- When the file's content was last read and hashed (UTC)
+    is_ignored BOOLEAN NOT NULL DEFAULT 0, -- Indicates the item is hidden from reports and skipped by scans
+    symlink_target TEXT,              -- What a symlink points to, as stored in the link (NULL for other items)
+    symlink_dangling BOOL,            -- Whether the symlink's target was missing when last scanned (NULL for other items)
+    hash_in_flux BOOLEAN NOT NULL DEFAULT 0, -- Indicates the file changed while it was last being hashed, so it needs hashing again
+    owner_uid INTEGER,                -- User id of the item's owner (NULL where the platform doesn't have them)
+    owner_gid INTEGER,                -- Group id of the item's group (NULL where the platform doesn't have them)
+    sample_hash TEXT,                 -- Hash of sampled parts of a large file, prefixed with how it was sampled (NULL if not sampled)
+    hash_algo TEXT,                   -- Algorithm file_hash and confirm_hash were taken with (NULL if not computed)
+    similarity_digest TEXT,           -- Sketch of a small file's content for estimating how much a modification changed it (NULL if not computed)
+    is_offline BOOLEAN NOT NULL DEFAULT 0, -- Indicates the file's content was held elsewhere (e.g. a cloud placeholder) when last scanned
     FOREIGN KEY (root_id) REFERENCES roots(id),
     FOREIGN KEY (last_scan_id) REFERENCES scans(id),
     FOREIGN KEY (last_hash_scan_id) REFERENCES scans(id),
@@ -54,20 +91,809 @@ CREATE TABLE IF NOT EXISTS items (
 -- Indexes to optimize queries
 CREATE INDEX IF NOT EXISTS idx_items_path ON items (root_id, path);
 CREATE INDEX IF NOT EXISTS idx_items_scan ON items (root_id, last_scan_id, is_tombstone);
+CREATE INDEX IF NOT EXISTS idx_items_last_scan ON items (last_scan_id, path);
+CREATE INDEX IF NOT EXISTS idx_items_hash ON items (file_hash) WHERE file_hash IS NOT NULL;
+
+-- Full-text index over item paths. The trigram tokenizer supports case-insensitive
+-- substring matching; the triggers keep the index in step with the items table
+CREATE VIRTUAL TABLE IF NOT EXISTS items_fts USING fts5(path, content = 'items', content_rowid = 'id', tokenize = 'trigram');
+
+CREATE TRIGGER IF NOT EXISTS items_fts_insert AFTER INSERT ON items BEGIN
+    INSERT INTO items_fts (rowid, path) VALUES (new.id, new.path);
+END;
+
+CREATE TRIGGER IF NOT EXISTS items_fts_delete AFTER DELETE ON items BEGIN
+    INSERT INTO items_fts (items_fts, rowid, path) VALUES ('delete', old.id, old.path);
+END;
+
+CREATE TRIGGER IF NOT EXISTS items_fts_update AFTER UPDATE OF path ON items BEGIN
+    INSERT INTO items_fts (items_fts, rowid, path) VALUES ('delete', old.id, old.path);
+    INSERT INTO items_fts (rowid, path) VALUES (new.id, new.path);
+END;
+
+-- Schedules table holds policies for scanning roots periodically
+CREATE TABLE IF NOT EXISTS schedules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    root_id INTEGER NOT NULL,          -- The root to scan
+    kind TEXT NOT NULL,                -- How thorough the scan is ('shallow', 'deep' or 'verify')
+    interval INTEGER NOT NULL,         -- Seconds between scans
+    anchor INTEGER NOT NULL,           -- A time (UTC) the scan is due. It's also due every interval before and after
+    last_run INTEGER DEFAULT NULL,     -- When a scheduled scan at least this thorough last completed (UTC)
+    FOREIGN KEY (root_id) REFERENCES roots(id),
+    UNIQUE (root_id, kind)
+);
+
+-- Allowed owners table lists the users expected to own a root's items. Roots without
+-- any allowed owners accept every owner
+CREATE TABLE IF NOT EXISTS allowed_owners (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    root_id INTEGER NOT NULL,          -- The root whose items the user may own
+    uid INTEGER NOT NULL,              -- User id of the allowed owner
+    FOREIGN KEY (root_id) REFERENCES roots(id),
+    UNIQUE (root_id, uid)
+);
+
+-- Root groups table gathers roots that together hold one dataset, such as a collection
+-- spread across several volumes, so they can be reported on as one
+CREATE TABLE IF NOT EXISTS root_groups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,                -- The name of the group
+    root_id INTEGER NOT NULL,          -- A root in the group
+    FOREIGN KEY (root_id) REFERENCES roots(id),
+    UNIQUE (name, root_id)
+);
+
+-- Containers table lists parent directories whose immediate subdirectories are each
+-- registered and scanned as a root of their own. Container roots links each container to
+-- the roots registered for its subdirectories
+CREATE TABLE IF NOT EXISTS containers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL UNIQUE          -- Canonical path of the parent directory
+);
+
+CREATE TABLE IF NOT EXISTS container_roots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    container_id INTEGER NOT NULL,     -- The container holding the root's directory
+    root_id INTEGER NOT NULL UNIQUE,   -- A root registered for one of the container's subdirectories
+    archived_at INTEGER DEFAULT NULL,  -- When the subdirectory was found to be gone (NULL while it exists)
+    FOREIGN KEY (container_id) REFERENCES containers(id),
+    FOREIGN KEY (root_id) REFERENCES roots(id)
+);
+
+-- Scan exclusions table lists the paths a scan passed over, when asked to record them
+CREATE TABLE IF NOT EXISTS scan_exclusions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan_id INTEGER NOT NULL,          -- The scan which excluded the path
+    path TEXT NOT NULL,                -- Path relative to the root path
+    reason TEXT NOT NULL,              -- Why the path was excluded ('ignored', 'database', 'checksum', 'unreadable', 'excluded' or 'gitignore')
+    FOREIGN KEY (scan_id) REFERENCES scans(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_scan_exclusions_scan ON scan_exclusions (scan_id);
+
+-- Scan exclusion counts table holds how many paths each scan passed over for each reason,
+-- whether or not the paths themselves were recorded
+CREATE TABLE IF NOT EXISTS scan_exclusion_counts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan_id INTEGER NOT NULL,          -- The scan which excluded the paths
+    reason TEXT NOT NULL,              -- Why the paths were excluded ('ignored', 'database', 'checksum', 'unreadable', 'excluded' or 'gitignore')
+    count INTEGER NOT NULL,            -- How many paths were excluded for the reason
+    FOREIGN KEY (scan_id) REFERENCES scans(id),
+    UNIQUE (scan_id, reason)
+);
+
+-- Scan exclude rules table holds the rules a scan excluded paths by, so that reports can
+-- explain what's missing and a resumed scan follows the rules it was started with
+CREATE TABLE IF NOT EXISTS scan_exclude_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan_id INTEGER NOT NULL,          -- The scan which followed the rule
+    kind TEXT NOT NULL,                -- 'exclude' for an --exclude glob, 'gitignore' for following .gitignore and .ignore files, 'include' for an --include glob
+    pattern TEXT DEFAULT NULL,         -- The glob, for 'exclude' and 'include'
+    FOREIGN KEY (scan_id) REFERENCES scans(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_scan_exclude_rules_scan ON scan_exclude_rules (scan_id);
+
+-- Unchecked directories table lists the directories a budgeted scan didn't get to, or that
+-- couldn't be read. Nothing beneath them was checked, so their items are neither seen nor
+-- deleted by the scan
+CREATE TABLE IF NOT EXISTS unchecked_dirs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan_id INTEGER NOT NULL,          -- The scan which didn't read the directory
+    path TEXT NOT NULL,                -- Path relative to the root path
+    FOREIGN KEY (scan_id) REFERENCES scans(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_unchecked_dirs_scan ON unchecked_dirs (scan_id);
 
 -- Changes table tracks modifications between scans
 CREATE TABLE IF NOT EXISTS changes (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     scan_id INTEGER NOT NULL,                 -- The scan in which the change was detected
     item_id INTEGER NOT NULL,                -- The file or directory that changed
-    change_type CHAR(1) NOT NULL,             -- ('A' for added, 'D' for deleted, 'M' for modified, 'T' for type changed)
+    change_type CHAR(1) NOT NULL,             -- ('A' for added, 'D' for deleted, 'M' for modified, 'T' for type changed, 'R' for moved)
     prev_last_modified INTEGER DEFAULT NULL,  -- Stores the previous last_modified timestamp (if changed)
     prev_file_size INTEGER DEFAULT NULL,      -- Stores the previous file_size (if changed)
     prev_hash TEXT DEFAULT NULL,              -- Stores the previous hash value (if changed)
     prev_is_valid BOOL DEFAULT NULL,          -- Stores the previous is_valid value (if changed)
+    prev_symlink_target TEXT DEFAULT NULL,    -- Stores the previous symlink target (if changed)
+    prev_owner_uid INTEGER DEFAULT NULL,      -- Stores the previous owner's user id (if ownership changed)
+    prev_owner_gid INTEGER DEFAULT NULL,      -- Stores the previous group id (if ownership changed)
+    prev_sample_hash TEXT DEFAULT NULL,       -- Stores the previous sampled hash (if changed)
+    old_path TEXT DEFAULT NULL,               -- The item's path before it moved (moves only)
+    similarity INTEGER DEFAULT NULL,          -- Percentage of the previous content a modified file kept (NULL if not computed)
+    acknowledged BOOLEAN NOT NULL DEFAULT 0,  -- Indicates a reviewer has acknowledged the change
+    review_comment TEXT DEFAULT NULL,         -- A reviewer's comment on the change
+    reviewer TEXT DEFAULT NULL,               -- Who reviewed the change
+    FOREIGN KEY (scan_id) REFERENCES scans(id),
+    FOREIGN KEY (item_id) REFERENCES items(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_changes_scan ON changes (scan_id, change_type);
+CREATE INDEX IF NOT EXISTS idx_changes_item ON changes (item_id, change_type);
+
+-- Verifications table records each run of `verify`, which re-read a root's files whose size
+-- and modification time hadn't changed since they were hashed. Verification mismatches
+-- lists the files whose content no longer matched their hash, a sign of bit rot
+CREATE TABLE IF NOT EXISTS verifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    root_id INTEGER NOT NULL,                 -- The root whose files were verified
+    time_of_verification INTEGER NOT NULL,    -- When the verification was run (UTC)
+    files_matched INTEGER NOT NULL DEFAULT 0,    -- Files whose content matched their hash
+    files_mismatched INTEGER NOT NULL DEFAULT 0, -- Files whose content didn't match their hash
+    files_skipped INTEGER NOT NULL DEFAULT 0,    -- Files not verified because they changed, were gone, were being written or couldn't be read
+    FOREIGN KEY (root_id) REFERENCES roots(id)
+);
+
+CREATE TABLE IF NOT EXISTS verification_mismatches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    verification_id INTEGER NOT NULL,  -- The verification which found the mismatch
+    item_id INTEGER NOT NULL,          -- The file whose content didn't match
+    hash_algo TEXT NOT NULL,           -- Algorithm both hashes were taken with
+    expected_hash TEXT NOT NULL,       -- The hash recorded by the file's last hashing scan
+    found_hash TEXT NOT NULL,          -- The hash of the file's content when it was verified
+    FOREIGN KEY (verification_id) REFERENCES verifications(id),
+    FOREIGN KEY (item_id) REFERENCES items(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_verification_mismatches_verification ON verification_mismatches (verification_id);
+
+-- Audit log table records each operation which changed the database, with who ran it and
+-- how, so that what was done to the database can be reconstructed. It's append-only
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    time INTEGER NOT NULL,             -- When the operation was run (UTC)
+    user TEXT NOT NULL,                -- The user who ran it
+    operation TEXT NOT NULL,           -- The command run, such as 'scan' or 'schedule set'
+    parameters TEXT NOT NULL           -- The arguments it was run with, with secrets left out
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_operation ON audit_log (operation);
+
+CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log BEGIN
+    SELECT RAISE(ABORT, 'The audit log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log BEGIN
+    SELECT RAISE(ABORT, 'The audit log is append-only');
+END;
+
+-- A completed or aborted scan is a record of what was found, so it and the changes it
+-- found can't be changed afterwards. Reviewers' annotations of changes aren't part of the
+-- record. Scans can still be removed along with their root
+CREATE TRIGGER IF NOT EXISTS scans_immutable BEFORE UPDATE ON scans WHEN old.state IN (4, 5) BEGIN
+    SELECT RAISE(ABORT, 'Completed and aborted scans can''t be changed');
+END;
+
+CREATE TRIGGER IF NOT EXISTS changes_immutable
+    BEFORE UPDATE OF scan_id, item_id, change_type, prev_last_modified, prev_file_size, prev_hash, prev_is_valid,
+        prev_symlink_target, prev_owner_uid, prev_owner_gid, prev_sample_hash, old_path, similarity ON changes
+    WHEN (SELECT state FROM scans WHERE id = old.scan_id) IN (4, 5)
+BEGIN
+    SELECT RAISE(ABORT, 'The changes of completed and aborted scans can''t be changed');
+END;
+
+COMMIT;
+"#;
+
+// Upgrades from an older schema version, as (from version, to version, sql)
+pub const UPGRADE_SCHEMA_SQL: &[(&str, &str, &str)] = &[
+    ("2", "3", UPGRADE_2_TO_3_SQL),
+    ("3", "4", UPGRADE_3_TO_4_SQL),
+    ("4", "5", UPGRADE_4_TO_5_SQL),
+    ("5", "6", UPGRADE_5_TO_6_SQL),
+    ("6", "7", UPGRADE_6_TO_7_SQL),
+    ("7", "8", UPGRADE_7_TO_8_SQL),
+    ("8", "9", UPGRADE_8_TO_9_SQL),
+    ("9", "10", UPGRADE_9_TO_10_SQL),
+    ("10", "11", UPGRADE_10_TO_11_SQL),
+    ("11", "12", UPGRADE_11_TO_12_SQL),
+    ("12", "13", UPGRADE_12_TO_13_SQL),
+    ("13", "14", UPGRADE_13_TO_14_SQL),
+    ("14", "15", UPGRADE_14_TO_15_SQL),
+    ("15", "16", UPGRADE_15_TO_16_SQL),
+    ("16", "17", UPGRADE_16_TO_17_SQL),
+    ("17", "18", UPGRADE_17_TO_18_SQL),
+    ("18", "19", UPGRADE_18_TO_19_SQL),
+    ("19", "20", UPGRADE_19_TO_20_SQL),
+    ("20", "21", UPGRADE_20_TO_21_SQL),
+    ("21", "22", UPGRADE_21_TO_22_SQL),
+    ("22", "23", UPGRADE_22_TO_23_SQL),
+    ("23", "24", UPGRADE_23_TO_24_SQL),
+    ("24", "25", UPGRADE_24_TO_25_SQL),
+    ("25", "26", UPGRADE_25_TO_26_SQL),
+    ("26", "27", UPGRADE_26_TO_27_SQL),
+    ("27", "28", UPGRADE_27_TO_28_SQL),
+    ("28", "29", UPGRADE_28_TO_29_SQL),
+    ("29", "30", UPGRADE_29_TO_30_SQL),
+    ("30", "31", UPGRADE_30_TO_31_SQL),
+    ("31", "32", UPGRADE_31_TO_32_SQL),
+    ("32", "33", UPGRADE_32_TO_33_SQL),
+    ("33", "34", UPGRADE_33_TO_34_SQL),
+];
+
+const UPGRADE_2_TO_3_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE scans ADD COLUMN empty_guardrail INTEGER DEFAULT NULL;
+
+UPDATE meta SET value = '3' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_3_TO_4_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE scans ADD COLUMN digest TEXT DEFAULT NULL;
+
+UPDATE meta SET value = '4' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_4_TO_5_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE scans ADD COLUMN confirm_threshold INTEGER DEFAULT NULL;
+ALTER TABLE scans ADD COLUMN hash_confirmations INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE scans ADD COLUMN hash_collisions INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE items ADD COLUMN confirm_hash TEXT DEFAULT NULL;
+
+UPDATE meta SET value = '5' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_5_TO_6_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE scans ADD COLUMN hash_delta BOOLEAN NOT NULL DEFAULT 0;
+
+UPDATE meta SET value = '6' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_6_TO_7_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE items ADD COLUMN last_verified INTEGER;
+
+-- Files hashed before verification was tracked were last verified by their last hashing scan
+UPDATE items SET last_verified = (SELECT time_of_scan FROM scans WHERE scans.id = items.last_hash_scan_id)
+    WHERE last_hash_scan_id IS NOT NULL;
+
+UPDATE meta SET value = '7' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+// Item paths were stored in full. They become relative to the root by removing the
+// root's path and the separator which follows it. Either separator is accepted so the
+// upgrade works for databases created on any platform
+const UPGRADE_7_TO_8_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+UPDATE items
+    SET path = substr(path, length(rtrim((SELECT path FROM roots WHERE roots.id = items.root_id), '/\')) + 2)
+    WHERE substr(path, 1, length(rtrim((SELECT path FROM roots WHERE roots.id = items.root_id), '/\')))
+            = rtrim((SELECT path FROM roots WHERE roots.id = items.root_id), '/\')
+        AND substr(path, length(rtrim((SELECT path FROM roots WHERE roots.id = items.root_id), '/\')) + 1, 1) IN ('/', '\');
+
+UPDATE meta SET value = '8' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_8_TO_9_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+-- Full-text index over item paths. The trigram tokenizer supports case-insensitive
+-- substring matching; the triggers keep the index in step with the items table
+CREATE VIRTUAL TABLE items_fts USING fts5(path, content = 'items', content_rowid = 'id', tokenize = 'trigram');
+
+CREATE TRIGGER items_fts_insert AFTER INSERT ON items BEGIN
+    INSERT INTO items_fts (rowid, path) VALUES (new.id, new.path);
+END;
+
+CREATE TRIGGER items_fts_delete AFTER DELETE ON items BEGIN
+    INSERT INTO items_fts (items_fts, rowid, path) VALUES ('delete', old.id, old.path);
+END;
+
+CREATE TRIGGER items_fts_update AFTER UPDATE OF path ON items BEGIN
+    INSERT INTO items_fts (items_fts, rowid, path) VALUES ('delete', old.id, old.path);
+    INSERT INTO items_fts (rowid, path) VALUES (new.id, new.path);
+END;
+
+INSERT INTO items_fts (items_fts) VALUES ('rebuild');
+
+UPDATE meta SET value = '9' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_9_TO_10_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE roots ADD COLUMN timestamp_granularity INTEGER DEFAULT NULL;
+ALTER TABLE roots ADD COLUMN case_sensitive BOOLEAN DEFAULT NULL;
+ALTER TABLE roots ADD COLUMN supports_symlinks BOOLEAN DEFAULT NULL;
+ALTER TABLE roots ADD COLUMN supports_xattrs BOOLEAN DEFAULT NULL;
+
+UPDATE meta SET value = '10' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_10_TO_11_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE items ADD COLUMN is_ignored BOOLEAN NOT NULL DEFAULT 0;
+
+UPDATE meta SET value = '11' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_11_TO_12_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+-- Schedules table holds policies for scanning roots periodically
+CREATE TABLE schedules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    root_id INTEGER NOT NULL,          -- The root to scan
+    kind TEXT NOT NULL,                -- How thorough the scan is ('shallow', 'deep' or 'verify')
+    interval INTEGER NOT NULL,         -- Seconds between scans
+    anchor INTEGER NOT NULL,           -- A time (UTC) the scan is due. It's also due every interval before and after
+    last_run INTEGER DEFAULT NULL,     -- When a scheduled scan at least this thorough last completed (UTC)
+    FOREIGN KEY (root_id) REFERENCES roots(id),
+    UNIQUE (root_id, kind)
+);
+
+UPDATE meta SET value = '12' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_12_TO_13_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE items ADD COLUMN symlink_target TEXT;
+ALTER TABLE items ADD COLUMN symlink_dangling BOOL;
+ALTER TABLE changes ADD COLUMN prev_symlink_target TEXT DEFAULT NULL;
+
+UPDATE meta SET value = '13' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_13_TO_14_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE changes ADD COLUMN acknowledged BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE changes ADD COLUMN review_comment TEXT DEFAULT NULL;
+ALTER TABLE changes ADD COLUMN reviewer TEXT DEFAULT NULL;
+
+UPDATE meta SET value = '14' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_14_TO_15_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE items ADD COLUMN hash_in_flux BOOLEAN NOT NULL DEFAULT 0;
+
+UPDATE meta SET value = '15' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_15_TO_16_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE scans ADD COLUMN excluded_count INTEGER NOT NULL DEFAULT 0;
+
+-- Scan exclusions table lists the paths a scan passed over, when asked to record them
+CREATE TABLE scan_exclusions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan_id INTEGER NOT NULL,          -- The scan which excluded the path
+    path TEXT NOT NULL,                -- Path relative to the root path
+    reason TEXT NOT NULL,              -- Why the path was excluded ('ignored' or 'database')
+    FOREIGN KEY (scan_id) REFERENCES scans(id)
+);
+
+CREATE INDEX idx_scan_exclusions_scan ON scan_exclusions (scan_id);
+
+UPDATE meta SET value = '16' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_16_TO_17_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE items ADD COLUMN owner_uid INTEGER DEFAULT NULL;
+ALTER TABLE items ADD COLUMN owner_gid INTEGER DEFAULT NULL;
+ALTER TABLE changes ADD COLUMN prev_owner_uid INTEGER DEFAULT NULL;
+ALTER TABLE changes ADD COLUMN prev_owner_gid INTEGER DEFAULT NULL;
+
+-- Allowed owners table lists the users expected to own a root's items. Roots without
+-- any allowed owners accept every owner
+CREATE TABLE allowed_owners (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    root_id INTEGER NOT NULL,          -- The root whose items the user may own
+    uid INTEGER NOT NULL,              -- User id of the allowed owner
+    FOREIGN KEY (root_id) REFERENCES roots(id),
+    UNIQUE (root_id, uid)
+);
+
+UPDATE meta SET value = '17' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_17_TO_18_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE scans ADD COLUMN root_scan_number INTEGER NOT NULL DEFAULT 0;
+
+UPDATE scans SET root_scan_number = (
+    SELECT COUNT(*) FROM scans s WHERE s.root_id = scans.root_id AND s.id <= scans.id);
+
+UPDATE meta SET value = '18' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_18_TO_19_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE scans ADD COLUMN budget_secs INTEGER DEFAULT NULL;
+ALTER TABLE scans ADD COLUMN dirs_checked INTEGER DEFAULT NULL;
+ALTER TABLE scans ADD COLUMN dirs_unchecked INTEGER NOT NULL DEFAULT 0;
+
+-- Unchecked directories table lists the directories a budgeted scan didn't get to
+CREATE TABLE unchecked_dirs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan_id INTEGER NOT NULL,          -- The scan which didn't read the directory
+    path TEXT NOT NULL,                -- Path relative to the root path
+    FOREIGN KEY (scan_id) REFERENCES scans(id)
+);
+
+CREATE INDEX idx_unchecked_dirs_scan ON unchecked_dirs (scan_id);
+
+UPDATE meta SET value = '19' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_19_TO_20_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+-- Root groups table gathers roots that together hold one dataset
+CREATE TABLE root_groups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,                -- The name of the group
+    root_id INTEGER NOT NULL,          -- A root in the group
+    FOREIGN KEY (root_id) REFERENCES roots(id),
+    UNIQUE (name, root_id)
+);
+
+UPDATE meta SET value = '20' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_20_TO_21_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+-- Indexes for the queries that slow down as databases grow: a scan's changes, an item's
+-- changes, the items of a scan in path order, and items by hash
+CREATE INDEX IF NOT EXISTS idx_items_last_scan ON items (last_scan_id, path);
+CREATE INDEX IF NOT EXISTS idx_items_hash ON items (file_hash) WHERE file_hash IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_changes_scan ON changes (scan_id, change_type);
+CREATE INDEX IF NOT EXISTS idx_changes_item ON changes (item_id, change_type);
+
+UPDATE meta SET value = '21' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_21_TO_22_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE scans ADD COLUMN hash_concurrency INTEGER DEFAULT NULL;
+ALTER TABLE scans ADD COLUMN hash_buffer_size INTEGER DEFAULT NULL;
+
+UPDATE meta SET value = '22' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_22_TO_23_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE items ADD COLUMN sample_hash TEXT;
+ALTER TABLE changes ADD COLUMN prev_sample_hash TEXT DEFAULT NULL;
+
+UPDATE meta SET value = '23' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_23_TO_24_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE scans ADD COLUMN db_size_start INTEGER DEFAULT NULL;
+ALTER TABLE scans ADD COLUMN db_growth INTEGER DEFAULT NULL;
+
+UPDATE meta SET value = '24' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_24_TO_25_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+CREATE TABLE scan_exclusion_counts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan_id INTEGER NOT NULL,          -- The scan which excluded the paths
+    reason TEXT NOT NULL,              -- Why the paths were excluded ('ignored', 'database', 'checksum' or 'unreadable')
+    count INTEGER NOT NULL,            -- How many paths were excluded for the reason
     FOREIGN KEY (scan_id) REFERENCES scans(id),
+    UNIQUE (scan_id, reason)
+);
+
+UPDATE meta SET value = '25' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_25_TO_26_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+CREATE TABLE scan_exclude_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    scan_id INTEGER NOT NULL,          -- The scan which followed the rule
+    kind TEXT NOT NULL,                -- 'exclude' for an --exclude glob, 'gitignore' for following .gitignore and .ignore files
+    pattern TEXT DEFAULT NULL,         -- The glob, for 'exclude'
+    FOREIGN KEY (scan_id) REFERENCES scans(id)
+);
+
+CREATE INDEX idx_scan_exclude_rules_scan ON scan_exclude_rules (scan_id);
+
+UPDATE meta SET value = '26' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_26_TO_27_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+CREATE TABLE containers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    path TEXT NOT NULL UNIQUE          -- Canonical path of the parent directory
+);
+
+CREATE TABLE container_roots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    container_id INTEGER NOT NULL,     -- The container holding the root's directory
+    root_id INTEGER NOT NULL UNIQUE,   -- A root registered for one of the container's subdirectories
+    archived_at INTEGER DEFAULT NULL,  -- When the subdirectory was found to be gone (NULL while it exists)
+    FOREIGN KEY (container_id) REFERENCES containers(id),
+    FOREIGN KEY (root_id) REFERENCES roots(id)
+);
+
+UPDATE meta SET value = '27' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_27_TO_28_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+ALTER TABLE scans ADD COLUMN hash_algo TEXT DEFAULT NULL;
+ALTER TABLE items ADD COLUMN hash_algo TEXT DEFAULT NULL;
+
+-- Hashes taken before the algorithm could be chosen are md5
+UPDATE scans SET hash_algo = 'md5' WHERE hashing = 1;
+UPDATE items SET hash_algo = 'md5' WHERE file_hash IS NOT NULL;
+
+UPDATE meta SET value = '28' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_28_TO_29_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+-- Audit log table records each operation which changed the database, with who ran it and
+-- how, so that what was done to the database can be reconstructed. It's append-only
+CREATE TABLE audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    time INTEGER NOT NULL,             -- When the operation was run (UTC)
+    user TEXT NOT NULL,                -- The user who ran it
+    operation TEXT NOT NULL,           -- The command run, such as 'scan' or 'schedule set'
+    parameters TEXT NOT NULL           -- The arguments it was run with, with secrets left out
+);
+
+CREATE INDEX idx_audit_log_operation ON audit_log (operation);
+
+CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON audit_log BEGIN
+    SELECT RAISE(ABORT, 'The audit log is append-only');
+END;
+
+CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON audit_log BEGIN
+    SELECT RAISE(ABORT, 'The audit log is append-only');
+END;
+
+-- A completed or aborted scan is a record of what was found, so it and the changes it
+-- found can't be changed afterwards. Reviewers' annotations of changes aren't part of the
+-- record. Scans can still be removed along with their root
+CREATE TRIGGER scans_immutable BEFORE UPDATE ON scans WHEN old.state IN (4, 5) BEGIN
+    SELECT RAISE(ABORT, 'Completed and aborted scans can''t be changed');
+END;
+
+CREATE TRIGGER changes_immutable
+    BEFORE UPDATE OF scan_id, item_id, change_type, prev_last_modified, prev_file_size, prev_hash, prev_is_valid,
+        prev_symlink_target, prev_owner_uid, prev_owner_gid, prev_sample_hash ON changes
+    WHEN (SELECT state FROM scans WHERE id = old.scan_id) IN (4, 5)
+BEGIN
+    SELECT RAISE(ABORT, 'The changes of completed and aborted scans can''t be changed');
+END;
+
+UPDATE meta SET value = '29' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_29_TO_30_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+-- Verifications table records each run of `verify`, which re-read a root's files whose size
+-- and modification time hadn't changed since they were hashed. Verification mismatches
+-- lists the files whose content no longer matched their hash, a sign of bit rot
+CREATE TABLE verifications (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    root_id INTEGER NOT NULL,                 -- The root whose files were verified
+    time_of_verification INTEGER NOT NULL,    -- When the verification was run (UTC)
+    files_matched INTEGER NOT NULL DEFAULT 0,    -- Files whose content matched their hash
+    files_mismatched INTEGER NOT NULL DEFAULT 0, -- Files whose content didn't match their hash
+    files_skipped INTEGER NOT NULL DEFAULT 0,    -- Files not verified because they changed, were gone, were being written or couldn't be read
+    FOREIGN KEY (root_id) REFERENCES roots(id)
+);
+
+CREATE TABLE verification_mismatches (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    verification_id INTEGER NOT NULL,  -- The verification which found the mismatch
+    item_id INTEGER NOT NULL,          -- The file whose content didn't match
+    hash_algo TEXT NOT NULL,           -- Algorithm both hashes were taken with
+    expected_hash TEXT NOT NULL,       -- The hash recorded by the file's last hashing scan
+    found_hash TEXT NOT NULL,          -- The hash of the file's content when it was verified
+    FOREIGN KEY (verification_id) REFERENCES verifications(id),
     FOREIGN KEY (item_id) REFERENCES items(id)
 );
 
+CREATE INDEX idx_verification_mismatches_verification ON verification_mismatches (verification_id);
+
+UPDATE meta SET value = '30' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_30_TO_31_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+-- Change counts are recorded when a scan completes, rather than counted each time the scan
+-- is listed
+ALTER TABLE scans ADD COLUMN add_count INTEGER DEFAULT NULL;
+ALTER TABLE scans ADD COLUMN modify_count INTEGER DEFAULT NULL;
+ALTER TABLE scans ADD COLUMN delete_count INTEGER DEFAULT NULL;
+ALTER TABLE scans ADD COLUMN type_change_count INTEGER DEFAULT NULL;
+
+-- Completed scans can't be changed, so the trigger is set aside while their counts are filled in
+DROP TRIGGER scans_immutable;
+
+UPDATE scans SET
+    add_count = (SELECT COUNT(*) FROM changes c WHERE c.scan_id = scans.id AND c.change_type = 'A'),
+    modify_count = (SELECT COUNT(*) FROM changes c WHERE c.scan_id = scans.id AND c.change_type = 'M'),
+    delete_count = (SELECT COUNT(*) FROM changes c WHERE c.scan_id = scans.id AND c.change_type = 'D'),
+    type_change_count = (SELECT COUNT(*) FROM changes c WHERE c.scan_id = scans.id AND c.change_type = 'T')
+    WHERE state = 4;
+
+CREATE TRIGGER scans_immutable BEFORE UPDATE ON scans WHEN old.state IN (4, 5) BEGIN
+    SELECT RAISE(ABORT, 'Completed and aborted scans can''t be changed');
+END;
+
+UPDATE meta SET value = '31' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_31_TO_32_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+-- A file that left one path and appeared with the same content at another in the same scan
+-- is recorded as a move of the item, with the path it left
+ALTER TABLE changes ADD COLUMN old_path TEXT DEFAULT NULL;
+ALTER TABLE scans ADD COLUMN move_count INTEGER DEFAULT NULL;
+
+-- Earlier scans recorded moves as a deletion and an addition
+DROP TRIGGER scans_immutable;
+
+UPDATE scans SET move_count = 0 WHERE state = 4;
+
+CREATE TRIGGER scans_immutable BEFORE UPDATE ON scans WHEN old.state IN (4, 5) BEGIN
+    SELECT RAISE(ABORT, 'Completed and aborted scans can''t be changed');
+END;
+
+DROP TRIGGER changes_immutable;
+
+CREATE TRIGGER changes_immutable
+    BEFORE UPDATE OF scan_id, item_id, change_type, prev_last_modified, prev_file_size, prev_hash, prev_is_valid,
+        prev_symlink_target, prev_owner_uid, prev_owner_gid, prev_sample_hash, old_path ON changes
+    WHEN (SELECT state FROM scans WHERE id = old.scan_id) IN (4, 5)
+BEGIN
+    SELECT RAISE(ABORT, 'The changes of completed and aborted scans can''t be changed');
+END;
+
+UPDATE meta SET value = '32' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_32_TO_33_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+-- Hashing scans can keep a sketch of small files' content, and record on each modification
+-- how much of the previous content the file kept
+ALTER TABLE items ADD COLUMN similarity_digest TEXT;
+ALTER TABLE changes ADD COLUMN similarity INTEGER DEFAULT NULL;
+
+DROP TRIGGER changes_immutable;
+
+CREATE TRIGGER changes_immutable
+    BEFORE UPDATE OF scan_id, item_id, change_type, prev_last_modified, prev_file_size, prev_hash, prev_is_valid,
+        prev_symlink_target, prev_owner_uid, prev_owner_gid, prev_sample_hash, old_path, similarity ON changes
+    WHEN (SELECT state FROM scans WHERE id = old.scan_id) IN (4, 5)
+BEGIN
+    SELECT RAISE(ABORT, 'The changes of completed and aborted scans can''t be changed');
+END;
+
+UPDATE meta SET value = '33' WHERE key = 'schema_version';
+
+COMMIT;
+"#;
+
+const UPGRADE_33_TO_34_SQL: &str = r#"
+BEGIN TRANSACTION;
+
+-- Files whose content is held elsewhere are recorded so hashing can leave them alone
+ALTER TABLE items ADD COLUMN is_offline BOOLEAN NOT NULL DEFAULT 0;
+
+UPDATE meta SET value = '34' WHERE key = 'schema_version';
+
 COMMIT;
 "#;
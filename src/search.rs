@@ -0,0 +1,192 @@
+use chrono::Utc;
+use rusqlite::{params_from_iter, types::Value};
+
+use std::fmt;
+use std::path::MAIN_SEPARATOR_STR;
+
+use crate::database::Database;
+use crate::error::FsPulseError;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SearchHitKind {
+    Root,
+    Item,
+}
+
+impl fmt::Display for SearchHitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SearchHitKind::Root => write!(f, "Root"),
+            SearchHitKind::Item => write!(f, "Item"),
+        }
+    }
+}
+
+/// A root or item matching a search. Item paths are shown in full (joined to their root)
+/// so that results from different roots can be told apart
+#[derive(Clone, Debug)]
+pub struct SearchHit {
+    pub kind: SearchHitKind,
+    pub id: i64,
+    pub root_id: i64,
+    pub path: String,
+    pub item_type: Option<String>,
+    pub is_tombstone: bool,
+}
+
+/// Conditions on an item's recorded changes which narrow a search to items whose history
+/// matches. With `within_days`, only changes recorded in that many days count, and an item
+/// needs at least one unless another condition is given
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ChangeFilter {
+    /// At least this many changes of any type
+    pub min_changes: Option<u32>,
+    /// Only changes recorded in this many days count
+    pub within_days: Option<u32>,
+    /// A modification in which the file's hash changed but its size didn't
+    pub content_changed: bool,
+    /// A change of type, such as from a file to a directory
+    pub type_changed: bool,
+}
+
+impl ChangeFilter {
+    pub fn is_empty(&self) -> bool {
+        self.min_changes.is_none() && self.within_days.is_none() && !self.content_changed && !self.type_changed
+    }
+
+    /// SQL conditions on the item aliased `i`, binding the parameters they need to `params`
+    fn conditions(&self, params: &mut Vec<Value>) -> Vec<String> {
+        let window = match self.within_days {
+            Some(days) => {
+                params.push((Utc::now().timestamp() - i64::from(days) * 86_400).into());
+                format!("AND c.scan_id IN (SELECT id FROM scans WHERE time_of_scan >= ?{})", params.len())
+            },
+            None => String::new(),
+        };
+
+        let mut conditions = Vec::new();
+
+        let min_changes = match self.min_changes {
+            None if self.within_days.is_some() && !self.content_changed && !self.type_changed => Some(1),
+            min_changes => min_changes,
+        };
+        if let Some(min_changes) = min_changes {
+            params.push(i64::from(min_changes).into());
+            conditions.push(format!(
+                "(SELECT COUNT(*) FROM changes c WHERE c.item_id = i.id {}) >= ?{}", window, params.len()));
+        }
+
+        // A modification records the size before it, if the metadata changed. The size
+        // after it is the size recorded before the next such modification, or the current size
+        if self.content_changed {
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM changes c
+                    WHERE c.item_id = i.id AND c.change_type = 'M' AND c.prev_hash IS NOT NULL {}
+                    AND (c.prev_file_size IS NULL OR c.prev_file_size = COALESCE(
+                        (SELECT n.prev_file_size FROM changes n
+                            WHERE n.item_id = c.item_id AND n.id > c.id AND n.prev_file_size IS NOT NULL
+                            ORDER BY n.id LIMIT 1),
+                        i.file_size)))",
+                window));
+        }
+
+        if self.type_changed {
+            conditions.push(format!(
+                "EXISTS (SELECT 1 FROM changes c WHERE c.item_id = i.id AND c.change_type = 'T' {})", window));
+        }
+
+        conditions
+    }
+}
+
+pub struct Search {
+    // No fields
+}
+
+impl Search {
+    /// Calls `func` with each root and item matching every word of `query`, ignoring case.
+    /// Roots are matched on their full path and items on their path within the root.
+    /// Roots come first, then items ranked by the full-text index. With a change filter,
+    /// only items whose changes match are found, and the query may be empty
+    pub fn for_each_hit<F>(db: &Database, query: &str, filter: &ChangeFilter, limit: i64, mut func: F) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&SearchHit) -> Result<(), FsPulseError>,
+    {
+        let words: Vec<&str> = query.split_whitespace().collect();
+        if words.is_empty() && filter.is_empty() {
+            return Err(FsPulseError::Error("Search query is empty".to_string()));
+        }
+
+        let mut params: Vec<Value> = vec![MAIN_SEPARATOR_STR.to_owned().into()];
+        let mut root_conditions = Vec::new();
+        let mut item_conditions = Vec::new();
+        let mut phrases = Vec::new();
+
+        for word in &words {
+            params.push(word.to_string().into());
+            root_conditions.push(format!("instr(lower(r.path), lower(?{})) > 0", params.len()));
+
+            // The trigram index can only match words of three or more characters
+            if word.chars().count() >= 3 {
+                phrases.push(format!("\"{}\"", word.replace('"', "\"\"")));
+            } else {
+                item_conditions.push(format!("instr(lower(i.path), lower(?{})) > 0", params.len()));
+            }
+        }
+
+        let (item_source, item_rank) = if phrases.is_empty() {
+            ("items i".to_string(), "0")
+        } else {
+            params.push(phrases.join(" AND ").into());
+            item_conditions.push(format!("items_fts MATCH ?{}", params.len()));
+            ("items_fts JOIN items i ON i.id = items_fts.rowid".to_string(), "items_fts.rank")
+        };
+
+        item_conditions.extend(filter.conditions(&mut params));
+        params.push(limit.into());
+
+        // Roots have no changes, so a change filter excludes them all
+        if !filter.is_empty() {
+            root_conditions = vec!["0".to_string()];
+        }
+        if item_conditions.is_empty() {
+            item_conditions.push("1".to_string());
+        }
+
+        let sql = format!(
+            "SELECT 0, r.id, r.id, r.path, NULL, 0, 0 AS rank
+                FROM roots r
+                WHERE {}
+            UNION ALL
+            SELECT 1, i.id, i.root_id, rtrim(r.path, ?1) || ?1 || i.path, i.item_type, i.is_tombstone, {}
+                FROM {}
+                JOIN roots r ON r.id = i.root_id
+                WHERE i.is_ignored = 0 AND {}
+            ORDER BY 1, 7, 4
+            LIMIT ?{}",
+            root_conditions.join(" AND "),
+            item_rank,
+            item_source,
+            item_conditions.join(" AND "),
+            params.len(),
+        );
+
+        let mut stmt = db.conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(params), |row| {
+            Ok(SearchHit {
+                kind: if row.get::<_, i64>(0)? == 0 { SearchHitKind::Root } else { SearchHitKind::Item },
+                id: row.get(1)?,
+                root_id: row.get(2)?,
+                path: row.get(3)?,
+                item_type: row.get(4)?,
+                is_tombstone: row.get(5)?,
+            })
+        })?;
+
+        for row in rows {
+            func(&row?)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use md5::{Digest, Md5};
+
+use crate::changes::{Change, Deletion};
+use crate::config::Config;
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::items::Item;
+use crate::prompts::PromptPolicy;
+use crate::excludes::ExcludeRules;
+use crate::scan_machine::{do_scan_machine, ScanOptions};
+use crate::scans::{Scan, ScanState};
+
+/// Files of the fixture tree and their contents. The tree also holds a directory `dir`
+/// with `dir/nested.txt`
+const FIXTURE_FILES: &[(&str, &str)] = &[
+    ("keep.txt", "unchanged\n"),
+    ("modify.txt", "before\n"),
+    ("delete.txt", "deleted\n"),
+    ("rename_from.txt", "renamed\n"),
+    ("type_change", "becomes a directory\n"),
+    ("dir/nested.txt", "nested\n"),
+];
+
+const MODIFIED_CONTENT: &str = "after the change\n";
+
+/// Runs fspulse against a tree it builds in a temporary directory, checking that each
+/// kind of change is detected and recorded, so an installation can be confirmed to work
+pub struct SelfTest {
+    dir: PathBuf,
+    checks: usize,
+    failures: usize,
+}
+
+impl SelfTest {
+    /// Runs the self-test. The temporary directory is removed afterwards unless `keep` is
+    /// set. Returns an error if any check fails
+    pub fn run(keep: bool) -> Result<(), FsPulseError> {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos());
+        let dir = std::env::temp_dir().join(format!("fspulse-selftest-{}-{}", process::id(), nanos));
+
+        let mut test = SelfTest { dir, checks: 0, failures: 0 };
+        let result = test.run_checks();
+
+        if keep {
+            println!("Kept the self-test directory '{}'", test.dir.display());
+        } else {
+            let _ = fs::remove_dir_all(&test.dir);
+        }
+
+        result?;
+
+        if test.failures > 0 {
+            return Err(FsPulseError::Error(format!("Self-test failed: {} of {} checks failed", test.failures, test.checks)));
+        }
+
+        println!("Self-test passed: {} checks", test.checks);
+        Ok(())
+    }
+
+    fn run_checks(&mut self) -> Result<(), FsPulseError> {
+        let root_path = self.dir.join("root");
+        let db_path = self.dir.join("db");
+        fs::create_dir_all(root_path.join("dir"))?;
+        fs::create_dir_all(&db_path)?;
+
+        for (path, content) in FIXTURE_FILES {
+            fs::write(root_path.join(path), content)?;
+        }
+
+        let mut db = Database::new(Some(db_path))?;
+        let root_path = root_path.canonicalize()?.to_string_lossy().into_owned();
+
+        // First scan: everything is new
+        println!("Self-test: scanning the new tree");
+        let first = self.scan(&mut db, &root_path)?;
+        let changes = Self::changes_by_path(&db, &first)?;
+        for (path, _) in FIXTURE_FILES {
+            self.check_change(&changes, path, Some("A"));
+        }
+        self.check_change(&changes, "dir", Some("A"));
+        self.check(first.file_count() == Some(FIXTURE_FILES.len() as i64), "the first scan counts every file");
+
+        let hashes = Self::hashes_by_path(&db, &first)?;
+        for (path, content) in FIXTURE_FILES {
+            let expected = hex::encode(Md5::digest(content.as_bytes()));
+            self.check(hashes.get(*path) == Some(&expected), &format!("'{}' is hashed correctly", path));
+        }
+
+        // Modify, delete, rename and change the type of items
+        let modify_path = Path::new(&root_path).join("modify.txt");
+        fs::write(&modify_path, MODIFIED_CONTENT)?;
+        File::options().write(true).open(&modify_path)?
+            .set_modified(SystemTime::now() + Duration::from_secs(10))?;
+        fs::remove_file(Path::new(&root_path).join("delete.txt"))?;
+        fs::rename(Path::new(&root_path).join("rename_from.txt"), Path::new(&root_path).join("rename_to.txt"))?;
+        fs::remove_file(Path::new(&root_path).join("type_change"))?;
+        fs::create_dir(Path::new(&root_path).join("type_change"))?;
+
+        println!("Self-test: scanning the changed tree");
+        let second = self.scan(&mut db, &root_path)?;
+        let changes = Self::changes_by_path(&db, &second)?;
+        self.check_change(&changes, "keep.txt", None);
+        self.check_change(&changes, "dir/nested.txt", None);
+        self.check_change(&changes, "modify.txt", Some("M"));
+        self.check_change(&changes, "delete.txt", Some("D"));
+        self.check_change(&changes, "rename_from.txt", None);
+        self.check_change(&changes, "rename_to.txt", Some("R"));
+        self.check(
+            changes.get("rename_to.txt").is_some_and(|change| change.old_path.as_deref() == Some("rename_from.txt")),
+            "the renamed file is recorded as moved from its old path",
+        );
+        self.check_change(&changes, "type_change", Some("T"));
+
+        let hashes = Self::hashes_by_path(&db, &second)?;
+        let expected = hex::encode(Md5::digest(MODIFIED_CONTENT.as_bytes()));
+        self.check(hashes.get("modify.txt") == Some(&expected), "the modified file is hashed again");
+
+        match changes.get("delete.txt") {
+            Some(change) => {
+                let deletion = Deletion::get_for_item(&db, change.item_id)?;
+                self.check(
+                    deletion.is_some_and(|d| d.last_seen_scan_id == first.id() && d.deleted_scan_id == second.id()),
+                    "the deleted file was last seen by the first scan and found missing by the second",
+                );
+            },
+            None => self.check(false, "the deleted file was last seen by the first scan and found missing by the second"),
+        }
+
+        // Third scan: nothing changed
+        println!("Self-test: scanning the unchanged tree");
+        let third = self.scan(&mut db, &root_path)?;
+        self.check(Self::changes_by_path(&db, &third)?.is_empty(), "a scan of an unchanged tree finds no changes");
+        self.check(
+            third.digest().is_some() && third.digest() == second.digest(),
+            "scans of the same tree have the same digest",
+        );
+        self.check(
+            third.digest() != first.digest(),
+            "scans of different trees have different digests",
+        );
+
+        let head = Scan::find_by_ref(&db, &format!("{}@HEAD~1", root_path))?;
+        self.check(head.id() == second.id(), "HEAD~1 refers to the second scan");
+
+        Ok(())
+    }
+
+    fn scan(&mut self, db: &mut Database, root_path: &str) -> Result<Scan, FsPulseError> {
+        let options = ScanOptions {
+            hash: true,
+            prompts: PromptPolicy::NonInteractive,
+            ..Default::default()
+        };
+        do_scan_machine(db, None, Some(root_path.to_owned()), false, options, &ExcludeRules::default(), &Config::default())?;
+
+        let scan = Scan::get_latest(db)?
+            .ok_or_else(|| FsPulseError::Error("The self-test scan wasn't recorded".to_string()))?;
+        self.check(scan.state() == ScanState::Completed, &format!("scan {} completed", scan.id()));
+
+        Ok(scan)
+    }
+
+    fn changes_by_path(db: &Database, scan: &Scan) -> Result<BTreeMap<String, Change>, FsPulseError> {
+        let mut changes = BTreeMap::new();
+        let mut rows = Change::changes_in_scan(db, scan.id())?;
+        for change in rows.iter()? {
+            let change = change?;
+            changes.insert(change.item_path.clone(), change);
+        }
+
+        Ok(changes)
+    }
+
+    fn hashes_by_path(db: &Database, scan: &Scan) -> Result<BTreeMap<String, String>, FsPulseError> {
+        let mut hashes = BTreeMap::new();
+        let mut items = Item::items_in_latest_scan(db, scan.id())?;
+        for item in items.iter()? {
+            let item = item?;
+            if let Some(hash) = item.file_hash() {
+                hashes.insert(item.path().to_owned(), hash.to_owned());
+            }
+        }
+
+        Ok(hashes)
+    }
+
+    fn check_change(&mut self, changes: &BTreeMap<String, Change>, path: &str, expected: Option<&str>) {
+        let found = changes.get(path).map(|change| change.change_type.as_str());
+        let description = match expected {
+            Some(change_type) => format!("'{}' is recorded with change type '{}' (found {})", path, change_type, found.unwrap_or("none")),
+            None => format!("'{}' is unchanged (found {})", path, found.unwrap_or("none")),
+        };
+
+        self.check(found == expected, &description);
+    }
+
+    fn check(&mut self, passed: bool, description: &str) {
+        self.checks += 1;
+        if passed {
+            println!("    ok: {}", description);
+        } else {
+            self.failures += 1;
+            println!("    FAILED: {}", description);
+        }
+    }
+}
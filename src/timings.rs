@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rusqlite::trace::{TraceEvent, TraceEventCodes};
+use rusqlite::Connection;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Time spent in each phase, with the number of times it was entered, in the order the
+/// phases were first entered
+static PHASES: Mutex<Vec<(&'static str, Duration, u64)>> = Mutex::new(Vec::new());
+
+const QUERIES: &str = "database queries";
+const WRITES: &str = "database writes";
+const REPORT: &str = "report";
+
+/// Collects how long a command spends in each of its phases, shown with `--timings`.
+/// Phases can overlap: the time spent running SQL is also counted in the phase that ran it
+pub struct Timings {
+    // No fields
+}
+
+impl Timings {
+    pub fn enable() {
+        ENABLED.store(true, Ordering::Relaxed);
+    }
+
+    pub fn enabled() -> bool {
+        ENABLED.load(Ordering::Relaxed)
+    }
+
+    /// Starts timing a phase, which ends when the returned timer is dropped
+    pub fn start(phase: &'static str) -> PhaseTimer {
+        PhaseTimer {
+            phase,
+            start: Self::enabled().then(Instant::now),
+        }
+    }
+
+    /// Prints the timings to stderr when the returned guard is dropped, at the end of the
+    /// command, whether or not it succeeded
+    pub fn print_on_exit() -> TimingsReport {
+        TimingsReport {
+            total: Self::start("total"),
+        }
+    }
+
+    /// Times every SQL statement run on the connection, as a query or a write. A
+    /// statement's time runs from its first step to its reset, so for queries it includes
+    /// handling the rows as they're read
+    pub fn trace_sql(conn: &Connection) {
+        if Self::enabled() {
+            conn.trace_v2(TraceEventCodes::SQLITE_TRACE_PROFILE, Some(Self::record_sql));
+        }
+    }
+
+    fn record_sql(event: TraceEvent<'_>) {
+        if let TraceEvent::Profile(stmt, elapsed) = event {
+            let sql = stmt.sql();
+            let keyword = sql.split_whitespace().next().unwrap_or_default().to_uppercase();
+            let phase = match keyword.as_str() {
+                "SELECT" | "WITH" | "EXPLAIN" => QUERIES,
+                _ => WRITES,
+            };
+            Self::record(phase, elapsed);
+        }
+    }
+
+    fn record(phase: &'static str, elapsed: Duration) {
+        let mut phases = PHASES.lock().unwrap();
+        match phases.iter_mut().find(|(name, _, _)| *name == phase) {
+            Some((_, total, count)) => {
+                *total += elapsed;
+                *count += 1;
+            },
+            None => phases.push((phase, elapsed, 1)),
+        }
+    }
+
+    fn print() {
+        let phases = PHASES.lock().unwrap();
+        let elapsed = |phase: &str| phases.iter().find(|(name, _, _)| *name == phase).map(|(_, total, _)| *total);
+
+        eprintln!("Timings:");
+        for (phase, total, count) in phases.iter() {
+            eprintln!("    {:<24} {:>10}  ({} times)", phase, Self::format(*total), count);
+        }
+
+        // Tables are written once their rows have been read
+        if let Some(report) = elapsed(REPORT) {
+            let rendering = report.saturating_sub(elapsed(QUERIES).unwrap_or_default());
+            eprintln!("    {:<24} {:>10}", "report: rendering", Self::format(rendering));
+        }
+    }
+
+    fn format(duration: Duration) -> String {
+        if duration < Duration::from_secs(1) {
+            format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+        } else {
+            format!("{:.2}s", duration.as_secs_f64())
+        }
+    }
+}
+
+pub struct PhaseTimer {
+    phase: &'static str,
+    start: Option<Instant>,
+}
+
+impl Drop for PhaseTimer {
+    fn drop(&mut self) {
+        if let Some(start) = self.start {
+            Timings::record(self.phase, start.elapsed());
+        }
+    }
+}
+
+pub struct TimingsReport {
+    total: PhaseTimer,
+}
+
+impl Drop for TimingsReport {
+    fn drop(&mut self) {
+        if let Some(start) = self.total.start.take() {
+            Timings::record(self.total.phase, start.elapsed());
+            Timings::print();
+        }
+    }
+}
@@ -1,6 +1,11 @@
-use std::path::MAIN_SEPARATOR_STR;
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::path::{MAIN_SEPARATOR, MAIN_SEPARATOR_STR};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use chrono::{DateTime, Local, Utc};
+use indicatif::ProgressBar;
+use rusqlite::types::Value;
 
 const NO_DIR_SEPARATOR: &str = "";
 
@@ -48,7 +53,7 @@ impl Utils {
         }
     }
 
-    pub fn _format_db_time(db_time: i64) -> String {
+    pub fn format_db_time(db_time: i64) -> String {
         let datetime_utc = DateTime::<Utc>::from_timestamp(db_time, 0)
             .unwrap_or_else(|| DateTime::<Utc>::from_timestamp(0, 0).unwrap());
 
@@ -67,10 +72,218 @@ impl Utils {
 
     }
 
+    pub fn format_db_time_or_none(db_time: Option<i64>) -> Option<String> {
+        db_time.map(Self::format_db_time)
+    }
+
     pub fn format_db_time_short_or_none(db_time: Option<i64>) -> String {
         db_time.map_or("-".to_string(), Self::format_db_time_short)
     }
 
+    /// Converts a file time to seconds since the Unix epoch. Times before the epoch
+    /// are kept as negative values rather than being lost
+    pub fn system_time_to_db_time(time: SystemTime) -> i64 {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(after) => after.as_secs() as i64,
+            Err(before) => -(before.duration().as_secs() as i64),
+        }
+    }
+
+    /// Formats a signed number of seconds as a short duration such as "+2h 05m" or "-3d 4h"
+    pub fn format_skew(seconds: i64) -> String {
+        let sign = if seconds < 0 { "-" } else { "+" };
+        format!("{}{}", sign, Self::format_duration(seconds.unsigned_abs()))
+    }
+
+    /// Formats a number of seconds as a short duration such as "2h 05m" or "3d 4h"
+    pub fn format_duration(secs: u64) -> String {
+        let (days, hours, minutes) = (secs / 86_400, (secs % 86_400) / 3_600, (secs % 3_600) / 60);
+
+        if days > 0 {
+            format!("{}d {}h", days, hours)
+        } else if hours > 0 {
+            format!("{}h {:02}m", hours, minutes)
+        } else if minutes > 0 {
+            format!("{}m {:02}s", minutes, secs % 60)
+        } else {
+            format!("{}s", secs)
+        }
+    }
+
+    /// Formats a number of bytes in the largest binary unit that keeps it at least 1,
+    /// such as "512 B" or "1.4 GiB"
+    pub fn format_bytes(bytes: u64) -> String {
+        const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+        if bytes < 1024 {
+            return format!("{} B", bytes);
+        }
+
+        let mut value = bytes as f64 / 1024.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+
+    /// Formats a count with thousands separators, such as "152,334"
+    pub fn format_count(count: i64) -> String {
+        let digits = count.unsigned_abs().to_string();
+        let mut formatted = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+
+        if count < 0 {
+            formatted.push('-');
+        }
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(3) {
+                formatted.push(',');
+            }
+            formatted.push(c);
+        }
+
+        formatted
+    }
+
+    /// Formats a change in a count with its sign, such as "+1,204" or "-3"
+    pub fn format_count_delta(delta: i64) -> String {
+        if delta < 0 {
+            Self::format_count(delta)
+        } else {
+            format!("+{}", Self::format_count(delta))
+        }
+    }
+
+    /// Formats a change in a number of bytes with its sign, such as "+1.4 GiB" or "-512 B"
+    pub fn format_bytes_delta(delta: i64) -> String {
+        let sign = if delta < 0 { "-" } else { "+" };
+        format!("{}{}", sign, Self::format_bytes(delta.unsigned_abs()))
+    }
+
+    /// Parses an interval such as "90m" (or "90min"), "6h", "7d" or "4w" into seconds. A
+    /// number without a unit is taken as seconds
+    pub fn parse_interval(s: &str) -> Result<i64, String> {
+        let s = s.trim();
+        let (number, unit_seconds) = match s.char_indices().last() {
+            _ if s.ends_with("min") => (&s[..s.len() - 3], 60),
+            Some((i, 's')) => (&s[..i], 1),
+            Some((i, 'm')) => (&s[..i], 60),
+            Some((i, 'h')) => (&s[..i], 3_600),
+            Some((i, 'd')) => (&s[..i], 86_400),
+            Some((i, 'w')) => (&s[..i], 604_800),
+            _ => (s, 1),
+        };
+
+        match number.trim().parse::<i64>() {
+            Ok(n) if n > 0 => n.checked_mul(unit_seconds)
+                .ok_or_else(|| format!("'{}' is too long an interval", s)),
+            _ => Err(format!("'{}' is not an interval such as 90m, 6h, 7d or 4w", s)),
+        }
+    }
+
+    /// Prints a message above a progress bar. A hidden bar, as when stderr isn't a
+    /// terminal, drops what's printed through it, so the message goes to stderr instead
+    pub fn println_above(bar: &ProgressBar, message: String) {
+        if bar.is_hidden() {
+            eprintln!("{}", message);
+        } else {
+            bar.println(message);
+        }
+    }
+
+    /// Formats seconds as an interval in the largest unit that divides it evenly
+    pub fn format_interval(seconds: i64) -> String {
+        for (unit, unit_seconds) in [("w", 604_800), ("d", 86_400), ("h", 3_600), ("m", 60)] {
+            if seconds % unit_seconds == 0 {
+                return format!("{}{}", seconds / unit_seconds, unit);
+            }
+        }
+
+        format!("{}s", seconds)
+    }
+
+    /// Shortens a path to at most `max_width` characters by replacing the middle with an
+    /// ellipsis. More of the end is kept than the start, since that's where the path is
+    /// most specific
+    pub fn truncate_middle(path: &str, max_width: usize) -> String {
+        let char_count = path.chars().count();
+        if char_count <= max_width || max_width < 5 {
+            return path.to_owned();
+        }
+
+        let kept = max_width - 1; // Leave room for the ellipsis
+        let head = kept / 3;
+        let tail = kept - head;
+
+        let start: String = path.chars().take(head).collect();
+        let end: String = path.chars().skip(char_count - tail).collect();
+
+        format!("{}…{}", start, end)
+    }
+
+    /// Escapes the characters of a path which could garble a terminal or be mistaken for
+    /// others: control characters, invisible formatting characters (such as those which
+    /// reverse the direction of text), the replacement character recorded in place of
+    /// bytes which weren't valid UTF-8, and backslashes where they aren't separators
+    pub fn escape_path(path: &str) -> Cow<'_, str> {
+        if !path.chars().any(Self::needs_escape) {
+            return Cow::Borrowed(path);
+        }
+
+        let mut escaped = String::with_capacity(path.len() + 8);
+        for c in path.chars() {
+            match c {
+                '\\' if Self::needs_escape(c) => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                c if Self::needs_escape(c) => {
+                    let _ = write!(escaped, "\\u{{{:x}}}", c as u32);
+                },
+                c => escaped.push(c),
+            }
+        }
+
+        Cow::Owned(escaped)
+    }
+
+    fn needs_escape(c: char) -> bool {
+        match c {
+            '\\' => MAIN_SEPARATOR != '\\',
+            '\u{fffd}' | '\u{200b}'..='\u{200f}' | '\u{202a}'..='\u{202e}' | '\u{2060}'..='\u{2069}' | '\u{feff}' => true,
+            c => c.is_control(),
+        }
+    }
+
+    /// The condition selecting the paths in `column` which are `prefix` or lie beneath it,
+    /// to add to a query's WHERE clause, with the values it binds in order. LIKE ignores
+    /// the case of ASCII letters, so the start of each path it matches is compared exactly
+    /// as well
+    pub fn path_prefix_condition(column: &str, prefix: &str) -> (String, Vec<Value>) {
+        let beneath = format!("{}{}", prefix, MAIN_SEPARATOR);
+        let mut pattern = String::with_capacity(beneath.len() + 8);
+        for c in beneath.chars() {
+            if matches!(c, '\\' | '%' | '_') {
+                pattern.push('\\');
+            }
+            pattern.push(c);
+        }
+        pattern.push('%');
+
+        let condition = format!(
+            "({column} = ? OR ({column} LIKE ? ESCAPE '\\' AND substr({column}, 1, ?) = ?))");
+        let values = vec![
+            prefix.to_owned().into(),
+            pattern.into(),
+            (beneath.chars().count() as i64).into(),
+            beneath.into(),
+        ];
+
+        (condition, values)
+    }
+
     pub fn opt_bool_or_none_as_str(opt_bool: Option<bool>) -> &'static str {
         match opt_bool {
             Some(true) => "T",
@@ -86,4 +299,93 @@ impl Utils {
         }
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::{params_from_iter, Connection};
+
+    #[test]
+    fn intervals_take_a_unit() {
+        assert_eq!(Utils::parse_interval("45"), Ok(45));
+        assert_eq!(Utils::parse_interval("45s"), Ok(45));
+        assert_eq!(Utils::parse_interval("10m"), Ok(600));
+        assert_eq!(Utils::parse_interval("10min"), Ok(600));
+        assert_eq!(Utils::parse_interval("6h"), Ok(21_600));
+        assert_eq!(Utils::parse_interval("7d"), Ok(604_800));
+        assert_eq!(Utils::parse_interval("4w"), Ok(2_419_200));
+        assert_eq!(Utils::parse_interval(" 2 h "), Ok(7_200));
+    }
+
+    #[test]
+    fn bad_intervals_are_rejected() {
+        for s in ["", "0", "0m", "-5m", "m", "min", "10x", "1.5h", "10 mins"] {
+            assert!(Utils::parse_interval(s).is_err(), "'{}' was accepted", s);
+        }
+    }
+
+    #[test]
+    fn overflowing_intervals_are_rejected() {
+        assert!(Utils::parse_interval("99999999999999999999").is_err());
+        assert!(Utils::parse_interval(&format!("{}w", i64::MAX / 2)).is_err());
+        assert_eq!(Utils::parse_interval(&format!("{}s", i64::MAX)), Ok(i64::MAX));
+    }
+
+    fn paths_under(paths: &[&str], prefix: &str) -> Vec<String> {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE items (path TEXT)", []).unwrap();
+        for path in paths {
+            conn.execute("INSERT INTO items (path) VALUES (?)", [path]).unwrap();
+        }
+
+        let (condition, values) = Utils::path_prefix_condition("path", prefix);
+        let mut stmt = conn.prepare(&format!("SELECT path FROM items WHERE {} ORDER BY path", condition)).unwrap();
+        let rows = stmt.query_map(params_from_iter(values), |row| row.get(0)).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    }
+
+    fn path(parts: &[&str]) -> String {
+        parts.join(MAIN_SEPARATOR_STR)
+    }
+
+    #[test]
+    fn prefix_matches_whole_path_components() {
+        let photos = path(&["", "data", "photos"]);
+        let inside = path(&["", "data", "photos", "a.jpg"]);
+        let deeper = path(&["", "data", "photos", "2024", "b.jpg"]);
+        let sibling = path(&["", "data", "photos2"]);
+        let in_sibling = path(&["", "data", "photos2", "c.jpg"]);
+
+        assert_eq!(
+            paths_under(&[&photos, &inside, &deeper, &sibling, &in_sibling], &photos),
+            vec![photos.clone(), deeper, inside],
+        );
+    }
+
+    #[test]
+    fn prefix_is_case_sensitive() {
+        let photos = path(&["", "data", "photos"]);
+        let upper = path(&["", "data", "Photos", "a.jpg"]);
+        assert!(paths_under(&[&upper], &photos).is_empty());
+    }
+
+    #[test]
+    fn prefix_wildcards_are_escaped() {
+        // Each name, with paths its wildcard or escape would otherwise match
+        let cases = [
+            ("50%", vec!["50Z", "50abc"]),
+            ("a_b", vec!["aZb"]),
+            ("back\\slash", vec!["backZslash", "backslash"]),
+        ];
+        for (name, lookalikes) in cases {
+            let prefix = path(&["", "data", name]);
+            let inside = path(&["", "data", name, "x"]);
+            let mut paths = vec![inside.clone()];
+            paths.extend(lookalikes.iter().map(|lookalike| path(&["", "data", lookalike, "x"])));
+
+            let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+            assert_eq!(paths_under(&paths, &prefix), vec![inside]);
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use std::fs;
+use std::time::SystemTime;
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::capabilities::FsCapabilities;
+use crate::database::Database;
+use crate::error::FsPulseError;
+use crate::hash::{HashAlgo, HashPool, HashQueue, HashResult, DEFAULT_BUFFER_SIZE};
+use crate::items::ItemType;
+use crate::roots::Root;
+use crate::scans::{Scan, ScanState};
+use crate::utils::Utils;
+
+/// A file to verify, as its last hashing scan recorded it
+struct RecordedFile {
+    item_id: i64,
+    path: String,
+    file_size: Option<i64>,
+    last_modified: Option<i64>,
+    hash_algo: String,
+    hash: String,
+}
+
+/// A run of `verify`, which re-read a root's files whose size and modification time hadn't
+/// changed since they were hashed. Content that no longer matches its hash changed without
+/// a write that the file system noticed, which is a sign of bit rot or other corruption
+#[derive(Clone, Debug)]
+pub struct Verification {
+    id: i64,
+    root_id: i64,
+    time_of_verification: i64,
+    files_matched: i64,
+    files_mismatched: i64,
+    files_skipped: i64,
+}
+
+/// A file whose content didn't match its hash when it was verified, though its size and
+/// modification time were unchanged
+#[derive(Clone, Debug)]
+pub struct Mismatch {
+    pub verification_id: i64,
+    pub time_of_verification: i64,
+    pub item_id: i64,
+    pub path: String,
+    pub file_size: Option<i64>,
+    pub hash_algo: String,
+    pub expected_hash: String,
+    pub found_hash: String,
+}
+
+impl Verification {
+    /// Re-hashes the root's files whose size and modification time match what was recorded
+    /// when they were last hashed, on `hash_threads` threads, and records those whose hash
+    /// no longer matches. Each file is hashed with the algorithm its hash was taken with.
+    /// With `limit`, only that many files are verified, those verified longest ago first.
+    /// Files which changed, were deleted, or are being written are skipped, since a scan
+    /// will see them. Matching files are recorded as verified, but no hash is replaced
+    pub fn run(db: &Database, root: &Root, limit: Option<u32>, hash_threads: usize) -> Result<(Self, Vec<Mismatch>), FsPulseError> {
+        if let Some(scan) = Scan::get_latest_for_root(db, root.id())?
+            .filter(|s| s.state() != ScanState::Completed && s.state() != ScanState::Aborted)
+        {
+            return Err(FsPulseError::Error(format!(
+                "Scan Id {} of '{}' is incomplete. Resume or abort it with `fspulse scan` before verifying",
+                scan.id(), root.path())));
+        }
+
+        let mtime_tolerance = FsCapabilities::get_for_root(db, root.id())?.unwrap_or_default().mtime_tolerance();
+        let now = Utils::system_time_to_db_time(SystemTime::now());
+
+        // A scan that sees a file's metadata change without hashing it leaves the old hash
+        // in place, so only files with no change recorded since they were hashed have a
+        // hash that describes their current metadata. Files last seen offline are skipped,
+        // since reading them would fetch their content
+        let files = {
+            let mut stmt = db.conn.prepare(
+                "SELECT i.id, i.path, i.file_size, i.last_modified, i.hash_algo, i.file_hash
+                    FROM items i
+                    WHERE i.root_id = ? AND i.item_type = ? AND i.is_tombstone = 0 AND i.is_ignored = 0
+                    AND i.file_hash IS NOT NULL AND i.hash_algo IS NOT NULL AND i.hash_in_flux = 0 AND i.is_offline = 0
+                    AND NOT EXISTS (SELECT 1 FROM changes c WHERE c.item_id = i.id AND c.scan_id > i.last_hash_scan_id)
+                    ORDER BY i.last_verified ASC, i.path ASC
+                    LIMIT ?"
+            )?;
+            let rows = stmt.query_map((root.id(), ItemType::File.as_str(), limit.map_or(-1, i64::from)), |row| {
+                Ok(RecordedFile {
+                    item_id: row.get(0)?,
+                    path: row.get(1)?,
+                    file_size: row.get(2)?,
+                    last_modified: row.get(3)?,
+                    hash_algo: row.get(4)?,
+                    hash: row.get(5)?,
+                })
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let id: i64 = db.conn.query_row(
+            "INSERT INTO verifications (root_id, time_of_verification) VALUES (?, ?) RETURNING id",
+            (root.id(), now),
+            |row| row.get(0),
+        )?;
+        let mut verification = Verification {
+            id,
+            root_id: root.id(),
+            time_of_verification: now,
+            files_matched: 0,
+            files_mismatched: 0,
+            files_skipped: 0,
+        };
+        let mut mismatches = Vec::new();
+
+        let bar = ProgressBar::new(files.len() as u64);
+        bar.set_style(ProgressStyle::default_bar()
+            .template("{msg}\n[{bar:40}] {pos}/{len} files ({eta})")
+            .expect("valid progress template")
+            .progress_chars("#>-"));
+        bar.set_message(format!("Verifying '{}'", root.path()));
+
+        let hash_pool = HashPool::new(hash_threads);
+        let mut queue = HashQueue::new(&hash_pool);
+
+        for file in files {
+            let absolute_path = root.item_path(&file.path);
+            let unchanged = fs::metadata(&absolute_path).is_ok_and(|metadata| {
+                let modified = metadata.modified().ok().map(Utils::system_time_to_db_time);
+                let same_time = match (file.last_modified, modified) {
+                    (Some(recorded), Some(current)) => (recorded - current).abs() <= mtime_tolerance,
+                    (recorded, current) => recorded == current,
+                };
+                metadata.is_file() && file.file_size == Some(metadata.len() as i64) && same_time
+            });
+            if !unchanged {
+                verification.files_skipped += 1;
+                bar.inc(1);
+                continue;
+            }
+
+            while queue.queued() >= hash_pool.threads() {
+                match queue.next()? {
+                    Some((file, hashes)) => verification.check(db, root, &bar, file, hashes, &mut mismatches)?,
+                    None => break,
+                }
+            }
+
+            let algo: HashAlgo = file.hash_algo.parse()?;
+            queue.submit(file, &absolute_path, &ProgressBar::hidden(), algo, false, DEFAULT_BUFFER_SIZE)?;
+        }
+
+        while let Some((file, hashes)) = queue.next()? {
+            verification.check(db, root, &bar, file, hashes, &mut mismatches)?;
+        }
+
+        bar.finish_and_clear();
+
+        db.conn.execute(
+            "UPDATE verifications SET files_matched = ?, files_mismatched = ?, files_skipped = ? WHERE id = ?",
+            (verification.files_matched, verification.files_mismatched, verification.files_skipped, verification.id),
+        )?;
+
+        Ok((verification, mismatches))
+    }
+
+    /// Records what hashing the file found
+    fn check(
+        &mut self,
+        db: &Database,
+        root: &Root,
+        bar: &ProgressBar,
+        file: RecordedFile,
+        hashes: HashResult,
+        mismatches: &mut Vec<Mismatch>,
+    ) -> Result<(), FsPulseError> {
+        bar.inc(1);
+
+        let found_hash = match hashes {
+            Ok(Some((hash, _))) => hash,
+            // Being written, so it's not at rest
+            Ok(None) => {
+                self.files_skipped += 1;
+                return Ok(());
+            },
+            Err(error) => {
+                Utils::println_above(bar, format!("Error verifying '{}': {}", root.item_path(&file.path).display(), error));
+                self.files_skipped += 1;
+                return Ok(());
+            },
+        };
+
+        if found_hash == file.hash {
+            db.conn.execute(
+                "UPDATE items SET last_verified = strftime('%s', 'now', 'utc') WHERE id = ?",
+                [file.item_id],
+            )?;
+            self.files_matched += 1;
+            return Ok(());
+        }
+
+        db.conn.execute(
+            "INSERT INTO verification_mismatches (verification_id, item_id, hash_algo, expected_hash, found_hash) VALUES (?, ?, ?, ?, ?)",
+            (self.id, file.item_id, &file.hash_algo, &file.hash, &found_hash),
+        )?;
+        self.files_mismatched += 1;
+
+        mismatches.push(Mismatch {
+            verification_id: self.id,
+            time_of_verification: self.time_of_verification,
+            item_id: file.item_id,
+            path: file.path,
+            file_size: file.file_size,
+            hash_algo: file.hash_algo,
+            expected_hash: file.hash,
+            found_hash,
+        });
+
+        Ok(())
+    }
+
+    /// Calls `func` with each verification, optionally of one root, oldest first
+    pub fn for_each_verification<F>(db: &Database, root_id: Option<i64>, mut func: F) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&Verification) -> Result<(), FsPulseError>,
+    {
+        let mut stmt = db.conn.prepare(
+            "SELECT id, root_id, time_of_verification, files_matched, files_mismatched, files_skipped
+                FROM verifications
+                WHERE ?1 IS NULL OR root_id = ?1
+                ORDER BY id ASC"
+        )?;
+        let rows = stmt.query_map([root_id], |row| {
+            Ok(Verification {
+                id: row.get(0)?,
+                root_id: row.get(1)?,
+                time_of_verification: row.get(2)?,
+                files_matched: row.get(3)?,
+                files_mismatched: row.get(4)?,
+                files_skipped: row.get(5)?,
+            })
+        })?;
+
+        for row in rows {
+            func(&row?)?;
+        }
+
+        Ok(())
+    }
+
+    /// Calls `func` with each mismatch found by verifying, optionally of one root, in the
+    /// order they were found
+    pub fn for_each_mismatch<F>(db: &Database, root_id: Option<i64>, mut func: F) -> Result<(), FsPulseError>
+    where
+        F: FnMut(&Mismatch) -> Result<(), FsPulseError>,
+    {
+        let mut stmt = db.conn.prepare(
+            "SELECT m.verification_id, v.time_of_verification, m.item_id, i.path, i.file_size, m.hash_algo, m.expected_hash, m.found_hash
+                FROM verification_mismatches m
+                JOIN verifications v ON v.id = m.verification_id
+                JOIN items i ON i.id = m.item_id
+                WHERE ?1 IS NULL OR v.root_id = ?1
+                ORDER BY m.id ASC"
+        )?;
+        let rows = stmt.query_map([root_id], |row| {
+            Ok(Mismatch {
+                verification_id: row.get(0)?,
+                time_of_verification: row.get(1)?,
+                item_id: row.get(2)?,
+                path: row.get(3)?,
+                file_size: row.get(4)?,
+                hash_algo: row.get(5)?,
+                expected_hash: row.get(6)?,
+                found_hash: row.get(7)?,
+            })
+        })?;
+
+        for row in rows {
+            func(&row?)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn root_id(&self) -> i64 {
+        self.root_id
+    }
+
+    pub fn time_of_verification(&self) -> i64 {
+        self.time_of_verification
+    }
+
+    pub fn files_matched(&self) -> i64 {
+        self.files_matched
+    }
+
+    pub fn files_mismatched(&self) -> i64 {
+        self.files_mismatched
+    }
+
+    pub fn files_skipped(&self) -> i64 {
+        self.files_skipped
+    }
+}
@@ -0,0 +1,145 @@
+use crate::capabilities::PROBE_DIR_PREFIX;
+use crate::checksums::CHECKSUM_FILE_NAME;
+use crate::config::Config;
+use crate::database::{Database, PROJECT_DIR_NAME};
+use crate::error::FsPulseError;
+use crate::excludes::ExcludeRules;
+use crate::roots::Root;
+use crate::scan_machine::{do_scan_machine, do_watch_checkpoint, ScanOptions};
+
+use log::{info, warn};
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+pub struct Watch {
+    // No fields
+}
+
+/// Changes seen since the last checkpoint
+#[derive(Default)]
+struct Pending {
+    /// Directories whose entries changed, relative to the root
+    dirs: BTreeSet<PathBuf>,
+    /// When the first of the changes was seen
+    since: Option<Instant>,
+    /// When the latest of the changes was seen
+    last: Option<Instant>,
+    /// The watcher lost track of changes, so the whole root has to be scanned
+    rescan: bool,
+}
+
+impl Pending {
+    fn mark(&mut self, dir: PathBuf) {
+        self.dirs.insert(dir);
+        self.seen();
+    }
+
+    fn mark_rescan(&mut self) {
+        self.rescan = true;
+        self.seen();
+    }
+
+    fn seen(&mut self) {
+        let now = Instant::now();
+        self.since.get_or_insert(now);
+        self.last = Some(now);
+    }
+}
+
+impl Watch {
+    /// Scans the root, then watches it for changes and records them as scans. A checkpoint
+    /// is recorded once the root has been quiet for `settle`, or once changes have waited
+    /// `max_wait` while it's kept busy. Every scan follows the exclude rules. Runs until
+    /// interrupted
+    pub fn run(
+        db: &mut Database,
+        root: &Root,
+        settle: Duration,
+        max_wait: Duration,
+        options: ScanOptions,
+        rules: &ExcludeRules,
+        config: &Config,
+    ) -> Result<(), FsPulseError> {
+        let root_path = PathBuf::from(root.path());
+
+        // Watch before the first scan so that nothing changed during it is missed
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+        watcher.watch(&root_path, RecursiveMode::Recursive)?;
+
+        do_scan_machine(db, Some(root.id() as u32), None, false, options, rules, config)?;
+        println!("Watching '{}' (Ctrl-C to stop)", root.path());
+
+        let db_files: Vec<PathBuf> = ["", "-wal", "-shm", "-journal"].iter()
+            .map(|suffix| PathBuf::from(format!("{}{}", db.path(), suffix)))
+            .collect();
+        let mut pending = Pending::default();
+
+        loop {
+            match receiver.recv_timeout(settle) {
+                Ok(Ok(event)) => Self::note_event(&root_path, &db_files, event, &mut pending),
+                Ok(Err(err)) => {
+                    warn!("Watch error on '{}': {}", root.path(), err);
+                    pending.mark_rescan();
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(FsPulseError::Error(format!("Stopped receiving changes to '{}'", root.path())));
+                }
+            }
+
+            let (Some(since), Some(last)) = (pending.since, pending.last) else {
+                continue;
+            };
+
+            // A busy root still gets a checkpoint every `max_wait`
+            if last.elapsed() < settle && since.elapsed() < max_wait {
+                continue;
+            }
+
+            let pending = std::mem::take(&mut pending);
+            if pending.rescan {
+                info!("Rescanning '{}' after the watch lost track of changes", root.path());
+                do_scan_machine(db, Some(root.id() as u32), None, false, options, rules, config)?;
+            } else {
+                info!("Recording changes to '{}' in: {:?}", root.path(), pending.dirs);
+                do_watch_checkpoint(db, root, &pending.dirs, options, rules, config)?;
+            }
+        }
+    }
+
+    /// Notes the directories an event shows to have changed. Changes fspulse makes itself,
+    /// to its database, probe directories and checksum files, aren't recorded
+    fn note_event(root_path: &Path, db_files: &[PathBuf], event: Event, pending: &mut Pending) {
+        if event.need_rescan() {
+            pending.mark_rescan();
+            return;
+        }
+        if matches!(event.kind, EventKind::Access(_)) {
+            return;
+        }
+
+        for path in event.paths {
+            if db_files.contains(&path) || path.file_name().is_some_and(|name| name == CHECKSUM_FILE_NAME) {
+                continue;
+            }
+            let Ok(relative) = path.strip_prefix(root_path) else {
+                continue;
+            };
+            let own = relative.components().any(|component| {
+                let name = component.as_os_str();
+                name == PROJECT_DIR_NAME || name.to_string_lossy().starts_with(PROBE_DIR_PREFIX)
+            });
+            if own {
+                continue;
+            }
+
+            // A change to an entry shows when its parent is read. The root has no parent in
+            // the root, so a change to it is read from the root itself
+            pending.mark(relative.parent().unwrap_or(relative).to_path_buf());
+        }
+    }
+}